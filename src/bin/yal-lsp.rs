@@ -0,0 +1,46 @@
+// The code base favours explicit `return` statements
+#![allow(clippy::needless_return)]
+
+// The language server of YAL. It speaks the Language Server
+// Protocol over stdin and stdout, and logs its errors to stderr.
+
+use mixal_compiler::json::{parse_json, JsonValue};
+use mixal_compiler::language_server::{read_message, write_message, LanguageServer, PARSE_ERROR};
+use std::{io, process::ExitCode};
+
+fn main() -> ExitCode {
+    let mut server = LanguageServer::default();
+    let mut reader = io::stdin().lock();
+    let mut writer = io::stdout().lock();
+    loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            // The client has exited without an 'exit' notification
+            Ok(None) => return ExitCode::FAILURE,
+            Err(message) => {
+                eprintln!("error: {}", message);
+                return ExitCode::FAILURE;
+            }
+        };
+        let responses = match parse_json(&message) {
+            Ok(message) => server.handle_message(&message),
+            Err(message) => vec![JsonValue::object(vec![
+                ("jsonrpc", JsonValue::string("2.0")),
+                ("id", JsonValue::Null),
+                ("error", JsonValue::object(vec![
+                    ("code", JsonValue::Number(PARSE_ERROR)),
+                    ("message", JsonValue::String(message))
+                ]))
+            ])]
+        };
+        for response in responses {
+            if let Err(error) = write_message(&mut writer, &response.to_string()) {
+                eprintln!("error: could not write to stdout: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+        if let Some(exit_code) = server.exit_code {
+            return ExitCode::from(exit_code);
+        }
+    }
+}
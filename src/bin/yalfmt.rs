@@ -0,0 +1,150 @@
+// The code base favours explicit `return` statements
+#![allow(clippy::needless_return)]
+
+// The formatter of YAL programs. It prints the formatted programs,
+// overwrites the files with them ('--write') or only reports the
+// files that are not formatted ('--check').
+
+use mixal_compiler::formatter::{format, BraceStyle, FormatOptions};
+use std::{env, fs, io::{self, Read, Write}, process::ExitCode};
+
+const USAGE: &str = "\
+Usage: yalfmt [options] [<file>...]
+
+Options:
+      --indent <n>             The number of spaces of an indentation level (default: 4)
+      --brace-style <style>    Where to open the blocks: same-line or next-line (default: same-line)
+      --check                  Only report the files that are not formatted
+  -w, --write                  Overwrite the files with their formatted version
+  -h, --help                   Print this message
+
+Without any <file>, or with '-', the program is read from stdin and
+the formatted program is written to stdout.";
+
+// The status codes that the formatter exits with
+const EXIT_NOT_FORMATTED: u8 = 1;
+const EXIT_INVALID_ARGUMENTS: u8 = 2;
+const EXIT_IO_ERROR: u8 = 3;
+
+const STANDARD_STREAM_PATH: &str = "-";
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Print,
+    Check,
+    Write
+}
+
+struct Arguments {
+    options: FormatOptions,
+    mode: Mode,
+    files: Vec<String>
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|x| x == "-h" || x == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let arguments = match parse_arguments(&args) {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+        }
+    };
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for file in &arguments.files {
+        let source = match read_source(file) {
+            Ok(source) => source,
+            Err(message) => {
+                eprintln!("error: {}", message);
+                return ExitCode::from(EXIT_IO_ERROR);
+            }
+        };
+        let formatted = match format(&source, &arguments.options) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    eprintln!("{}: {}", file, diagnostic);
+                }
+                exit_code = ExitCode::from(EXIT_NOT_FORMATTED);
+                continue;
+            }
+        };
+        let result = match arguments.mode {
+            Mode::Check => {
+                if formatted != source {
+                    println!("{}", file);
+                    exit_code = ExitCode::from(EXIT_NOT_FORMATTED);
+                }
+                Ok(())
+            },
+            Mode::Write if file != STANDARD_STREAM_PATH => {
+                if formatted == source {
+                    Ok(())
+                } else {
+                    fs::write(file, &formatted).map_err(|error| format!("could not write '{}': {}", file, error))
+                }
+            },
+            _ => io::stdout().write_all(formatted.as_bytes())
+                .map_err(|error| format!("could not write to stdout: {}", error))
+        };
+        if let Err(message) = result {
+            eprintln!("error: {}", message);
+            return ExitCode::from(EXIT_IO_ERROR);
+        }
+    }
+    return exit_code;
+}
+
+fn parse_arguments(args: &[String]) -> Result<Arguments, String> {
+    let mut arguments = Arguments { options: FormatOptions::default(), mode: Mode::Print, files: vec![] };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // Options may be given either as '--option value' or as '--option=value'
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None)
+        };
+        let mut value = |name: &str| {
+            return inline_value.clone()
+                .or_else(|| args.next().cloned())
+                .ok_or(format!("missing value for '{}'", name));
+        };
+        match name {
+            "--indent" => {
+                let indent = value(name)?;
+                arguments.options.indent_width = indent.parse().map_err(|_| format!("invalid indent '{}'", indent))?;
+            },
+            "--brace-style" => {
+                arguments.options.brace_style = match value(name)?.as_str() {
+                    "same-line" => BraceStyle::SameLine,
+                    "next-line" => BraceStyle::NextLine,
+                    other => return Err(format!("unknown brace style '{}'", other))
+                };
+            },
+            "--check" => arguments.mode = Mode::Check,
+            "-w" | "--write" => arguments.mode = Mode::Write,
+            _ if name.starts_with('-') && name != STANDARD_STREAM_PATH => {
+                return Err(format!("unknown option '{}'", name));
+            },
+            _ => arguments.files.push(arg.clone())
+        }
+    }
+    if arguments.files.is_empty() {
+        arguments.files.push(String::from(STANDARD_STREAM_PATH));
+    }
+    return Ok(arguments);
+}
+
+fn read_source(file: &str) -> Result<String, String> {
+    if file == STANDARD_STREAM_PATH {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map_err(|error| format!("could not read stdin: {}", error))?;
+        return Ok(source);
+    }
+    return fs::read_to_string(file).map_err(|error| format!("could not read '{}': {}", file, error));
+}
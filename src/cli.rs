@@ -0,0 +1,467 @@
+use mixal_compiler::mixal::memory_layout::{MemoryLayout, Region};
+
+pub const USAGE: &str = "\
+Usage: mixal-compiler <command> [options] <file>
+
+Commands:
+  build    Compile a YAL program
+  run      Compile a YAL program and run it with mixvm
+  check    Only report the diagnostics of a YAL program
+  debug    Run a YAL program in the MIX emulator, one statement at a time
+  fuzz     Compile random programs and report the ones that the compiler fails on
+  repl     Run declarations and statements as they are typed, without a <file>
+
+Options:
+  -o, --output <path>          Write the output to <path>, '-' writes it to stdout
+      --emit <stage>           The stage to output: tokens, ast, ir, cfg, mixal or mix (default: mix).
+                               'tokens=json', 'ast=json' and 'ast=dot' output the tokens or the tree
+                               with their spans as JSON or as a Graphviz graph, and 'cfg' outputs the
+                               control flow graph of the generated code as a Graphviz graph
+      --message-format <fmt>   How to print the diagnostics: human or json (default: human)
+      --interpret              Run the program with the interpreter instead of mixvm ('run' only)
+      --profile                Run the program in the MIX emulator instead of mixvm, and report the time
+                               of every line and loop ('run' only)
+      --profile-output <path>  Also write the program with the time of every line to <path>
+      --memory-map             Print the regions of the MIX memory that the program uses
+      --memory-base <r>=<n>    Start the region <r> at the address <n>, where <r> is one of temporaries,
+                               globals, stack, constants, io-buffers or code (default: 0, 1, 1000, 1500, 1986, 2000)
+      --annotate               Precede the instructions of every line of the program with that line as a
+                               MIXAL comment, in the 'mixal' file
+      --listing <path>         Write the address, line and column of every instruction to <path>
+      --no-loop-optimizations  Compute the invariant expressions of the loops in every iteration, and
+                               multiply their variables instead of adding to the products
+  -h, --help                   Print this message
+
+Options of 'fuzz', which does not take a <file>:
+      --seed <n>               The seed of the first random program (default: 0)
+      --count <n>              The number of random programs (default: 100)
+
+Use '-' as <file> to read the program from stdin.
+The outputs of the 'tokens', 'ast' and 'ir' stages are written to stdout by default,
+the 'mixal' and 'mix' files are written to '<file directory>/bin/<file name>.<stage>'.";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Subcommand {
+    Build,
+    Run,
+    Check,
+    Debug
+}
+
+// The stages of the compilation that can be output with '--emit'
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EmitStage {
+    Tokens(DumpFormat),
+    Ast(DumpFormat),
+    // The MIXAL instructions produced by the assembler,
+    // before they are written to a MIXAL source file
+    Ir,
+    // The control flow graph of the generated code, in the DOT language
+    Cfg,
+    Mixal,
+    Mix
+}
+
+// How the tokens and the tree of a program are written
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DumpFormat {
+    // One token or node per line, in the format of `Debug`
+    Text,
+    // Along with the id and the span of every token or node
+    Json,
+    // A Graphviz graph, only for the tree
+    Dot
+}
+
+impl EmitStage {
+    // Parses a stage, optionally followed by '=' and a format, eg 'ast=dot'
+    fn from_name(name: &str) -> Option<EmitStage> {
+        let (stage, format) = match name.split_once('=') {
+            Some((stage, format)) => (stage, Some(format)),
+            None => (name, None)
+        };
+        let format = match format {
+            None | Some("text") => DumpFormat::Text,
+            Some("json") => DumpFormat::Json,
+            Some("dot") => DumpFormat::Dot,
+            Some(_) => return None
+        };
+        match (stage, format) {
+            ("tokens", DumpFormat::Text | DumpFormat::Json) => Some(EmitStage::Tokens(format)),
+            ("ast", _) => Some(EmitStage::Ast(format)),
+            ("ir", DumpFormat::Text) => Some(EmitStage::Ir),
+            ("cfg", DumpFormat::Text | DumpFormat::Dot) => Some(EmitStage::Cfg),
+            ("mixal", DumpFormat::Text) => Some(EmitStage::Mixal),
+            ("mix", DumpFormat::Text) => Some(EmitStage::Mix),
+            _ => None
+        }
+    }
+
+    // Whether the output of the stage is printed to stdout
+    // when no output path is provided
+    pub fn writes_to_stdout_by_default(&self) -> bool {
+        return matches!(self, EmitStage::Tokens(_) | EmitStage::Ast(_) | EmitStage::Ir | EmitStage::Cfg);
+    }
+}
+
+// How the diagnostics are printed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MessageFormat {
+    // Human-readable messages, along with the progress of the compilation
+    Human,
+    // One JSON object per diagnostic and line, without any other output
+    Json
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliOptions {
+    pub subcommand: Subcommand,
+    // The path of the YAL source code file, '-' for stdin
+    pub input: String,
+    // The path of the output file, '-' for stdout
+    pub output: Option<String>,
+    pub emit: EmitStage,
+    pub message_format: MessageFormat,
+    // Whether 'run' executes the program with the
+    // interpreter, without producing any files
+    pub interpret: bool,
+    // Whether 'run' executes the program in the MIX emulator and
+    // reports where it spends its time, without producing any files
+    pub profile: bool,
+    // The path of the program annotated with the time of every line
+    pub profile_output: Option<String>,
+    // Whether the regions of the memory layout are printed, once the program is compiled
+    pub memory_map: bool,
+    pub memory_layout: MemoryLayout,
+    // Whether the lines of the program are written as comments in the MIXAL file
+    pub annotate: bool,
+    // The path of the file that maps the instructions to the lines of the program
+    pub listing: Option<String>,
+    // Whether the loops are optimized before the code is generated
+    pub optimize_loops: bool
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FuzzOptions {
+    // The programs of the seeds `seed..seed + count` are generated
+    pub seed: u64,
+    pub count: u64
+}
+
+pub enum CliRequest {
+    Help,
+    Compile(CliOptions),
+    Fuzz(FuzzOptions),
+    Repl
+}
+
+// Parses the command line arguments, without the name of the executable.
+// On failure, the returned error describes the invalid argument.
+pub fn parse_arguments(args: &[String]) -> Result<CliRequest, String> {
+    if args.iter().any(|x| x == "-h" || x == "--help") {
+        return Ok(CliRequest::Help);
+    }
+    let subcommand = match args.first().map(|x| x.as_str()) {
+        Some("fuzz") => return parse_fuzz_arguments(&args[1..]),
+        Some("repl") => match args.get(1) {
+            Some(arg) => return Err(format!("unexpected argument '{}'", arg)),
+            None => return Ok(CliRequest::Repl)
+        },
+        Some("build") => Subcommand::Build,
+        Some("run") => Subcommand::Run,
+        Some("check") => Subcommand::Check,
+        Some("debug") => Subcommand::Debug,
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+        None => return Err(String::from("no command provided"))
+    };
+
+    let mut input = None;
+    let mut output = None;
+    let mut emit = None;
+    let mut message_format = MessageFormat::Human;
+    let mut interpret = false;
+    let mut profile = false;
+    let mut profile_output = None;
+    let mut memory_map = false;
+    let mut memory_layout = MemoryLayout::default();
+    let mut annotate = false;
+    let mut listing = None;
+    let mut optimize_loops = true;
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        // Options may be given either as '--option value' or as '--option=value'
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None)
+        };
+        let mut value = |name: &str| {
+            return inline_value.clone()
+                .or_else(|| args.next().cloned())
+                .ok_or(format!("missing value for '{}'", name));
+        };
+        match name {
+            "-o" | "--output" => output = Some(value(name)?),
+            "--interpret" => interpret = true,
+            "--profile" => profile = true,
+            "--profile-output" => profile_output = Some(value(name)?),
+            "--memory-map" => memory_map = true,
+            "--annotate" => annotate = true,
+            "--listing" => listing = Some(value(name)?),
+            "--no-loop-optimizations" => optimize_loops = false,
+            "--memory-base" => {
+                let base = value(name)?;
+                let invalid_base = || format!("invalid memory base '{}', expected <region>=<address>", base);
+                let (region, address) = base.split_once('=').ok_or_else(invalid_base)?;
+                let region = Region::from_name(region).ok_or(format!("unknown memory region '{}'", region))?;
+                memory_layout.set_base(region, address.parse().map_err(|_| invalid_base())?);
+            },
+            "--emit" => {
+                let stage = value(name)?;
+                emit = Some(EmitStage::from_name(&stage).ok_or(format!("unknown stage '{}'", stage))?);
+            },
+            "--message-format" => {
+                message_format = match value(name)?.as_str() {
+                    "human" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => return Err(format!("unknown message format '{}'", other))
+                };
+            },
+            _ if name.starts_with('-') && name != "-" => return Err(format!("unknown option '{}'", name)),
+            _ => {
+                if input.is_some() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+                input = Some(arg.clone());
+            }
+        }
+    }
+
+    let input = input.ok_or(String::from("no input file provided"))?;
+    if subcommand == Subcommand::Check && (output.is_some() || emit.is_some()) {
+        return Err(String::from("the 'check' command does not produce any output"));
+    }
+    if subcommand == Subcommand::Debug && (output.is_some() || emit.is_some() || input == "-") {
+        return Err(String::from("the 'debug' command reads its commands from stdin and does not produce any output"));
+    }
+    if subcommand == Subcommand::Run && emit.is_some() {
+        return Err(String::from("the 'run' command always emits a MIX executable file"));
+    }
+    if interpret && (subcommand != Subcommand::Run || output.is_some()) {
+        return Err(String::from("'--interpret' can only be used with the 'run' command, without an output"));
+    }
+    if subcommand == Subcommand::Run && output.as_deref() == Some("-") {
+        return Err(String::from("the 'run' command cannot write the MIX executable file to stdout"));
+    }
+    if profile && (subcommand != Subcommand::Run || interpret || output.is_some()) {
+        return Err(String::from("'--profile' can only be used with the 'run' command, without '--interpret' or an output"));
+    }
+    if profile_output.is_some() && !profile {
+        return Err(String::from("'--profile-output' requires '--profile'"));
+    }
+    let generates_code = !matches!(emit, Some(EmitStage::Tokens(_) | EmitStage::Ast(_)));
+    let writes_program = !matches!(subcommand, Subcommand::Check | Subcommand::Debug) && !interpret && !profile;
+    if memory_map && (!writes_program || !generates_code) {
+        return Err(String::from("'--memory-map' requires the generation of the MIX program"));
+    }
+    if listing.is_some() && (!writes_program || !generates_code) {
+        return Err(String::from("'--listing' requires the generation of the MIX program"));
+    }
+    let writes_mixal = !matches!(emit, Some(EmitStage::Tokens(_) | EmitStage::Ast(_) | EmitStage::Ir | EmitStage::Cfg));
+    if annotate && (!writes_program || !writes_mixal) {
+        return Err(String::from("'--annotate' requires the generation of the MIXAL file"));
+    }
+    memory_layout.validate()?;
+    return Ok(CliRequest::Compile(CliOptions {
+        subcommand,
+        input,
+        output,
+        emit: emit.unwrap_or(EmitStage::Mix),
+        message_format,
+        interpret,
+        profile,
+        profile_output,
+        memory_map,
+        memory_layout,
+        annotate,
+        listing,
+        optimize_loops
+    }));
+}
+
+fn parse_fuzz_arguments(args: &[String]) -> Result<CliRequest, String> {
+    let mut options = FuzzOptions { seed: 0, count: 100 };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None)
+        };
+        let option = match name {
+            "--seed" => &mut options.seed,
+            "--count" => &mut options.count,
+            _ if name.starts_with('-') => return Err(format!("unknown option '{}'", name)),
+            _ => return Err(format!("unexpected argument '{}'", arg))
+        };
+        let value = inline_value.or_else(|| args.next().cloned()).ok_or(format!("missing value for '{}'", name))?;
+        *option = value.parse().map_err(|_| format!("invalid value '{}' for '{}'", value, name))?;
+    }
+    return Ok(CliRequest::Fuzz(options));
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<CliOptions, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        match parse_arguments(&args)? {
+            CliRequest::Help | CliRequest::Fuzz(_) | CliRequest::Repl => panic!("expected options"),
+            CliRequest::Compile(options) => return Ok(options)
+        }
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let options = parse("build program.yal").unwrap();
+        assert_eq!(options, CliOptions {
+            subcommand: Subcommand::Build,
+            input: String::from("program.yal"),
+            output: None,
+            emit: EmitStage::Mix,
+            message_format: MessageFormat::Human,
+            interpret: false,
+            profile: false,
+            profile_output: None,
+            memory_map: false,
+            memory_layout: MemoryLayout::default(),
+            annotate: false,
+            listing: None,
+            optimize_loops: true
+        });
+        assert_eq!(parse("run program.yal").unwrap().subcommand, Subcommand::Run);
+        assert_eq!(parse("check -").unwrap().input, "-");
+        assert_eq!(parse("compile program.yal"), Err(String::from("unknown command 'compile'")));
+        assert_eq!(parse(""), Err(String::from("no command provided")));
+        assert_eq!(parse("build"), Err(String::from("no input file provided")));
+    }
+
+    #[test]
+    fn test_options() {
+        let options = parse("build -o out.mixal --emit mixal program.yal --message-format=json").unwrap();
+        assert_eq!(options.output, Some(String::from("out.mixal")));
+        assert_eq!(options.emit, EmitStage::Mixal);
+        assert_eq!(options.message_format, MessageFormat::Json);
+        assert_eq!(parse("build --output=- --emit=ast program.yal").unwrap().output, Some(String::from("-")));
+        assert_eq!(parse("build --emit bytecode program.yal"), Err(String::from("unknown stage 'bytecode'")));
+        assert_eq!(parse("build --emit=ast=dot program.yal").unwrap().emit, EmitStage::Ast(DumpFormat::Dot));
+        assert_eq!(parse("build --emit ast=json program.yal").unwrap().emit, EmitStage::Ast(DumpFormat::Json));
+        assert_eq!(parse("build --emit tokens=json program.yal").unwrap().emit, EmitStage::Tokens(DumpFormat::Json));
+        assert_eq!(parse("build --emit tokens program.yal").unwrap().emit, EmitStage::Tokens(DumpFormat::Text));
+        assert_eq!(parse("build --emit tokens=dot program.yal"), Err(String::from("unknown stage 'tokens=dot'")));
+        assert_eq!(parse("build --emit mixal=json program.yal"), Err(String::from("unknown stage 'mixal=json'")));
+        assert_eq!(parse("build --emit cfg program.yal").unwrap().emit, EmitStage::Cfg);
+        assert!(parse("build --emit cfg --annotate program.yal").is_err());
+        assert_eq!(parse("build program.yal -o"), Err(String::from("missing value for '-o'")));
+        assert_eq!(parse("build program.yal --fast"), Err(String::from("unknown option '--fast'")));
+        assert_eq!(parse("build a.yal b.yal"), Err(String::from("unexpected argument 'b.yal'")));
+        assert!(parse("check --emit ast program.yal").is_err());
+        assert!(parse("run --emit ast program.yal").is_err());
+        assert!(parse("run -o - program.yal").is_err());
+        assert!(parse("run --interpret program.yal").unwrap().interpret);
+        assert!(parse("build --interpret program.yal").is_err());
+    }
+
+    #[test]
+    fn test_memory_options() {
+        let options = parse("build --memory-map --memory-base stack=1200 --memory-base=code=2500 program.yal").unwrap();
+        assert!(options.memory_map);
+        assert_eq!(options.memory_layout.base(Region::Stack), 1200);
+        assert_eq!(options.memory_layout.base(Region::Code), 2500);
+        assert!(parse("run --memory-map program.yal").unwrap().memory_map);
+        assert_eq!(
+            parse("build --memory-base heap=10 program.yal"),
+            Err(String::from("unknown memory region 'heap'"))
+        );
+        assert_eq!(
+            parse("build --memory-base stack program.yal"),
+            Err(String::from("invalid memory base 'stack', expected <region>=<address>"))
+        );
+        assert_eq!(
+            parse("build --memory-base globals=1600 program.yal"),
+            Err(String::from("the stack region must start after the globals region"))
+        );
+        assert!(parse("check --memory-map program.yal").is_err());
+        assert!(parse("run --interpret --memory-map program.yal").is_err());
+        assert!(parse("build --emit ast --memory-map program.yal").is_err());
+    }
+
+    #[test]
+    fn test_listing_options() {
+        let options = parse("build --emit mixal --annotate --listing=out.lst program.yal").unwrap();
+        assert!(options.annotate);
+        assert_eq!(options.listing, Some(String::from("out.lst")));
+        assert!(parse("run --annotate --listing out.lst program.yal").unwrap().annotate);
+        assert_eq!(parse("build program.yal --listing"), Err(String::from("missing value for '--listing'")));
+        assert!(parse("build --emit ir --listing out.lst program.yal").is_ok());
+        assert!(parse("build --emit ir --annotate program.yal").is_err());
+        assert!(parse("check --listing out.lst program.yal").is_err());
+        assert!(parse("run --interpret --annotate program.yal").is_err());
+    }
+
+    #[test]
+    fn test_profile_options() {
+        let options = parse("run --profile --profile-output=out.txt program.yal").unwrap();
+        assert!(options.profile);
+        assert_eq!(options.profile_output, Some(String::from("out.txt")));
+        assert!(parse("build --profile program.yal").is_err());
+        assert!(parse("run --profile --interpret program.yal").is_err());
+        assert!(parse("run --profile -o out.mix program.yal").is_err());
+        assert!(parse("run --profile --listing out.lst program.yal").is_err());
+        assert_eq!(
+            parse("run --profile-output out.txt program.yal"),
+            Err(String::from("'--profile-output' requires '--profile'"))
+        );
+        assert!(!parse("run --profile --no-loop-optimizations program.yal").unwrap().optimize_loops);
+    }
+
+    #[test]
+    fn test_debug_options() {
+        let options = parse("debug --memory-base code=2500 program.yal").unwrap();
+        assert_eq!(options.subcommand, Subcommand::Debug);
+        assert_eq!(options.memory_layout.base(Region::Code), 2500);
+        assert!(parse("debug -").is_err());
+        assert!(parse("debug -o out.mix program.yal").is_err());
+        assert!(parse("debug --emit mixal program.yal").is_err());
+        assert!(parse("debug --memory-map program.yal").is_err());
+        assert!(parse("debug --listing out.lst program.yal").is_err());
+    }
+
+    #[test]
+    fn test_fuzz_options() {
+        let parse_fuzz = |args: &str| {
+            let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+            match parse_arguments(&args) {
+                Ok(CliRequest::Fuzz(options)) => return Ok(options),
+                Ok(_) => panic!("expected fuzz options"),
+                Err(message) => return Err(message)
+            }
+        };
+        assert_eq!(parse_fuzz("fuzz"), Ok(FuzzOptions { seed: 0, count: 100 }));
+        assert_eq!(parse_fuzz("fuzz --seed=7 --count 20"), Ok(FuzzOptions { seed: 7, count: 20 }));
+        assert_eq!(parse_fuzz("fuzz --count x"), Err(String::from("invalid value 'x' for '--count'")));
+        assert_eq!(parse_fuzz("fuzz --seed"), Err(String::from("missing value for '--seed'")));
+        assert_eq!(parse_fuzz("fuzz program.yal"), Err(String::from("unexpected argument 'program.yal'")));
+    }
+
+    #[test]
+    fn test_repl_options() {
+        let args = vec![String::from("repl")];
+        assert!(matches!(parse_arguments(&args), Ok(CliRequest::Repl)));
+        let args = vec![String::from("repl"), String::from("program.yal")];
+        assert_eq!(parse_arguments(&args).err(), Some(String::from("unexpected argument 'program.yal'")));
+    }
+}
@@ -0,0 +1,216 @@
+use orange_trees::Node;
+use crate::lexer::Token;
+
+// The index of the entry and exit nodes in `ControlFlowGraph::nodes`
+pub const ENTRY_NODE: usize = 0;
+pub const EXIT_NODE: usize = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CfgNodeKind {
+    Entry,
+    Exit,
+    // An assignment, an increment or a print statement
+    Statement,
+    // The condition of an if statement or a loop, or
+    // the value that a switch statement branches on
+    Condition,
+    // A break or continue statement
+    Jump
+}
+
+#[derive(Debug, Clone)]
+pub struct CfgNode {
+    pub kind: CfgNodeKind,
+    // The AST node of the statement, or of the condition's expression.
+    // It is `None` only for the entry and exit nodes.
+    pub ast_node: Option<Node<usize, Token>>,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>
+}
+
+// A control flow graph of a program, built from its AST. Every node
+// of the graph is a single statement or condition, and the edges model
+// the order in which they may be executed. The graph is used by the
+// analyses that need to know which statements may run before others.
+#[derive(Debug)]
+pub struct ControlFlowGraph {
+    pub nodes: Vec<CfgNode>,
+
+    // The statements that can never be executed, because they follow
+    // a statement that always jumps away (e.g. a 'break' statement).
+    // Only the first statement of every unreachable sequence is stored.
+    pub unreachable_statements: Vec<Node<usize, Token>>,
+
+    // Each loop stores its label, if any, and the nodes of the 'continue'
+    // and 'break' statements that refer to it, so that they can be connected
+    // to the proper nodes once the loop has been fully built.
+    loop_stack: Vec<(Option<String>, Vec<usize>, Vec<usize>)>,
+    // The label of the loop that is about to be built
+    pending_loop_label: Option<String>,
+    in_unreachable_code: bool
+}
+
+impl ControlFlowGraph {
+    pub fn from_ast(ast: &Node<usize, Token>) -> ControlFlowGraph {
+        let mut graph = ControlFlowGraph {
+            nodes: vec![],
+            unreachable_statements: vec![],
+            loop_stack: vec![],
+            pending_loop_label: None,
+            in_unreachable_code: false
+        };
+        graph.add_node(CfgNodeKind::Entry, None, &[]);
+        graph.add_node(CfgNodeKind::Exit, None, &[]);
+        let dangling_nodes = graph.build_statement(ast, vec![ENTRY_NODE]);
+        graph.add_edges(&dangling_nodes, EXIT_NODE);
+        return graph;
+    }
+
+    fn add_node(
+        &mut self,
+        kind: CfgNodeKind,
+        ast_node: Option<&Node<usize, Token>>,
+        predecessors: &[usize]
+    ) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(CfgNode {
+            kind,
+            ast_node: ast_node.cloned(),
+            successors: vec![],
+            predecessors: vec![]
+        });
+        self.add_edges(predecessors, index);
+        return index;
+    }
+
+    fn add_edges(&mut self, from: &[usize], to: usize) {
+        for &node in from {
+            self.nodes[node].successors.push(to);
+            self.nodes[to].predecessors.push(node);
+        }
+    }
+
+    // Adds the statement `node` to the graph, connecting it to the
+    // `predecessors` nodes. Returns the nodes from which the control
+    // flows to the statement that follows `node` (e.g. for an if
+    // statement, the last node of both of its branches).
+    fn build_statement(&mut self, node: &Node<usize, Token>, predecessors: Vec<usize>) -> Vec<usize> {
+        let children = node.children();
+        match node.value() {
+            Token::Ast(_) => {
+                return self.build_sequence(children, predecessors);
+            },
+            Token::Assignment | Token::AdditionAssignment
+            | Token::SubtractionAssignment | Token::MultiplicationAssignment
+            | Token::DivisionAssignment | Token::ModuloAssignment
+            | Token::Increment | Token::Decrement | Token::Print => {
+                return vec![self.add_node(CfgNodeKind::Statement, Some(node), &predecessors)];
+            },
+            Token::If => {
+                let condition = self.add_node(CfgNodeKind::Condition, Some(&children[0]), &predecessors);
+                let mut dangling_nodes = self.build_statement(&children[1], vec![condition]);
+                if children.len() == 3 {
+                    let else_block = &children[2].children()[0];
+                    dangling_nodes.extend(self.build_statement(else_block, vec![condition]));
+                } else {
+                    dangling_nodes.push(condition);
+                }
+                return dangling_nodes;
+            },
+            Token::While => {
+                let condition = self.add_node(CfgNodeKind::Condition, Some(&children[0]), &predecessors);
+                self.loop_stack.push((self.pending_loop_label.take(), vec![], vec![]));
+                let body_nodes = self.build_statement(&children[1], vec![condition]);
+                let (_, continue_nodes, break_nodes) = self.loop_stack.pop().expect("to exist");
+                self.add_edges(&body_nodes, condition);
+                self.add_edges(&continue_nodes, condition);
+                return [vec![condition], break_nodes].concat();
+            },
+            Token::For => {
+                let loop_label = self.pending_loop_label.take();
+                let initialization_nodes = self.build_statement(&children[0], predecessors);
+                let condition = self.add_node(CfgNodeKind::Condition, Some(&children[1]), &initialization_nodes);
+                self.loop_stack.push((loop_label, vec![], vec![]));
+                let body_nodes = self.build_statement(&children[3], vec![condition]);
+                let (_, continue_nodes, break_nodes) = self.loop_stack.pop().expect("to exist");
+                let step_nodes = self.build_statement(&children[2], [body_nodes, continue_nodes].concat());
+                self.add_edges(&step_nodes, condition);
+                return [vec![condition], break_nodes].concat();
+            },
+            Token::Do => {
+                // The body is built first, so its first node is the target of
+                // the edge that goes back from the condition to the body
+                let body_start = self.nodes.len();
+                self.loop_stack.push((self.pending_loop_label.take(), vec![], vec![]));
+                let body_nodes = self.build_statement(&children[0], predecessors);
+                let (_, continue_nodes, break_nodes) = self.loop_stack.pop().expect("to exist");
+                let condition = self.add_node(
+                    CfgNodeKind::Condition,
+                    Some(&children[1]),
+                    &[body_nodes, continue_nodes].concat()
+                );
+                // With an empty body, the edge goes from the condition to itself
+                self.add_edges(&[condition], body_start);
+                return [vec![condition], break_nodes].concat();
+            },
+            Token::Label(label) => {
+                self.pending_loop_label = Some(label.clone());
+                return self.build_statement(&children[0], predecessors);
+            },
+            Token::Switch => {
+                let condition = self.add_node(CfgNodeKind::Condition, Some(&children[0]), &predecessors);
+                let mut dangling_nodes = vec![];
+                for case_node in &children[1..] {
+                    let block_node = case_node.children().last().expect("to exist");
+                    dangling_nodes.extend(self.build_statement(block_node, vec![condition]));
+                }
+                // Without a 'default' case, no case may run
+                if !children[1..].iter().any(|x| *x.value() == Token::Default) {
+                    dangling_nodes.push(condition);
+                }
+                return dangling_nodes;
+            },
+            Token::Continue | Token::Break => {
+                let jump = self.add_node(CfgNodeKind::Jump, Some(node), &predecessors);
+                let target_loop = match children.first().map(|x| x.value()) {
+                    Some(Token::Label(label)) =>
+                        self.loop_stack.iter_mut().rev().find(|x| x.0.as_ref() == Some(label)),
+                    _ => self.loop_stack.last_mut()
+                };
+                if let Some((_, continue_nodes, break_nodes)) = target_loop {
+                    if *node.value() == Token::Continue {
+                        continue_nodes.push(jump);
+                    } else {
+                        break_nodes.push(jump);
+                    }
+                }
+                return vec![];
+            },
+            _ => {
+                // Declarations do not affect the control flow
+                return predecessors;
+            }
+        }
+    }
+
+    fn build_sequence(&mut self, statements: &[Node<usize, Token>], predecessors: Vec<usize>) -> Vec<usize> {
+        let mut dangling_nodes = predecessors;
+        let mut entered_unreachable_code = false;
+        for statement in statements {
+            if dangling_nodes.is_empty() && !self.in_unreachable_code && !Self::is_declaration(statement) {
+                self.unreachable_statements.push(statement.clone());
+                self.in_unreachable_code = true;
+                entered_unreachable_code = true;
+            }
+            dangling_nodes = self.build_statement(statement, dangling_nodes);
+        }
+        if entered_unreachable_code {
+            self.in_unreachable_code = false;
+        }
+        return dangling_nodes;
+    }
+
+    fn is_declaration(node: &Node<usize, Token>) -> bool {
+        return matches!(node.value(), Token::Int | Token::Bool | Token::Const);
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::{BTreeSet, HashMap};
+use orange_trees::Node;
+use crate::lexer::{Token, Span};
+use crate::control_flow_graph::{ControlFlowGraph, CfgNodeKind, ENTRY_NODE};
+use crate::type_checker::Type;
+use crate::diagnostics::*;
+
+// This analyzer finds code that is valid, but is most likely a bug.
+// It runs a set of dataflow analyses on the control flow graph of
+// the program, and reports the suspicious code as warnings:
+//  * variables that may be read before being initialized,
+//  * variables that are never read,
+//  * assignments whose value is never read,
+//  * statements that can never be executed.
+// Warnings never prevent the program from being compiled.
+pub struct DataflowAnalyzer<'a> {
+    pub ast: &'a Node<usize, Token>,
+    pub spans: &'a [Span],
+    pub symbol_table: &'a HashMap<&'a String, Type>,
+    pub warnings: Vec<Diagnostic>,
+
+    // Every warning is stored along with the id of the node
+    // it refers to, so that they can be sorted by position.
+    located_warnings: Vec<(usize, &'static str, String)>
+}
+
+impl<'a> DataflowAnalyzer<'a> {
+    pub fn new(
+        ast: &'a Node<usize, Token>,
+        spans: &'a [Span],
+        symbol_table: &'a HashMap<&'a String, Type>
+    ) -> DataflowAnalyzer<'a> {
+        DataflowAnalyzer {
+            ast,
+            spans,
+            symbol_table,
+            warnings: vec![],
+            located_warnings: vec![]
+        }
+    }
+
+    pub fn run(&mut self) {
+        let graph = ControlFlowGraph::from_ast(self.ast);
+        let unread_variables = self.check_for_unread_variables();
+        self.check_for_uninitialized_reads(&graph);
+        self.check_for_unread_assignments(&graph, &unread_variables);
+        self.check_for_unreachable_code(&graph);
+
+        self.located_warnings.sort_by_key(|(node_id, _, _)| *node_id);
+        self.warnings = self.located_warnings.iter().map(|(node_id, code, message)| {
+            Diagnostic::warning(code, self.spans.get(*node_id).copied(), message.clone())
+        }).collect();
+    }
+
+    fn check_for_unread_variables(&mut self) -> BTreeSet<String> {
+        let mut unread_variables = BTreeSet::new();
+        let declaration_nodes = self.ast.find(&|x| Type::from_token(x.value()).is_some());
+        for declaration_node in declaration_nodes {
+            for identifier_node in declaration_node.children() {
+                if let Token::Id(identifier) = identifier_node.value() {
+                    let is_accessed = |kind: AccessKind| !self.ast.find(&|x| {
+                        get_variable_accesses(x).iter().any(|access| access.kind == kind && access.variable == *identifier)
+                    }).is_empty();
+                    if is_accessed(AccessKind::Read) {
+                        continue;
+                    }
+                    let is_assigned = is_accessed(AccessKind::Write) || is_accessed(AccessKind::MayWrite);
+                    let message = if is_assigned {
+                        format!("variable '{}' is assigned, but its value is never read", identifier)
+                    } else {
+                        format!("variable '{}' is never used", identifier)
+                    };
+                    self.located_warnings.push((*identifier_node.id(), UNUSED_VARIABLE, message));
+                    unread_variables.insert(identifier.clone());
+                }
+            }
+        }
+        return unread_variables;
+    }
+
+    // A forward analysis that computes the variables that may not
+    // have been assigned a value when a node of the graph is reached.
+    // When a node reads one of these variables, a warning is reported.
+    fn check_for_uninitialized_reads(&mut self, graph: &ControlFlowGraph) {
+        // Constants are not variables, they have a value from the start
+        let constant_nodes = self.ast.find(&|x| *x.value() == Token::Const);
+        let all_variables: BTreeSet<String> = self.symbol_table.keys()
+            .filter(|x| !constant_nodes.iter().any(|node| *node.children()[0].value() == Token::Id((**x).clone())))
+            .map(|x| (*x).clone())
+            .collect();
+        let mut uninitialized_in = vec![BTreeSet::new(); graph.nodes.len()];
+        let mut uninitialized_out = vec![BTreeSet::new(); graph.nodes.len()];
+        uninitialized_out[ENTRY_NODE] = all_variables;
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, node) in graph.nodes.iter().enumerate() {
+                if index == ENTRY_NODE {
+                    continue;
+                }
+                let mut variables_in = BTreeSet::new();
+                for predecessor in &node.predecessors {
+                    variables_in.extend(uninitialized_out[*predecessor].iter().cloned());
+                }
+                let mut variables_out = variables_in.clone();
+                if let Some(ast_node) = &node.ast_node {
+                    for access in get_variable_accesses_by_cfg_node(ast_node, &node.kind) {
+                        if access.kind == AccessKind::Write {
+                            variables_out.remove(&access.variable);
+                        }
+                    }
+                }
+                if variables_out != uninitialized_out[index] {
+                    changed = true;
+                }
+                uninitialized_in[index] = variables_in;
+                uninitialized_out[index] = variables_out;
+            }
+        }
+
+        let mut reported_variables = BTreeSet::new();
+        let mut uninitialized_reads = vec![];
+        for (index, node) in graph.nodes.iter().enumerate() {
+            if let Some(ast_node) = &node.ast_node {
+                // The accesses of the node are replayed in order, since
+                // it may assign a variable before reading it
+                let mut uninitialized = uninitialized_in[index].clone();
+                for access in get_variable_accesses_by_cfg_node(ast_node, &node.kind) {
+                    match access.kind {
+                        AccessKind::Read if uninitialized.contains(&access.variable) => {
+                            uninitialized_reads.push((access.node_id, access.variable));
+                        },
+                        AccessKind::Write => { uninitialized.remove(&access.variable); },
+                        _ => {}
+                    }
+                }
+            }
+        }
+        // Only the first read of every variable is reported
+        uninitialized_reads.sort();
+        for (node_id, variable) in uninitialized_reads {
+            if reported_variables.insert(variable.clone()) {
+                self.located_warnings.push((
+                    node_id,
+                    UNINITIALIZED_READ,
+                    format!("variable '{}' may be read before it is initialized", variable)
+                ));
+            }
+        }
+    }
+
+    // A backward analysis that computes the variables whose current
+    // value may be read after a node of the graph is executed. When
+    // a node assigns a variable that is not in this set, the assigned
+    // value is never read, thus a warning is reported.
+    fn check_for_unread_assignments(&mut self, graph: &ControlFlowGraph, unread_variables: &BTreeSet<String>) {
+        let mut live_in: Vec<BTreeSet<String>> = vec![BTreeSet::new(); graph.nodes.len()];
+        let mut live_out: Vec<BTreeSet<String>> = vec![BTreeSet::new(); graph.nodes.len()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, node) in graph.nodes.iter().enumerate().rev() {
+                let mut variables_out = BTreeSet::new();
+                for successor in &node.successors {
+                    variables_out.extend(live_in[*successor].iter().cloned());
+                }
+                let mut variables_in = variables_out.clone();
+                if let Some(ast_node) = &node.ast_node {
+                    for access in get_variable_accesses_by_cfg_node(ast_node, &node.kind).into_iter().rev() {
+                        update_live_variables(&mut variables_in, access);
+                    }
+                }
+                if variables_in != live_in[index] {
+                    changed = true;
+                }
+                live_in[index] = variables_in;
+                live_out[index] = variables_out;
+            }
+        }
+
+        for (index, node) in graph.nodes.iter().enumerate() {
+            if node.predecessors.is_empty() {
+                // Unreachable code is reported separately
+                continue;
+            }
+            if let Some(ast_node) = &node.ast_node {
+                // The accesses of the node are replayed backwards, since
+                // a value it assigns may be read later in the node
+                let mut live_variables = live_out[index].clone();
+                for access in get_variable_accesses_by_cfg_node(ast_node, &node.kind).into_iter().rev() {
+                    let is_unread = access.kind != AccessKind::Read
+                        && !live_variables.contains(&access.variable)
+                        && !unread_variables.contains(&access.variable);
+                    if is_unread {
+                        self.located_warnings.push((
+                            access.node_id,
+                            UNREAD_ASSIGNMENT,
+                            format!("value assigned to '{}' is never read", access.variable)
+                        ));
+                    }
+                    update_live_variables(&mut live_variables, access);
+                }
+            }
+        }
+    }
+
+    fn check_for_unreachable_code(&mut self, graph: &ControlFlowGraph) {
+        for statement in &graph.unreachable_statements {
+            self.located_warnings.push((
+                get_statement_start_id(statement),
+                UNREACHABLE_CODE,
+                String::from("unreachable statement")
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AccessKind {
+    Read,
+    Write,
+    // An assignment that may be skipped, in the right operand of '&&' or '||'
+    MayWrite
+}
+
+// A read or an assignment of a variable, along with
+// the id of the node of the variable
+#[derive(Debug, Clone, PartialEq)]
+struct VariableAccess {
+    kind: AccessKind,
+    variable: String,
+    node_id: usize
+}
+
+// Going backwards, an assignment ends the range where the
+// variable is live, unless it may be skipped, and a read starts it
+fn update_live_variables(live_variables: &mut BTreeSet<String>, access: VariableAccess) {
+    match access.kind {
+        AccessKind::Read => { live_variables.insert(access.variable); },
+        AccessKind::Write => { live_variables.remove(&access.variable); },
+        AccessKind::MayWrite => {}
+    }
+}
+
+// Returns the accesses of variables by the statement `node` (without
+// descending into nested statements), in the order they happen
+fn get_variable_accesses(node: &Node<usize, Token>) -> Vec<VariableAccess> {
+    let children = node.children();
+    let mut accesses = vec![];
+    match node.value() {
+        Token::Assignment | Token::AdditionAssignment
+        | Token::SubtractionAssignment | Token::MultiplicationAssignment
+        | Token::DivisionAssignment | Token::ModuloAssignment
+        | Token::Increment | Token::Decrement => add_expression_accesses(node, false, &mut accesses),
+        Token::Print => add_expression_accesses(&children[0], false, &mut accesses),
+        Token::If | Token::While | Token::Switch => add_expression_accesses(&children[0], false, &mut accesses),
+        Token::For | Token::Do => add_expression_accesses(&children[1], false, &mut accesses),
+        _ => {}
+    }
+    return accesses;
+}
+
+fn get_variable_accesses_by_cfg_node(node: &Node<usize, Token>, kind: &CfgNodeKind) -> Vec<VariableAccess> {
+    match kind {
+        CfgNodeKind::Condition => {
+            let mut accesses = vec![];
+            add_expression_accesses(node, false, &mut accesses);
+            return accesses;
+        },
+        _ => get_variable_accesses(node)
+    }
+}
+
+// Adds the accesses of the expression in the order of its evaluation,
+// which is the order of the generated code: the right operand of a
+// binary operator is evaluated first, except for '&&' and '||', whose
+// right operand is evaluated last and only if it is needed, and for
+// the branches of '?', which are evaluated after the condition.
+fn add_expression_accesses(node: &Node<usize, Token>, may_be_skipped: bool, accesses: &mut Vec<VariableAccess>) {
+    let children = node.children();
+    let write_kind = if may_be_skipped { AccessKind::MayWrite } else { AccessKind::Write };
+    match node.value() {
+        Token::Id(_) => accesses.push(new_access(AccessKind::Read, node)),
+        Token::Assignment => {
+            add_expression_accesses(&children[1], may_be_skipped, accesses);
+            accesses.push(new_access(write_kind, &children[0]));
+        },
+        Token::AdditionAssignment | Token::SubtractionAssignment
+        | Token::MultiplicationAssignment | Token::DivisionAssignment
+        | Token::ModuloAssignment => {
+            // The assigned variable is also read, e.g. `a += 1` is `a = a + 1`
+            add_expression_accesses(&children[1], may_be_skipped, accesses);
+            accesses.push(new_access(AccessKind::Read, &children[0]));
+            accesses.push(new_access(write_kind, &children[0]));
+        },
+        // The variable is also read, eg `a++` is `a = a + 1`
+        Token::Increment | Token::Decrement => {
+            accesses.push(new_access(AccessKind::Read, &children[0]));
+            accesses.push(new_access(write_kind, &children[0]));
+        },
+        Token::And | Token::Or => {
+            add_expression_accesses(&children[0], may_be_skipped, accesses);
+            add_expression_accesses(&children[1], true, accesses);
+        },
+        // Only one of the branches is evaluated
+        Token::QuestionMark => {
+            add_expression_accesses(&children[0], may_be_skipped, accesses);
+            add_expression_accesses(&children[1], true, accesses);
+            add_expression_accesses(&children[2], true, accesses);
+        },
+        _ => {
+            for child in children.iter().rev() {
+                add_expression_accesses(child, may_be_skipped, accesses);
+            }
+        }
+    }
+}
+
+fn new_access(kind: AccessKind, identifier_node: &Node<usize, Token>) -> VariableAccess {
+    if let Token::Id(identifier) = identifier_node.value() {
+        return VariableAccess { kind, variable: identifier.clone(), node_id: *identifier_node.id() };
+    }
+    unreachable!();
+}
+
+// Returns the id of the node that models the first token of the statement
+fn get_statement_start_id(node: &Node<usize, Token>) -> usize {
+    match node.value() {
+        Token::Assignment | Token::AdditionAssignment
+        | Token::SubtractionAssignment | Token::MultiplicationAssignment
+        | Token::DivisionAssignment | Token::ModuloAssignment => *node.children()[0].id(),
+        _ => *node.id()
+    }
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+    use crate::utilities::get_tokens_and_spans_from_program;
+    use crate::diagnostics::has_errors;
+
+    // Returns the warnings in the form "<line>:<column>: <message>"
+    fn get_warnings(program: &str) -> Vec<String> {
+        let (tokens, spans) = get_tokens_and_spans_from_program(program).expect("to be valid");
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let mut semantic_analyzer = SemanticAnalyzer::new(&parser.ast, &spans);
+        let diagnostics = semantic_analyzer.run();
+        assert!(!has_errors(&diagnostics));
+        return diagnostics.iter().map(|x| {
+            let span = x.span.expect("to exist");
+            format!("{}:{}: {}", span.line, span.column, x.message)
+        }).collect();
+    }
+
+    #[test]
+    fn test_no_warnings() {
+        let warnings = get_warnings(
+            "{
+                var i, sum : int;
+                sum = 0;
+                for (i = 0; i < 10; i += 1) {
+                    if (i == 5) continue;
+                    sum += i;
+                }
+                print sum;
+            }"
+        );
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_uninitialized_read() {
+        let warnings = get_warnings(
+            "{
+                var a, b : int;
+                if (b > 0) a = 1;
+                print a;
+            }"
+        );
+        assert_eq!(warnings, vec![
+            "3:21: variable 'b' may be read before it is initialized",
+            "4:23: variable 'a' may be read before it is initialized"
+        ]);
+    }
+
+    #[test]
+    fn test_initialized_in_both_branches() {
+        let warnings = get_warnings(
+            "{
+                var a : int;
+                if (true) a = 1; else a = 2;
+                print a;
+            }"
+        );
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let warnings = get_warnings(
+            "{
+                var a, b : int;
+                b = 1;
+            }"
+        );
+        assert_eq!(warnings, vec![
+            "2:21: variable 'a' is never used",
+            "2:24: variable 'b' is assigned, but its value is never read"
+        ]);
+    }
+
+    #[test]
+    fn test_unread_assignment() {
+        let warnings = get_warnings(
+            "{
+                var a : int;
+                a = 1;
+                a = 2;
+                print a;
+            }"
+        );
+        assert_eq!(warnings, vec!["3:17: value assigned to 'a' is never read"]);
+    }
+
+    #[test]
+    fn test_assignment_read_in_next_iteration() {
+        let warnings = get_warnings(
+            "{
+                var a, i : int;
+                a = 0;
+                i = 0;
+                while (i < 3) {
+                    print a;
+                    a = i;
+                    i += 1;
+                }
+            }"
+        );
+        assert_eq!(warnings, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_switch() {
+        let warnings = get_warnings(
+            "{
+                var a, b : int;
+                a = 1;
+                switch (a) {
+                    case 1: b = 1;
+                    case 2: b = 2;
+                }
+                print b;
+                switch (a) {
+                    case 1: b = 1;
+                    default: b = 2;
+                }
+                print b;
+            }"
+        );
+        assert_eq!(warnings, vec!["8:23: variable 'b' may be read before it is initialized"]);
+    }
+
+    #[test]
+    fn test_increments_and_assignment_expressions() {
+        let warnings = get_warnings(
+            "{
+                var a, b, c, d : int;
+                print (a = 1) + a;
+                b = 0;
+                while ((b += 1) < 3) c++;
+                print c;
+                d = (a = 2) + 1;
+                print d--;
+                print a - (a = 3);
+                c = 1;
+                if (b > 0 || (c = 2) > 0) print c;
+            }"
+        );
+        // The right operand is evaluated first, so `a` is read before it is assigned
+        assert_eq!(warnings, vec![
+            "3:24: value assigned to 'a' is never read",
+            "3:33: variable 'a' may be read before it is initialized",
+            "5:38: variable 'c' may be read before it is initialized",
+            "7:22: value assigned to 'a' is never read",
+            "8:23: value assigned to 'd' is never read"
+        ]);
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        let warnings = get_warnings(
+            "{
+                var a, b, c : int;
+                a = 1;
+                print a > 0 ? (b = 1) : 2;
+                print b;
+                c = 1;
+                print a > 0 ? c : (c = 2);
+            }"
+        );
+        assert_eq!(warnings, vec![
+            "5:23: variable 'b' may be read before it is initialized",
+            "7:36: value assigned to 'c' is never read"
+        ]);
+    }
+
+    #[test]
+    fn test_do_while_and_labels() {
+        // The 'break' leaves both loops, skipping the assignment to 'b'
+        let warnings = get_warnings(
+            "{
+                var i, b : int;
+                i = 0;
+                outer: do {
+                    do {
+                        i += 1;
+                        if (i > 2) break outer;
+                    } while (false);
+                    b = i;
+                    continue outer;
+                    print i;
+                } while (i < 2);
+                print b;
+            }"
+        );
+        assert_eq!(warnings, vec![
+            "11:21: unreachable statement",
+            "13:23: variable 'b' may be read before it is initialized"
+        ]);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let warnings = get_warnings(
+            "{
+                var i : int;
+                i = 0;
+                while (i < 3) {
+                    i += 1;
+                    break;
+                    print i;
+                    i += 2;
+                }
+                print i;
+            }"
+        );
+        assert_eq!(warnings, vec!["7:21: unreachable statement"]);
+    }
+}
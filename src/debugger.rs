@@ -0,0 +1,546 @@
+use orange_trees::Node;
+use std::collections::{BTreeSet, HashMap};
+use crate::lexer::{Token, Span};
+use crate::parser::Parser;
+use crate::json::JsonValue;
+use crate::type_checker::{Type, TypeChecker};
+use crate::interpreter::{evaluate_constant_expression, get_constant_values};
+use crate::utilities::{get_tokens_and_spans_from_program, is_assignment_operator};
+use crate::mixal::{emulator::MixMachine, loader::MixProgram, word::MixWord};
+use crate::mixal::{instruction::MixalInstruction, memory_layout::MemoryLayout, utilities::instruction_addresses};
+
+// The number of instructions after which a command that resumes the
+// program gives up, since the program probably never stops
+const MAX_INSTRUCTIONS_PER_COMMAND: u64 = 10_000_000;
+
+pub const DEBUGGER_HELP: &str = "\
+break <line>         Stop before the statements of the line (or 'b')
+delete <line>        Remove the breakpoint of the line
+continue             Run until a breakpoint, a change of a watched expression or the end (or 'c', 'run')
+step                 Run until the next statement (or 's')
+next                 Run until the next statement that is not nested in the current one (or 'n')
+print <expression>   Print the value of an expression of the variables and constants (or 'p')
+watch <expression>   Stop when the value of the expression changes
+info                 List the breakpoints and the watched expressions
+quit                 Exit the debugger (or 'q')";
+
+// A statement of the program, along with the addresses
+// of the instructions that were generated for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugStatement {
+    pub line: usize,
+    pub column: usize,
+    // The number of statements that enclose the statement
+    pub depth: usize,
+    pub addresses: Vec<u16>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugVariable {
+    pub name: String,
+    pub variable_type: Type,
+    pub address: u16
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugConstant {
+    pub name: String,
+    pub constant_type: Type,
+    pub value: i64
+}
+
+// What a debugger needs to know about a compiled program to present it in
+// terms of its YAL source code, rather than of the MIX memory. It is written
+// next to the MIX executable file, so that other tools can use it too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugInfo {
+    // The statements, in the order of their first instruction
+    pub statements: Vec<DebugStatement>,
+    // The variables, in the order of their addresses
+    pub variables: Vec<DebugVariable>,
+    pub constants: Vec<DebugConstant>
+}
+
+impl DebugInfo {
+    // The `ast` and the `spans` are the ones the `instructions` were generated
+    // from, and the variables are placed where the `memory_layout` says.
+    pub fn new(
+        instructions: &[MixalInstruction],
+        ast: &Node<usize, Token>,
+        spans: &[Span],
+        memory_layout: &MemoryLayout
+    ) -> DebugInfo {
+        let mut depths = HashMap::new();
+        find_statement_depths(ast, 0, &mut depths);
+        let mut statements: Vec<DebugStatement> = vec![];
+        // key: the id of a statement node
+        // value: the index of the statement in `statements`
+        let mut statement_indices = HashMap::new();
+        for (instruction, address) in instructions.iter().zip(instruction_addresses(instructions)) {
+            let (Some(id), Some(address)) = (instruction.source, address) else {
+                continue;
+            };
+            let Some(span) = spans.get(id) else {
+                continue;
+            };
+            let index = *statement_indices.entry(id).or_insert_with(|| {
+                statements.push(DebugStatement {
+                    line: span.line,
+                    column: span.column,
+                    depth: depths.get(&id).copied().unwrap_or(0),
+                    addresses: vec![]
+                });
+                return statements.len() - 1;
+            });
+            statements[index].addresses.push(address);
+        }
+
+        let mut variable_types = HashMap::new();
+        for declaration_node in ast.find(&|x| Type::from_token(x.value()).is_some()) {
+            for child in declaration_node.children() {
+                if let Token::Id(identifier) = child.value() {
+                    variable_types.insert(identifier, Type::from_token(declaration_node.value()).expect("to exist"));
+                }
+            }
+        }
+        let variables = memory_layout.globals.iter()
+            .map(|(name, address)| DebugVariable {
+                name: name.clone(),
+                variable_type: variable_types.get(name).copied().unwrap_or(Type::Int),
+                address: *address
+            })
+            .collect();
+
+        // The type of a constant depends on the types of the previous ones
+        let constant_values = get_constant_values(ast);
+        let mut constant_types = HashMap::new();
+        let mut constants = vec![];
+        for constant_node in ast.find(&|x| *x.value() == Token::Const) {
+            let children = constant_node.children();
+            let constant_type = TypeChecker::new(&constant_types, spans)
+                .infer_expression_type(&children[1])
+                .unwrap_or(Type::Int);
+            let Token::Id(identifier) = children[0].value() else {
+                continue;
+            };
+            constant_types.insert(identifier, constant_type);
+            if let Some(value) = constant_values.get(identifier) {
+                constants.push(DebugConstant { name: identifier.clone(), constant_type, value: value.to_i64() });
+            }
+        }
+        return DebugInfo { statements, variables, constants };
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let statements = self.statements.iter().map(|x| JsonValue::object(vec![
+            ("line", JsonValue::Number(x.line as i64)),
+            ("column", JsonValue::Number(x.column as i64)),
+            ("depth", JsonValue::Number(x.depth as i64)),
+            ("addresses", JsonValue::Array(x.addresses.iter().map(|x| JsonValue::Number(*x as i64)).collect()))
+        ]));
+        let variables = self.variables.iter().map(|x| JsonValue::object(vec![
+            ("name", JsonValue::string(&x.name)),
+            ("type", JsonValue::string(&x.variable_type.to_string())),
+            ("address", JsonValue::Number(x.address as i64))
+        ]));
+        let constants = self.constants.iter().map(|x| JsonValue::object(vec![
+            ("name", JsonValue::string(&x.name)),
+            ("type", JsonValue::string(&x.constant_type.to_string())),
+            ("value", JsonValue::Number(x.value))
+        ]));
+        return JsonValue::object(vec![
+            ("statements", JsonValue::Array(statements.collect())),
+            ("variables", JsonValue::Array(variables.collect())),
+            ("constants", JsonValue::Array(constants.collect()))
+        ]);
+    }
+}
+
+// Stores the number of enclosing statements of every node. The assembler
+// attributes the instructions to the innermost statement, so every node
+// that is not a block can be the statement of an instruction.
+fn find_statement_depths(node: &Node<usize, Token>, depth: usize, depths: &mut HashMap<usize, usize>) {
+    let children_depth = match node.value() {
+        Token::Ast(_) => depth,
+        _ => {
+            depths.insert(*node.id(), depth);
+            depth + 1
+        }
+    };
+    for child in node.children() {
+        find_statement_depths(child, children_depth, depths);
+    }
+}
+
+// How far the program runs when it is resumed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resume {
+    // Until a breakpoint or a change of a watched expression
+    Continue,
+    // Until the next statement
+    StepInto,
+    // Until the next statement that is enclosed by at most this many statements
+    StepOver(usize)
+}
+
+// An expression whose value is checked after every instruction
+struct Watch {
+    text: String,
+    expression: Node<usize, Token>,
+    expression_type: Type,
+    // `None` when the expression cannot be evaluated, ie it divides by zero
+    value: Option<i64>
+}
+
+// Runs a compiled program in the MIX emulator, one command at a time,
+// and presents its state in terms of the lines and the variables of
+// its YAL source code.
+pub struct Debugger {
+    debug_info: DebugInfo,
+    source_lines: Vec<String>,
+    machine: MixMachine,
+    // key: the address of an instruction
+    // value: the index of its statement in `debug_info.statements`
+    address_statements: HashMap<u16, usize>,
+    // The lines that the program stops at
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<Watch>,
+    // The statement of the last executed instruction, or of the
+    // instruction the program stopped at
+    current_statement: Option<usize>,
+    // The number of lines of the output of the program that have been shown
+    shown_output_lines: usize
+}
+
+impl Debugger {
+    pub fn new(program: &MixProgram, debug_info: DebugInfo, source: &str) -> Debugger {
+        let mut address_statements = HashMap::new();
+        for (index, statement) in debug_info.statements.iter().enumerate() {
+            for address in &statement.addresses {
+                address_statements.insert(*address, index);
+            }
+        }
+        Debugger {
+            debug_info,
+            source_lines: source.lines().map(|x| x.trim().to_string()).collect(),
+            machine: MixMachine::new(program),
+            address_statements,
+            breakpoints: BTreeSet::new(),
+            watches: vec![],
+            current_statement: None,
+            shown_output_lines: 0
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.machine.halted;
+    }
+
+    // Executes a command of the user and returns what it prints, along
+    // with the output of the program. Fails if the command is invalid.
+    pub fn execute(&mut self, command: &str) -> Result<String, String> {
+        let (name, argument) = match command.trim().split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command.trim(), "")
+        };
+        let require_argument = |description: &str| {
+            if argument.is_empty() {
+                return Err(format!("'{}' expects {}", name, description));
+            }
+            return Ok(argument);
+        };
+        match name {
+            "b" | "break" => {
+                let line = self.parse_line(require_argument("a line")?)?;
+                self.breakpoints.insert(line);
+                return Ok(format!("breakpoint at line {}: {}\n", line, self.line_text(line)));
+            },
+            "delete" => {
+                let line = self.parse_line(require_argument("a line")?)?;
+                if !self.breakpoints.remove(&line) {
+                    return Err(format!("there is no breakpoint at line {}", line));
+                }
+                return Ok(String::new());
+            },
+            "c" | "continue" | "run" => return Ok(self.resume(Resume::Continue)),
+            "s" | "step" => return Ok(self.resume(Resume::StepInto)),
+            "n" | "next" => {
+                let depth = self.current_statement.map(|x| self.debug_info.statements[x].depth).unwrap_or(usize::MAX);
+                return Ok(self.resume(Resume::StepOver(depth)));
+            },
+            "p" | "print" => {
+                let (expression, expression_type) = self.parse_expression(require_argument("an expression")?)?;
+                let value = self.evaluate(&expression);
+                return Ok(format!("{} = {}\n", argument, format_value(value, expression_type)));
+            },
+            "watch" => {
+                let (expression, expression_type) = self.parse_expression(require_argument("an expression")?)?;
+                let value = self.evaluate(&expression);
+                let output = format!("watching {} = {}\n", argument, format_value(value, expression_type));
+                self.watches.push(Watch { text: argument.to_string(), expression, expression_type, value });
+                return Ok(output);
+            },
+            "info" => {
+                let mut output = String::new();
+                for line in &self.breakpoints {
+                    output.push_str(&format!("breakpoint at line {}: {}\n", line, self.line_text(*line)));
+                }
+                for watch in &self.watches {
+                    output.push_str(&format!("watching {} = {}\n", watch.text, format_value(watch.value, watch.expression_type)));
+                }
+                return Ok(output);
+            },
+            "h" | "help" => return Ok(format!("{}\n", DEBUGGER_HELP)),
+            "" => return Ok(String::new()),
+            _ => return Err(format!("unknown command '{}', type 'help' for the commands", name))
+        }
+    }
+
+    // Runs the program until it has to stop, and describes why it stopped.
+    // A statement starts when the program reaches one of its instructions
+    // coming from an instruction of another statement.
+    fn resume(&mut self, resume: Resume) -> String {
+        let mut output = String::new();
+        let max_instructions = self.machine.executed_instructions + MAX_INSTRUCTIONS_PER_COMMAND;
+        loop {
+            if self.machine.halted {
+                output.push_str("the program has halted\n");
+                break;
+            }
+            if self.machine.executed_instructions >= max_instructions {
+                output.push_str(&format!("the program is still running after {} instructions\n", MAX_INSTRUCTIONS_PER_COMMAND));
+                break;
+            }
+            let statement = self.address_statements.get(&self.machine.program_counter).copied();
+            if let Some(statement) = statement.filter(|x| Some(*x) != self.current_statement) {
+                let line = self.debug_info.statements[statement].line;
+                let is_breakpoint = self.breakpoints.contains(&line);
+                let should_stop = match resume {
+                    Resume::Continue => is_breakpoint,
+                    Resume::StepInto => true,
+                    Resume::StepOver(depth) => is_breakpoint || self.debug_info.statements[statement].depth <= depth
+                };
+                if should_stop {
+                    self.current_statement = Some(statement);
+                    output.push_str(&self.statement_location(statement, is_breakpoint));
+                    break;
+                }
+            }
+            if let Err(error) = self.machine.step() {
+                output.push_str(&format!("the program has failed: {}\n", error));
+                break;
+            }
+            if statement.is_some() {
+                self.current_statement = statement;
+            }
+            let changes = self.update_watches();
+            if !changes.is_empty() {
+                output.push_str(&changes);
+                if let Some(statement) = self.current_statement {
+                    output.push_str(&self.statement_location(statement, false));
+                }
+                break;
+            }
+        }
+        // The output of the program is shown before the reason it stopped
+        let program_output: String = self.machine.output[self.shown_output_lines..].iter()
+            .map(|x| format!("{}\n", x))
+            .collect();
+        self.shown_output_lines = self.machine.output.len();
+        return program_output + &output;
+    }
+
+    // Evaluates the watched expressions, and describes the ones whose value has changed
+    fn update_watches(&mut self) -> String {
+        let mut changes = String::new();
+        for index in 0..self.watches.len() {
+            let value = self.evaluate(&self.watches[index].expression);
+            let watch = &mut self.watches[index];
+            if value != watch.value {
+                changes.push_str(&format!(
+                    "{} changed from {} to {}\n",
+                    watch.text, format_value(watch.value, watch.expression_type), format_value(value, watch.expression_type)
+                ));
+                watch.value = value;
+            }
+        }
+        return changes;
+    }
+
+    fn statement_location(&self, statement: usize, is_breakpoint: bool) -> String {
+        let line = self.debug_info.statements[statement].line;
+        let prefix = if is_breakpoint { "breakpoint at " } else { "" };
+        return format!("{}line {}: {}\n", prefix, line, self.line_text(line));
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        return self.source_lines.get(line - 1).map(|x| x.as_str()).unwrap_or_default();
+    }
+
+    // Parses the line of a breakpoint, which must have a statement
+    fn parse_line(&self, argument: &str) -> Result<usize, String> {
+        let line: usize = argument.parse().map_err(|_| format!("invalid line '{}'", argument))?;
+        if !self.debug_info.statements.iter().any(|x| x.line == line) {
+            return Err(format!("there is no statement at line {}", line));
+        }
+        return Ok(line);
+    }
+
+    // Parses a YAL expression that only reads the variables and the constants
+    fn parse_expression(&self, text: &str) -> Result<(Node<usize, Token>, Type), String> {
+        let (tokens, spans) = get_tokens_and_spans_from_program(&format!("{{ print {}; }}", text))
+            .map_err(|diagnostic| diagnostic.message)?;
+        let mut parser = Parser::new(tokens);
+        if !parser.analyze_grammar() {
+            return Err(format!("invalid expression '{}'", text));
+        }
+        let print_node = parser.ast.find(&|x| *x.value() == Token::Print)[0].clone();
+        let expression = print_node.children()[0].clone();
+        let modifies_variables = |x: &Node<usize, Token>| {
+            return is_assignment_operator(x.value()) || matches!(x.value(), Token::Increment | Token::Decrement);
+        };
+        if !expression.find(&modifies_variables).is_empty() {
+            return Err(String::from("the expression cannot modify the variables"));
+        }
+
+        let mut symbol_table = HashMap::new();
+        for variable in &self.debug_info.variables {
+            symbol_table.insert(&variable.name, variable.variable_type);
+        }
+        for constant in &self.debug_info.constants {
+            symbol_table.insert(&constant.name, constant.constant_type);
+        }
+        for identifier_node in expression.find(&|x| matches!(x.value(), Token::Id(_))) {
+            if let Token::Id(identifier) = identifier_node.value() {
+                if !symbol_table.contains_key(identifier) {
+                    return Err(format!("unknown variable '{}'", identifier));
+                }
+            }
+        }
+        let mut type_checker = TypeChecker::new(&symbol_table, &spans);
+        let expression_type = type_checker.infer_expression_type(&expression).unwrap_or(Type::Int);
+        if let Some(diagnostic) = type_checker.diagnostics.first() {
+            return Err(diagnostic.message.clone());
+        }
+        return Ok((expression, expression_type));
+    }
+
+    // Evaluates an expression with the current values of the variables.
+    // Returns `None` if the expression divides by zero.
+    fn evaluate(&self, expression: &Node<usize, Token>) -> Option<i64> {
+        let mut values = HashMap::new();
+        for variable in &self.debug_info.variables {
+            values.insert(variable.name.clone(), self.machine.memory[variable.address as usize]);
+        }
+        for constant in &self.debug_info.constants {
+            values.insert(constant.name.clone(), MixWord::from_i64(constant.value));
+        }
+        return evaluate_constant_expression(expression, &values).map(|x| x.to_i64());
+    }
+}
+
+fn format_value(value: Option<i64>, value_type: Type) -> String {
+    match (value, value_type) {
+        (None, _) => return String::from("<division by zero>"),
+        (Some(value), Type::Bool) => return (value != 0).to_string(),
+        (Some(value), Type::Int) => return value.to_string()
+    }
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, CompileOptions};
+    use crate::mixal::loader::load_program;
+
+    const PROGRAM: &str = "\
+{
+    const N = 3;
+    var i, total : int;
+    var done : bool;
+    total = 0;
+    for (i = 0; i < N; i++) {
+        total += i * 10;
+    }
+    done = true;
+    print total;
+}";
+
+    fn new_debugger() -> Debugger {
+        let artifacts = compile(PROGRAM, &CompileOptions::default()).unwrap();
+        let program = load_program(artifacts.instructions.as_ref().unwrap()).unwrap();
+        return Debugger::new(&program, artifacts.debug_info().unwrap(), PROGRAM);
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let artifacts = compile(PROGRAM, &CompileOptions::default()).unwrap();
+        let debug_info = artifacts.debug_info().unwrap();
+        let lines: Vec<(usize, usize)> = debug_info.statements.iter().map(|x| (x.line, x.depth)).collect();
+        // The loop starts with the product of 'i * 10', which it keeps
+        // in a variable, and then runs the initialization of 'i'
+        assert_eq!(lines, vec![(5, 0), (6, 0), (6, 1), (7, 1), (6, 1), (9, 0), (10, 0)]);
+        assert_eq!(debug_info.variables, vec![
+            DebugVariable { name: String::from("i"), variable_type: Type::Int, address: 1 },
+            DebugVariable { name: String::from("total"), variable_type: Type::Int, address: 2 },
+            DebugVariable { name: String::from("done"), variable_type: Type::Bool, address: 3 }
+        ]);
+        assert_eq!(debug_info.constants, vec![
+            DebugConstant { name: String::from("N"), constant_type: Type::Int, value: 3 }
+        ]);
+        let json = debug_info.to_json().to_string();
+        assert!(json.contains("{\"name\":\"done\",\"type\":\"bool\",\"address\":3}"));
+    }
+
+    #[test]
+    fn test_breakpoints_and_steps() {
+        let mut debugger = new_debugger();
+        assert_eq!(debugger.execute("break 7"), Ok(String::from("breakpoint at line 7: total += i * 10;\n")));
+        assert_eq!(debugger.execute("break 3"), Err(String::from("there is no statement at line 3")));
+        assert_eq!(debugger.execute("continue"), Ok(String::from("breakpoint at line 7: total += i * 10;\n")));
+        assert_eq!(debugger.execute("continue").unwrap(), "breakpoint at line 7: total += i * 10;\n");
+        assert_eq!(debugger.execute("print total"), Ok(String::from("total = 0\n")));
+        assert_eq!(debugger.execute("p i < N && !done"), Ok(String::from("i < N && !done = true\n")));
+        assert_eq!(debugger.execute("step").unwrap(), "line 6: for (i = 0; i < N; i++) {\n");
+        assert_eq!(debugger.execute("delete 7"), Ok(String::new()));
+        // Stepping over the loop stops at its condition, and
+        // skips the statements of its body and its increment
+        for i in [2, 3] {
+            assert_eq!(debugger.execute("next").unwrap(), "line 6: for (i = 0; i < N; i++) {\n");
+            assert_eq!(debugger.execute("p i"), Ok(format!("i = {}\n", i)));
+        }
+        assert_eq!(debugger.execute("next").unwrap(), "line 9: done = true;\n");
+        assert_eq!(debugger.execute("p done"), Ok(String::from("done = false\n")));
+        assert_eq!(debugger.execute("s").unwrap(), "line 10: print total;\n");
+        assert_eq!(debugger.execute("p done"), Ok(String::from("done = true\n")));
+        assert!(!debugger.is_halted());
+        assert_eq!(debugger.execute("c").unwrap(), "    +0000000030\nthe program has halted\n");
+        assert!(debugger.is_halted());
+    }
+
+    #[test]
+    fn test_watches() {
+        let mut debugger = new_debugger();
+        assert_eq!(debugger.execute("watch total / i"), Ok(String::from("watching total / i = <division by zero>\n")));
+        assert_eq!(debugger.execute("c").unwrap(), "total / i changed from <division by zero> to 0\nline 6: for (i = 0; i < N; i++) {\n");
+        assert_eq!(debugger.execute("c").unwrap(), "total / i changed from 0 to 10\nline 7: total += i * 10;\n");
+        assert_eq!(debugger.execute("info").unwrap(), "watching total / i = 10\n");
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let mut debugger = new_debugger();
+        assert_eq!(debugger.execute("jump 5"), Err(String::from("unknown command 'jump', type 'help' for the commands")));
+        assert_eq!(debugger.execute("break"), Err(String::from("'break' expects a line")));
+        assert_eq!(debugger.execute("break x"), Err(String::from("invalid line 'x'")));
+        assert_eq!(debugger.execute("delete 5"), Err(String::from("there is no breakpoint at line 5")));
+        assert_eq!(debugger.execute("print x + 1"), Err(String::from("unknown variable 'x'")));
+        assert_eq!(debugger.execute("print total = 1"), Err(String::from("the expression cannot modify the variables")));
+        assert_eq!(debugger.execute("print total +"), Err(String::from("invalid expression 'total +'")));
+        assert!(debugger.execute("print total && done").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+use crate::lexer::Span;
+use crate::json::JsonValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning")
+        }
+    }
+}
+
+// The codes of the diagnostics. Once a code is
+// assigned to a check, it must never change, since
+// tools that consume the diagnostics rely on them.
+pub const INVALID_TOKEN: &str = "E0001";
+pub const SYNTAX_ERROR: &str = "E0002";
+pub const VARIABLE_RE_DECLARATION: &str = "E0003";
+pub const UNDECLARED_IDENTIFIER: &str = "E0004";
+pub const JUMP_OUTSIDE_OF_LOOP: &str = "E0005";
+pub const MISMATCHED_OPERAND_TYPES: &str = "E0006";
+pub const NON_BOOL_CONDITION: &str = "E0007";
+pub const MISMATCHED_ASSIGNMENT_TYPES: &str = "E0008";
+pub const DUPLICATE_CASE_LABEL: &str = "E0009";
+pub const NON_CONSTANT_CASE_LABEL: &str = "E0010";
+pub const UNKNOWN_LABEL: &str = "E0011";
+pub const MISPLACED_LABEL: &str = "E0012";
+pub const ASSIGNMENT_TO_CONSTANT: &str = "E0013";
+pub const NON_CONSTANT_INITIALIZER: &str = "E0014";
+pub const MEMORY_OVERFLOW: &str = "E0015";
+pub const LITERAL_OUT_OF_RANGE: &str = "E0016";
+pub const UNINITIALIZED_READ: &str = "W0001";
+pub const UNUSED_VARIABLE: &str = "W0002";
+pub const UNREAD_ASSIGNMENT: &str = "W0003";
+pub const UNREACHABLE_CODE: &str = "W0004";
+
+// A message produced by a phase of the compiler about
+// a problem in the compiled program. The span points
+// to the token that the message refers to, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub span: Option<Span>,
+    pub message: String,
+    pub notes: Vec<String>
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, span: Option<Span>, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, code, span, message, notes: vec![] }
+    }
+
+    pub fn warning(code: &'static str, span: Option<Span>, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, code, span, message, notes: vec![] }
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        return self;
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let span = self.span.as_ref().map(Span::to_json).unwrap_or(JsonValue::Null);
+        return JsonValue::object(vec![
+            ("severity", JsonValue::String(self.severity.to_string())),
+            ("code", JsonValue::string(self.code)),
+            ("message", JsonValue::String(self.message.clone())),
+            ("span", span),
+            ("notes", JsonValue::Array(self.notes.iter().map(|x| JsonValue::String(x.clone())).collect()))
+        ]);
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: ", self.severity, self.code)?;
+        if let Some(span) = &self.span {
+            write!(f, "{}:{}: ", span.line, span.column)?;
+        }
+        write!(f, "{}", self.message)?;
+        for note in &self.notes {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        return Ok(());
+    }
+}
+
+// All the diagnostics reported for a program
+pub type Diagnostics = Vec<Diagnostic>;
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    return diagnostics.iter().any(|x| x.severity == Severity::Error);
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+    use crate::utilities::get_tokens_and_spans_from_program;
+
+    fn get_diagnostics(program: &str) -> Vec<Diagnostic> {
+        let (tokens, spans) = match get_tokens_and_spans_from_program(program) {
+            Ok(tokens_and_spans) => tokens_and_spans,
+            Err(diagnostic) => return vec![diagnostic]
+        };
+        let mut parser = Parser::new(tokens);
+        if !parser.analyze_grammar() {
+            return vec![parser.syntax_error(&spans)];
+        }
+        let mut semantic_analyzer = SemanticAnalyzer::new(&parser.ast, &spans);
+        return semantic_analyzer.run();
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        let diagnostics = get_diagnostics("{\n  var a : int;\n  a = 1 $ 2;\n}");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "error[E0001]: 3:9: invalid token '$'");
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let diagnostics = get_diagnostics("{ var a : int; a = 1; } a");
+        assert_eq!(diagnostics[0].to_string(), "error[E0002]: 1:25: unexpected token 'a'");
+        let diagnostics = get_diagnostics("{ var a : int;");
+        assert_eq!(diagnostics[0].to_string(), "error[E0002]: 1:14: unexpected end of program");
+    }
+
+    #[test]
+    fn test_syntax_error_after_a_failed_alternative() {
+        // The declaration alternative of the block rule fails at the
+        // missing semicolon. The next alternatives must start from the
+        // beginning of the declaration, otherwise the error is reported
+        // at a later token.
+        let diagnostics = get_diagnostics("{\n  var a : int\n  a = 1;\n}");
+        assert_eq!(diagnostics[0].to_string(), "error[E0002]: 3:3: unexpected token 'a'");
+    }
+
+    #[test]
+    fn test_semantic_errors_and_warnings() {
+        let diagnostics = get_diagnostics(
+            "{\n  var a, a : int;\n  b = 1;\n  break;\n  print a;\n}"
+        );
+        let diagnostics: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+        assert_eq!(diagnostics, vec![
+            "error[E0003]: 2:10: re-declaration of identifier 'a'\n  = note: 'a' is first declared at 2:7",
+            "error[E0004]: 3:3: undeclared identifier 'b'\n  = note: declare it with 'var b : int;'",
+            "error[E0005]: 4:3: continue/break statement outside of loop"
+        ]);
+        let diagnostics = get_diagnostics("{ var a : int; print a; }");
+        assert_eq!(diagnostics[0].to_string(), "warning[W0001]: 1:22: variable 'a' may be read before it is initialized");
+    }
+
+    #[test]
+    fn test_declaration_notes_of_undeclared_identifiers() {
+        // The type of the suggested declaration comes from the use
+        let diagnostics = get_diagnostics("{ var a : bool; a = b; if (c) a = !d; print e + 1; print f; }");
+        let notes: Vec<String> = diagnostics.iter().map(|x| x.notes[0].clone()).collect();
+        assert_eq!(notes, vec![
+            "declare it with 'var b : bool;'",
+            "declare it with 'var c : bool;'",
+            "declare it with 'var d : bool;'",
+            "declare it with 'var e : int;'",
+            "declare it with 'var f : <type>;'"
+        ]);
+    }
+
+    #[test]
+    fn test_case_label_errors() {
+        let diagnostics = get_diagnostics(
+            "{\n  var a : int;\n  switch (a) {\n    case 1, 2 - 1: a = 1;\n    case a + 1, 1 / 0:\n    default:\n    default:\n  }\n}"
+        );
+        let diagnostics: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+        let non_constant_note = "case labels can only contain literals, constants and operators, and cannot divide by zero";
+        assert_eq!(diagnostics, vec![
+            String::from("error[E0009]: 4:13: duplicate case label with the value 1\n  = note: the first label with this value is at 4:10"),
+            format!("error[E0010]: 5:10: case label is not a constant expression\n  = note: {}", non_constant_note),
+            format!("error[E0010]: 5:17: case label is not a constant expression\n  = note: {}", non_constant_note),
+            String::from("error[E0009]: 7:5: duplicate default label\n  = note: the first default label is at 6:5")
+        ]);
+    }
+
+    #[test]
+    fn test_constant_errors() {
+        let diagnostics = get_diagnostics(
+            "{\n  const N = 2;\n  var a : int;\n  const M = a * N;\n  N = 3;\n  a = N++ + M;\n  print a;\n}"
+        );
+        let diagnostics: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+        assert_eq!(diagnostics, vec![
+            "error[E0014]: 4:13: the value of constant 'M' is not a constant expression\n  = note: \
+            it can only contain literals, previous constants and operators, and cannot divide by zero",
+            "error[E0013]: 5:3: cannot assign to constant 'N'\n  = note: 'N' is declared as a constant at 2:9",
+            "error[E0013]: 6:7: cannot assign to constant 'N'\n  = note: 'N' is declared as a constant at 2:9"
+        ]);
+        let diagnostics = get_diagnostics("{ const N = 2; const B = N > 1; var a : int; a = B; print a; }");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error[E0008]: 1:48: cannot assign a value of type bool to a variable of type int"
+        );
+    }
+
+    #[test]
+    fn test_literal_out_of_range() {
+        let diagnostics = get_diagnostics(
+            "{\n  var a : int;\n  a = 1073741823;\n  a = 1073741824;\n  print a;\n}"
+        );
+        let diagnostics: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+        assert_eq!(diagnostics, vec![
+            "error[E0016]: 4:7: the literal 1073741824 does not fit in a MIX word\n  = note: \
+            the magnitude of a number can be at most 1073741823"
+        ]);
+    }
+
+    #[test]
+    fn test_json_format() {
+        let diagnostic = Diagnostic::error(
+            UNDECLARED_IDENTIFIER,
+            Some(Span { start: 4, end: 5, line: 2, column: 3 }),
+            String::from("undeclared identifier 'b'")
+        ).with_note(String::from("a \"quoted\" note"));
+        assert_eq!(
+            diagnostic.to_json().to_string(),
+            "{\"severity\":\"error\",\"code\":\"E0004\",\"message\":\"undeclared identifier 'b'\",\
+            \"span\":{\"start\":4,\"end\":5,\"line\":2,\"column\":3},\"notes\":[\"a \\\"quoted\\\" note\"]}"
+        );
+        let diagnostic = Diagnostic::warning(UNREACHABLE_CODE, None, String::from("unreachable statement"));
+        assert_eq!(
+            diagnostic.to_json().to_string(),
+            "{\"severity\":\"warning\",\"code\":\"W0004\",\"message\":\"unreachable statement\",\"span\":null,\"notes\":[]}"
+        );
+    }
+}
@@ -1,56 +1,90 @@
-use std::{fs, process::Command};
+use std::{fs, io::{self, Read}, path::{Path, PathBuf}};
 
-// This class is used for storing the names 
+// The path that refers to stdin when used as an input,
+// and to stdout when used as an output.
+pub const STANDARD_STREAM_PATH: &str = "-";
+
+// The name of the output files when the YAL source code is read from stdin.
+const STDIN_OUTPUT_FILE_NAME: &str = "stdin";
+
+// This class is used for storing the names
 // of files and directories that are used
 // throughout all the phases of the compiler.
 pub struct FilesHandler {
-    // The file path of the YAL source code file.
-    pub yal_source_code_file_path: String,
-
-    // The file path of the YAL source code file, without the file name.
-    pub yal_source_code_path: String,
-
     // The source code contained in the YAL source code file.
     pub yal_source_code: String,
 
-    // The name of the output file(s) that will be produced.
-    // This is just the name of the file(s), without the extensions.
-    // Defaults to the provided name of the YAL source code file.
-    pub output_file_name: String,
-
-    // The file path of the MIXAL output file. This file 
+    // The file path of the MIXAL output file. This file
     // is used to store the MIXAL instructions that are
-    // produced by the assembler. It defaults to:
-    // /<yal_source_code_path>/bin/<output_file_name>.mixal
-    pub mixal_output_file_path: String,
+    // produced by the assembler. It defaults to
+    // <source code directory>/bin/<source code file name>.mixal,
+    // where the directory is the current one for stdin.
+    pub mixal_output_file_path: PathBuf,
 
     // The file path for the MIX output file. This is
     // a binary file that is generated by running the
-    // `mixasm` command for a MIXAL output file. This 
+    // `mixasm` command for a MIXAL output file. This
     // file can be executed using the `mixvm` command.
-    // It defaults to: /<yal_source_code_path>/bin/<output_file_name>.mix 
-    pub mix_output_file_path: String,
+    // It defaults to the path of the MIXAL file, with the '.mix' extension.
+    pub mix_output_file_path: PathBuf,
+
+    // The file path of the debug information of the MIX output
+    // file, which maps its instructions and its memory words to
+    // the lines and the variables of the YAL source code. It is
+    // the path of the MIXAL file, with the '.debug' extension.
+    pub debug_info_output_file_path: PathBuf
 }
 
 impl FilesHandler {
-    pub fn new(yal_source_code_file_path: &String) -> FilesHandler {
-        let yal_source_code_file_path = yal_source_code_file_path.clone();
-        let yal_source_code_path = yal_source_code_file_path.rsplit_once("/").expect("to split").0.to_string();
-        let yal_source_code = fs::read_to_string(yal_source_code_file_path.clone()).expect("File to exist");
-        let output_file_name = yal_source_code_file_path.clone().split("/").last().unwrap().split(".").next().unwrap().to_string();
-
-        Command::new("mkdir").arg("-p").arg(format!("{}/bin", yal_source_code_path)).output().expect("failed to execute process");
-
-        let mixal_output_file_path = format!("{}/bin/{}.mixal", yal_source_code_path, output_file_name);
-        let mix_output_file_path = format!("{}/bin/{}.mix", yal_source_code_path, output_file_name);
-        
-        FilesHandler { 
-            yal_source_code_file_path, 
-            yal_source_code_path,
-            yal_source_code, 
-            output_file_name, 
-            mixal_output_file_path, 
-            mix_output_file_path
+    // Reads the YAL source code from the given path. If an output path is
+    // provided, the output files are placed next to it, using its name.
+    pub fn new(yal_source_code_file_path: &str, output_file_path: Option<&str>) -> Result<FilesHandler, String> {
+        let (yal_source_code, yal_source_code_path, source_file_name) = if yal_source_code_file_path == STANDARD_STREAM_PATH {
+            let mut yal_source_code = String::new();
+            io::stdin().read_to_string(&mut yal_source_code)
+                .map_err(|error| format!("could not read from stdin: {}", error))?;
+            (yal_source_code, PathBuf::from("."), STDIN_OUTPUT_FILE_NAME.to_string())
+        } else {
+            let path = Path::new(yal_source_code_file_path);
+            let yal_source_code = fs::read_to_string(path)
+                .map_err(|error| format!("could not read '{}': {}", yal_source_code_file_path, error))?;
+            let yal_source_code_path = match path.parent() {
+                Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+                _ => PathBuf::from(".")
+            };
+            let source_file_name = path.file_stem()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or(STDIN_OUTPUT_FILE_NAME.to_string());
+            (yal_source_code, yal_source_code_path, source_file_name)
+        };
+
+        let mixal_output_file_path = match output_file_path {
+            Some(output_file_path) if output_file_path != STANDARD_STREAM_PATH => {
+                PathBuf::from(output_file_path).with_extension("mixal")
+            },
+            _ => yal_source_code_path.join("bin").join(format!("{}.mixal", source_file_name))
+        };
+        let mix_output_file_path = mixal_output_file_path.with_extension("mix");
+        let debug_info_output_file_path = mixal_output_file_path.with_extension("debug");
+
+        return Ok(FilesHandler {
+            yal_source_code,
+            mixal_output_file_path,
+            mix_output_file_path,
+            debug_info_output_file_path
+        });
+    }
+
+    // Writes the given content to the file at the given path,
+    // creating its directory if it does not exist.
+    pub fn write_output_file(path: &Path, content: &[u8]) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            if parent != Path::new("") {
+                fs::create_dir_all(parent)
+                    .map_err(|error| format!("could not create '{}': {}", parent.display(), error))?;
+            }
         }
-    }    
+        return fs::write(path, content)
+            .map_err(|error| format!("could not write '{}': {}", path.display(), error));
+    }
 }
@@ -0,0 +1,606 @@
+use orange_trees::Node;
+use crate::{lex, parse};
+use crate::diagnostics::Diagnostics;
+use crate::lexer::{Token, Span};
+use crate::utilities::token_to_source_text;
+
+// Where the opening brace of a block is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BraceStyle {
+    // At the end of the line of the control statement, eg `if (x) {`,
+    // and the 'else' follows the closing brace, eg `} else {`
+    SameLine,
+    // On its own line, aligned with the control statement
+    NextLine
+}
+
+pub struct FormatOptions {
+    // The number of spaces of an indentation level
+    pub indent_width: usize,
+    pub brace_style: BraceStyle
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions { indent_width: 4, brace_style: BraceStyle::SameLine }
+    }
+}
+
+// Formats the YAL program in `source`, which must be syntactically valid.
+// The formatted program has the same tokens, in the same order, so the
+// parentheses are kept as they are written, and so are the comments.
+// Formatting a formatted program does not change it.
+pub fn format(source: &str, options: &FormatOptions) -> Result<String, Diagnostics> {
+    let (tokens, spans) = lex(source)?;
+    let ast = parse(tokens.clone(), &spans)?;
+    let mut formatter = Formatter::new(source, &tokens, &spans, options);
+    formatter.write_program(ast.children().get(0).expect("to exist"));
+    return Ok(formatter.output);
+}
+
+// A comment of the source code, which the lexer skips.
+// The offsets are in bytes, as the ones of the spans.
+struct Comment {
+    start: usize,
+    end: usize
+}
+
+// This class writes a program statement by statement, following its AST.
+// The tokens of each line are taken from the token stream, and the
+// comments from the source code between the spans of the tokens.
+struct Formatter<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    spans: &'a [Span],
+    options: &'a FormatOptions,
+    comments: Vec<Comment>,
+    // The index of the first comment that has not been written
+    next_comment: usize,
+    output: String,
+    indentation: usize,
+    last_token: usize,
+    // The offset in the source code where the last
+    // written token or comment ends
+    written_until: usize,
+    // Whether the tokens of a statement are being written, so that
+    // a comment within it is followed by a continuation line
+    in_statement: bool
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, tokens: &'a [Token], spans: &'a [Span], options: &'a FormatOptions) -> Formatter<'a> {
+        Formatter {
+            source,
+            tokens,
+            spans,
+            options,
+            comments: find_comments(source, spans),
+            next_comment: 0,
+            output: String::new(),
+            indentation: 0,
+            last_token: 0,
+            written_until: 0,
+            in_statement: false
+        }
+    }
+
+    fn write_program(&mut self, program: &Node<usize, Token>) {
+        // The program is enclosed in the first and the last tokens
+        let closing_brace = self.tokens.len() - 1;
+        self.write_token(0, false);
+        self.end_line();
+        self.write_block_statements(program, closing_brace);
+        self.write_token(closing_brace, false);
+        self.end_line();
+        // The comments after the program
+        self.write_comments_before(usize::MAX);
+    }
+
+    // Writes the statements of a block, one indentation level deeper,
+    // along with the comments before its closing brace
+    fn write_block_statements(&mut self, block: &Node<usize, Token>, closing_brace: usize) {
+        self.indentation += 1;
+        for statement in block.children() {
+            self.write_statement(statement);
+        }
+        self.write_comments_before(self.spans[closing_brace].start);
+        self.indentation -= 1;
+    }
+
+    fn write_statement(&mut self, node: &Node<usize, Token>) {
+        let children = node.children();
+        let start = *node.id();
+        match node.value() {
+            Token::If => {
+                self.write_tokens(start, self.find_matching_token(start + 1) + 1);
+                let then_block = children.get(1).expect("to exist");
+                self.write_body(then_block);
+                let Some(else_node) = children.get(2) else {
+                    self.end_line();
+                    return;
+                };
+                let is_braced = self.tokens[*then_block.id()] == Token::LeftBrace;
+                if !is_braced || self.options.brace_style == BraceStyle::NextLine {
+                    self.end_line();
+                }
+                self.write_token(*else_node.id(), true);
+                let else_block = else_node.children().get(0).expect("to exist");
+                match else_block.children().get(0) {
+                    // An 'else if' stays on the same line
+                    Some(statement) if *else_block.value() == Token::Ast(String::from("SINGLE_BLOCK"))
+                        && *statement.value() == Token::If => self.write_statement(statement),
+                    _ => {
+                        self.write_body(else_block);
+                        self.end_line();
+                    }
+                }
+            },
+            Token::While | Token::For => {
+                self.write_tokens(start, self.find_matching_token(start + 1) + 1);
+                self.write_body(children.last().expect("to exist"));
+                self.end_line();
+            },
+            Token::Do => {
+                self.write_token(start, true);
+                let block = children.get(0).expect("to exist");
+                self.write_body(block);
+                // The 'while' follows the closing brace, eg `} while (a);`
+                let is_braced = self.tokens[*block.id()] == Token::LeftBrace;
+                if !is_braced || self.options.brace_style == BraceStyle::NextLine {
+                    self.end_line();
+                }
+                let while_token = self.last_token + 1;
+                self.write_tokens(while_token, self.find_statement_end(while_token) + 1);
+                self.end_line();
+            },
+            Token::Label(_) => {
+                // The label is on the line of the statement, eg `outer: while (a) {`
+                self.write_token(start, true);
+                self.write_token(start + 1, false);
+                self.write_statement(children.get(0).expect("to exist"));
+            },
+            Token::Switch => {
+                let opening_brace = self.find_matching_token(start + 1) + 1;
+                self.write_tokens(start, opening_brace);
+                if self.options.brace_style == BraceStyle::NextLine {
+                    self.end_line();
+                }
+                self.write_token(opening_brace, true);
+                self.end_line();
+                let closing_brace = self.find_matching_token(opening_brace);
+                // The cases are indented inside the braces, and their statements once more
+                self.indentation += 1;
+                let case_nodes = &children[1..];
+                for (index, case_node) in case_nodes.iter().enumerate() {
+                    let block = case_node.children().last().expect("to exist");
+                    let colon = *block.id();
+                    self.write_tokens(*case_node.id(), colon);
+                    self.write_token(colon, false);
+                    self.end_line();
+                    let next_token = case_nodes.get(index + 1).map_or(closing_brace, |x| *x.id());
+                    self.write_block_statements(block, next_token);
+                }
+                self.indentation -= 1;
+                self.write_comments_before(self.spans[closing_brace].start);
+                self.write_token(closing_brace, true);
+                self.end_line();
+            },
+            Token::Continue | Token::Break => {
+                self.write_tokens(start, self.find_statement_end(start) + 1);
+                self.end_line();
+            },
+            _ => {
+                // The declarations start from 'var' or 'const', the assignments from the
+                // identifier and the empty statements are empty blocks at ';'
+                let start = match node.value() {
+                    Token::Print | Token::Int | Token::Bool | Token::Const | Token::Ast(_) => start,
+                    Token::Increment | Token::Decrement => start.min(*children.get(0).expect("to exist").id()),
+                    _ => *children.get(0).expect("to exist").id()
+                };
+                self.write_tokens(start, self.find_statement_end(start) + 1);
+                self.end_line();
+            }
+        }
+    }
+
+    // Writes the block of a control statement, after its header
+    fn write_body(&mut self, block: &Node<usize, Token>) {
+        let start = *block.id();
+        if self.tokens[start] == Token::LeftBrace {
+            if self.options.brace_style == BraceStyle::NextLine {
+                self.end_line();
+            }
+            self.write_token(start, true);
+            self.end_line();
+            let closing_brace = self.find_matching_token(start);
+            self.write_block_statements(block, closing_brace);
+            self.write_token(closing_brace, true);
+        } else {
+            // A single statement, without braces
+            self.end_line();
+            self.indentation += 1;
+            self.write_statement(block.children().get(0).expect("to exist"));
+            self.indentation -= 1;
+        }
+    }
+
+    // Writes the tokens in the range [start, end), which belong to the same line
+    fn write_tokens(&mut self, start: usize, end: usize) {
+        for index in start..end {
+            self.write_token(index, index == start || self.needs_space_before(index));
+            self.in_statement = true;
+        }
+        self.in_statement = false;
+    }
+
+    fn write_token(&mut self, index: usize, space_before: bool) {
+        let span = self.spans[index];
+        self.write_comments_before(span.start);
+        if self.is_at_line_start() {
+            // Blank lines between statements are kept, as a single one
+            let newlines = self.count_newlines(self.written_until, span.start);
+            if newlines > 1 && !self.in_statement && self.tokens[index] != Token::RightBrace {
+                self.write_blank_line();
+            }
+            self.write_indentation();
+        } else if space_before {
+            self.output.push(' ');
+        }
+        self.output.push_str(&token_to_source_text(&self.tokens[index]));
+        self.last_token = index;
+        self.written_until = span.end;
+    }
+
+    fn needs_space_before(&self, index: usize) -> bool {
+        let token = &self.tokens[index];
+        let previous_token = &self.tokens[index - 1];
+        if matches!(token, Token::Semicolon | Token::Comma | Token::RightParen)
+            || matches!(previous_token, Token::LeftParen | Token::ExclamationMark) {
+            return false;
+        }
+        let is_increment_or_decrement = |x: &Token| matches!(x, Token::Increment | Token::Decrement);
+        // A postfix '++' or '--' is attached to its variable, eg `a++`
+        if is_increment_or_decrement(token) && matches!(previous_token, Token::Id(_)) {
+            return false;
+        }
+        let follows_operand = index >= 2 && matches!(
+            self.tokens[index - 2],
+            Token::Id(_) | Token::Num(_) | Token::True | Token::False | Token::RightParen
+            | Token::Increment | Token::Decrement
+        );
+        // A prefix '++' or '--' is attached to its variable, eg `++a`,
+        // since only a variable can precede a postfix one
+        if is_increment_or_decrement(previous_token) && matches!(token, Token::Id(_))
+            && !matches!(self.tokens[index - 2], Token::Id(_)) {
+            return false;
+        }
+        // A unary minus is attached to its operand, eg `a + -b`, unless
+        // the operand is a '--', since `---b` would lex as `--` and `-`
+        let is_unary_minus = *previous_token == Token::Minus && !follows_operand;
+        return !is_unary_minus || *token == Token::Decrement;
+    }
+
+    // Ends the current line, after the comment that follows its last token, if any
+    fn end_line(&mut self) {
+        let next_token_start = self.spans.get(self.last_token + 1).map_or(self.source.len(), |x| x.start);
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start < next_token_start && self.count_newlines(self.written_until, comment.start) == 0 {
+                self.output.push(' ');
+                self.write_comment();
+            }
+        }
+        if !self.is_at_line_start() {
+            self.output.push('\n');
+        }
+    }
+
+    fn write_comments_before(&mut self, position: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start >= position {
+                break;
+            }
+            let newlines = self.count_newlines(self.written_until, comment.start);
+            if newlines == 0 && !self.is_at_line_start() {
+                // The comment stays at the end of the line
+                self.output.push(' ');
+            } else {
+                if !self.is_at_line_start() {
+                    self.output.push('\n');
+                }
+                if newlines > 1 {
+                    self.write_blank_line();
+                }
+                self.write_indentation();
+            }
+            self.write_comment();
+            self.output.push('\n');
+        }
+    }
+
+    // Writes the next comment, which ends its line
+    fn write_comment(&mut self) {
+        let comment = &self.comments[self.next_comment];
+        self.output.push_str(self.source[comment.start..comment.end].trim_end());
+        self.written_until = comment.end;
+        self.next_comment += 1;
+    }
+
+    fn write_indentation(&mut self) {
+        // The continuation lines of a statement are indented once more
+        let levels = self.indentation + self.in_statement as usize;
+        self.output.push_str(&" ".repeat(levels * self.options.indent_width));
+    }
+
+    fn write_blank_line(&mut self) {
+        let follows_opening_brace = self.output.trim_end().ends_with('{');
+        if !self.output.is_empty() && !self.output.ends_with("\n\n") && !follows_opening_brace {
+            self.output.push('\n');
+        }
+    }
+
+    fn is_at_line_start(&self) -> bool {
+        return self.output.is_empty() || self.output.ends_with('\n');
+    }
+
+    fn count_newlines(&self, start: usize, end: usize) -> usize {
+        return self.source[start..end].matches('\n').count();
+    }
+
+    // Returns the index of the bracket that closes the one at `index`
+    fn find_matching_token(&self, index: usize) -> usize {
+        let opening_token = &self.tokens[index];
+        let closing_token = if *opening_token == Token::LeftParen { Token::RightParen } else { Token::RightBrace };
+        let mut depth = 0;
+        for (offset, token) in self.tokens[index..].iter().enumerate() {
+            if token == opening_token {
+                depth += 1;
+            } else if *token == closing_token {
+                depth -= 1;
+                if depth == 0 {
+                    return index + offset;
+                }
+            }
+        }
+        panic!("The brackets of a parsed program must be balanced");
+    }
+
+    // Returns the index of the ';' that ends the statement starting at `index`
+    fn find_statement_end(&self, index: usize) -> usize {
+        return index + self.tokens[index..].iter().position(|x| *x == Token::Semicolon).expect("to exist");
+    }
+}
+
+// Finds the comments in the source code between the tokens
+fn find_comments(source: &str, spans: &[Span]) -> Vec<Comment> {
+    let mut comments = vec![];
+    let mut gap_start = 0;
+    let gap_ends = spans.iter().map(|x| (x.start, x.end)).chain([(source.len(), source.len())]);
+    for (gap_end, next_gap_start) in gap_ends {
+        let mut position = gap_start;
+        while let Some(offset) = source[position..gap_end].find("//") {
+            let start = position + offset;
+            let end = source[start..gap_end].find('\n').map_or(gap_end, |x| start + x);
+            comments.push(Comment { start, end });
+            position = end;
+        }
+        gap_start = next_gap_start;
+    }
+    return comments;
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzer::generate_program;
+
+    fn format_default(source: &str) -> String {
+        return format(source, &FormatOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "{var a,b:int;var done:bool; a=-1;b = a+-(2+ 3)*2;
+            if(!done&&a<b){print a;}else if (a==b) print b; else{;}
+            for(a=0;a<10;a+=1)if(a%2==0)continue;
+        while (true) { break; } }";
+        assert_eq!(format_default(source), "\
+{
+    var a, b : int;
+    var done : bool;
+    a = -1;
+    b = a + -(2 + 3) * 2;
+    if (!done && a < b) {
+        print a;
+    } else if (a == b)
+        print b;
+    else {
+        ;
+    }
+    for (a = 0; a < 10; a += 1)
+        if (a % 2 == 0)
+            continue;
+    while (true) {
+        break;
+    }
+}
+");
+    }
+
+    #[test]
+    fn test_format_options() {
+        let source = "{ var a : int; if (a > 0) { a -= 1; } else { a += 1; } }";
+        let options = FormatOptions { indent_width: 2, brace_style: BraceStyle::NextLine };
+        assert_eq!(format(source, &options).unwrap(), "\
+{
+  var a : int;
+  if (a > 0)
+  {
+    a -= 1;
+  }
+  else
+  {
+    a += 1;
+  }
+}
+");
+    }
+
+    #[test]
+    fn test_switch() {
+        let source = "{ var a : int; switch(a){case 1,-2 :print a;a=1; // one
+        default:case 3:; } }";
+        assert_eq!(format_default(source), "\
+{
+    var a : int;
+    switch (a) {
+        case 1, -2:
+            print a;
+            a = 1; // one
+        default:
+        case 3:
+            ;
+    }
+}
+");
+        let options = FormatOptions { indent_width: 2, brace_style: BraceStyle::NextLine };
+        assert_eq!(format("{ switch (1) { default: print 1; } }", &options).unwrap(), "\
+{
+  switch (1)
+  {
+    default:
+      print 1;
+  }
+}
+");
+    }
+
+    #[test]
+    fn test_do_while_and_labels() {
+        let source = "{ var a : int; outer:while(a<3){do{a+=1;continue outer;}while(a<2); do a-=1;while(false);break outer;} }";
+        assert_eq!(format_default(source), "\
+{
+    var a : int;
+    outer: while (a < 3) {
+        do {
+            a += 1;
+            continue outer;
+        } while (a < 2);
+        do
+            a -= 1;
+        while (false);
+        break outer;
+    }
+}
+");
+        let options = FormatOptions { indent_width: 2, brace_style: BraceStyle::NextLine };
+        assert_eq!(format("{ inner: do { break; } while (true); }", &options).unwrap(), "\
+{
+  inner: do
+  {
+    break;
+  }
+  while (true);
+}
+");
+    }
+
+    #[test]
+    fn test_increments_and_assignment_expressions() {
+        let source = "{ var a, b : int; a ++; -- b; if (a) ++ a; print a ++ + - --b; b = - -- a; a = b += 1; }";
+        assert_eq!(format(source, &FormatOptions::default()).expect("to format"), "\
+{
+    var a, b : int;
+    a++;
+    --b;
+    if (a)
+        ++a;
+    print a++ + - --b;
+    b = - --a;
+    a = b += 1;
+}
+");
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        let source = "{ var a : int; a=a>0?-1:a<0?1:0; switch(a){case 1>0?1:2:print(a?1:-a);} }";
+        assert_eq!(format(source, &FormatOptions::default()).expect("to format"), "\
+{
+    var a : int;
+    a = a > 0 ? -1 : a < 0 ? 1 : 0;
+    switch (a) {
+        case 1 > 0 ? 1 : 2:
+            print (a ? 1 : -a);
+    }
+}
+");
+    }
+
+    #[test]
+    fn test_constants() {
+        let source = "{ const N=10;const M=-N*2; var a : int; a=N+M; }";
+        assert_eq!(format(source, &FormatOptions::default()).expect("to format"), "\
+{
+    const N = 10;
+    const M = -N * 2;
+    var a : int;
+    a = N + M;
+}
+");
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "// The header
+{ var a : int; // the counter
+
+
+    // Counts to 3
+    while (a < 3) { // the loop
+        a += // a comment that breaks the line
+            1;
+        // the end of the loop
+    }
+} // the end
+// the footer";
+        assert_eq!(format_default(source), "\
+// The header
+{
+    var a : int; // the counter
+
+    // Counts to 3
+    while (a < 3) { // the loop
+        a += // a comment that breaks the line
+            1;
+        // the end of the loop
+    }
+} // the end
+// the footer
+");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources: Vec<String> = (0..50).map(|seed| generate_program(seed).to_source())
+            .chain([String::from("{ if (true) { ; } // a\n // b\n else if (false) ; else print 1; }")])
+            .collect();
+        for brace_style in [BraceStyle::SameLine, BraceStyle::NextLine] {
+            let options = FormatOptions { indent_width: 3, brace_style };
+            for source in &sources {
+                let formatted = format(source, &options).unwrap();
+                assert_eq!(format(&formatted, &options).unwrap(), formatted);
+                // Only the whitespace changes
+                assert_eq!(lex(&formatted).unwrap().0, lex(source).unwrap().0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        assert!(format("{ var a : int; a = ; }", &FormatOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,904 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::mem::discriminant;
+use std::panic::{self, AssertUnwindSafe};
+use orange_trees::Node;
+use crate::{compile, CompileOptions};
+use crate::interpreter::Interpreter;
+use crate::lexer::Token;
+use crate::mixal::{emulator::MixMachine, loader::load_program};
+use crate::type_checker::Type;
+use crate::utilities::token_to_source_text;
+
+// The limits of the generated programs, which keep them small
+// enough to run quickly and to be read once they are minimized
+const MAX_STATEMENTS_PER_BLOCK: u64 = 4;
+const MAX_BLOCK_DEPTH: usize = 2;
+const MAX_EXPRESSION_DEPTH: usize = 3;
+const MAX_LOOP_ITERATIONS: u64 = 4;
+const VARIABLES_PER_TYPE: usize = 3;
+// The largest number that fits in a MIX word
+const MAX_NUMBER: u64 = (1 << 30) - 1;
+
+// The bounded loops of the generated programs never execute as many
+// instructions, so a MIX program that does is assumed to never halt
+const MAX_INSTRUCTIONS: u64 = 10_000_000;
+
+// A xorshift pseudorandom number generator. It is implemented here,
+// rather than taken from a crate, so that a seed always generates
+// the same program, whatever the platform or the dependencies.
+pub struct Random {
+    state: u64
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // The seed is scrambled (splitmix64), since close seeds would
+        // produce similar sequences and xorshift needs a non-zero state
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        Random { state: state.max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+
+    // A number in the range [0, bound)
+    pub fn below(&mut self, bound: u64) -> u64 {
+        return self.next_u64() % bound;
+    }
+
+    // Whether an event that happens with the given probability, in percent, happens
+    pub fn chance(&mut self, percent: u64) -> bool {
+        return self.below(100) < percent;
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        return &items[self.below(items.len() as u64) as usize];
+    }
+}
+
+// An expression of a generated program. The operators are kept as
+// tokens, and the negative numbers are unary minuses, as in the AST.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(u64),
+    Boolean(bool),
+    Variable(String, Type),
+    // '-' or '!' applied to an expression
+    Unary(Token, Box<Expression>),
+    // '++' or '--', whether it is a prefix one, and the variable
+    Increment(Token, bool, String),
+    Binary(Token, Box<Expression>, Box<Expression>),
+    // `c ? a : b`, whose branches have the same type
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>)
+}
+
+impl Expression {
+    pub fn expression_type(&self) -> Type {
+        match self {
+            Expression::Number(_) => return Type::Int,
+            Expression::Boolean(_) => return Type::Bool,
+            Expression::Variable(_, variable_type) => return *variable_type,
+            Expression::Increment(_, _, _) => return Type::Int,
+            Expression::Conditional(_, then_operand, _) => return then_operand.expression_type(),
+            Expression::Unary(operator, _) | Expression::Binary(operator, _, _) => {
+                match operator {
+                    Token::Plus | Token::Minus | Token::Asterisk | Token::Slash | Token::Percent => return Type::Int,
+                    _ => return Type::Bool
+                }
+            }
+        }
+    }
+
+    // Whether the expression is a `BASE` of the grammar without parentheses
+    fn is_atom(&self) -> bool {
+        return matches!(
+            self,
+            Expression::Number(_) | Expression::Boolean(_) | Expression::Variable(_, _) | Expression::Increment(_, _, _)
+        );
+    }
+
+    // Writes the expression with only the parentheses that the precedence and
+    // the left associativity of the operators require, or with parentheses
+    // around every operand that is not an atom if `parenthesize_all` is set.
+    fn to_source(&self, parenthesize_all: bool) -> String {
+        match self {
+            Expression::Number(number) => return number.to_string(),
+            Expression::Boolean(value) => return value.to_string(),
+            Expression::Variable(identifier, _) => return identifier.clone(),
+            Expression::Increment(operator, true, identifier) => {
+                return format!("{}{}", token_to_source_text(operator), identifier);
+            },
+            Expression::Increment(operator, false, identifier) => {
+                return format!("{}{}", identifier, token_to_source_text(operator));
+            },
+            // The operand of a unary operator is a `BASE` of the grammar
+            Expression::Unary(operator, operand) => {
+                let operand_source = operand.to_operand_source(true, parenthesize_all);
+                // Otherwise, `-` and `--a` would lex as `--` and `-a`
+                if operand_source.starts_with('-') {
+                    return format!("{}({})", token_to_source_text(operator), operand_source);
+                }
+                return format!("{}{}", token_to_source_text(operator), operand_source);
+            },
+            Expression::Binary(operator, left_operand, right_operand) => {
+                let precedence = operator_precedence(operator);
+                let left_needs_parentheses = match left_operand.as_ref() {
+                    Expression::Binary(left_operator, _, _) => operator_precedence(left_operator) < precedence,
+                    Expression::Conditional(_, _, _) => true,
+                    _ => false
+                };
+                let right_needs_parentheses = match right_operand.as_ref() {
+                    Expression::Binary(right_operator, _, _) => operator_precedence(right_operator) <= precedence,
+                    Expression::Conditional(_, _, _) => true,
+                    // The right operand of a multiplicative operator is a `BASE`, eg `a * (-b)`
+                    Expression::Unary(_, _) => precedence == MULTIPLICATIVE_PRECEDENCE,
+                    _ => false
+                };
+                return format!(
+                    "{} {} {}",
+                    left_operand.to_operand_source(left_needs_parentheses, parenthesize_all),
+                    token_to_source_text(operator),
+                    right_operand.to_operand_source(right_needs_parentheses, parenthesize_all)
+                );
+            },
+            // The condition has a higher precedence than '?', and the branches
+            // are any expressions, so only a nested condition needs parentheses
+            Expression::Conditional(condition, then_operand, else_operand) => {
+                let condition_needs_parentheses = matches!(condition.as_ref(), Expression::Conditional(_, _, _));
+                return format!(
+                    "{} ? {} : {}",
+                    condition.to_operand_source(condition_needs_parentheses, parenthesize_all),
+                    then_operand.to_operand_source(false, parenthesize_all),
+                    else_operand.to_operand_source(false, parenthesize_all)
+                );
+            }
+        }
+    }
+
+    fn to_operand_source(&self, needs_parentheses: bool, parenthesize_all: bool) -> String {
+        if !self.is_atom() && (needs_parentheses || parenthesize_all) {
+            return format!("({})", self.to_source(parenthesize_all));
+        }
+        return self.to_source(parenthesize_all);
+    }
+
+    fn collect_variables<'a>(&'a self, variables: &mut HashSet<&'a String>) {
+        match self {
+            Expression::Variable(identifier, _) | Expression::Increment(_, _, identifier) => {
+                variables.insert(identifier);
+            },
+            Expression::Unary(_, operand) => operand.collect_variables(variables),
+            Expression::Binary(_, left_operand, right_operand) => {
+                left_operand.collect_variables(variables);
+                right_operand.collect_variables(variables);
+            },
+            Expression::Conditional(condition, then_operand, else_operand) => {
+                condition.collect_variables(variables);
+                then_operand.collect_variables(variables);
+                else_operand.collect_variables(variables);
+            },
+            _ => {}
+        }
+    }
+}
+
+const MULTIPLICATIVE_PRECEDENCE: u8 = 6;
+
+// The precedence of the binary operators, as in
+// "docs/grammar-with-op-precedence-without-left-recursive-rules.txt"
+fn operator_precedence(operator: &Token) -> u8 {
+    match operator {
+        Token::Or => return 1,
+        Token::And => return 2,
+        Token::Equals | Token::NotEquals => return 3,
+        Token::LessThan | Token::LessThanOrEquals | Token::GreaterThan | Token::GreaterThanOrEquals => return 4,
+        Token::Plus | Token::Minus => return 5,
+        _ => return MULTIPLICATIVE_PRECEDENCE
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopKind {
+    While,
+    For,
+    DoWhile
+}
+
+// A statement of a generated program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    // The variable, the assignment operator and the assigned expression
+    Assignment(String, Token, Expression),
+    Print(Expression),
+    If(Expression, Vec<Statement>, Option<Vec<Statement>>),
+    // A loop whose body runs at most `iterations` times (a do-while
+    // loop runs it once even if `iterations` is 0). Its counter
+    // is a variable that only the loop itself modifies: it is set to 0
+    // before the loop and incremented before the body runs, so that
+    // 'continue' can not skip it.
+    Loop {
+        kind: LoopKind,
+        counter: String,
+        iterations: u64,
+        body: Vec<Statement>
+    },
+    Break,
+    Continue,
+    Empty
+}
+
+impl Statement {
+    fn write_source(&self, indentation: usize, parenthesize_all: bool, text: &mut String) {
+        let indent = "    ".repeat(indentation);
+        let expression_source = |expression: &Expression| expression.to_source(parenthesize_all);
+        match self {
+            Statement::Assignment(identifier, operator, expression) => {
+                text.push_str(&format!(
+                    "{}{} {} {};\n", indent, identifier, token_to_source_text(operator), expression_source(expression)
+                ));
+            },
+            Statement::Print(expression) => text.push_str(&format!("{}print {};\n", indent, expression_source(expression))),
+            Statement::If(condition, then_statements, else_statements) => {
+                text.push_str(&format!("{}if ({}) ", indent, expression_source(condition)));
+                write_block_source(then_statements, indentation, parenthesize_all, text);
+                if let Some(else_statements) = else_statements {
+                    text.push_str(&format!("{}else ", indent));
+                    write_block_source(else_statements, indentation, parenthesize_all, text);
+                }
+            },
+            Statement::Loop { kind: LoopKind::While, counter, iterations, body } => {
+                text.push_str(&format!("{}{} = 0;\n", indent, counter));
+                text.push_str(&format!("{}while ({} < {}) {{\n", indent, counter, iterations));
+                text.push_str(&format!("{}    {} += 1;\n", indent, counter));
+                for statement in body {
+                    statement.write_source(indentation + 1, parenthesize_all, text);
+                }
+                text.push_str(&format!("{}}}\n", indent));
+            },
+            Statement::Loop { kind: LoopKind::For, counter, iterations, body } => {
+                // The counter is incremented by the 3rd part of the
+                // loop, which also runs after a 'continue'
+                text.push_str(&format!(
+                    "{}for ({} = 0; {} < {}; {} += 1) ", indent, counter, counter, iterations, counter
+                ));
+                write_block_source(body, indentation, parenthesize_all, text);
+            },
+            Statement::Loop { kind: LoopKind::DoWhile, counter, iterations, body } => {
+                text.push_str(&format!("{}{} = 0;\n", indent, counter));
+                text.push_str(&format!("{}do {{\n", indent));
+                text.push_str(&format!("{}    {} += 1;\n", indent, counter));
+                for statement in body {
+                    statement.write_source(indentation + 1, parenthesize_all, text);
+                }
+                text.push_str(&format!("{}}} while ({} < {});\n", indent, counter, iterations));
+            },
+            Statement::Break => text.push_str(&format!("{}break;\n", indent)),
+            Statement::Continue => text.push_str(&format!("{}continue;\n", indent)),
+            Statement::Empty => text.push_str(&format!("{};\n", indent))
+        }
+    }
+
+    fn is_simple(&self) -> bool {
+        return !matches!(self, Statement::If(_, _, _) | Statement::Loop { .. });
+    }
+
+    fn collect_variables<'a>(&'a self, variables: &mut HashSet<&'a String>) {
+        match self {
+            Statement::Assignment(identifier, _, expression) => {
+                variables.insert(identifier);
+                expression.collect_variables(variables);
+            },
+            Statement::Print(expression) => expression.collect_variables(variables),
+            Statement::If(condition, then_statements, else_statements) => {
+                condition.collect_variables(variables);
+                for statement in then_statements.iter().chain(else_statements.iter().flatten()) {
+                    statement.collect_variables(variables);
+                }
+            },
+            Statement::Loop { counter, body, .. } => {
+                variables.insert(counter);
+                for statement in body {
+                    statement.collect_variables(variables);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+// Writes the block of a control statement. A single simple statement is
+// written without braces, which exercises the `SINGLE_BLOCK` of the parser.
+// Control statements are always enclosed in braces, so that an 'else'
+// never belongs to a nested 'if' that it was not generated for.
+fn write_block_source(statements: &[Statement], indentation: usize, parenthesize_all: bool, text: &mut String) {
+    if statements.len() == 1 && statements[0].is_simple() {
+        text.push('\n');
+        statements[0].write_source(indentation + 1, parenthesize_all, text);
+        return;
+    }
+    text.push_str("{\n");
+    for statement in statements {
+        statement.write_source(indentation + 1, parenthesize_all, text);
+    }
+    text.push_str(&format!("{}}}\n", "    ".repeat(indentation)));
+}
+
+// A random, well-formed and well-typed YAL program that always halts
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedProgram {
+    // The declared variables, in the order of their declarations
+    pub variables: Vec<(String, Type)>,
+    pub statements: Vec<Statement>
+}
+
+impl GeneratedProgram {
+    pub fn to_source(&self) -> String {
+        return self.write_source(false);
+    }
+
+    // The same program, with parentheses around every operand
+    // that is not an atom, which makes the precedence of the
+    // operators irrelevant to the parser.
+    pub fn to_parenthesized_source(&self) -> String {
+        return self.write_source(true);
+    }
+
+    fn write_source(&self, parenthesize_all: bool) -> String {
+        let mut text = String::from("{\n");
+        for declared_type in [Type::Int, Type::Bool] {
+            let identifiers: Vec<&str> = self.variables.iter()
+                .filter(|(_, variable_type)| *variable_type == declared_type)
+                .map(|(identifier, _)| identifier.as_str())
+                .collect();
+            if !identifiers.is_empty() {
+                text.push_str(&format!("    var {} : {};\n", identifiers.join(", "), declared_type));
+            }
+        }
+        for statement in &self.statements {
+            statement.write_source(1, parenthesize_all, &mut text);
+        }
+        text.push_str("}\n");
+        return text;
+    }
+}
+
+// This class generates random programs from the grammar of YAL. The
+// programs only use declared variables, the operands of the operators
+// have the right types, 'break' and 'continue' only appear in loops
+// and every loop is bounded, so the compiler must accept all of them.
+struct ProgramGenerator {
+    random: Random,
+    // The variables that the statements may assign
+    variables: Vec<(String, Type)>,
+    // The counters of the loops, which the statements may only read
+    loop_counters: Vec<String>
+}
+
+impl ProgramGenerator {
+    fn generate_program(&mut self) -> GeneratedProgram {
+        for index in 0..VARIABLES_PER_TYPE {
+            self.variables.push((format!("x{}", index), Type::Int));
+            self.variables.push((format!("b{}", index), Type::Bool));
+        }
+        let mut statements = self.generate_block(0, false);
+        // Otherwise, most mismatches would not be visible
+        statements.push(Statement::Print(self.generate_expression(Type::Int, MAX_EXPRESSION_DEPTH)));
+
+        let mut variables = self.variables.clone();
+        variables.extend(self.loop_counters.iter().map(|counter| (counter.clone(), Type::Int)));
+        return GeneratedProgram { variables, statements };
+    }
+
+    fn generate_block(&mut self, depth: usize, in_loop: bool) -> Vec<Statement> {
+        let statements_count = 1 + self.random.below(MAX_STATEMENTS_PER_BLOCK);
+        return (0..statements_count).map(|_| self.generate_statement(depth, in_loop)).collect();
+    }
+
+    fn generate_statement(&mut self, depth: usize, in_loop: bool) -> Statement {
+        let can_nest = depth < MAX_BLOCK_DEPTH;
+        loop {
+            match self.random.below(100) {
+                0..=34 => {
+                    let (identifier, variable_type) = self.random.choose(&self.variables).clone();
+                    let operator = match variable_type {
+                        Type::Bool => Token::Assignment,
+                        Type::Int => self.random.choose(&[
+                            Token::Assignment, Token::Assignment, Token::AdditionAssignment,
+                            Token::SubtractionAssignment, Token::MultiplicationAssignment,
+                            Token::DivisionAssignment, Token::ModuloAssignment
+                        ]).clone()
+                    };
+                    let expression = self.generate_expression(variable_type, MAX_EXPRESSION_DEPTH);
+                    return Statement::Assignment(identifier, operator, expression);
+                },
+                35..=59 => {
+                    let expression_type = if self.random.chance(70) { Type::Int } else { Type::Bool };
+                    return Statement::Print(self.generate_expression(expression_type, MAX_EXPRESSION_DEPTH));
+                },
+                60..=74 if can_nest => {
+                    let condition = self.generate_expression(Type::Bool, MAX_EXPRESSION_DEPTH);
+                    let then_statements = self.generate_block(depth + 1, in_loop);
+                    let else_statements = if self.random.chance(40) {
+                        Some(self.generate_block(depth + 1, in_loop))
+                    } else {
+                        None
+                    };
+                    return Statement::If(condition, then_statements, else_statements);
+                },
+                75..=86 if can_nest => {
+                    let counter = format!("i{}", self.loop_counters.len());
+                    self.loop_counters.push(counter.clone());
+                    let kind = *self.random.choose(&[LoopKind::While, LoopKind::For, LoopKind::DoWhile]);
+                    let iterations = self.random.below(MAX_LOOP_ITERATIONS + 1);
+                    let body = self.generate_block(depth + 1, true);
+                    return Statement::Loop { kind, counter, iterations, body };
+                },
+                87..=91 if in_loop => return Statement::Break,
+                92..=96 if in_loop => return Statement::Continue,
+                97..=99 => return Statement::Empty,
+                _ => continue
+            }
+        }
+    }
+
+    fn generate_expression(&mut self, expression_type: Type, depth: usize) -> Expression {
+        if depth == 0 || self.random.chance(25) {
+            return self.generate_leaf(expression_type);
+        }
+        let is_whole_expression = depth == MAX_EXPRESSION_DEPTH;
+        let depth = depth - 1;
+        // Only the whole expression may be a conditional one, since the code of
+        // nested ones would quickly outgrow the memory of the MIX computer
+        if is_whole_expression && self.random.chance(10) {
+            return Expression::Conditional(
+                Box::new(self.generate_expression(Type::Bool, depth)),
+                Box::new(self.generate_expression(expression_type, depth)),
+                Box::new(self.generate_expression(expression_type, depth))
+            );
+        }
+        match expression_type {
+            Type::Int => {
+                if self.random.chance(10) {
+                    return Expression::Unary(Token::Minus, Box::new(self.generate_expression(Type::Int, depth)));
+                }
+                // The counters of the loops are never incremented, so that the loops stay bounded
+                if self.random.chance(5) {
+                    let operator = self.random.choose(&[Token::Increment, Token::Decrement]).clone();
+                    let candidates: Vec<String> = self.variables.iter()
+                        .filter(|(_, variable_type)| *variable_type == Type::Int)
+                        .map(|(identifier, _)| identifier.clone())
+                        .collect();
+                    let identifier = self.random.choose(&candidates).clone();
+                    return Expression::Increment(operator, self.random.chance(50), identifier);
+                }
+                let operator = self.random.choose(&[
+                    Token::Plus, Token::Minus, Token::Minus, Token::Asterisk, Token::Slash, Token::Percent
+                ]).clone();
+                let left_operand = self.generate_expression(Type::Int, depth);
+                // Most divisors are not zero, so that most programs do not stop at an exception
+                let right_operand = if matches!(operator, Token::Slash | Token::Percent) && self.random.chance(70) {
+                    Expression::Number(1 + self.random.below(9))
+                } else {
+                    self.generate_expression(Type::Int, depth)
+                };
+                return Expression::Binary(operator, Box::new(left_operand), Box::new(right_operand));
+            },
+            Type::Bool => {
+                let (operator, operands_type) = match self.random.below(10) {
+                    0 => return Expression::Unary(Token::ExclamationMark, Box::new(self.generate_expression(Type::Bool, depth))),
+                    1..=4 => (self.random.choose(&[
+                        Token::LessThan, Token::LessThanOrEquals, Token::GreaterThan,
+                        Token::GreaterThanOrEquals, Token::Equals, Token::NotEquals
+                    ]).clone(), Type::Int),
+                    5..=7 => (self.random.choose(&[Token::And, Token::Or]).clone(), Type::Bool),
+                    _ => (self.random.choose(&[Token::Equals, Token::NotEquals]).clone(), Type::Bool)
+                };
+                return Expression::Binary(
+                    operator,
+                    Box::new(self.generate_expression(operands_type, depth)),
+                    Box::new(self.generate_expression(operands_type, depth))
+                );
+            }
+        }
+    }
+
+    fn generate_leaf(&mut self, expression_type: Type) -> Expression {
+        if self.random.chance(50) {
+            let mut candidates: Vec<(String, Type)> = self.variables.iter()
+                .filter(|(_, variable_type)| *variable_type == expression_type)
+                .cloned()
+                .collect();
+            if expression_type == Type::Int {
+                candidates.extend(self.loop_counters.iter().map(|counter| (counter.clone(), Type::Int)));
+            }
+            let (identifier, variable_type) = self.random.choose(&candidates).clone();
+            return Expression::Variable(identifier, variable_type);
+        }
+        match expression_type {
+            // Mostly small numbers, and some that overflow when they are added or multiplied
+            Type::Int if self.random.chance(85) => return Expression::Number(self.random.below(10)),
+            Type::Int => return Expression::Number(self.random.below(MAX_NUMBER + 1)),
+            Type::Bool => return Expression::Boolean(self.random.chance(50))
+        }
+    }
+}
+
+// Generates the program of the given seed
+pub fn generate_program(seed: u64) -> GeneratedProgram {
+    let mut generator = ProgramGenerator { random: Random::new(seed), variables: vec![], loop_counters: vec![] };
+    return generator.generate_program();
+}
+
+// The ways the compiler may fail on a generated program
+#[derive(Debug, Clone, PartialEq)]
+pub enum FuzzFailure {
+    // A phase of the compiler, the emulator or the interpreter panicked
+    Panic(String),
+    // The compiler rejected the program, although it is valid
+    Rejected(String),
+    // The program prints something other than the same program with all
+    // its operands parenthesized, so the parser disregards the precedence
+    // or the associativity of the operators
+    ParseMismatch(String),
+    // The generated MIXAL could not be assembled
+    AssemblyFailure(String),
+    // The MIX program failed or did not halt
+    EmulationFailure(String),
+    // The MIX program prints something other than the interpreter
+    OutputMismatch(String)
+}
+
+impl FuzzFailure {
+    // Whether both failures are of the same kind, eg both are panics
+    pub fn is_same_kind(&self, other: &FuzzFailure) -> bool {
+        return discriminant(self) == discriminant(other);
+    }
+}
+
+impl fmt::Display for FuzzFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FuzzFailure::Panic(message) => write!(f, "the compiler panicked: {}", message),
+            FuzzFailure::Rejected(message) => write!(f, "the program was rejected:\n{}", message),
+            FuzzFailure::ParseMismatch(message) => {
+                write!(f, "the program and its parenthesized version print different outputs: {}", message)
+            },
+            FuzzFailure::AssemblyFailure(message) => write!(f, "the generated MIXAL does not assemble: {}", message),
+            FuzzFailure::EmulationFailure(message) => write!(f, "the MIX program failed: {}", message),
+            FuzzFailure::OutputMismatch(message) => {
+                write!(f, "the MIX program and the interpreter print different outputs: {}", message)
+            }
+        }
+    }
+}
+
+// Compiles and runs the program, both in the MIX emulator and in the interpreter
+pub fn check_program(program: &GeneratedProgram) -> Result<(), FuzzFailure> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run_checks(program)));
+    match result {
+        Ok(result) => return result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|x| x.to_string())
+                .or(payload.downcast_ref::<String>().cloned())
+                .unwrap_or(String::from("unknown cause"));
+            return Err(FuzzFailure::Panic(message));
+        }
+    }
+}
+
+fn run_checks(program: &GeneratedProgram) -> Result<(), FuzzFailure> {
+    let compile_to_instructions = |source: &str| {
+        return compile(source, &CompileOptions::default()).map_err(|diagnostics| {
+            let diagnostics: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+            return FuzzFailure::Rejected(diagnostics.join("\n"));
+        });
+    };
+    let artifacts = compile_to_instructions(&program.to_source())?;
+    let parenthesized_artifacts = compile_to_instructions(&program.to_parenthesized_source())?;
+
+    let output = interpret(artifacts.ast.as_ref().expect("to exist"));
+    let parenthesized_output = interpret(parenthesized_artifacts.ast.as_ref().expect("to exist"));
+    if let Some(difference) = find_first_diverging_line(&parenthesized_output, &output) {
+        return Err(FuzzFailure::ParseMismatch(difference));
+    }
+
+    let loaded_program = load_program(artifacts.instructions.as_ref().expect("to exist"))
+        .map_err(FuzzFailure::AssemblyFailure)?;
+    let mut machine = MixMachine::new(&loaded_program);
+    machine.run(MAX_INSTRUCTIONS).map_err(FuzzFailure::EmulationFailure)?;
+    if let Some(difference) = find_first_diverging_line(&output, &machine.output) {
+        return Err(FuzzFailure::OutputMismatch(difference));
+    }
+    return Ok(());
+}
+
+fn interpret(ast: &Node<usize, Token>) -> Vec<String> {
+    let mut interpreter = Interpreter::new(ast);
+    // An exception is part of the output of the program
+    let _ = interpreter.run();
+    return interpreter.output;
+}
+
+// Describes the first line where `actual` differs from `expected`, if any
+pub fn find_first_diverging_line(expected: &[String], actual: &[String]) -> Option<String> {
+    for index in 0..expected.len().max(actual.len()) {
+        let expected_line = expected.get(index).map(|x| format!("'{}'", x)).unwrap_or(String::from("the end of the output"));
+        let actual_line = actual.get(index).map(|x| format!("'{}'", x)).unwrap_or(String::from("the end of the output"));
+        if expected_line != actual_line {
+            return Some(format!("line {}: expected {}, found {}", index + 1, expected_line, actual_line));
+        }
+    }
+    return None;
+}
+
+// Reduces the program while `is_failing` holds for it. The statements
+// are removed or replaced by their blocks, the expressions by their
+// operands or by literals and the unused variables are removed, until
+// none of these reductions keeps the program failing.
+pub fn minimize_program(program: &GeneratedProgram, is_failing: impl Fn(&GeneratedProgram) -> bool) -> GeneratedProgram {
+    let mut program = program.clone();
+    'reduce: loop {
+        for candidate in program_reductions(&program) {
+            if is_failing(&candidate) {
+                program = candidate;
+                continue 'reduce;
+            }
+        }
+        return program;
+    }
+}
+
+fn program_reductions(program: &GeneratedProgram) -> Vec<GeneratedProgram> {
+    let mut reductions: Vec<GeneratedProgram> = statements_reductions(&program.statements).into_iter()
+        .map(|statements| GeneratedProgram { variables: program.variables.clone(), statements })
+        .collect();
+
+    let mut used_variables = HashSet::new();
+    for statement in &program.statements {
+        statement.collect_variables(&mut used_variables);
+    }
+    if used_variables.len() < program.variables.len() {
+        let variables = program.variables.iter()
+            .filter(|(identifier, _)| used_variables.contains(identifier))
+            .cloned()
+            .collect();
+        reductions.push(GeneratedProgram { variables, statements: program.statements.clone() });
+    }
+    return reductions;
+}
+
+// Every reduction of a list of statements reduces a single statement
+fn statements_reductions(statements: &[Statement]) -> Vec<Vec<Statement>> {
+    let mut reductions = vec![];
+    for (index, statement) in statements.iter().enumerate() {
+        // The statement can always be removed
+        let replacements = std::iter::once(vec![]).chain(statement_reductions(statement));
+        for replacement in replacements {
+            let mut reduction = statements[..index].to_vec();
+            reduction.extend(replacement);
+            reduction.extend_from_slice(&statements[index + 1..]);
+            reductions.push(reduction);
+        }
+    }
+    return reductions;
+}
+
+// The lists of statements that may replace `statement`
+fn statement_reductions(statement: &Statement) -> Vec<Vec<Statement>> {
+    let mut reductions = vec![];
+    match statement {
+        Statement::Assignment(identifier, operator, expression) => {
+            if *operator != Token::Assignment {
+                reductions.push(vec![Statement::Assignment(identifier.clone(), Token::Assignment, expression.clone())]);
+            }
+            for expression in expression_reductions(expression) {
+                reductions.push(vec![Statement::Assignment(identifier.clone(), operator.clone(), expression)]);
+            }
+        },
+        Statement::Print(expression) => {
+            for expression in expression_reductions(expression) {
+                reductions.push(vec![Statement::Print(expression)]);
+            }
+        },
+        Statement::If(condition, then_statements, else_statements) => {
+            reductions.push(then_statements.clone());
+            if let Some(else_statements) = else_statements {
+                reductions.push(else_statements.clone());
+                reductions.push(vec![Statement::If(condition.clone(), then_statements.clone(), None)]);
+            }
+            for condition in expression_reductions(condition) {
+                reductions.push(vec![Statement::If(condition, then_statements.clone(), else_statements.clone())]);
+            }
+            for then_statements in statements_reductions(then_statements) {
+                reductions.push(vec![Statement::If(condition.clone(), then_statements, else_statements.clone())]);
+            }
+            for else_statements in else_statements.iter().flat_map(|x| statements_reductions(x)) {
+                reductions.push(vec![Statement::If(condition.clone(), then_statements.clone(), Some(else_statements))]);
+            }
+        },
+        Statement::Loop { kind, counter, iterations, body } => {
+            // Without the loop, a 'break' or a 'continue' of the body would be invalid
+            if !has_loop_jump(body) {
+                reductions.push(body.clone());
+            }
+            if *iterations > 1 {
+                reductions.push(vec![Statement::Loop { kind: *kind, counter: counter.clone(), iterations: 1, body: body.clone() }]);
+            }
+            for body in statements_reductions(body) {
+                reductions.push(vec![Statement::Loop { kind: *kind, counter: counter.clone(), iterations: *iterations, body }]);
+            }
+        },
+        _ => {}
+    }
+    return reductions;
+}
+
+// Whether any of the statements is a 'break' or a 'continue'
+// of the enclosing loop, rather than of a nested one
+fn has_loop_jump(statements: &[Statement]) -> bool {
+    return statements.iter().any(|statement| match statement {
+        Statement::Break | Statement::Continue => true,
+        Statement::If(_, then_statements, else_statements) => {
+            has_loop_jump(then_statements) || else_statements.as_deref().is_some_and(has_loop_jump)
+        },
+        _ => false
+    });
+}
+
+// The expressions of the same type that may replace `expression`
+fn expression_reductions(expression: &Expression) -> Vec<Expression> {
+    let mut reductions = vec![];
+    let literal = match expression.expression_type() {
+        Type::Int => Expression::Number(0),
+        Type::Bool => Expression::Boolean(false)
+    };
+    if *expression != literal {
+        reductions.push(literal);
+    }
+    match expression {
+        Expression::Number(number) if *number > 1 => reductions.push(Expression::Number(1)),
+        Expression::Increment(_, _, identifier) => reductions.push(Expression::Variable(identifier.clone(), Type::Int)),
+        Expression::Unary(operator, operand) => {
+            reductions.push(operand.as_ref().clone());
+            for operand in expression_reductions(operand) {
+                reductions.push(Expression::Unary(operator.clone(), Box::new(operand)));
+            }
+        },
+        Expression::Binary(operator, left_operand, right_operand) => {
+            for operand in [left_operand, right_operand] {
+                if operand.expression_type() == expression.expression_type() {
+                    reductions.push(operand.as_ref().clone());
+                }
+            }
+            for operand in expression_reductions(left_operand) {
+                reductions.push(Expression::Binary(operator.clone(), Box::new(operand), right_operand.clone()));
+            }
+            for operand in expression_reductions(right_operand) {
+                reductions.push(Expression::Binary(operator.clone(), left_operand.clone(), Box::new(operand)));
+            }
+        },
+        Expression::Conditional(condition, then_operand, else_operand) => {
+            reductions.push(then_operand.as_ref().clone());
+            reductions.push(else_operand.as_ref().clone());
+            for operand in expression_reductions(condition) {
+                reductions.push(Expression::Conditional(Box::new(operand), then_operand.clone(), else_operand.clone()));
+            }
+            for operand in expression_reductions(then_operand) {
+                reductions.push(Expression::Conditional(condition.clone(), Box::new(operand), else_operand.clone()));
+            }
+            for operand in expression_reductions(else_operand) {
+                reductions.push(Expression::Conditional(condition.clone(), then_operand.clone(), Box::new(operand)));
+            }
+        },
+        _ => {}
+    }
+    return reductions;
+}
+
+// A generated program that the compiler fails on
+pub struct FuzzCase {
+    pub seed: u64,
+    // The failure of the minimized program
+    pub failure: FuzzFailure,
+    pub program: GeneratedProgram
+}
+
+// Generates and checks the program of the given seed. If the compiler
+// fails on it, the program is minimized, keeping the kind of the failure.
+pub fn fuzz(seed: u64) -> Option<FuzzCase> {
+    let program = generate_program(seed);
+    let failure = check_program(&program).err()?;
+    let program = minimize_program(&program, |candidate| {
+        return check_program(candidate).is_err_and(|x| x.is_same_kind(&failure));
+    });
+    let failure = check_program(&program).expect_err("to still fail");
+    return Some(FuzzCase { seed, failure, program });
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u64) -> Box<Expression> {
+        return Box::new(Expression::Number(value));
+    }
+
+    fn binary(operator: Token, left_operand: Box<Expression>, right_operand: Box<Expression>) -> Box<Expression> {
+        return Box::new(Expression::Binary(operator, left_operand, right_operand));
+    }
+
+    #[test]
+    fn test_expression_source() {
+        let expression = binary(Token::Minus, binary(Token::Minus, number(1), number(2)), number(3));
+        assert_eq!(expression.to_source(false), "1 - 2 - 3");
+        assert_eq!(expression.to_source(true), "(1 - 2) - 3");
+        let expression = binary(Token::Minus, number(1), binary(Token::Minus, number(2), number(3)));
+        assert_eq!(expression.to_source(false), "1 - (2 - 3)");
+        let negated = Box::new(Expression::Unary(Token::Minus, binary(Token::Plus, number(2), number(3))));
+        let expression = binary(Token::Asterisk, negated.clone(), negated);
+        assert_eq!(expression.to_source(false), "-(2 + 3) * (-(2 + 3))");
+        let comparison = binary(Token::LessThan, number(1), binary(Token::Asterisk, number(2), number(3)));
+        let expression = binary(Token::And, comparison, Box::new(Expression::Boolean(true)));
+        assert_eq!(expression.to_source(false), "1 < 2 * 3 && true");
+    }
+
+    #[test]
+    fn test_check_program() {
+        let variable = || Box::new(Expression::Variable(String::from("x0"), Type::Int));
+        let program = GeneratedProgram {
+            variables: vec![(String::from("x0"), Type::Int)],
+            statements: vec![
+                Statement::Assignment(String::from("x0"), Token::Assignment, *number(100)),
+                Statement::Print(*binary(Token::Minus, binary(Token::Minus, number(1), number(2)), number(3))),
+                Statement::Print(*binary(Token::Slash, binary(Token::Slash, variable(), number(5)), number(2))),
+                Statement::Print(*binary(Token::Percent, variable(), binary(Token::Percent, number(7), number(4)))),
+                Statement::Print(*binary(Token::Slash, variable(), number(0)))
+            ]
+        };
+        assert_eq!(check_program(&program), Ok(()));
+    }
+
+    #[test]
+    fn test_generate_program() {
+        assert_eq!(generate_program(7), generate_program(7));
+        assert_ne!(generate_program(7), generate_program(8));
+        let program = generate_program(7);
+        assert!(compile(&program.to_source(), &CompileOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_generated_programs() {
+        for seed in 0..100 {
+            if let Some(case) = fuzz(seed) {
+                panic!("seed {}: {}\n{}", case.seed, case.failure, case.program.to_source());
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimize_program() {
+        let program = generate_program(3);
+        let has_negative_print = |program: &GeneratedProgram| program.statements.iter().any(|statement| {
+            return matches!(statement, Statement::Print(Expression::Unary(Token::Minus, _)));
+        });
+        let mut program_with_negative_print = program.clone();
+        program_with_negative_print.statements.insert(0, Statement::Print(Expression::Unary(
+            Token::Minus,
+            binary(Token::Plus, number(4), Box::new(Expression::Variable(String::from("x0"), Type::Int)))
+        )));
+        let minimized = minimize_program(&program_with_negative_print, has_negative_print);
+        assert_eq!(minimized.to_source(), "{\n    print -0;\n}\n");
+    }
+}
@@ -0,0 +1,532 @@
+use orange_trees::Node;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use crate::lexer::Token;
+use crate::mixal::word::MixWord;
+use crate::utilities::{
+    arithmetic_assignment_operator_to_arithmetic_operator, boolean_literal_to_number,
+    is_assignment_operator, is_postfix_increment_or_decrement
+};
+
+// The code of the exception that the generated code throws
+// when the divisor of a division or a modulo operator is zero.
+pub const DIVISION_BY_ZERO_EXCEPTION: i32 = 1;
+
+// An exception that halted the program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exception {
+    pub code: i32
+}
+
+// What runs after a statement. 'continue' and 'break'
+// carry the label of the loop that they refer to, if any.
+enum Flow {
+    Next,
+    Continue(Option<String>),
+    Break(Option<String>)
+}
+
+// This class executes a program by walking its AST. It is a
+// reference implementation of the code that `MixalAssembler`
+// generates: the values are stored in MIX words, so the arithmetic
+// follows the MIX semantics (including overflows, truncating
+// division and negative zero), and the printed lines are the
+// ones that MIX prints, without their trailing whitespace.
+pub struct Interpreter<'a> {
+    pub ast: &'a Node<usize, Token>,
+    // The current values of the declared variables
+    pub variables: HashMap<String, MixWord>,
+    // The lines that the program has printed
+    pub output: Vec<String>
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(ast: &'a Node<usize, Token>) -> Interpreter<'a> {
+        Interpreter {
+            ast,
+            variables: HashMap::new(),
+            output: vec![]
+        }
+    }
+
+    // Runs the program, which must be semantically valid. If the
+    // program throws an exception, it is printed and returned.
+    pub fn run(&mut self) -> Result<(), Exception> {
+        if let Err(exception) = self.execute_statement(self.ast) {
+            // The generated code prints "EXCEPTION" followed by the
+            // code, with the 5 digits that the 'CHAR' instruction produces
+            self.output.push(format!("EXCEPTION {:05}", exception.code));
+            return Err(exception);
+        }
+        return Ok(());
+    }
+
+    fn execute_statement(&mut self, node: &Node<usize, Token>) -> Result<Flow, Exception> {
+        match node.value() {
+            Token::Ast(_) => {
+                for child in node.children() {
+                    match self.execute_statement(child)? {
+                        Flow::Next => {},
+                        flow => return Ok(flow)
+                    }
+                }
+            },
+            Token::Int | Token::Bool => {
+                for child in node.children() {
+                    if let Token::Id(identifier) = child.value() {
+                        self.variables.insert(identifier.clone(), MixWord::default());
+                    }
+                }
+            },
+            // A constant is a variable that is never assigned again
+            Token::Const => {
+                let children = node.children();
+                let value = self.evaluate_expression(children.get(1).expect("to exist"))?;
+                self.assign(children.get(0).expect("to exist"), value);
+            },
+            Token::Assignment | Token::AdditionAssignment | Token::SubtractionAssignment
+            | Token::MultiplicationAssignment | Token::DivisionAssignment
+            | Token::ModuloAssignment | Token::Increment | Token::Decrement => {
+                self.evaluate_expression(node)?;
+            },
+            Token::If => {
+                let children = node.children();
+                let condition = self.evaluate_expression(children.get(0).expect("to exist"))?;
+                if !condition.is_zero() {
+                    return self.execute_statement(children.get(1).expect("to exist"));
+                } else if let Some(else_node) = children.get(2) {
+                    return self.execute_statement(else_node.children().get(0).expect("to exist"));
+                }
+            },
+            Token::While | Token::For | Token::Do => return self.execute_loop(node, None),
+            Token::Label(label) => return self.execute_loop(node.children().get(0).expect("to exist"), Some(label)),
+            Token::Switch => {
+                let children = node.children();
+                let value = self.evaluate_expression(children.get(0).expect("to exist"))?;
+                let mut matching_case = None;
+                for case_node in &children[1..] {
+                    let labels = &case_node.children()[..case_node.children().len() - 1];
+                    for label in labels {
+                        if self.evaluate_expression(label)?.compare(value) == Ordering::Equal {
+                            matching_case = Some(case_node);
+                            break;
+                        }
+                    }
+                    if matching_case.is_some() {
+                        break;
+                    }
+                }
+                // The 'default' case runs if no label matches, wherever it is written
+                let matching_case = matching_case.or(children[1..].iter().find(|x| *x.value() == Token::Default));
+                if let Some(case_node) = matching_case {
+                    // The cases do not fall through, and 'break' or 'continue'
+                    // refer to the loop that encloses the switch statement
+                    return self.execute_statement(case_node.children().last().expect("to exist"));
+                }
+            },
+            Token::Continue => return Ok(Flow::Continue(get_jump_label(node))),
+            Token::Break => return Ok(Flow::Break(get_jump_label(node))),
+            Token::Print => {
+                let value = self.evaluate_expression(node.children().get(0).expect("to exist"))?;
+                self.output.push(format_printed_value(value));
+            },
+            _ => {}
+        }
+        return Ok(Flow::Next);
+    }
+
+    // Runs the loop modeled by `node`. The 'continue' and 'break' statements
+    // without a label, or with the label of the loop, are handled here, the
+    // others are returned so that they reach the enclosing loops.
+    fn execute_loop(&mut self, node: &Node<usize, Token>, label: Option<&String>) -> Result<Flow, Exception> {
+        let children = node.children();
+        match node.value() {
+            Token::While => {
+                let expression_node = children.get(0).expect("to exist");
+                let code_block_node = children.get(1).expect("to exist");
+                while !self.evaluate_expression(expression_node)?.is_zero() {
+                    match self.execute_statement(code_block_node)? {
+                        Flow::Break(target) if targets_loop(&target, label) => break,
+                        Flow::Continue(target) if targets_loop(&target, label) => {},
+                        Flow::Next => {},
+                        flow => return Ok(flow)
+                    }
+                }
+            },
+            Token::For => {
+                let expression_node = children.get(1).expect("to exist");
+                let statement_node = children.get(2).expect("to exist");
+                let code_block_node = children.get(3).expect("to exist");
+                self.execute_statement(children.get(0).expect("to exist"))?;
+                while !self.evaluate_expression(expression_node)?.is_zero() {
+                    // 'continue' still runs the 3rd part of the loop
+                    match self.execute_statement(code_block_node)? {
+                        Flow::Break(target) if targets_loop(&target, label) => break,
+                        Flow::Continue(target) if targets_loop(&target, label) => {},
+                        Flow::Next => {},
+                        flow => return Ok(flow)
+                    }
+                    self.execute_statement(statement_node)?;
+                }
+            },
+            Token::Do => {
+                let code_block_node = children.get(0).expect("to exist");
+                let expression_node = children.get(1).expect("to exist");
+                loop {
+                    // 'continue' still evaluates the condition
+                    match self.execute_statement(code_block_node)? {
+                        Flow::Break(target) if targets_loop(&target, label) => break,
+                        Flow::Continue(target) if targets_loop(&target, label) => {},
+                        Flow::Next => {},
+                        flow => return Ok(flow)
+                    }
+                    if self.evaluate_expression(expression_node)?.is_zero() {
+                        break;
+                    }
+                }
+            },
+            // The semantic analyzer rejects labels on other statements
+            _ => return self.execute_statement(node)
+        }
+        return Ok(Flow::Next);
+    }
+
+    fn assign(&mut self, identifier_node: &Node<usize, Token>, value: MixWord) {
+        if let Token::Id(identifier) = identifier_node.value() {
+            self.variables.insert(identifier.clone(), value);
+        }
+    }
+
+    fn evaluate_expression(&mut self, node: &Node<usize, Token>) -> Result<MixWord, Exception> {
+        if let Token::Num(number) = boolean_literal_to_number(node.value()) {
+            return Ok(MixWord::from_i64(number as i64));
+        } else if let Token::Id(identifier) = node.value() {
+            return Ok(*self.variables.get(identifier).expect("to exist"));
+        } else if let Token::ExclamationMark = node.value() {
+            let value = self.evaluate_expression(node.children().get(0).expect("to exist"))?;
+            return Ok(boolean_to_word(value.is_zero()));
+        } else if let Token::Increment | Token::Decrement = node.value() {
+            let identifier_node = node.children().get(0).expect("to exist");
+            let value = self.evaluate_expression(identifier_node)?;
+            let increment = MixWord::from_i64(if *node.value() == Token::Increment { 1 } else { -1 });
+            let new_value = value.add(increment).0;
+            self.assign(identifier_node, new_value);
+            return Ok(if is_postfix_increment_or_decrement(node) { value } else { new_value });
+        } else if let Token::QuestionMark = node.value() {
+            // Only the chosen branch is evaluated
+            let children = node.children();
+            let condition = self.evaluate_expression(children.get(0).expect("to exist"))?;
+            let branch = if condition.is_zero() { children.get(2) } else { children.get(1) };
+            return self.evaluate_expression(branch.expect("to exist"));
+        }
+
+        let children = node.children();
+        let left_operand = children.get(0).expect("to exist");
+        let right_operand = children.get(1).expect("to exist");
+        match node.value() {
+            // The value of an assignment is the assigned value
+            Token::Assignment => {
+                let value = self.evaluate_expression(right_operand)?;
+                self.assign(left_operand, value);
+                return Ok(value);
+            },
+            Token::AdditionAssignment | Token::SubtractionAssignment
+            | Token::MultiplicationAssignment | Token::DivisionAssignment
+            | Token::ModuloAssignment => {
+                let operator = arithmetic_assignment_operator_to_arithmetic_operator(node.value().clone());
+                let right_value = self.evaluate_expression(right_operand)?;
+                let left_value = self.evaluate_expression(left_operand)?;
+                let value = Interpreter::apply_operator(&operator, left_value, right_value)?;
+                self.assign(left_operand, value);
+                return Ok(value);
+            },
+            // Short-circuit evaluation
+            Token::And => {
+                let result = !self.evaluate_expression(left_operand)?.is_zero()
+                    && !self.evaluate_expression(right_operand)?.is_zero();
+                return Ok(boolean_to_word(result));
+            },
+            Token::Or => {
+                let result = !self.evaluate_expression(left_operand)?.is_zero()
+                    || !self.evaluate_expression(right_operand)?.is_zero();
+                return Ok(boolean_to_word(result));
+            },
+            operator => {
+                // The generated code evaluates the right operand first
+                let right_value = self.evaluate_expression(right_operand)?;
+                let left_value = self.evaluate_expression(left_operand)?;
+                return Interpreter::apply_operator(operator, left_value, right_value);
+            }
+        }
+    }
+
+    fn apply_operator(operator: &Token, left_value: MixWord, right_value: MixWord) -> Result<MixWord, Exception> {
+        let result = match operator {
+            Token::Plus => left_value.add(right_value).0,
+            Token::Minus => left_value.subtract(right_value).0,
+            // Only the LSBits of the product are kept, along with its sign
+            Token::Asterisk => left_value.multiply(right_value).1,
+            Token::Slash | Token::Percent => {
+                if right_value.is_zero() {
+                    return Err(Exception { code: DIVISION_BY_ZERO_EXCEPTION });
+                }
+                // The dividend is loaded to RX, and RA only keeps its sign
+                let (quotient, remainder) = MixWord::new(left_value.negative, 0)
+                    .divide(left_value, right_value)
+                    .expect("to not overflow");
+                if *operator == Token::Slash { quotient } else { remainder }
+            },
+            Token::Equals => boolean_to_word(left_value.compare(right_value) == Ordering::Equal),
+            Token::NotEquals => boolean_to_word(left_value.compare(right_value) != Ordering::Equal),
+            Token::LessThan => boolean_to_word(left_value.compare(right_value) == Ordering::Less),
+            Token::GreaterThan => boolean_to_word(left_value.compare(right_value) == Ordering::Greater),
+            Token::LessThanOrEquals => boolean_to_word(left_value.compare(right_value) != Ordering::Greater),
+            Token::GreaterThanOrEquals => boolean_to_word(left_value.compare(right_value) != Ordering::Less),
+            _ => panic!("First argument must be a binary operator")
+        };
+        return Ok(result);
+    }
+}
+
+// Returns the label of the 'continue' or 'break' statement `node`
+fn get_jump_label(node: &Node<usize, Token>) -> Option<String> {
+    if let Some(Token::Label(label)) = node.children().first().map(|x| x.value()) {
+        return Some(label.clone());
+    }
+    return None;
+}
+
+// Returns whether a 'continue' or 'break' statement with the
+// label `target` refers to a loop with the label `label`
+fn targets_loop(target: &Option<String>, label: Option<&String>) -> bool {
+    return target.is_none() || target.as_ref() == label;
+}
+
+// Evaluates an expression that only reads the given constants, as
+// the generated code would. Returns `None` if the expression reads
+// a variable, assigns one, or throws an exception (ie divides by zero).
+pub fn evaluate_constant_expression(node: &Node<usize, Token>, constants: &HashMap<String, MixWord>) -> Option<MixWord> {
+    let is_not_constant = |x: &Node<usize, Token>| match x.value() {
+        Token::Id(identifier) => !constants.contains_key(identifier),
+        token => is_assignment_operator(token) || matches!(token, Token::Increment | Token::Decrement)
+    };
+    if !node.find(&is_not_constant).is_empty() {
+        return None;
+    }
+    let mut interpreter = Interpreter::new(node);
+    interpreter.variables = constants.clone();
+    return interpreter.evaluate_expression(node).ok();
+}
+
+// Returns the values of the constants of the program, which must be
+// semantically valid. A constant may be used in the value of the
+// constants that are declared after it.
+pub fn get_constant_values(ast: &Node<usize, Token>) -> HashMap<String, MixWord> {
+    let mut constants = HashMap::new();
+    for constant_node in ast.find(&|x| *x.value() == Token::Const) {
+        let children = constant_node.children();
+        if let (Token::Id(identifier), Some(value)) = (
+            children[0].value(), evaluate_constant_expression(&children[1], &constants)
+        ) {
+            constants.insert(identifier.clone(), value);
+        }
+    }
+    return constants;
+}
+
+fn boolean_to_word(value: bool) -> MixWord {
+    return MixWord::from_i64(value as i64);
+}
+
+// The generated code prints a word with the sign of the value,
+// right-aligned in 5 characters, followed by the 10 digits of its
+// magnitude that the 'CHAR' instruction produces.
+pub fn format_printed_value(value: MixWord) -> String {
+    let sign = if value.negative { '-' } else { '+' };
+    return format!("    {}{:010}", sign, value.magnitude);
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::utilities::get_tokens_from_program;
+
+    fn run_program(program: &str) -> (Vec<String>, Result<(), Exception>) {
+        let mut parser = Parser::new(get_tokens_from_program(program));
+        assert!(parser.analyze_grammar());
+        let mut interpreter = Interpreter::new(&parser.ast);
+        let result = interpreter.run();
+        return (interpreter.output, result);
+    }
+
+    fn printed_values(program: &str) -> Vec<String> {
+        return run_program(program).0.iter().map(|x| x.trim().to_string()).collect();
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let output = printed_values("{
+            var a, b : int;
+            a = 7; b = -2;
+            print a + b; print a - b * 3; print a / b; print a % b; print -a % 2;
+            print 1073741823 + 1; print 65536 * 65536 + 5;
+        }");
+        assert_eq!(output, vec![
+            "+0000000005", "+0000000013", "-0000000003", "+0000000001", "-0000000001",
+            "+0000000000", "+0000000005"
+        ]);
+    }
+
+    #[test]
+    fn test_negative_zero() {
+        let output = printed_values("{ var a : int; print -a; print -a + 0; print -6 % 3; print -(-a); }");
+        assert_eq!(output, vec!["-0000000000", "-0000000000", "-0000000000", "+0000000000"]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let output = printed_values("{
+            var i, sum : int;
+            var done : bool;
+            for (i = 0; i < 10; i += 1) {
+                if (i % 2 == 0) { continue; }
+                if (i > 7 || done) { break; }
+                sum += i;
+            }
+            while (!done) { done = sum >= 16 && true; sum -= 1; }
+            print sum; print i; print done;
+        }");
+        assert_eq!(output, vec!["+0000000015", "+0000000009", "+0000000001"]);
+    }
+
+    #[test]
+    fn test_switch() {
+        let output = printed_values("{
+            var i : int;
+            for (i = 0; i < 6; i += 1) {
+                switch (i - 2) {
+                    default: print 0;
+                    case -2: print 1;
+                    case 1, 2: print 2; if (i == 4) break;
+                    case 3 - 3: print 3; continue;
+                }
+                print 9;
+            }
+        }");
+        assert_eq!(output, vec![
+            "+0000000001", "+0000000009", "+0000000000", "+0000000009", "+0000000003",
+            "+0000000002", "+0000000009", "+0000000002"
+        ]);
+    }
+
+    #[test]
+    fn test_increments_and_assignment_expressions() {
+        let output = printed_values("{
+            var a, b, c : int;
+            var d : bool;
+            a = 5;
+            print a++;
+            print ++a;
+            print a--;
+            print --a;
+            // The right operand is evaluated first
+            print a++ - a;
+            a = b = 3;
+            print a * b;
+            c = 0;
+            while ((c += 1) < 3) print c;
+            print d = !d;
+            b = 1;
+            b += b++;
+            print b;
+        }");
+        assert_eq!(output, vec![
+            "+0000000005", "+0000000007", "+0000000007", "+0000000005", "+0000000000",
+            "+0000000009", "+0000000001", "+0000000002", "+0000000001", "+0000000003"
+        ]);
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        let output = printed_values("{
+            var a, b : int;
+            a = 3;
+            print a > 2 ? 1 : 0;
+            print a < 0 ? -1 : a == 0 ? 0 : 1;
+            // Only the chosen branch is evaluated
+            print a > 0 ? a : a / 0;
+            print a > 0 ? b++ : b--;
+            print b;
+        }");
+        assert_eq!(output, vec!["+0000000001", "+0000000001", "+0000000003", "+0000000000", "+0000000001"]);
+    }
+
+    #[test]
+    fn test_do_while_and_labels() {
+        let output = printed_values("{
+            var i, j : int;
+            do { i += 1; if (i == 2) continue; print i; } while (i < 3);
+            do print 7; while (false);
+            outer: for (i = 0; i < 3; i += 1) {
+                j = 0;
+                inner: while (true) {
+                    j += 1;
+                    if (j == 2) continue outer;
+                    if (i == 2) break outer;
+                    do { break inner; } while (true);
+                }
+                print 8;
+            }
+            print i; print j;
+        }");
+        assert_eq!(output, vec![
+            "+0000000001", "+0000000003", "+0000000007", "+0000000008", "+0000000008",
+            "+0000000002", "+0000000001"
+        ]);
+    }
+
+    #[test]
+    fn test_constant_expressions() {
+        let evaluate = |program: &str| {
+            let mut parser = Parser::new(get_tokens_from_program(program));
+            assert!(parser.analyze_grammar());
+            let print_node = parser.ast.find(&|x| *x.value() == Token::Print)[0].clone();
+            let constants = get_constant_values(&parser.ast);
+            return evaluate_constant_expression(&print_node.children()[0], &constants).map(|x| x.to_i64());
+        };
+        assert_eq!(evaluate("{ print -(7 / 2) * 3 + 1; }"), Some(-8));
+        assert_eq!(evaluate("{ print 1 < 2 && !false; }"), Some(1));
+        assert_eq!(evaluate("{ var a : int; print a * 0; }"), None);
+        assert_eq!(evaluate("{ print 1 / 0; }"), None);
+        assert_eq!(evaluate("{ const N = 3; const M = N * N; print M > N ? M - 1 : 0; }"), Some(8));
+        assert_eq!(evaluate("{ const N = 3; var a : int; const M = a; print M; }"), None);
+        assert_eq!(evaluate("{ var a : int; print (a = 1); }"), None);
+    }
+
+    #[test]
+    fn test_constants() {
+        let output = printed_values("{
+            const N = 4;
+            var i, total : int;
+            const HALF = N / 2;
+            const DONE = false;
+            for (i = 0; i < N; i++) { total += HALF; }
+            print total; print -N; print !DONE;
+        }");
+        assert_eq!(output, vec!["+0000000008", "-0000000004", "+0000000001"]);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let (output, result) = run_program("{ var a : int; print 1; print 1 / a; print 2; }");
+        assert_eq!(output, vec!["    +0000000001", "EXCEPTION 00001"]);
+        assert_eq!(result, Err(Exception { code: DIVISION_BY_ZERO_EXCEPTION }));
+        let (output, _) = run_program("{ var a : int; a %= 0; }");
+        assert_eq!(output, vec!["EXCEPTION 00001"]);
+    }
+}
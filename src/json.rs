@@ -0,0 +1,325 @@
+use std::fmt;
+
+// A minimal model of a JSON value, used for the machine-readable
+// outputs of the compiler and the messages of the language server.
+// Only integer numbers are supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<JsonValue>),
+    // The fields are kept in insertion order, so that
+    // the serialized output is stable.
+    Object(Vec<(String, JsonValue)>)
+}
+
+impl JsonValue {
+    pub fn object(fields: Vec<(&str, JsonValue)>) -> JsonValue {
+        return JsonValue::Object(
+            fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect()
+        );
+    }
+
+    pub fn string(value: &str) -> JsonValue {
+        return JsonValue::String(value.to_string());
+    }
+
+    // Returns the value of the field `key`, if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => return fields.iter().find(|(x, _)| x == key).map(|(_, value)| value),
+            _ => return None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => return Some(value),
+            _ => return None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::Number(value) => return Some(*value),
+            _ => return None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => return Some(*value),
+            _ => return None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(values) => return Some(values),
+            _ => return None
+        }
+    }
+}
+
+// Parses a JSON document. On failure, the returned error
+// describes the problem and its byte offset in the text.
+pub fn parse_json(text: &str) -> Result<JsonValue, String> {
+    let mut parser = JsonParser { text: text.as_bytes(), pos: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.text.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    return Ok(value);
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    pos: usize
+}
+
+impl<'a> JsonParser<'a> {
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            Some(b'{') => return self.parse_object(),
+            Some(b'[') => return self.parse_array(),
+            Some(b'"') => return Ok(JsonValue::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => return self.parse_number(),
+            Some(_) if self.consume_keyword("null") => return Ok(JsonValue::Null),
+            Some(_) if self.consume_keyword("true") => return Ok(JsonValue::Bool(true)),
+            Some(_) if self.consume_keyword("false") => return Ok(JsonValue::Bool(false)),
+            Some(_) => return Err(self.error("unexpected character")),
+            None => return Err(self.error("unexpected end of text"))
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, String> {
+        let mut fields = vec![];
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.consume(b'}') {
+            return Ok(JsonValue::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.text.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if !self.consume(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            fields.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            if self.consume(b'}') {
+                return Ok(JsonValue::Object(fields));
+            }
+            if !self.consume(b',') {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, String> {
+        let mut values = vec![];
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.consume(b']') {
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            if self.consume(b']') {
+                return Ok(JsonValue::Array(values));
+            }
+            if !self.consume(b',') {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        // The opening quote
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let Some(&byte) = self.text.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escaped) = self.text.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let character = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("invalid escape sequence"))
+                    };
+                    bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes());
+                },
+                byte => bytes.push(byte)
+            }
+        }
+        return String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in string"));
+    }
+
+    // Parses the 4 hexadecimal digits after '\u', and the low
+    // surrogate that follows them if they are a high surrogate
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hexadecimal_digits()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or(self.error("invalid unicode escape"));
+        }
+        if !self.text[self.pos..].starts_with(b"\\u") {
+            return Err(self.error("unpaired surrogate"));
+        }
+        self.pos += 2;
+        let low = self.parse_hexadecimal_digits()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        return char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or(self.error("invalid unicode escape"));
+    }
+
+    fn parse_hexadecimal_digits(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or(self.error("invalid unicode escape"))?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.error("invalid unicode escape"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        return Ok(value);
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.pos;
+        self.consume(b'-');
+        while self.text.get(self.pos).is_some_and(|x| x.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.text.get(self.pos).is_some_and(|x| matches!(x, b'.' | b'e' | b'E')) {
+            return Err(self.error("only integer numbers are supported"));
+        }
+        let digits = std::str::from_utf8(&self.text[start..self.pos]).expect("to be ASCII");
+        return digits.parse().map(JsonValue::Number).map_err(|_| self.error("invalid number"));
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.text[self.pos..].starts_with(keyword.as_bytes()) {
+            self.pos += keyword.len();
+            return true;
+        }
+        return false;
+    }
+
+    fn consume(&mut self, byte: u8) -> bool {
+        if self.text.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).is_some_and(|x| matches!(x, b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        return format!("invalid JSON at offset {}: {}", self.pos, message);
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(value) => write!(f, "{}", value),
+            JsonValue::Number(value) => write!(f, "{}", value),
+            JsonValue::String(value) => write_escaped_string(f, value),
+            JsonValue::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            JsonValue::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_escaped_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for character in value.chars() {
+        match character {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?
+        }
+    }
+    write!(f, "\"")
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json() {
+        let value = parse_json(r#" {"id": 1, "params": {"ok": true, "list": [null, -20, "a\"b\u00e9\ud83d\ude00"]}, "x": false} "#).unwrap();
+        assert_eq!(value.get("id"), Some(&JsonValue::Number(1)));
+        assert_eq!(value.get("x").and_then(|x| x.as_bool()), Some(false));
+        let list = value.get("params").and_then(|x| x.get("list")).and_then(|x| x.as_array()).unwrap();
+        assert_eq!(list, &[JsonValue::Null, JsonValue::Number(-20), JsonValue::string("a\"bé😀")]);
+        assert_eq!(parse_json(&value.to_string()), Ok(value));
+        assert_eq!(parse_json("[]"), Ok(JsonValue::Array(vec![])));
+    }
+
+    #[test]
+    fn test_parse_json_errors() {
+        assert_eq!(parse_json("{\"a\" 1}"), Err(String::from("invalid JSON at offset 5: expected ':'")));
+        assert_eq!(parse_json("[1, 2"), Err(String::from("invalid JSON at offset 5: expected ',' or ']'")));
+        assert_eq!(parse_json("1.5"), Err(String::from("invalid JSON at offset 1: only integer numbers are supported")));
+        assert_eq!(parse_json("\"abc"), Err(String::from("invalid JSON at offset 4: unterminated string")));
+        assert_eq!(parse_json("nul"), Err(String::from("invalid JSON at offset 0: unexpected character")));
+        assert_eq!(parse_json("1 2"), Err(String::from("invalid JSON at offset 2: unexpected trailing characters")));
+    }
+}
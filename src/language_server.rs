@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use crate::{lex, parse};
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity, has_errors};
+use crate::json::JsonValue;
+use crate::lexer::{Token, Span};
+use crate::mixal::memory_layout::MemoryLayout;
+use crate::mixal::word::MixWord;
+use crate::semantic_analyzer::SemanticAnalyzer;
+use crate::type_checker::Type;
+
+// The error codes of JSON-RPC and of the Language Server Protocol
+pub const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// The kinds of the protocol for the synchronization of the
+// documents, the severities of the diagnostics and the symbols
+const FULL_TEXT_DOCUMENT_SYNC: i64 = 1;
+const ERROR_SEVERITY: i64 = 1;
+const WARNING_SEVERITY: i64 = 2;
+const VARIABLE_SYMBOL_KIND: i64 = 13;
+const CONSTANT_SYMBOL_KIND: i64 = 14;
+
+// What the compiler knows about an open document
+struct Analysis {
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    // key: a declared variable
+    // value: the type of the variable and the index of the token that declares it
+    declarations: HashMap<String, (Type, usize)>,
+    // key: a declared constant
+    // value: its value, unless it is not a valid constant expression
+    constants: HashMap<String, Option<MixWord>>,
+    // key: a declared variable
+    // value: its MIX memory address, known only if the program is valid
+    addresses: HashMap<String, u16>,
+    diagnostics: Diagnostics
+}
+
+impl Analysis {
+    fn new(source: &str) -> Analysis {
+        let mut analysis = Analysis {
+            tokens: vec![],
+            spans: vec![],
+            declarations: HashMap::new(),
+            constants: HashMap::new(),
+            addresses: HashMap::new(),
+            diagnostics: vec![]
+        };
+        match lex(source) {
+            Ok((tokens, spans)) => {
+                analysis.tokens = tokens;
+                analysis.spans = spans;
+            },
+            Err(diagnostics) => {
+                analysis.diagnostics = diagnostics;
+                return analysis;
+            }
+        }
+        let ast = match parse(analysis.tokens.clone(), &analysis.spans) {
+            Ok(ast) => ast,
+            Err(diagnostics) => {
+                analysis.diagnostics = diagnostics;
+                return analysis;
+            }
+        };
+
+        let mut semantic_analyzer = SemanticAnalyzer::new(&ast, &analysis.spans);
+        analysis.diagnostics = semantic_analyzer.run();
+        // A variable that is declared more than once
+        // is defined by its first declaration
+        let declaration_nodes = ast.find(&|x| Type::from_token(x.value()).is_some() || *x.value() == Token::Const);
+        for declaration_node in declaration_nodes {
+            let is_constant = *declaration_node.value() == Token::Const;
+            // The only identifier of a constant declaration is its first child
+            let identifier_nodes = if is_constant {
+                &declaration_node.children()[..1]
+            } else {
+                declaration_node.children()
+            };
+            for identifier_node in identifier_nodes {
+                if let Token::Id(identifier) = identifier_node.value() {
+                    if analysis.declarations.contains_key(identifier) {
+                        continue;
+                    }
+                    let variable_type = *semantic_analyzer.symbol_table.get(identifier).expect("to exist");
+                    analysis.declarations.insert(identifier.clone(), (variable_type, *identifier_node.id()));
+                    if is_constant {
+                        analysis.constants.insert(identifier.clone(), semantic_analyzer.constants.get(identifier).copied());
+                    }
+                }
+            }
+        }
+        // The analysis stops before the code is generated, since it runs on
+        // every change. The assembler allocates the variables in the order
+        // of their declarations, so their addresses are known without it.
+        if !has_errors(&analysis.diagnostics) {
+            let mut memory_layout = MemoryLayout::default();
+            for declaration_node in ast.find(&|x| Type::from_token(x.value()).is_some()) {
+                for identifier_node in declaration_node.children() {
+                    if let Token::Id(identifier) = identifier_node.value() {
+                        let address = memory_layout.allocate_global(identifier);
+                        analysis.addresses.insert(identifier.clone(), address);
+                    }
+                }
+            }
+        }
+        return analysis;
+    }
+
+    // Returns the index of the identifier token at the given offset, if any.
+    // The offset right after an identifier also points to it.
+    fn find_identifier(&self, offset: usize) -> Option<usize> {
+        return self.spans.iter()
+            .position(|x| x.start <= offset && offset <= x.end)
+            .filter(|index| matches!(self.tokens[*index], Token::Id(_)));
+    }
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis
+}
+
+// This class implements a language server for YAL programs. It handles
+// the messages of the client one at a time and returns the messages to
+// send back: the response, if the message is a request, and any
+// notifications, eg the diagnostics of a document that has changed.
+#[derive(Default)]
+pub struct LanguageServer {
+    // key: the URI of an open document
+    documents: HashMap<String, Document>,
+    is_shut_down: bool,
+    // The status code to exit with, once the client has sent 'exit'
+    pub exit_code: Option<u8>
+}
+
+impl LanguageServer {
+    pub fn handle_message(&mut self, message: &JsonValue) -> Vec<JsonValue> {
+        let method = message.get("method").and_then(|x| x.as_str()).unwrap_or("");
+        let params = message.get("params").unwrap_or(&JsonValue::Null);
+        let Some(id) = message.get("id") else {
+            return self.handle_notification(method, params);
+        };
+
+        if self.is_shut_down {
+            return vec![error_response(id, INVALID_REQUEST, "the server has been shut down")];
+        }
+        let result = match method {
+            "initialize" => Ok(JsonValue::object(vec![
+                ("capabilities", JsonValue::object(vec![
+                    ("textDocumentSync", JsonValue::Number(FULL_TEXT_DOCUMENT_SYNC)),
+                    ("definitionProvider", JsonValue::Bool(true)),
+                    ("referencesProvider", JsonValue::Bool(true)),
+                    ("hoverProvider", JsonValue::Bool(true)),
+                    ("documentSymbolProvider", JsonValue::Bool(true))
+                ])),
+                ("serverInfo", JsonValue::object(vec![("name", JsonValue::string("yal-lsp"))]))
+            ])),
+            "shutdown" => {
+                self.is_shut_down = true;
+                Ok(JsonValue::Null)
+            },
+            "textDocument/definition" => self.find_definition(params),
+            "textDocument/references" => self.find_references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.list_document_symbols(params),
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, &format!("unknown method '{}'", method))]
+        };
+        match result {
+            Ok(result) => return vec![JsonValue::object(vec![
+                ("jsonrpc", JsonValue::string("2.0")),
+                ("id", id.clone()),
+                ("result", result)
+            ])],
+            Err(message) => return vec![error_response(id, INVALID_PARAMS, &message)]
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        let uri = params.get("textDocument").and_then(|x| x.get("uri")).and_then(|x| x.as_str());
+        match (method, uri) {
+            ("exit", _) => {
+                // The client must request a shutdown before the exit
+                self.exit_code = Some(if self.is_shut_down { 0 } else { 1 });
+            },
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params.get("textDocument").and_then(|x| x.get("text")).and_then(|x| x.as_str());
+                return self.update_document(uri, text.unwrap_or(""));
+            },
+            ("textDocument/didChange", Some(uri)) => {
+                // The documents are synchronized in full, so the last change has the whole text
+                let changes = params.get("contentChanges").and_then(|x| x.as_array()).unwrap_or(&[]);
+                if let Some(text) = changes.last().and_then(|x| x.get("text")).and_then(|x| x.as_str()) {
+                    return self.update_document(uri, text);
+                }
+            },
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, vec![])];
+            },
+            _ => {}
+        }
+        return vec![];
+    }
+
+    fn update_document(&mut self, uri: &str, text: &str) -> Vec<JsonValue> {
+        let document = Document { text: text.to_string(), analysis: Analysis::new(text) };
+        let diagnostics = document.analysis.diagnostics.iter()
+            .map(|x| diagnostic_to_json(text, x))
+            .collect();
+        self.documents.insert(uri.to_string(), document);
+        return vec![publish_diagnostics(uri, diagnostics)];
+    }
+
+    // Returns the document and the index of the identifier token at the
+    // position of the request, or `None` if there is no identifier there
+    fn find_identifier<'a>(&'a self, params: &'a JsonValue) -> Result<Option<(&'a str, &'a Document, usize)>, String> {
+        let uri = params.get("textDocument").and_then(|x| x.get("uri")).and_then(|x| x.as_str())
+            .ok_or(String::from("missing 'textDocument.uri'"))?;
+        let document = self.documents.get(uri).ok_or(format!("the document '{}' is not open", uri))?;
+        let position = params.get("position").ok_or(String::from("missing 'position'"))?;
+        let (Some(line), Some(character)) = (
+            position.get("line").and_then(|x| x.as_i64()),
+            position.get("character").and_then(|x| x.as_i64())
+        ) else {
+            return Err(String::from("invalid 'position'"));
+        };
+        let offset = position_to_offset(&document.text, line as usize, character as usize);
+        return Ok(offset
+            .and_then(|offset| document.analysis.find_identifier(offset))
+            .map(|index| (uri, document, index)));
+    }
+
+    fn find_definition(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let Some((uri, document, index)) = self.find_identifier(params)? else {
+            return Ok(JsonValue::Null);
+        };
+        let analysis = &document.analysis;
+        let Token::Id(identifier) = &analysis.tokens[index] else { unreachable!() };
+        match analysis.declarations.get(identifier) {
+            Some((_, declaration_index)) => return Ok(location(uri, &document.text, &analysis.spans[*declaration_index])),
+            None => return Ok(JsonValue::Null)
+        }
+    }
+
+    fn find_references(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let Some((uri, document, index)) = self.find_identifier(params)? else {
+            return Ok(JsonValue::Null);
+        };
+        let analysis = &document.analysis;
+        let identifier = &analysis.tokens[index];
+        let include_declaration = params.get("context")
+            .and_then(|x| x.get("includeDeclaration"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(true);
+        let declaration_index = match identifier {
+            Token::Id(identifier) => analysis.declarations.get(identifier).map(|(_, x)| *x),
+            _ => None
+        };
+        let references = analysis.tokens.iter().enumerate()
+            .filter(|(index, token)| *token == identifier && (include_declaration || Some(*index) != declaration_index))
+            .map(|(index, _)| location(uri, &document.text, &analysis.spans[index]))
+            .collect();
+        return Ok(JsonValue::Array(references));
+    }
+
+    // Shows the type of the variable and where it is stored in the MIX memory
+    fn hover(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let Some((_, document, index)) = self.find_identifier(params)? else {
+            return Ok(JsonValue::Null);
+        };
+        let analysis = &document.analysis;
+        let Token::Id(identifier) = &analysis.tokens[index] else { unreachable!() };
+        let Some((variable_type, _)) = analysis.declarations.get(identifier) else {
+            return Ok(JsonValue::Null);
+        };
+        let mut contents = match analysis.constants.get(identifier) {
+            Some(Some(value)) if *variable_type == Type::Bool => {
+                format!("```yal\nconst {} = {};\n```", identifier, !value.is_zero())
+            },
+            Some(Some(value)) => format!("```yal\nconst {} = {};\n```", identifier, value.to_i64()),
+            // The value could not be evaluated, there is an error
+            Some(None) => format!("```yal\nconst {}\n```", identifier),
+            None => format!("```yal\nvar {} : {};\n```", identifier, variable_type)
+        };
+        if let Some(address) = analysis.addresses.get(identifier) {
+            contents.push_str(&format!("\nStored at the MIX memory address {}", address));
+        }
+        return Ok(JsonValue::object(vec![
+            ("contents", JsonValue::object(vec![
+                ("kind", JsonValue::string("markdown")),
+                ("value", JsonValue::String(contents))
+            ])),
+            ("range", range(&document.text, &analysis.spans[index]))
+        ]));
+    }
+
+    // Lists the declared variables and constants of the document
+    fn list_document_symbols(&self, params: &JsonValue) -> Result<JsonValue, String> {
+        let uri = params.get("textDocument").and_then(|x| x.get("uri")).and_then(|x| x.as_str())
+            .ok_or(String::from("missing 'textDocument.uri'"))?;
+        let document = self.documents.get(uri).ok_or(format!("the document '{}' is not open", uri))?;
+        let mut declarations: Vec<(&String, &(Type, usize))> = document.analysis.declarations.iter().collect();
+        declarations.sort_by_key(|(_, (_, index))| *index);
+        let symbols = declarations.into_iter().map(|(identifier, (variable_type, index))| {
+            let range = range(&document.text, &document.analysis.spans[*index]);
+            return JsonValue::object(vec![
+                ("name", JsonValue::String(identifier.clone())),
+                ("detail", JsonValue::String(variable_type.to_string())),
+                ("kind", JsonValue::Number(if document.analysis.constants.contains_key(identifier.as_str()) {
+                    CONSTANT_SYMBOL_KIND
+                } else {
+                    VARIABLE_SYMBOL_KIND
+                })),
+                ("range", range.clone()),
+                ("selectionRange", range)
+            ]);
+        }).collect();
+        return Ok(JsonValue::Array(symbols));
+    }
+}
+
+fn error_response(id: &JsonValue, code: i64, message: &str) -> JsonValue {
+    return JsonValue::object(vec![
+        ("jsonrpc", JsonValue::string("2.0")),
+        ("id", id.clone()),
+        ("error", JsonValue::object(vec![
+            ("code", JsonValue::Number(code)),
+            ("message", JsonValue::string(message))
+        ]))
+    ]);
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<JsonValue>) -> JsonValue {
+    return JsonValue::object(vec![
+        ("jsonrpc", JsonValue::string("2.0")),
+        ("method", JsonValue::string("textDocument/publishDiagnostics")),
+        ("params", JsonValue::object(vec![
+            ("uri", JsonValue::string(uri)),
+            ("diagnostics", JsonValue::Array(diagnostics))
+        ]))
+    ]);
+}
+
+fn diagnostic_to_json(text: &str, diagnostic: &Diagnostic) -> JsonValue {
+    let span = diagnostic.span.unwrap_or_default();
+    let severity = match diagnostic.severity {
+        Severity::Error => ERROR_SEVERITY,
+        Severity::Warning => WARNING_SEVERITY
+    };
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+    return JsonValue::object(vec![
+        ("range", range(text, &span)),
+        ("severity", JsonValue::Number(severity)),
+        ("code", JsonValue::string(diagnostic.code)),
+        ("source", JsonValue::string("yal")),
+        ("message", JsonValue::String(message))
+    ]);
+}
+
+fn location(uri: &str, text: &str, span: &Span) -> JsonValue {
+    return JsonValue::object(vec![("uri", JsonValue::string(uri)), ("range", range(text, span))]);
+}
+
+fn range(text: &str, span: &Span) -> JsonValue {
+    let position = |offset: usize| {
+        let (line, character) = offset_to_position(text, offset);
+        return JsonValue::object(vec![
+            ("line", JsonValue::Number(line as i64)),
+            ("character", JsonValue::Number(character as i64))
+        ]);
+    };
+    return JsonValue::object(vec![("start", position(span.start)), ("end", position(span.end))]);
+}
+
+// Converts a byte offset of the text to a position of the protocol, which
+// has a 0-based line and a 0-based character counted in UTF-16 code units
+pub fn offset_to_position(text: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |x| x + 1);
+    let line = text[..line_start].matches('\n').count();
+    let character = text[line_start..offset].chars().map(|x| x.len_utf16()).sum();
+    return (line, character);
+}
+
+// The inverse of `offset_to_position`. A character past the end
+// of its line is the end of the line, as the protocol requires.
+pub fn position_to_offset(text: &str, line: usize, character: usize) -> Option<usize> {
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let line_text = text[line_start..].split('\n').next().unwrap_or("");
+    let mut utf16_count = 0;
+    for (offset, c) in line_text.char_indices() {
+        if utf16_count >= character {
+            return Some(line_start + offset);
+        }
+        utf16_count += c.len_utf16();
+    }
+    return Some(line_start + line_text.len());
+}
+
+// Reads the content of the next message of the base protocol, which is
+// a 'Content-Length' header, possibly other headers, an empty line and
+// the content. Returns `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<String>, String> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        let bytes_read = reader.read_line(&mut header).map_err(|error| format!("could not read a header: {}", error))?;
+        if bytes_read == 0 {
+            if content_length.is_none() {
+                return Ok(None);
+            }
+            return Err(String::from("unexpected end of input in the headers"));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                let length = value.trim().parse().map_err(|_| format!("invalid content length '{}'", value.trim()))?;
+                content_length = Some(length);
+            }
+        }
+    }
+    let content_length = content_length.ok_or(String::from("missing 'Content-Length' header"))?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content).map_err(|error| format!("could not read a message: {}", error))?;
+    return String::from_utf8(content).map(Some).map_err(|_| String::from("the message is not valid UTF-8"));
+}
+
+pub fn write_message(writer: &mut impl Write, content: &str) -> io::Result<()> {
+    write!(writer, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    return writer.flush();
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, CompileOptions};
+
+    #[test]
+    fn test_positions() {
+        let text = "{ var é : int;\n  é = 1; }";
+        assert_eq!(offset_to_position(text, 6), (0, 6));
+        assert_eq!(offset_to_position(text, 10), (0, 9));
+        assert_eq!(offset_to_position(text, 20), (1, 3));
+        assert_eq!(position_to_offset(text, 0, 9), Some(10));
+        assert_eq!(position_to_offset(text, 1, 3), Some(20));
+        assert_eq!(position_to_offset(text, 1, 100), Some(text.len()));
+        assert_eq!(position_to_offset(text, 2, 0), None);
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut input = "Content-Length: 2\r\nContent-Type: json\r\n\r\n{}Content-Length: 4\r\n\r\nnull".as_bytes();
+        assert_eq!(read_message(&mut input), Ok(Some(String::from("{}"))));
+        assert_eq!(read_message(&mut input), Ok(Some(String::from("null"))));
+        assert_eq!(read_message(&mut input), Ok(None));
+        let mut output = vec![];
+        write_message(&mut output, "{}").unwrap();
+        assert_eq!(output, b"Content-Length: 2\r\n\r\n{}");
+    }
+
+    #[test]
+    fn test_analysis() {
+        let analysis = Analysis::new("{ var a, b : int; var c : bool; b = 1; print b; }");
+        assert_eq!(analysis.declarations.get("b"), Some(&(Type::Int, 4)));
+        assert_eq!(analysis.declarations.get("c"), Some(&(Type::Bool, 9)));
+        assert_eq!(analysis.addresses.get("c"), Some(&3));
+        assert_eq!(analysis.find_identifier(10), Some(4));
+        assert_eq!(analysis.find_identifier(14), None);
+        let analysis = Analysis::new("{ var a : int; a = true; }");
+        assert!(has_errors(&analysis.diagnostics));
+        assert!(analysis.addresses.is_empty());
+    }
+
+    // The addresses are computed without generating the code,
+    // so they must match the ones of the generated code
+    #[test]
+    fn test_addresses() {
+        let source = "{ var a, b : int; var c : bool; var d : int; \
+            for (a = 0; a < 3; a = a + 1) { d = a * 2; b = b + d; } c = b > 2; print b; }";
+        let analysis = Analysis::new(source);
+        let artifacts = compile(source, &CompileOptions::default()).unwrap();
+        let globals = &artifacts.memory_layout.as_ref().unwrap().globals;
+        assert_eq!(globals.len(), 4);
+        for (name, address) in globals {
+            assert_eq!(analysis.addresses.get(name), Some(address), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let analysis = Analysis::new("{ const N = 4; const B = N > 2; var a : int; a = N; print a; }");
+        assert_eq!(analysis.declarations.get("B"), Some(&(Type::Bool, 7)));
+        assert_eq!(analysis.constants.get("N"), Some(&Some(MixWord::from_i64(4))));
+        assert_eq!(analysis.constants.get("B"), Some(&Some(MixWord::from_i64(1))));
+        assert_eq!(analysis.constants.get("a"), None);
+        // Constants take no memory
+        assert_eq!(analysis.addresses.get("N"), None);
+        assert_eq!(analysis.addresses.get("a"), Some(&1));
+    }
+}
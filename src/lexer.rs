@@ -1,8 +1,10 @@
 use logos::{Lexer, Logos};
+use crate::json::JsonValue;
 
 // Definition of the language's tokens.
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(skip r"[ \t\n]+")] // Ignore this regex pattern between tokens
+#[logos(skip r"//[^\n]*")] // Ignore the comments, which last until the end of the line
 pub enum Token {
     #[token("print")]
     Print,
@@ -14,10 +16,18 @@ pub enum Token {
     While,
     #[token("for")]
     For,
+    #[token("do")]
+    Do,
     #[token("continue")]
     Continue,
     #[token("break")]
     Break,
+    #[token("switch")]
+    Switch,
+    #[token("case")]
+    Case,
+    #[token("default")]
+    Default,
 
     #[token("(")]
     LeftParen,
@@ -30,8 +40,12 @@ pub enum Token {
 
     #[token("var")]
     Var,
+    #[token("const")]
+    Const,
     #[token("int")]
     Int,
+    #[token("bool")]
+    Bool,
 
     #[token(",")]
     Comma,
@@ -68,6 +82,11 @@ pub enum Token {
     #[token("%=")]
     ModuloAssignment,
 
+    #[token("++")]
+    Increment,
+    #[token("--")]
+    Decrement,
+
     #[token("==")]
     Equals,
     #[token("!=")]
@@ -85,6 +104,13 @@ pub enum Token {
     And,
     #[token("||")]
     Or,
+    #[token("?")]
+    QuestionMark,
+
+    #[token("true")]
+    True,
+    #[token("false")]
+    False,
 
     #[regex("[a-zA-Z]([a-zA-Z]|[0-9]|_)*", to_string)]
     Id(String),
@@ -95,7 +121,34 @@ pub enum Token {
     // We use it as a value in AST nodes
     // in order to group together tokens,
     // for example blocks of statements
-    Ast(String)    
+    Ast(String),
+    // The following variant is not a token either.
+    // It is the value of the AST nodes that model
+    // the label of a loop, eg 'outer' in `outer: while`,
+    // so that labels are never taken for variables.
+    Label(String)
+}
+
+// The location of a token in the source code. The `start` and `end`
+// fields are byte offsets in the source code, while `line` and `column`
+// are 1-based and point to the first character of the token.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize
+}
+
+impl Span {
+    pub fn to_json(&self) -> JsonValue {
+        return JsonValue::object(vec![
+            ("start", JsonValue::Number(self.start as i64)),
+            ("end", JsonValue::Number(self.end as i64)),
+            ("line", JsonValue::Number(self.line as i64)),
+            ("column", JsonValue::Number(self.column as i64))
+        ]);
+    }
 }
 
 fn to_string(lex: &mut Lexer<Token>) -> Option<String> {
@@ -104,5 +157,5 @@ fn to_string(lex: &mut Lexer<Token>) -> Option<String> {
 }
 
 fn to_num(lex: &mut Lexer<Token>) -> Option<i32> {
-    Some(lex.slice().parse().ok()?)
+    lex.slice().parse().ok()
 }
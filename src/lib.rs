@@ -0,0 +1,237 @@
+// The code base favours explicit `return` statements and accessing
+// the children of AST nodes by position, e.g. `children.get(0)`.
+#![allow(clippy::needless_return, clippy::get_first)]
+
+// The compiler of YAL programs to MIXAL. Each phase of the compiler
+// is exposed by its own module, and `compile` runs all of them.
+// The library never prints or spawns processes; the `mixal-compiler`
+// executable is responsible for the files and the MIX tools.
+
+pub mod parser;
+pub mod lexer;
+pub mod utilities;
+pub mod mixal;
+pub mod semantic_analyzer;
+pub mod type_checker;
+pub mod control_flow_graph;
+pub mod dataflow_analyzer;
+pub mod diagnostics;
+pub mod json;
+pub mod interpreter;
+pub mod fuzzer;
+pub mod formatter;
+pub mod language_server;
+pub mod debugger;
+pub mod profiler;
+pub mod repl;
+
+use orange_trees::Node;
+use crate::lexer::{Token, Span};
+use crate::parser::Parser;
+use crate::semantic_analyzer::SemanticAnalyzer;
+use crate::mixal::{assembler::MixalAssembler, instruction::MixalInstruction};
+use crate::mixal::utilities::{instructions_to_annotated_mixal, instructions_to_mixal, instructions_to_source_listing};
+use crate::mixal::memory_layout::MemoryLayout;
+use crate::mixal::{flow_graph::FlowGraph, mnemonic::MixalMnemonic};
+use crate::debugger::DebugInfo;
+use crate::diagnostics::{Diagnostic, Diagnostics, has_errors};
+use crate::utilities::get_tokens_and_spans_from_program;
+
+// The phases of the compiler, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Lexing,
+    Parsing,
+    SemanticAnalysis,
+    CodeGeneration
+}
+
+pub struct CompileOptions {
+    // The last phase to run. The artifacts of the
+    // phases after it are not produced.
+    pub stop_after: Stage,
+    // Where the generated program is placed in the MIX memory
+    pub memory_layout: MemoryLayout,
+    // Whether the invariant expressions of the loops are computed before
+    // them, and the products of their variables are turned into additions
+    pub optimize_loops: bool
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        CompileOptions { stop_after: Stage::CodeGeneration, memory_layout: MemoryLayout::default(), optimize_loops: true }
+    }
+}
+
+// The outputs of the phases of the compiler that have run
+pub struct Artifacts {
+    pub tokens: Vec<Token>,
+    // The span of the token `tokens[i]` is stored in `spans[i]`
+    pub spans: Vec<Span>,
+    pub ast: Option<Node<usize, Token>>,
+    // The warnings about the program, which does not have any errors
+    pub diagnostics: Diagnostics,
+    pub instructions: Option<Vec<MixalInstruction>>,
+    // The memory layout of the generated program, along with how much of every region it uses
+    pub memory_layout: Option<MemoryLayout>
+}
+
+impl Artifacts {
+    // Returns the generated program as the text of a MIXAL source file
+    pub fn mixal(&self) -> Option<String> {
+        return self.instructions.as_deref().map(instructions_to_mixal);
+    }
+
+    // Same as `mixal`, but the instructions of every line of the
+    // program, whose text is `source`, are preceded by that line
+    pub fn annotated_mixal(&self, source: &str) -> Option<String> {
+        return self.instructions.as_deref().map(|x| instructions_to_annotated_mixal(x, source, &self.spans));
+    }
+
+    // Returns the address of every word of the generated program,
+    // along with the line and column of its statement
+    pub fn source_listing(&self) -> Option<String> {
+        return self.instructions.as_deref().map(|x| instructions_to_source_listing(x, &self.spans));
+    }
+
+    // Returns the control flow graph of the code of the generated
+    // program, which follows its data and ends before 'END'
+    pub fn flow_graph(&self) -> Option<FlowGraph> {
+        let instructions = self.instructions.as_ref()?;
+        let code_start = instructions.iter().rposition(|x| x.mnemonic == MixalMnemonic::ORIG).expect("to exist") + 1;
+        return Some(FlowGraph::new(instructions[code_start..instructions.len() - 1].to_vec()));
+    }
+
+    // Returns what a debugger needs to map the generated program to the YAL program
+    pub fn debug_info(&self) -> Option<DebugInfo> {
+        return match (&self.instructions, &self.ast, &self.memory_layout) {
+            (Some(instructions), Some(ast), Some(memory_layout)) => {
+                Some(DebugInfo::new(instructions, ast, &self.spans, memory_layout))
+            },
+            _ => None
+        };
+    }
+}
+
+// Compiles the YAL program in `source`. If the program has errors, all
+// the diagnostics of the first phase that found an error are returned.
+pub fn compile(source: &str, options: &CompileOptions) -> Result<Artifacts, Diagnostics> {
+    let (tokens, spans) = lex(source)?;
+    let mut artifacts = Artifacts {
+        tokens, spans, ast: None, diagnostics: vec![], instructions: None, memory_layout: None
+    };
+    if options.stop_after == Stage::Lexing {
+        return Ok(artifacts);
+    }
+
+    let ast = parse(artifacts.tokens.clone(), &artifacts.spans)?;
+    if options.stop_after >= Stage::SemanticAnalysis {
+        artifacts.diagnostics = analyze(&ast, &artifacts.spans)?;
+    }
+    if options.stop_after == Stage::CodeGeneration {
+        let (instructions, memory_layout) = generate(&ast, &options.memory_layout, options.optimize_loops)?;
+        artifacts.instructions = Some(instructions);
+        artifacts.memory_layout = Some(memory_layout);
+    }
+    artifacts.ast = Some(ast);
+    return Ok(artifacts);
+}
+
+pub fn lex(source: &str) -> Result<(Vec<Token>, Vec<Span>), Diagnostics> {
+    return get_tokens_and_spans_from_program(source).map_err(|diagnostic| vec![diagnostic]);
+}
+
+pub fn parse(tokens: Vec<Token>, spans: &[Span]) -> Result<Node<usize, Token>, Diagnostics> {
+    let mut parser = Parser::new(tokens);
+    if !parser.analyze_grammar() {
+        return Err(vec![parser.syntax_error(spans)]);
+    }
+    return Ok(parser.ast);
+}
+
+// Runs the semantic checks and returns the warnings about the program
+pub fn analyze(ast: &Node<usize, Token>, spans: &[Span]) -> Result<Diagnostics, Diagnostics> {
+    let mut semantic_analyzer = SemanticAnalyzer::new(ast, spans);
+    let diagnostics: Vec<Diagnostic> = semantic_analyzer.run();
+    if has_errors(&diagnostics) {
+        return Err(diagnostics);
+    }
+    return Ok(diagnostics);
+}
+
+// Generates the MIXAL instructions of a valid program. Fails if the
+// program does not fit in the regions of the memory layout.
+pub fn generate(
+    ast: &Node<usize, Token>,
+    memory_layout: &MemoryLayout,
+    optimize_loops: bool
+) -> Result<(Vec<MixalInstruction>, MemoryLayout), Diagnostics> {
+    let mut assembler = MixalAssembler::new(ast.clone(), memory_layout.clone());
+    assembler.optimize_loops = optimize_loops;
+    assembler.run();
+    let overflows = assembler.memory_layout.overflows();
+    if !overflows.is_empty() {
+        return Err(overflows);
+    }
+    return Ok((assembler.instructions, assembler.memory_layout));
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixal::memory_layout::Region;
+
+    #[test]
+    fn test_compile() {
+        let artifacts = compile("{ var a : int; a = 1; print a; }", &CompileOptions::default()).unwrap();
+        assert_eq!(artifacts.tokens.len(), artifacts.spans.len());
+        assert!(artifacts.ast.is_some());
+        assert!(artifacts.diagnostics.is_empty());
+        let mixal = artifacts.mixal().unwrap();
+        assert!(mixal.starts_with("TEMP EQU 0\n"));
+        assert!(mixal.contains(" ORIG 1\nA CON 0\n ORIG 2000\n"));
+        assert!(mixal.contains(" STA A(0:5)\n"));
+        assert!(mixal.ends_with(" END 2000\n"));
+        let annotated_mixal = artifacts.annotated_mixal("{ var a : int; a = 1; print a; }").unwrap();
+        assert!(annotated_mixal.contains(" ORIG 2000\n* 1: { var a : int; a = 1; print a; }\n ENTA 1\n"));
+        assert_eq!(annotated_mixal.matches("* 1:").count(), 1);
+
+        let options = CompileOptions { stop_after: Stage::Parsing, ..CompileOptions::default() };
+        let artifacts = compile("{ var a : int; b = 1; }", &options).unwrap();
+        assert!(artifacts.ast.is_some());
+        assert!(artifacts.instructions.is_none());
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let diagnostics = compile("{ var a : int; b = 1; }", &CompileOptions::default()).err().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, diagnostics::UNDECLARED_IDENTIFIER);
+        let diagnostics = compile("{ var a : int", &CompileOptions::default()).err().unwrap();
+        assert_eq!(diagnostics[0].code, diagnostics::SYNTAX_ERROR);
+    }
+
+    #[test]
+    fn test_memory_overflow() {
+        let source = "{ var a, b, c : int; a = 1; print a * (b + c * (a - b)); }";
+        let artifacts = compile(source, &CompileOptions::default()).unwrap();
+        let memory_layout = artifacts.memory_layout.unwrap();
+        assert_eq!(memory_layout.used(Region::Globals), 3);
+        assert_eq!(memory_layout.used(Region::Stack), 3);
+
+        let mut options = CompileOptions::default();
+        options.memory_layout.set_base(Region::Stack, 3);
+        options.memory_layout.set_base(Region::Constants, 4);
+        options.memory_layout.set_base(Region::Code, 3990);
+        let diagnostics = compile(source, &options).err().unwrap();
+        let messages: Vec<&str> = diagnostics.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].starts_with("the globals region overflows: the program needs 3 words, but it has 2"));
+        assert!(messages[1].starts_with("the stack region overflows"));
+        assert!(messages[2].starts_with("the code region overflows"));
+    }
+}
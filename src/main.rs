@@ -1,57 +1,336 @@
-mod parser;
-mod lexer;
-mod utilities;
-mod mixal;
+// The code base favours explicit `return` statements and accessing
+// the children of AST nodes by position, e.g. `children.get(0)`.
+#![allow(clippy::needless_return, clippy::get_first)]
+
 mod files_handler;
-mod semantic_analyzer;
+mod cli;
+mod mix_toolchain;
+
+use mixal_compiler::{compile, Artifacts, CompileOptions, Stage};
+use mixal_compiler::debugger::Debugger;
+use mixal_compiler::profiler::profile_program;
+use mixal_compiler::repl::Repl;
+use mixal_compiler::mixal::loader::load_program;
+use mixal_compiler::mixal::utilities::instructions_to_listing;
+use mixal_compiler::utilities::{ast_to_dot, ast_to_json, ast_to_string, tokens_to_json};
+use mixal_compiler::interpreter::Interpreter;
+use mixal_compiler::fuzzer::fuzz;
+use mixal_compiler::diagnostics::{Diagnostic, INVALID_TOKEN, MEMORY_OVERFLOW, SYNTAX_ERROR};
+use crate::mix_toolchain::{assemble_mixal_file, run_mix_binary_file_and_print_output};
+use crate::files_handler::{FilesHandler, STANDARD_STREAM_PATH};
+use crate::cli::{parse_arguments, CliOptions, CliRequest, DumpFormat, EmitStage, FuzzOptions, MessageFormat, Subcommand, USAGE};
+use std::{env, fs, io::{self, Write}, panic, path::{Path, PathBuf}, process::ExitCode};
+
+// The status codes that the compiler exits with
+const EXIT_INVALID_PROGRAM: u8 = 1;
+const EXIT_INVALID_ARGUMENTS: u8 = 2;
+const EXIT_IO_ERROR: u8 = 3;
+
+// The reasons for which a compilation may fail
+enum Failure {
+    // The program has errors, which have already been reported
+    InvalidProgram,
+    // A file could not be read or written, or an external tool failed
+    Io(String)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_arguments(&args) {
+        Ok(CliRequest::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Ok(CliRequest::Compile(options)) => options,
+        Ok(CliRequest::Fuzz(options)) => return run_fuzzer(&options),
+        Ok(CliRequest::Repl) => match run_repl() {
+            Ok(()) => return ExitCode::SUCCESS,
+            Err(message) => {
+                eprintln!("error: {}", message);
+                return ExitCode::from(EXIT_IO_ERROR);
+            }
+        },
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(EXIT_INVALID_ARGUMENTS);
+        }
+    };
+
+    match run_command(&options) {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(Failure::InvalidProgram) => return ExitCode::from(EXIT_INVALID_PROGRAM),
+        Err(Failure::Io(message)) => {
+            eprintln!("error: {}", message);
+            return ExitCode::from(EXIT_IO_ERROR);
+        }
+    }
+}
+
+// Runs the phases of the compiler that are needed for the given options.
+// The progress and the diagnostics are printed to stderr, so that
+// stdout only contains the requested output.
+fn run_command(options: &CliOptions) -> Result<(), Failure> {
+    let message_format = options.message_format;
+    let print_progress = |message: &str| {
+        if message_format == MessageFormat::Human {
+            eprintln!("{}", message);
+        }
+    };
+    let emit = if options.subcommand == Subcommand::Build { Some(options.emit) } else { None };
+    let file_handler = FilesHandler::new(&options.input, options.output.as_deref()).map_err(Failure::Io)?;
+
+    let stop_after = match (options.subcommand, emit) {
+        (Subcommand::Check, _) => Stage::SemanticAnalysis,
+        (Subcommand::Run, _) if options.interpret => Stage::SemanticAnalysis,
+        (_, Some(EmitStage::Tokens(_))) => Stage::Lexing,
+        (_, Some(EmitStage::Ast(_))) => Stage::Parsing,
+        _ => Stage::CodeGeneration
+    };
+
+    print_progress("------------------------------------");
+
+    let compile_options = CompileOptions {
+        stop_after,
+        memory_layout: options.memory_layout.clone(),
+        optimize_loops: options.optimize_loops
+    };
+    let artifacts = match compile(&file_handler.yal_source_code, &compile_options) {
+        Ok(artifacts) => artifacts,
+        Err(diagnostics) => {
+            print_diagnostics(&diagnostics, &message_format);
+            let is_syntax_error = diagnostics.iter().any(|x| x.code == INVALID_TOKEN || x.code == SYNTAX_ERROR);
+            if is_syntax_error {
+                print_progress("Parsing failed");
+            } else if diagnostics.iter().all(|x| x.code == MEMORY_OVERFLOW) {
+                print_progress("Parsing successful");
+                print_progress("All semantic checks passed");
+                print_progress("The program does not fit in the MIX memory");
+            } else {
+                print_progress("Parsing successful");
+                print_progress("Some semantic checks failed");
+            }
+            return Err(Failure::InvalidProgram);
+        }
+    };
+    if let Some(EmitStage::Tokens(format)) = emit {
+        let tokens: String = match format {
+            DumpFormat::Json => format!("{}\n", tokens_to_json(&artifacts.tokens, &artifacts.spans)),
+            _ => artifacts.tokens.iter().map(|x| format!("{:?}\n", x)).collect()
+        };
+        return write_output(tokens.as_bytes(), text_output_path(options, &file_handler));
+    }
+    print_progress("Parsing successful");
+    if let Some(EmitStage::Ast(format)) = emit {
+        let ast = artifacts.ast.as_ref().expect("to exist");
+        let ast = match format {
+            DumpFormat::Text => ast_to_string(ast),
+            DumpFormat::Json => format!("{}\n", ast_to_json(ast, &artifacts.spans)),
+            DumpFormat::Dot => ast_to_dot(ast, &artifacts.spans)
+        };
+        return write_output(ast.as_bytes(), text_output_path(options, &file_handler));
+    }
+    print_diagnostics(&artifacts.diagnostics, &message_format);
+    print_progress("All semantic checks passed");
+    if options.subcommand == Subcommand::Check {
+        return Ok(());
+    }
+    if options.interpret {
+        print_progress("------------------------------------");
+        let mut interpreter = Interpreter::new(artifacts.ast.as_ref().expect("to exist"));
+        // An exception is part of the output of the program, as in MIX
+        let _ = interpreter.run();
+        let output: String = interpreter.output.iter().map(|x| format!("{}\n", x)).collect();
+        write_output(output.as_bytes(), None)?;
+        print_progress("------------------------------------");
+        return Ok(());
+    }
 
-use crate::mixal::utilities::run_mix_binary_file_and_print_output;
-use crate::{utilities::get_tokens_from_program, mixal::assembler::MixalAssembler, files_handler::FilesHandler};
-use crate::parser::Parser;
-use crate::semantic_analyzer::SemanticAnalyzer;
-use std::env;
+    if options.profile {
+        let (output, profile) = profile_program(&artifacts)
+            .map_err(|error| Failure::Io(format!("could not run the program in the MIX emulator: {}", error)))?;
+        print_progress("------------------------------------");
+        let output: String = output.iter().map(|x| format!("{}\n", x)).collect();
+        write_output(output.as_bytes(), None)?;
+        print_progress("------------------------------------");
+        // The report goes to stderr, like the memory map
+        eprint!("{}", profile.report(&file_handler.yal_source_code));
+        if let Some(profile_output) = &options.profile_output {
+            let annotated_source = profile.annotated_source(&file_handler.yal_source_code);
+            FilesHandler::write_output_file(Path::new(profile_output), annotated_source.as_bytes())
+                .map_err(Failure::Io)?;
+        }
+        return Ok(());
+    }
+    if options.subcommand == Subcommand::Debug {
+        return run_debugger(&artifacts, &file_handler.yal_source_code);
+    }
 
-fn main() {
+    let instructions = artifacts.instructions.as_ref().expect("to exist");
+    let mixal = match options.annotate {
+        true => artifacts.annotated_mixal(&file_handler.yal_source_code),
+        false => artifacts.mixal()
+    }.expect("to exist");
+    // The memory map is a report, like the diagnostics, so it never mixes with the output
+    if options.memory_map {
+        eprint!("{}", artifacts.memory_layout.as_ref().expect("to exist").memory_map());
+    }
+    if let Some(listing) = &options.listing {
+        let source_listing = artifacts.source_listing().expect("to exist");
+        FilesHandler::write_output_file(Path::new(listing), source_listing.as_bytes()).map_err(Failure::Io)?;
+    }
+    match emit {
+        Some(EmitStage::Cfg) => {
+            let dot = artifacts.flow_graph().expect("to exist").to_dot();
+            return write_output(dot.as_bytes(), text_output_path(options, &file_handler));
+        },
+        Some(EmitStage::Ir) => {
+            let listing = instructions_to_listing(instructions);
+            return write_output(listing.as_bytes(), text_output_path(options, &file_handler));
+        },
+        Some(EmitStage::Mixal) => {
+            return write_output(mixal.as_bytes(), text_output_path(options, &file_handler));
+        },
+        _ => {}
+    }
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Please provide a YAL source code file path as an argument.");
-        return;
+    FilesHandler::write_output_file(&file_handler.mixal_output_file_path, mixal.as_bytes())
+        .map_err(Failure::Io)?;
+    assemble_mixal_file(&file_handler.mixal_output_file_path).map_err(Failure::Io)?;
+    let debug_info = artifacts.debug_info().expect("to exist").to_json();
+    FilesHandler::write_output_file(&file_handler.debug_info_output_file_path, format!("{}\n", debug_info).as_bytes())
+        .map_err(Failure::Io)?;
+    match options.output.as_deref() {
+        Some(STANDARD_STREAM_PATH) => {
+            let mix = fs::read(&file_handler.mix_output_file_path)
+                .map_err(|error| Failure::Io(format!(
+                    "could not read '{}': {}", file_handler.mix_output_file_path.display(), error
+                )))?;
+            return write_output(&mix, None);
+        },
+        // `mixasm` names the executable after the MIXAL file, so
+        // it is renamed if another name has been requested
+        Some(output) if Path::new(output) != file_handler.mix_output_file_path => {
+            fs::rename(&file_handler.mix_output_file_path, output)
+                .map_err(|error| Failure::Io(format!("could not write '{}': {}", output, error)))?;
+            print_progress(&format!("Created the MIX executable file at {}", output));
+        },
+        _ => print_progress(&format!(
+            "Created the MIX executable file at {}", file_handler.mix_output_file_path.display()
+        ))
     }
-    let file_handler = FilesHandler::new(&args[1]);
 
-    println!("------------------------------------");
+    print_progress("------------------------------------");
 
-    let tokens = get_tokens_from_program(&file_handler.yal_source_code);
-    let mut parser = Parser::new(tokens);
-    if parser.analyze_grammar() {
-        println!("Parsing successful");
-    } else {
-        println!("Parsing failed");
-        return;
+    if options.subcommand == Subcommand::Run {
+        let mix_output_file_path = options.output.as_ref()
+            .map(PathBuf::from)
+            .unwrap_or(file_handler.mix_output_file_path.clone());
+        run_mix_binary_file_and_print_output(&mix_output_file_path).map_err(Failure::Io)?;
+        print_progress("------------------------------------");
     }
+    return Ok(());
+}
 
-    let mut semantic_checker = SemanticAnalyzer::new(&parser.ast);
-    if semantic_checker.run() {
-        println!("All semantic checks passed");
-    } else {
-        println!("Some semantic checks failed");
-        return;
+// Runs the compiled program in the debugger, which reads its commands
+// from stdin until they end or the user quits
+fn run_debugger(artifacts: &Artifacts, source: &str) -> Result<(), Failure> {
+    let program = load_program(artifacts.instructions.as_ref().expect("to exist"))
+        .map_err(|error| Failure::Io(format!("could not load the MIX program: {}", error)))?;
+    let mut debugger = Debugger::new(&program, artifacts.debug_info().expect("to exist"), source);
+    eprintln!("Type 'help' for the commands of the debugger");
+    loop {
+        print!("(yaldb) ");
+        io::stdout().flush().map_err(|error| Failure::Io(format!("could not write to stdout: {}", error)))?;
+        let mut command = String::new();
+        let read_bytes = io::stdin().read_line(&mut command)
+            .map_err(|error| Failure::Io(format!("could not read from stdin: {}", error)))?;
+        if read_bytes == 0 || matches!(command.trim(), "q" | "quit") {
+            println!();
+            return Ok(());
+        }
+        match debugger.execute(&command) {
+            Ok(output) => print!("{}", output),
+            Err(message) => println!("error: {}", message)
+        }
     }
+}
 
-    let mut assembler = MixalAssembler::new(
-        parser.ast.clone(), 
-        file_handler.mixal_output_file_path.clone()
-    );
-    assembler.run();
+// Runs the declarations and the statements read from stdin until they end
+// or the user quits. An input continues on the next lines while it has
+// more opening braces than closing ones, eg the body of a loop.
+fn run_repl() -> Result<(), String> {
+    let mut repl = Repl::new();
+    eprintln!("Type ':help' for the commands of the REPL");
+    loop {
+        let mut input = String::new();
+        loop {
+            print!("{}", if input.is_empty() { "yal> " } else { "...  " });
+            io::stdout().flush().map_err(|error| format!("could not write to stdout: {}", error))?;
+            let read_bytes = io::stdin().read_line(&mut input)
+                .map_err(|error| format!("could not read from stdin: {}", error))?;
+            if read_bytes == 0 {
+                println!();
+                return Ok(());
+            }
+            if input.matches('{').count() <= input.matches('}').count() {
+                break;
+            }
+        }
+        if matches!(input.trim(), ":q" | ":quit") {
+            return Ok(());
+        }
+        match repl.evaluate(&input) {
+            Ok(output) => print!("{}", output),
+            Err(message) => println!("{}", message)
+        }
+    }
+}
 
-    println!("Created the MIX executable file at {}", file_handler.mix_output_file_path);
+// Checks the random programs of the requested seeds and prints the minimized
+// version of each program that the compiler fails on. Exits with the status
+// of an invalid program if there is any, since the compiler has a bug.
+fn run_fuzzer(options: &FuzzOptions) -> ExitCode {
+    // The panics are reported along with the programs that cause them
+    panic::set_hook(Box::new(|_| {}));
+    let mut failures_count = 0;
+    for seed in options.seed..options.seed + options.count {
+        if let Some(case) = fuzz(seed) {
+            failures_count += 1;
+            println!("seed {}: {}\n{}", case.seed, case.failure, case.program.to_source());
+        }
+    }
+    eprintln!("{} of {} random programs failed", failures_count, options.count);
+    if failures_count > 0 {
+        return ExitCode::from(EXIT_INVALID_PROGRAM);
+    }
+    return ExitCode::SUCCESS;
+}
+
+// The path to write the output of a textual stage to, or `None` for stdout.
+// Only the MIXAL file is written to a file when no output path is provided.
+fn text_output_path(options: &CliOptions, file_handler: &FilesHandler) -> Option<PathBuf> {
+    match options.output.as_deref() {
+        Some(STANDARD_STREAM_PATH) => return None,
+        Some(output) => return Some(PathBuf::from(output)),
+        None if options.emit.writes_to_stdout_by_default() => return None,
+        None => return Some(file_handler.mixal_output_file_path.clone())
+    }
+}
 
-    println!("------------------------------------");
+// Writes the output of a stage to the file at `output_path`, or to stdout
+fn write_output(content: &[u8], output_path: Option<PathBuf>) -> Result<(), Failure> {
+    match output_path {
+        Some(output_path) => return FilesHandler::write_output_file(&output_path, content).map_err(Failure::Io),
+        None => return io::stdout().write_all(content)
+            .map_err(|error| Failure::Io(format!("could not write to stdout: {}", error)))
+    }
+}
 
-    if args.len() > 2 && args[2] == "--run" {
-        run_mix_binary_file_and_print_output(&file_handler.mix_output_file_path);
-        println!("------------------------------------");
+fn print_diagnostics(diagnostics: &[Diagnostic], message_format: &MessageFormat) {
+    for diagnostic in diagnostics {
+        match message_format {
+            MessageFormat::Human => eprintln!("{}", diagnostic),
+            MessageFormat::Json => eprintln!("{}", diagnostic.to_json())
+        }
     }
-    
 }
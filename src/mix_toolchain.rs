@@ -0,0 +1,35 @@
+use std::path::Path;
+use std::process::Command;
+
+// Wrappers around the executables of the GNU MIX Development
+// Kit, which must be installed for these functions to work.
+
+// Runs `mixasm` for the MIXAL file at the given path. The
+// MIX executable file is created next to it, with the '.mix' extension.
+pub fn assemble_mixal_file(file_path: &Path) -> Result<(), String> {
+    let output = Command::new("mixasm")
+        .arg(file_path)
+        .output()
+        .map_err(|error| format!("could not run 'mixasm': {}", error))?;
+    if !output.status.success() {
+        return Err(format!(
+            "'mixasm' failed for '{}': {}",
+            file_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    return Ok(());
+}
+
+pub fn run_mix_binary_file_and_print_output(file_path: &Path) -> Result<(), String> {
+    let output = Command::new("mixvm")
+        .arg("--run")
+        .arg(file_path)
+        .output()
+        .map_err(|error| format!("could not run 'mixvm': {}", error))?;
+    output.stdout.iter().for_each(|x| print!("{}", *x as char));
+    if !output.status.success() {
+        return Err(format!("'mixvm' failed for '{}'", file_path.display()));
+    }
+    return Ok(());
+}
@@ -1,56 +1,212 @@
 use orange_trees::Node;
-use std::{fs::File, io::Write};
-use std::process::Command;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::lexer::Token;
-use crate::utilities::arithmetic_assignment_operator_to_arithmetic_operator;
+use crate::interpreter::{evaluate_constant_expression, get_constant_values};
+use crate::mixal::word::MixWord;
+use crate::utilities::{
+    arithmetic_assignment_operator_to_arithmetic_operator, boolean_literal_to_number,
+    is_postfix_increment_or_decrement, new_node_from_token, new_synthetic_node
+};
 use super::{instruction::*, mnemonic::*, register::*, utilities::*};
-
-const PROGRAM_INSTRUCTIONS_ALLOCATION_ADDRESS: u16 = 2000;
-// The following is defined in MIX specs. Measured in words.
-const STANDARD_OUTPUT_DEVICE_BLOCK_SIZE: u16 = 14;
+use super::{loader::mnemonic_to_opcode, memory_layout::{MemoryLayout, Region}, flow_graph::FlowGraph};
+use super::loop_optimizer::LoopOptimizer;
+
+// A switch statement is compiled to a jump table when it has at least
+// this many case labels, and the labels fill at least half of the range
+// between the smallest and the largest one. Otherwise, the value of the
+// switch is compared with every label in turn.
+const JUMP_TABLE_MIN_LABELS: usize = 4;
+// The largest number of entries of a jump table, ie of values between the
+// smallest and the largest label. The table must also fit in the words
+// of the code region that are left.
+const JUMP_TABLE_MAX_ENTRIES: usize = 256;
+
+// The symbols that the generated MIXAL defines for the base addresses
+// of the regions whose words are not named after what they hold
+const REGION_SYMBOLS: [(&str, Region); 3] = [
+    ("TEMP", Region::Temporaries), ("STACK", Region::Stack), ("OUTBUF", Region::IoBuffers)
+];
 
 pub struct MixalAssembler {
     pub ast: Node<usize, Token>,
-    pub output_file_path: String,
-    file: File,
-    vtable: HashMap<String, u16>,
-    next_memory_address_to_allocate: u16,
-    // The standard output device block is a block of memory that
-    // we allocate at the beginning of the program. We use that 
-    // block to store the characters that we want to print to the
-    // standard output device. The reason we allocate it at the
-    // beginning of the program is because we want to make sure
-    // that it is always available for use and it will not be 
-    // polluted with data from other parts of the program.
-    standard_output_device_block_memory_address: u16,
-    loop_stack: Vec<(String,String)>
+    // The generated instructions, in the order they are emitted
+    pub instructions: Vec<MixalInstruction>,
+    // key: a declared variable
+    // value: the MIX memory address where the variable is stored
+    pub vtable: HashMap<String, u16>,
+    // key: a variable that the loop optimizations have added
+    // value: the address of the word of the stack that holds it
+    loop_values: HashMap<String, u16>,
+    // Where the variables, the intermediate values, the
+    // output block and the instructions are placed
+    pub memory_layout: MemoryLayout,
+    // The loops that enclose the current statement, innermost last.
+    // Each one has its label, if any, and the labels of the
+    // instructions that 'continue' and 'break' jump to
+    loop_stack: Vec<(Option<String>, String, String)>,
+    // The label of the loop that is about to be assembled
+    pending_loop_label: Option<String>,
+    // The id of the node of the innermost statement that is being
+    // assembled, which the emitted instructions are attributed to
+    current_statement: Option<usize>,
+    // key: the address of a variable or of a word of the pool of constants
+    // value: the MIXAL symbol of the word, which the operands refer to
+    data_symbols: HashMap<u16, String>,
+    // The CON and ALF directives of the values that do not fit in
+    // the operand of an instruction, in the order of their addresses
+    constant_pool: Vec<MixalInstruction>,
+    // key: the mnemonic and the operand of a directive of the pool
+    // value: the address of the word that holds it
+    constant_addresses: HashMap<(MixalMnemonic, String), u16>,
+    // The number of labels of instructions created so far. The labels
+    // are numbered, eg 'L1', so that the generated code is always the same.
+    labels_count: usize,
+    // The labels and the symbols of the data words that are already
+    // used, to avoid duplicates, eg a variable named 'l1'
+    used_labels: HashSet<String>,
+    // Whether the loops are optimized before the code is generated
+    pub optimize_loops: bool
 }
 
 impl MixalAssembler {
-    pub fn new(ast: Node<usize, Token>, output_file_path: String) -> MixalAssembler{
+    pub fn new(ast: Node<usize, Token>, memory_layout: MemoryLayout) -> MixalAssembler{
+        // Constants take no memory, their uses become immediate values
+        let constants = get_constant_values(&ast);
         MixalAssembler {
-            ast,
-            output_file_path: output_file_path.clone(),
-            file: File::create(output_file_path).expect("to be created"),
-            vtable: HashMap::new(),            
-            // we purposely start this from 1 to save address 0 for 'temp',
-            // as some operations may need to allocate to memory temporarily
-            next_memory_address_to_allocate: 1,
-            // we allocate the standard output device block at the end of the address space.
-            standard_output_device_block_memory_address: PROGRAM_INSTRUCTIONS_ALLOCATION_ADDRESS - STANDARD_OUTPUT_DEVICE_BLOCK_SIZE,
-            loop_stack: vec![]
+            ast: inline_constants(&ast, &constants),
+            instructions: vec![],
+            vtable: HashMap::new(),
+            loop_values: HashMap::new(),
+            memory_layout,
+            loop_stack: vec![],
+            pending_loop_label: None,
+            current_statement: None,
+            data_symbols: HashMap::new(),
+            constant_pool: vec![],
+            constant_addresses: HashMap::new(),
+            labels_count: 0,
+            used_labels: REGION_SYMBOLS.iter().map(|(symbol, _)| symbol.to_string()).collect(),
+            optimize_loops: true
         }
     }
 
     pub fn run(&mut self) {
-        self.instruction_set_instructions_allocation_address(PROGRAM_INSTRUCTIONS_ALLOCATION_ADDRESS);
+        let code_address = self.memory_layout.base(Region::Code);
+        if self.optimize_loops {
+            let mut loop_optimizer = LoopOptimizer::new();
+            self.ast = loop_optimizer.run(&self.ast);
+            // The values live as long as the program, so they take the bottom
+            // of the stack, and are left out of the variables of the program
+            for variable in loop_optimizer.variables {
+                let address = self.memory_layout.push_stack();
+                self.loop_values.insert(variable, address);
+            }
+        }
         self.handle_root(self.ast.clone());        
-        self.instruction_end_program(PROGRAM_INSTRUCTIONS_ALLOCATION_ADDRESS);
-        Command::new("mixasm").arg(self.output_file_path.clone()).output().expect("to execute");
+        self.instruction_halt();
+        // The code that never runs and the jumps that are not needed are
+        // removed from the control flow graph of the generated code
+        let mut flow_graph = FlowGraph::new(std::mem::take(&mut self.instructions));
+        flow_graph.optimize();
+        let code = flow_graph.instructions();
+        // Every instruction takes a word, unlike the directives of the assembler
+        let code_size = code.iter().filter(|x| mnemonic_to_opcode(&x.mnemonic).is_some()).count();
+        self.memory_layout.set_code_size(code_size as u16);
+        // The data is placed before the code, since the
+        // pool of constants is only known once it is assembled
+        self.instructions = self.data_instructions();
+        self.instruction_set_instructions_allocation_address(code_address);
+        self.instructions.extend(code);
+        self.instruction_end_program(code_address);
+    }
+
+    // The directives that define the symbols of the regions, the
+    // variables, which start at zero, and the pool of constants
+    fn data_instructions(&self) -> Vec<MixalInstruction> {
+        let mut instructions = vec![];
+        for (symbol, region) in REGION_SYMBOLS {
+            instructions.push(MixalInstruction::new(
+                Some(symbol.to_string()),
+                MixalMnemonic::EQU,
+                Some(self.memory_layout.base(region).to_string())
+            ));
+        }
+        if !self.memory_layout.globals.is_empty() {
+            instructions.push(MixalInstruction::new(
+                None,
+                MixalMnemonic::ORIG,
+                Some(self.memory_layout.base(Region::Globals).to_string())
+            ));
+            for (_, address) in &self.memory_layout.globals {
+                let symbol = self.data_symbols.get(address).expect("to exist");
+                instructions.push(MixalInstruction::new(Some(symbol.clone()), MixalMnemonic::CON, Some(String::from("0"))));
+            }
+        }
+        if !self.constant_pool.is_empty() {
+            instructions.push(MixalInstruction::new(
+                None,
+                MixalMnemonic::ORIG,
+                Some(self.memory_layout.base(Region::Constants).to_string())
+            ));
+            instructions.extend(self.constant_pool.iter().cloned());
+        }
+        return instructions;
+    }
+
+    // The address of the word that an operator can use
+    // for its operands while it evaluates its result
+    fn scratch_address(&self) -> u16 {
+        return self.memory_layout.scratch_address();
+    }
+
+    // The address of a variable of the program or of the loop optimizations
+    fn variable_address(&self, identifier: &str) -> u16 {
+        if let Some(address) = self.loop_values.get(identifier) {
+            return *address;
+        }
+        return *self.vtable.get(identifier).expect("to exist");
+    }
+
+    // The operand that refers to the address in the generated MIXAL: the
+    // symbol of the variable or constant stored there, or the symbol of
+    // its region followed by the offset of the address in the region
+    fn address_operand(&self, address: u16) -> String {
+        if let Some(symbol) = self.data_symbols.get(&address) {
+            return symbol.clone();
+        }
+        for (symbol, region) in REGION_SYMBOLS {
+            let base = self.memory_layout.base(region);
+            if (base..self.memory_layout.end(region)).contains(&address) {
+                return match address - base {
+                    0 => symbol.to_string(),
+                    offset => format!("{}+{}", symbol, offset)
+                };
+            }
+        }
+        return address.to_string();
+    }
+
+    // Returns the address of the word of the pool of constants that
+    // holds the operand of the directive, adding the word if needed
+    fn constant_address(&mut self, mnemonic: MixalMnemonic, operand: String) -> u16 {
+        let key = (mnemonic, operand.clone());
+        if let Some(address) = self.constant_addresses.get(&key) {
+            return *address;
+        }
+        let address = self.memory_layout.allocate_constant();
+        let symbol = mangle_symbol(&format!("K{}", self.constant_pool.len() + 1), &self.used_labels);
+        self.used_labels.insert(symbol.clone());
+        self.data_symbols.insert(address, symbol.clone());
+        self.constant_addresses.insert(key, address);
+        self.constant_pool.push(MixalInstruction::new(Some(symbol), mnemonic, Some(operand)));
+        return address;
     }
 
     fn handle_root(&mut self, node: Node<usize, Token>) {        
+        let enclosing_statement = self.current_statement;
+        if !matches!(node.value(), Token::Ast(_)) {
+            self.current_statement = Some(*node.id());
+        }
         match node.value() {
             Token::Ast(_) => {
                 let children = node.children();
@@ -58,7 +214,7 @@ impl MixalAssembler {
                     self.handle_root(child.clone());
                 }
             },
-            Token::Int => {
+            Token::Int | Token::Bool => {
                 self.handle_variable_declaration(node.clone());
             },
             Token::Assignment => {
@@ -69,6 +225,9 @@ impl MixalAssembler {
             | Token::ModuloAssignment => {
                 self.handle_arithmetic_assignment_operator(node.clone())
             },
+            Token::Increment | Token::Decrement => {
+                self.handle_increment_or_decrement(node.clone(), false);
+            },
             Token::If => {
                 self.handle_if_statement(node.clone());
             },
@@ -78,12 +237,22 @@ impl MixalAssembler {
             Token::For => {
                 self.handle_for_loop(node.clone());
             },
+            Token::Do => {
+                self.handle_do_while_loop(node.clone());
+            },
+            Token::Label(label) => {
+                self.pending_loop_label = Some(label.clone());
+                self.handle_root(node.children().get(0).expect("to exist").clone());
+            },
+            Token::Switch => {
+                self.handle_switch_statement(node.clone());
+            },
             Token::Continue => {
-                let continue_label = self.loop_stack.last().expect("to exist").0.clone();
+                let continue_label = self.get_target_loop(&node).1.clone();
                 self.instruction_jump_to_label(continue_label);
             },
             Token::Break => {
-                let break_label = self.loop_stack.last().expect("to exist").1.clone();
+                let break_label = self.get_target_loop(&node).2.clone();
                 self.instruction_jump_to_label(break_label);
             },
             Token::Print => {
@@ -91,17 +260,19 @@ impl MixalAssembler {
             }            
             _ => {}
         }
+        self.current_statement = enclosing_statement;
     }
 
     fn handle_variable_declaration(&mut self, node: Node<usize, Token>) {
         let children = node.children();
         for child in children {
-            let memory_address_to_allocate = self.next_memory_address_to_allocate;
-            self.instruction_store_zero_to_address(memory_address_to_allocate);
             if let Token::Id(identifier) = child.value() {
+                let memory_address_to_allocate = self.memory_layout.allocate_global(identifier);
+                let symbol = mangle_symbol(identifier, &self.used_labels);
+                self.used_labels.insert(symbol.clone());
+                self.data_symbols.insert(memory_address_to_allocate, symbol);
                 self.vtable.insert(identifier.clone(), memory_address_to_allocate);
             }
-            self.next_memory_address_to_allocate += 1;
         }
     }
 
@@ -112,7 +283,7 @@ impl MixalAssembler {
         
         let identifier_token = children.get(0).expect("to exist").value();
         if let Token::Id(identifier) = identifier_token {
-            let identifier_memory_address = self.vtable.get(identifier).expect("to exist").clone();
+            let identifier_memory_address = self.variable_address(identifier);
             self.instruction_store_register_to_address(
                 identifier_memory_address,
                 MixalRegister::RA
@@ -139,29 +310,63 @@ impl MixalAssembler {
         self.handle_assignment_operator(new_assignment_node);
     }
 
+    // Adds 1 to (or subtracts 1 from) the variable of the `++` (or `--`)
+    // node, and loads the value of the expression to register RA: the
+    // new value of the variable, or the old one for a postfix operator
+    // whose value is used. The addition does not need a memory operand,
+    // since the 'INC' and 'DEC' instructions add their address.
+    fn handle_increment_or_decrement(&mut self, node: Node<usize, Token>, is_value_used: bool) {
+        let increment = if *node.value() == Token::Increment { 1 } else { -1 };
+        let identifier_token = node.children().get(0).expect("to exist").value();
+        if let Token::Id(identifier) = identifier_token {
+            let identifier_memory_address = self.variable_address(identifier);
+            if is_value_used && is_postfix_increment_or_decrement(&node) {
+                // The old value stays in RA, and the new one is computed in RX
+                self.instruction_load_address_to_register(identifier_memory_address, MixalRegister::RA);
+                self.instruction_load_address_to_register(identifier_memory_address, MixalRegister::RX);
+                self.instruction_increment_register(increment, MixalRegister::RX);
+                self.instruction_store_register_to_address(identifier_memory_address, MixalRegister::RX);
+            } else {
+                self.instruction_load_address_to_register(identifier_memory_address, MixalRegister::RA);
+                self.instruction_increment_register(increment, MixalRegister::RA);
+                self.instruction_store_register_to_address(identifier_memory_address, MixalRegister::RA);
+            }
+        }
+    }
+
     fn handle_if_statement(&mut self, node: Node<usize, Token>) {
+        let bottom_label = self.new_instruction_label();
+        self.handle_if_chain(node, bottom_label.clone());
+        self.instruction_nop_with_label(bottom_label);
+    }
+
+    // Generates an if statement, which jumps to 'bottom_label' after its
+    // blocks. The if statements of an 'else if' chain share the same
+    // bottom label, instead of jumping from one to the next.
+    fn handle_if_chain(&mut self, node: Node<usize, Token>, bottom_label: String) {
         let children = node.children();
         let expression_node = children.get(0).expect("to exist");
-        self.handle_expression_node(expression_node.clone());
-
-        let else_label = get_random_instruction_label();
-        let bottom_label = get_random_instruction_label();
-        
-        self.instruction_store_zero_to_address(0);
-        self.instruction_compare_ra(0);
-        self.instruction_jump_to_label_if_comparison_was_true(Token::Equals, else_label.clone());
         let block_node = children.get(1).expect("to exist");
+        let Some(else_node) = children.get(2) else {
+            self.instructions_jump_to_label_if_condition_is(expression_node, false, bottom_label);
+            self.handle_root(block_node.clone());
+            return;
+        };
+
+        let else_label = self.new_instruction_label();
+        self.instructions_jump_to_label_if_condition_is(expression_node, false, else_label.clone());
         self.handle_root(block_node.clone());
         self.instruction_jump_to_label(bottom_label.clone());
 
-        self.instruction_nop_with_label(else_label.clone());
-        if children.len() == 3 {
-            let else_node = children.get(2).expect("to exist");
-            let else_block_node = else_node.children().get(0).expect("to exist");
-            self.handle_root(else_block_node.clone());
+        self.instruction_nop_with_label(else_label);
+        let else_block_node = else_node.children().get(0).expect("to exist");
+        match else_block_node.children().get(0) {
+            Some(statement) if *else_block_node.value() == Token::Ast(String::from("SINGLE_BLOCK"))
+                && *statement.value() == Token::If => {
+                self.handle_if_chain(statement.clone(), bottom_label);
+            },
+            _ => self.handle_root(else_block_node.clone())
         }
-
-        self.instruction_nop_with_label(bottom_label.clone());
     }
 
     fn handle_while_loop(&mut self, node: Node<usize, Token>) {
@@ -169,19 +374,17 @@ impl MixalAssembler {
         let expression_node = children.get(0).expect("to exist");
         let code_block_node = children.get(1).expect("to exist");
 
-        let evaluate_expression_label = get_random_instruction_label();
-        let exit_loop_label = get_random_instruction_label();
+        let evaluate_expression_label = self.new_instruction_label();
+        let exit_loop_label = self.new_instruction_label();
 
         self.loop_stack.push((
+            self.pending_loop_label.take(),
             evaluate_expression_label.clone(),
             exit_loop_label.clone()
         ));
 
         self.instruction_nop_with_label(evaluate_expression_label.clone());
-        self.handle_expression_node(expression_node.clone());
-        self.instruction_store_zero_to_address(0);
-        self.instruction_compare_ra(0);
-        self.instruction_jump_to_label_if_comparison_was_true(Token::Equals, exit_loop_label.clone());
+        self.instructions_jump_to_label_if_condition_is(expression_node, false, exit_loop_label.clone());
         self.handle_root(code_block_node.clone());
         self.instruction_jump_to_label(evaluate_expression_label.clone());
         self.instruction_nop_with_label(exit_loop_label.clone());
@@ -196,26 +399,24 @@ impl MixalAssembler {
         let statement_node = children.get(2).expect("to exist");
         let code_block_node = children.get(3).expect("to exist");
 
-        let evaluate_expression_label = get_random_instruction_label();
-        let exit_loop_label = get_random_instruction_label();
+        let evaluate_expression_label = self.new_instruction_label();
+        let exit_loop_label = self.new_instruction_label();
         
         // In the case of a for loop, when we encounter 'continue', we still
         // need to run the 3rd part of the loop ('statement_node'). Because of
         // this, we will also need a label so we can skip the rest of the loop's 
         // code but still execute the 'statement_node'. We define that label here.
-        let evaluate_expression_label_for_continue = get_random_instruction_label();
+        let evaluate_expression_label_for_continue = self.new_instruction_label();
 
         self.loop_stack.push((
+            self.pending_loop_label.take(),
             evaluate_expression_label_for_continue.clone(),
             exit_loop_label.clone()
         ));
 
         self.handle_root(assignment_node.clone());
         self.instruction_nop_with_label(evaluate_expression_label.clone());
-        self.handle_expression_node(expression_node.clone());
-        self.instruction_store_zero_to_address(0);
-        self.instruction_compare_ra(0);
-        self.instruction_jump_to_label_if_comparison_was_true(Token::Equals, exit_loop_label.clone());
+        self.instructions_jump_to_label_if_condition_is(expression_node, false, exit_loop_label.clone());
         self.handle_root(code_block_node.clone());
         self.instruction_nop_with_label(evaluate_expression_label_for_continue.clone());
         self.handle_root(statement_node.clone());
@@ -225,6 +426,123 @@ impl MixalAssembler {
         self.loop_stack.pop();
     }
 
+    fn handle_do_while_loop(&mut self, node: Node<usize, Token>) {
+        let children = node.children();
+        let code_block_node = children.get(0).expect("to exist");
+        let expression_node = children.get(1).expect("to exist");
+
+        let loop_start_label = self.new_instruction_label();
+        let exit_loop_label = self.new_instruction_label();
+        // 'continue' skips the rest of the block but still evaluates the condition
+        let evaluate_expression_label = self.new_instruction_label();
+
+        self.loop_stack.push((
+            self.pending_loop_label.take(),
+            evaluate_expression_label.clone(),
+            exit_loop_label.clone()
+        ));
+
+        self.instruction_nop_with_label(loop_start_label.clone());
+        self.handle_root(code_block_node.clone());
+        self.instruction_nop_with_label(evaluate_expression_label.clone());
+        self.instructions_jump_to_label_if_condition_is(expression_node, true, loop_start_label.clone());
+        self.instruction_nop_with_label(exit_loop_label.clone());
+
+        self.loop_stack.pop();
+    }
+
+    // Jumps to the label if the bool expression has the `value`. A constant
+    // condition is not evaluated: the jump is either unconditional or left
+    // out, and the code that can then never run is removed with the flow graph.
+    fn instructions_jump_to_label_if_condition_is(&mut self, expression_node: &Node<usize, Token>, value: bool, label: String) {
+        if let Some(condition) = evaluate_constant_expression(expression_node, &HashMap::new()) {
+            if condition.is_zero() != value {
+                self.instruction_jump_to_label(label);
+            }
+            return;
+        }
+        self.handle_expression_node(expression_node.clone());
+        self.instruction_store_zero_to_address(self.scratch_address());
+        self.instruction_compare_ra(self.scratch_address());
+        let comparison = if value { Token::NotEquals } else { Token::Equals };
+        self.instruction_jump_to_label_if_comparison_was_true(comparison, label);
+    }
+
+    // Returns the loop that the 'continue' or 'break' statement `node`
+    // refers to: the loop with its label, or else the innermost loop.
+    fn get_target_loop(&self, node: &Node<usize, Token>) -> &(Option<String>, String, String) {
+        if let Some(Token::Label(label)) = node.children().first().map(|x| x.value()) {
+            return self.loop_stack.iter().rev()
+                .find(|x| x.0.as_ref() == Some(label))
+                .expect("to exist");
+        }
+        return self.loop_stack.last().expect("to exist");
+    }
+
+    // The cases of a switch statement do not fall through, so every case
+    // jumps to the bottom of the statement after its block. A 'break'
+    // or 'continue' in a case refers to the loop around the statement.
+    fn handle_switch_statement(&mut self, node: Node<usize, Token>) {
+        let children = node.children();
+        let expression_node = children.get(0).expect("to exist");
+        let case_nodes = &children[1..];
+
+        let bottom_label = self.new_instruction_label();
+        let case_labels: Vec<String> = case_nodes.iter().map(|_| self.new_instruction_label()).collect();
+        // Without a 'default' case, the values that match no label jump to the bottom
+        let default_label = case_nodes.iter()
+            .position(|x| *x.value() == Token::Default)
+            .map_or(bottom_label.clone(), |x| case_labels[x].clone());
+        // The values of the labels, along with the label of their case.
+        // The labels are checked by the semantic analyzer, so they are
+        // constant and no two of them have the same value.
+        let mut values = vec![];
+        for (case_node, case_label) in case_nodes.iter().zip(&case_labels) {
+            for label_node in &case_node.children()[..case_node.children().len() - 1] {
+                let value = evaluate_constant_expression(label_node, &HashMap::new()).expect("to be constant").to_i64();
+                values.push((value, case_label.clone()));
+            }
+        }
+        values.sort();
+
+        // The value of the switch is kept in memory while it is compared with the labels
+        let value_memory_address = self.memory_layout.push_stack();
+        self.handle_expression_node(expression_node.clone());
+        self.instruction_store_register_to_address(value_memory_address, MixalRegister::RA);
+
+        let table_entries = match (values.first(), values.last()) {
+            (Some((min, _)), Some((max, _))) => (max - min + 1) as usize,
+            _ => 0
+        };
+        let is_dense = table_entries <= 2 * values.len();
+        let fits = table_entries <= JUMP_TABLE_MAX_ENTRIES.min(self.code_words_left());
+        if values.len() >= JUMP_TABLE_MIN_LABELS && is_dense && fits {
+            self.instructions_jump_through_table(value_memory_address, &values, default_label);
+        } else {
+            for (value, case_label) in &values {
+                self.instructions_enter_immediate_value_to_register(*value as i32, MixalRegister::RA);
+                self.instruction_compare_ra(value_memory_address);
+                self.instruction_jump_to_label_if_comparison_was_true(Token::Equals, case_label.clone());
+            }
+            self.instruction_jump_to_label(default_label);
+        }
+        self.memory_layout.pop_stack();
+
+        for (case_node, case_label) in case_nodes.iter().zip(case_labels) {
+            self.instruction_nop_with_label(case_label);
+            self.handle_root(case_node.children().last().expect("to exist").clone());
+            self.instruction_jump_to_label(bottom_label.clone());
+        }
+        self.instruction_nop_with_label(bottom_label);
+    }
+
+    // The number of words of the code region that are
+    // not taken by the instructions assembled so far
+    fn code_words_left(&self) -> usize {
+        let code_size = self.instructions.iter().filter(|x| mnemonic_to_opcode(&x.mnemonic).is_some()).count();
+        return (self.memory_layout.size(Region::Code) as usize).saturating_sub(code_size);
+    }
+
     fn handle_print(&mut self, node: Node<usize, Token>) {
         let child = node.children();
         let expression_node = child.get(0).expect("to exist");
@@ -232,28 +550,34 @@ impl MixalAssembler {
         self.handle_expression_node(expression_node.clone());
         self.instruction_char();
         
-        self.instruction_store_register_to_address(self.standard_output_device_block_memory_address + 1, MixalRegister::RA);
-        self.instruction_store_register_to_address(self.standard_output_device_block_memory_address + 2, MixalRegister::RX);
+        let output_buffer_address = self.memory_layout.output_buffer_address();
+        self.instruction_store_register_to_address(output_buffer_address + 1, MixalRegister::RA);
+        self.instruction_store_register_to_address(output_buffer_address + 2, MixalRegister::RX);
 
-        let label = get_random_instruction_label();
+        let label = self.new_instruction_label();
         self.instruction_enter_two_byte_immediate_value_to_register(45, MixalRegister::RX);
         self.instruction_jump_to_label_if_register_ra_is_negative(label.clone());
         self.instruction_enter_two_byte_immediate_value_to_register(44, MixalRegister::RX);
         self.instruction_nop_with_label(label.clone());
-        self.instruction_store_register_to_address(self.standard_output_device_block_memory_address, MixalRegister::RX);
+        self.instruction_store_register_to_address(output_buffer_address, MixalRegister::RX);
 
-        self.instruction_out(self.standard_output_device_block_memory_address);
+        self.instruction_out(output_buffer_address);
     }
 
     // Evaluates the expression starting from `node`
     // and stores the result in register RA
     fn handle_expression_node(&mut self, node: Node<usize, Token>) {
-        if let Token::Num(number) = node.value() {
-            self.instructions_enter_immediate_value_to_register(*number, MixalRegister::RA);
+        if let Token::Num(number) = boolean_literal_to_number(node.value()) {
+            self.instructions_enter_immediate_value_to_register(number, MixalRegister::RA);
+            return;
+        } else if let Some(value) = evaluate_constant_expression(&node, &HashMap::new()) {
+            // A constant expression, eg `2 * 3` or a negative constant,
+            // is computed here and its value is entered directly
+            self.instructions_enter_word_to_register_ra(value);
             return;
         } else if let Token::Id(identifier) = node.value() {
             self.instruction_load_address_to_register(
-                self.vtable.get(identifier).expect("to exist").clone(),
+                self.variable_address(identifier),
                 MixalRegister::RA
             );
             return;
@@ -262,6 +586,22 @@ impl MixalAssembler {
             self.handle_expression_node(child.clone());
             self.instructions_logical_not();
             return;
+        } else if let Token::Increment | Token::Decrement = node.value() {
+            self.handle_increment_or_decrement(node, true);
+            return;
+        } else if let Token::Assignment = node.value() {
+            // Storing the assigned value leaves it in RA,
+            // which is the value of the assignment
+            self.handle_assignment_operator(node);
+            return;
+        } else if let Token::AdditionAssignment | Token::SubtractionAssignment
+            | Token::MultiplicationAssignment | Token::DivisionAssignment
+            | Token::ModuloAssignment = node.value() {
+            self.handle_arithmetic_assignment_operator(node);
+            return;
+        } else if let Token::QuestionMark = node.value() {
+            self.handle_conditional_expression(node);
+            return;
         }
 
         let children = node.children();
@@ -318,8 +658,7 @@ impl MixalAssembler {
             // use registers, but we don't implement it this way since the
             // code that decides that could be very comlicated.
 
-            let temp_memory_address = self.next_memory_address_to_allocate;
-            self.next_memory_address_to_allocate += 1;
+            let temp_memory_address = self.memory_layout.push_stack();
             if let Token::And = node.value() {
                 // The following method performs short-circuit evaluation
                 self.instructions_prepare_logical_and_operands(left_operand.clone(),right_operand.clone(), temp_memory_address);
@@ -337,17 +676,17 @@ impl MixalAssembler {
 
             // As explained above, division and modulo require some special treatment
             if let Token::Slash | Token::Percent = node.value() {
-                self.instruction_store_register_to_address(0, MixalRegister::RA);
-                self.instruction_load_address_to_register(0, MixalRegister::RX);
+                self.instruction_store_register_to_address(self.scratch_address(), MixalRegister::RA);
+                self.instruction_load_address_to_register(self.scratch_address(), MixalRegister::RX);
                 self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RA);
-                self.instruction_load_address_sign_to_register(0, MixalRegister::RA);                
+                self.instruction_load_address_sign_to_register(self.scratch_address(), MixalRegister::RA);                
             }
             
             let operator_fn = 
                 MixalAssembler::token_to_arithmetic_operator_instruction_fn(node.value());
             operator_fn(self, temp_memory_address);
 
-            self.next_memory_address_to_allocate -= 1;            
+            self.memory_layout.pop_stack();
         }
 
         // At this point, the operator result is evaluated.
@@ -390,9 +729,39 @@ impl MixalAssembler {
         }
     }
 
-    fn write_to_file(&mut self, str: String) {
-        self.file.write_all(str.as_bytes()).expect("to be written");
-    }    
+    // Evaluates the condition of the `c ? a : b` node and then only the
+    // branch that it chooses, as 'instructions_prepare_logical_and_operands'
+    // does for '&&'. The value of the branch is left in register RA.
+    fn handle_conditional_expression(&mut self, node: Node<usize, Token>) {
+        let children = node.children();
+        let else_label = self.new_instruction_label();
+        let bottom_label = self.new_instruction_label();
+
+        // If the condition is 0 (false), jump to the 'else' branch
+        self.instructions_jump_to_label_if_condition_is(children.get(0).expect("to exist"), false, else_label.clone());
+
+        self.handle_expression_node(children.get(1).expect("to exist").clone());
+        self.instruction_jump_to_label(bottom_label.clone());
+
+        self.instruction_nop_with_label(else_label);
+        self.handle_expression_node(children.get(2).expect("to exist").clone());
+        self.instruction_nop_with_label(bottom_label);
+    }
+
+    fn new_instruction_label(&mut self) -> String {
+        loop {
+            self.labels_count += 1;
+            let label = format!("L{}", self.labels_count);
+            if self.used_labels.insert(label.clone()) {
+                return label;
+            }
+        }
+    }
+
+    fn emit_instruction(&mut self, mut instruction: MixalInstruction) {
+        instruction.source = self.current_statement;
+        self.instructions.push(instruction);
+    }
 
     fn token_to_arithmetic_operator_instruction_fn(token: &Token) -> fn(&mut MixalAssembler, u16) {
         match token {
@@ -418,7 +787,7 @@ impl MixalAssembler {
     //   - "instructions_" when they generate
     //     a group of instructions.
     // When invoked, they will append the needed
-    // instruction(s) to 'self.output_file_path'.
+    // instruction(s) to 'self.instructions'.
     // The reason for these prefixes is to convey to
     // the consumer the approximate cost of the method.
     // For methods prefixed with "instruction_", the 
@@ -431,141 +800,135 @@ impl MixalAssembler {
     // ---------------------------------------------
 
     fn instruction_set_instructions_allocation_address(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             MixalMnemonic::ORIG,
-            Some(String::from(address.to_string()))
+            Some(address.to_string())
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_end_program(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             MixalMnemonic::END,
-            Some(String::from(address.to_string()))
+            Some(address.to_string())
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_nop_with_label(&mut self, label: String) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             Some(label),
             MixalMnemonic::NOP,
             None
         );
-        self.write_to_file(instruction.to_string());        
+        self.emit_instruction(instruction);        
     }
 
     fn instruction_load_address_to_register(&mut self, address: u16, register: MixalRegister) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             mixal_register_to_load_mnemonic(register),
-            Some(String::from(format!("{}(0:5)", address)))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_load_address_sign_to_register(&mut self, address: u16, register: MixalRegister) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             mixal_register_to_load_mnemonic(register),
-            Some(String::from(format!("{}(0:0)", address)))
+            Some(format!("{}(0:0)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_store_register_to_address(&mut self, address: u16, register: MixalRegister) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             mixal_register_to_store_mnemonic(register),
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_store_register_sign_to_address(&mut self, address: u16, register: MixalRegister) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             mixal_register_to_store_mnemonic(register),
-            Some(String::from(format!("{}(0:0)", address.to_string())))
+            Some(format!("{}(0:0)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_store_register_without_sign_to_address(&mut self, address: u16, register: MixalRegister) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             mixal_register_to_store_mnemonic(register),
-            Some(String::from(format!("{}(1:5)", address.to_string())))
+            Some(format!("{}(1:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
-    }
-
-    fn instruction_store_register_to_address_with_field_specification(
-        &mut self, 
-        address: u16, 
-        register: MixalRegister, 
-        field_start: i16,
-        field_end: i16
-    ) {
-        let mut instruction = MixalInstruction::new(
-            None,
-            mixal_register_to_store_mnemonic(register),
-            Some(String::from(format!("{}({}:{})", address.to_string(), field_start, field_end)))
-        );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_store_zero_to_address(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             MixalMnemonic::STZ,
-            Some(String::from(format!("{}(0:5)", address)))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_enter_two_byte_immediate_value_to_register(&mut self, value: i32, register: MixalRegister) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None,
             mixal_register_to_enter_mnemonic(register, value),
-            Some(String::from(value.abs().to_string()))
+            Some(value.abs().to_string())
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
+    }
+
+    fn instruction_increment_register(&mut self, value: i32, register: MixalRegister) {
+        let instruction = MixalInstruction::new(
+            None,
+            mixal_register_to_increment_mnemonic(register, value),
+            Some(value.abs().to_string())
+        );
+        self.emit_instruction(instruction);
     }
 
     fn instruction_add(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::ADD, 
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_subtract(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::SUB, 
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_multiply(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::MUL,
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     // Before running the 'DIV' instruction, we need to check if the divisor
     // is zero. If it is, we throw an exception and halt the program.
     fn instructions_divide_and_modulo(&mut self, address: u16) {
-        let divisor_not_zero_label = get_random_instruction_label();
+        let divisor_not_zero_label = self.new_instruction_label();
 
         // We use the register RI1 for the comparison because
         // registers RA and RX are already used for the dividend.
@@ -576,93 +939,112 @@ impl MixalAssembler {
         self.instructions_throw_exception(1);
 
         self.instruction_nop_with_label(divisor_not_zero_label);
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::DIV,
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_compare_ra(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::CMPA,
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_compare_rx(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::CMPX,
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_compare_ri1(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::CMP1,
-            Some(String::from(format!("{}(0:5)", address.to_string())))
+            Some(format!("{}(0:5)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string());
+        self.emit_instruction(instruction);
     }
 
     fn instruction_jump_to_label(&mut self, label: String) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::JSJ,
             Some(label)
         );
-        self.write_to_file(instruction.to_string());        
+        self.emit_instruction(instruction);        
+    }
+
+    fn instruction_jump_to_label_with_optional_label(&mut self, label: Option<String>, target_label: String) {
+        let instruction = MixalInstruction::new(
+            label,
+            MixalMnemonic::JSJ,
+            Some(target_label)
+        );
+        self.emit_instruction(instruction);
+    }
+
+    // Jumps to the address of the label plus the value of register RI1
+    fn instruction_jump_to_label_indexed_by_ri1(&mut self, label: String) {
+        let instruction = MixalInstruction::new(
+            None,
+            MixalMnemonic::JSJ,
+            Some(format!("{},1", label))
+        );
+        self.emit_instruction(instruction);
     }
 
     fn instruction_jump_to_label_if_comparison_was_true(&mut self, comparison_token: Token, label: String) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             comparison_token_to_jump_instruction(comparison_token),
             Some(label)
         );
-        self.write_to_file(instruction.to_string());        
+        self.emit_instruction(instruction);        
     }
 
     fn instruction_jump_to_label_if_register_ra_is_negative(&mut self, label: String) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::JAN,
             Some(label)
         );
-        self.write_to_file(instruction.to_string());        
+        self.emit_instruction(instruction);        
     }
 
     fn instruction_char(&mut self) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::CHAR,
             None
         );
-        self.write_to_file(instruction.to_string());         
+        self.emit_instruction(instruction);         
     }
 
     fn instruction_out(&mut self, address: u16) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::OUT,
-            Some(String::from(format!("{}(2:3)", address.to_string())))
+            Some(format!("{}(2:3)", self.address_operand(address)))
         );
-        self.write_to_file(instruction.to_string()); 
+        self.emit_instruction(instruction); 
     }
 
     fn instruction_halt(&mut self) {
-        let mut instruction = MixalInstruction::new(
+        let instruction = MixalInstruction::new(
             None, 
             MixalMnemonic::HLT,
             None
         );
-        self.write_to_file(instruction.to_string()); 
+        self.emit_instruction(instruction); 
     }
 
     fn instructions_prepare_leaf_operands_and_execute_operator(
@@ -673,31 +1055,40 @@ impl MixalAssembler {
     ) {
         let operator_fn = 
             MixalAssembler::token_to_arithmetic_operator_instruction_fn(operator);
+        let left_operand = &boolean_literal_to_number(left_operand);
+        let right_operand = &boolean_literal_to_number(right_operand);
 
         // Because an operand can be either a Number or a Variable, 
         // we must handle 4 cases, one for every combination.
         if let (Token::Num(number1), Token::Num(number2)) = (left_operand, right_operand) {
             self.instructions_enter_immediate_value_to_register(*number2, MixalRegister::RA);
-            self.instruction_store_register_to_address(0, MixalRegister::RA);
+            self.instruction_store_register_to_address(self.scratch_address(), MixalRegister::RA);
             self.instructions_enter_immediate_value_to_register(*number1, MixalRegister::RA);
-            operator_fn(self, 0);
+            operator_fn(self, self.scratch_address());
         } else if let (Token::Id(identifier1), Token::Id(identifier2)) = (left_operand, right_operand) {
-            let identifier1_address = self.vtable.get(identifier1).expect("to exist").clone();
-            let identifier2_address = self.vtable.get(identifier2).expect("to exist").clone();
+            let identifier1_address = self.variable_address(identifier1);
+            let identifier2_address = self.variable_address(identifier2);
             self.instruction_load_address_to_register(identifier1_address, MixalRegister::RA);
             operator_fn(self, identifier2_address);
         } else if let (Token::Num(number), Token::Id(identifier)) = (left_operand, right_operand) {
             self.instructions_enter_immediate_value_to_register(*number, MixalRegister::RA);
             operator_fn(
                 self,
-                self.vtable.get(identifier).expect("to exist").clone()
+                self.variable_address(identifier)
             );
         } else if let (Token::Id(identifier), Token::Num(number)) = (left_operand, right_operand) {
-            let identifier_address = self.vtable.get(identifier).expect("to exist").clone();
+            let identifier_address = self.variable_address(identifier);
             self.instruction_load_address_to_register(identifier_address, MixalRegister::RA);
+            if matches!(operator, Token::Plus | Token::Minus) && number.abs() < i32::pow(2, 12) {
+                // A number that fits in 2 MIX bytes is added by 'INCA' or
+                // 'DECA', eg `i += 1` needs no operand in memory
+                let increment = if *operator == Token::Plus { *number } else { -*number };
+                self.instruction_increment_register(increment, MixalRegister::RA);
+                return;
+            }
             self.instructions_enter_immediate_value_to_register(*number, MixalRegister::RX);
-            self.instruction_store_register_to_address(0, MixalRegister::RX);
-            operator_fn(self, 0);
+            self.instruction_store_register_to_address(self.scratch_address(), MixalRegister::RX);
+            operator_fn(self, self.scratch_address());
         }
     }
 
@@ -722,13 +1113,13 @@ impl MixalAssembler {
         // we must handle 4 cases, one for every combination.
         if let (Token::Num(number1), Token::Num(number2)) = (left_operand, right_operand) {
             self.instructions_enter_immediate_value_to_register(*number2, MixalRegister::RA);
-            self.instruction_store_register_to_address(0, MixalRegister::RA);
+            self.instruction_store_register_to_address(self.scratch_address(), MixalRegister::RA);
             self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RA);
             self.instructions_enter_immediate_value_to_register(*number1, MixalRegister::RX);
-            operator_fn(self, 0);
-        } if let (Token::Id(identifier1), Token::Id(identifier2)) = (left_operand, right_operand) {
-            let identifier1_address = self.vtable.get(identifier1).expect("to exist").clone();
-            let identifier2_address = self.vtable.get(identifier2).expect("to exist").clone();
+            operator_fn(self, self.scratch_address());
+        } else if let (Token::Id(identifier1), Token::Id(identifier2)) = (left_operand, right_operand) {
+            let identifier1_address = self.variable_address(identifier1);
+            let identifier2_address = self.variable_address(identifier2);
             self.instruction_load_address_to_register(identifier1_address, MixalRegister::RX);
             self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RA);
             self.instruction_load_address_sign_to_register(identifier1_address, MixalRegister::RA);
@@ -736,16 +1127,16 @@ impl MixalAssembler {
         } else if let (Token::Num(number), Token::Id(identifier)) = (left_operand, right_operand){
             self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RA);
             self.instructions_enter_immediate_value_to_register(*number, MixalRegister::RX);
-            let identifier_address = self.vtable.get(identifier).expect("to exist").clone();
+            let identifier_address = self.variable_address(identifier);
             operator_fn(self, identifier_address);
         } else if let (Token::Id(identifier), Token::Num(number)) = (left_operand, right_operand) {
-            let identifier_address = self.vtable.get(identifier).expect("to exist").clone();
+            let identifier_address = self.variable_address(identifier);
             self.instructions_enter_immediate_value_to_register(*number, MixalRegister::RA);
-            self.instruction_store_register_to_address(0, MixalRegister::RA);
+            self.instruction_store_register_to_address(self.scratch_address(), MixalRegister::RA);
             self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RA);
             self.instruction_load_address_sign_to_register(identifier_address, MixalRegister::RA);
             self.instruction_load_address_to_register(identifier_address, MixalRegister::RX);
-            operator_fn(self, 0);
+            operator_fn(self, self.scratch_address());
         }
     }
 
@@ -754,8 +1145,8 @@ impl MixalAssembler {
         origin_register: MixalRegister,
         destination_register: MixalRegister        
     ) {
-        self.instruction_store_register_to_address(0, origin_register);
-        self.instruction_load_address_to_register(0, destination_register);
+        self.instruction_store_register_to_address(self.scratch_address(), origin_register);
+        self.instruction_load_address_to_register(self.scratch_address(), destination_register);
     }
 
     fn instructions_move_register_without_sign_to_register(
@@ -763,13 +1154,13 @@ impl MixalAssembler {
         origin_register: MixalRegister,
         destination_register: MixalRegister
     ) {
-        self.instruction_store_register_sign_to_address(0, destination_register.clone());
-        self.instruction_store_register_without_sign_to_address(0, origin_register);
-        self.instruction_load_address_to_register(0, destination_register);
+        self.instruction_store_register_sign_to_address(self.scratch_address(), destination_register.clone());
+        self.instruction_store_register_without_sign_to_address(self.scratch_address(), origin_register);
+        self.instruction_load_address_to_register(self.scratch_address(), destination_register);
     }
 
     fn instructions_load_comparison_result_to_register_ra(&mut self, comparison_token: Token) {
-        let label = get_random_instruction_label();
+        let label = self.new_instruction_label();
 
         self.instruction_enter_two_byte_immediate_value_to_register(1, MixalRegister::RA);
         self.instruction_jump_to_label_if_comparison_was_true(
@@ -781,7 +1172,7 @@ impl MixalAssembler {
     }
 
     fn instructions_logical_and(&mut self, address: u16) {
-        let bottom_label = get_random_instruction_label();
+        let bottom_label = self.new_instruction_label();
 
         // Assume that the result is true
         self.instruction_enter_two_byte_immediate_value_to_register(1, MixalRegister::RI1);
@@ -789,20 +1180,20 @@ impl MixalAssembler {
         // store operand2 to RX
         self.instruction_load_address_to_register(address, MixalRegister::RX);
 
-        // store 0 to memory address 0
-        self.instruction_store_zero_to_address(0);
+        // store 0 to the scratch word
+        self.instruction_store_zero_to_address(self.scratch_address());
 
         // if RA is zero, set result to 0 and don't check RX
-        let label = get_random_instruction_label();
-        self.instruction_compare_ra(0);
+        let label = self.new_instruction_label();
+        self.instruction_compare_ra(self.scratch_address());
         self.instruction_jump_to_label_if_comparison_was_true(Token::NotEquals, label.clone());
         self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RI1);
         self.instruction_jump_to_label(bottom_label.clone());
         self.instruction_nop_with_label(label.clone());
 
         // if RX is zero, set result to 0
-        let label = get_random_instruction_label();
-        self.instruction_compare_rx(0);
+        let label = self.new_instruction_label();
+        self.instruction_compare_rx(self.scratch_address());
         self.instruction_jump_to_label_if_comparison_was_true(Token::NotEquals, label.clone());
         self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RI1);
         self.instruction_nop_with_label(label.clone());
@@ -816,14 +1207,14 @@ impl MixalAssembler {
     // If needed, one of the operands will be stored in RA and the
     // other one in 'address' (depends on the short-circuit evaluation).
     fn instructions_prepare_logical_and_operands(&mut self, left_operand: Node<usize, Token>, right_operand: Node<usize, Token>, address: u16) {
-        let anchor_label = get_random_instruction_label();
+        let anchor_label = self.new_instruction_label();
                 
         // Evaluate the left operand and compare it with 0.
         // If it is 0, we do not need to evaluate the right
         // operand, thus we jump to the 'anchor_label' label.
         self.handle_expression_node(left_operand.clone());
-        self.instruction_store_zero_to_address(0);
-        self.instruction_compare_ra(0);
+        self.instruction_store_zero_to_address(self.scratch_address());
+        self.instruction_compare_ra(self.scratch_address());
         self.instruction_jump_to_label_if_comparison_was_true(Token::Equals, anchor_label.clone());
 
         // If this code is reached, then the left operand is true. Thus, we
@@ -838,8 +1229,8 @@ impl MixalAssembler {
     }
     
     fn instructions_logical_or(&mut self, address: u16) {
-        let label_true = get_random_instruction_label();
-        let label_bottom = get_random_instruction_label();
+        let label_true = self.new_instruction_label();
+        let label_bottom = self.new_instruction_label();
 
         // Assume that the result is false
         self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RI1);
@@ -847,15 +1238,15 @@ impl MixalAssembler {
         // Store operand2 to RX
         self.instruction_load_address_to_register(address, MixalRegister::RX);
 
-        // Store 0 to memory address 0
-        self.instruction_store_zero_to_address(0);
+        // Store 0 to the scratch word
+        self.instruction_store_zero_to_address(self.scratch_address());
 
         // If RA != 0, jump to 'label_true'
-        self.instruction_compare_ra(0);
+        self.instruction_compare_ra(self.scratch_address());
         self.instruction_jump_to_label_if_comparison_was_true(Token::NotEquals, label_true.clone());
     
         // If RX != 0, jump to 'label_true'
-        self.instruction_compare_rx(0);
+        self.instruction_compare_rx(self.scratch_address());
         self.instruction_jump_to_label_if_comparison_was_true(Token::NotEquals, label_true.clone());
 
         // If this instruction is reached, it means that
@@ -875,14 +1266,14 @@ impl MixalAssembler {
     // If needed, one of the operands will be stored in RA and the
     // other one in 'address' (depends on the short-circuit evaluation).    
     fn instructions_prepare_logical_or_operands(&mut self, left_operand: Node<usize, Token>, right_operand: Node<usize, Token>, address: u16) {
-        let anchor_label = get_random_instruction_label();
+        let anchor_label = self.new_instruction_label();
                 
         // Evaluate the left operand and compare it with 0.
         // If it is not 0, we do not need to evaluate the
         // second operand, thus we jump to the 'anchor_label' label.
         self.handle_expression_node(left_operand.clone());
-        self.instruction_store_zero_to_address(0);
-        self.instruction_compare_ra(0);
+        self.instruction_store_zero_to_address(self.scratch_address());
+        self.instruction_compare_ra(self.scratch_address());
         self.instruction_jump_to_label_if_comparison_was_true(Token::NotEquals, anchor_label.clone());
 
         // If this is reached, then the left operand is false. Thus, we
@@ -897,90 +1288,174 @@ impl MixalAssembler {
     }
 
     fn instructions_logical_not(&mut self) {
-        let label = get_random_instruction_label();
+        let label = self.new_instruction_label();
     
-        self.instruction_store_zero_to_address(0);
-        self.instruction_compare_ra(0);
+        self.instruction_store_zero_to_address(self.scratch_address());
+        self.instruction_compare_ra(self.scratch_address());
         self.instruction_enter_two_byte_immediate_value_to_register(1, MixalRegister::RA);
         self.instruction_jump_to_label_if_comparison_was_true(Token::Equals, label.clone());
         self.instruction_enter_two_byte_immediate_value_to_register(0, MixalRegister::RA);
         self.instruction_nop_with_label(label.clone());        
     }
 
-    // Entering an immediate value to a register is tricky in MIX. 
-    // There is a group of instructions for doing this, but they
-    // support numbers up to 2 MIX bytes (12 bits). If we need to
-    // enter a value greater than 2^12 into a register, we need to
-    // handle it ourselves. This is what we do in this method.
+    // The instructions that enter an immediate value to a register
+    // support numbers up to 2 MIX bytes (12 bits). The values that
+    // do not fit are loaded from the pool of constants instead.
     fn instructions_enter_immediate_value_to_register(&mut self, value: i32, register: MixalRegister) {
-
-        // For numbers that can fit in 2 MIX bytes, 
-        // we can use a single mix instruction. 
-        if value < i32::pow(2, 12) {
+        if value.abs() < i32::pow(2, 12) {
             self.instruction_enter_two_byte_immediate_value_to_register(value, register);
-            return; 
-        } 
+            return;
+        }
+        let address = self.constant_address(MixalMnemonic::CON, value.to_string());
+        self.instruction_load_address_to_register(address, register);
+    }
 
-        // But, for numbers that do not fit in 2 MIX bytes,
-        // we need to manually "construct" the immediate value
-        // by breaking it in groups of 2 bytes. This is what we 
-        // do in the code below. Remember, 'value' is always > 0.
-        
-        // Copy the 'value' to a new mutable variable that we can modify
-        let mut mutable_value = value;
-
-        self.instruction_store_zero_to_address(0);
-
-        // Store the 2 LSBytes of the 'mutable_value' in register RA
-        self.instruction_enter_two_byte_immediate_value_to_register(mutable_value, MixalRegister::RA);
-        self.instruction_store_register_to_address_with_field_specification(0, MixalRegister::RA, 4, 5);
-
-        // Shift the 'mutable_value' 2 bytes to the right 
-        // in order to discard the 2 LSBytes
-        mutable_value = mutable_value >> 12;
-
-        // Store the 2 LSBytes of the 'mutable_value' in register RA.
-        // These bytes correspond to the third and forth byte of the
-        // original value (starting the count from the LSB).
-        self.instruction_enter_two_byte_immediate_value_to_register(mutable_value, MixalRegister::RA);
-        self.instruction_store_register_to_address_with_field_specification(0, MixalRegister::RA, 2, 3);
-
-        // If the value does not fit in 4 bytes, repeat the
-        // same proccess as above for the remaining byte
-        if value >= i32::pow(2, 24) {
-            mutable_value = mutable_value >> 12;
-            self.instruction_enter_two_byte_immediate_value_to_register(mutable_value, MixalRegister::RA);
-            self.instruction_store_register_to_address_with_field_specification(0, MixalRegister::RA, 1, 1);            
+    // Same as `instructions_enter_immediate_value_to_register`, but for
+    // any word, including the negative zero, which is entered by 'ENNA 0'
+    fn instructions_enter_word_to_register_ra(&mut self, value: MixWord) {
+        if value.negative && value.magnitude == 0 {
+            self.emit_instruction(MixalInstruction::new(None, MixalMnemonic::ENNA, Some(String::from("0"))));
+            return;
+        }
+        self.instructions_enter_immediate_value_to_register(value.to_i64() as i32, MixalRegister::RA);
+    }
+
+    // Jumps to the label of the case whose value is stored in
+    // 'value_address', through a table with a jump instruction for
+    // every value between the smallest and the largest label. The
+    // values outside of that range jump directly to 'default_label'.
+    // The 'values' are sorted, and stored along with their case label.
+    fn instructions_jump_through_table(&mut self, value_address: u16, values: &[(i64, String)], default_label: String) {
+        let min_value = values.first().expect("to exist").0;
+        let max_value = values.last().expect("to exist").0;
+        let table_label = self.new_instruction_label();
+
+        self.instructions_enter_immediate_value_to_register(min_value as i32, MixalRegister::RA);
+        self.instruction_compare_ra(value_address);
+        self.instruction_jump_to_label_if_comparison_was_true(Token::GreaterThan, default_label.clone());
+        self.instructions_enter_immediate_value_to_register(max_value as i32, MixalRegister::RA);
+        self.instruction_compare_ra(value_address);
+        self.instruction_jump_to_label_if_comparison_was_true(Token::LessThan, default_label.clone());
+
+        // The offset of the value in the table fits in
+        // register RI1, since the table is small
+        self.instructions_enter_immediate_value_to_register(min_value as i32, MixalRegister::RA);
+        self.instruction_store_register_to_address(self.scratch_address(), MixalRegister::RA);
+        self.instruction_load_address_to_register(value_address, MixalRegister::RA);
+        self.instruction_subtract(self.scratch_address());
+        self.instructions_move_register_to_register(MixalRegister::RA, MixalRegister::RI1);
+        self.instruction_jump_to_label_indexed_by_ri1(table_label.clone());
+
+        let mut values = values.iter().peekable();
+        for value in min_value..=max_value {
+            let target_label = match values.next_if(|(x, _)| *x == value) {
+                Some((_, case_label)) => case_label.clone(),
+                None => default_label.clone()
+            };
+            let label = if value == min_value { Some(table_label.clone()) } else { None };
+            self.instruction_jump_to_label_with_optional_label(label, target_label);
         }
-        self.instruction_load_address_to_register(0, register);        
     }
 
     fn instructions_throw_exception(&mut self, exception_code: i32) {
-        let memory1 = self.next_memory_address_to_allocate;
-        self.next_memory_address_to_allocate += 1;
-        let memory2 = self.next_memory_address_to_allocate;
-        self.next_memory_address_to_allocate += 1;
-        let memory3 = self.next_memory_address_to_allocate;
-        self.next_memory_address_to_allocate += 1;
-
-        // The two magic constants below are used to represent the
-        // MIX bytes that map to the characters "EXCEP" and "TION ".
-        // At some point, I need to add the functionality to print
-        // any text at the console, but for now I will do it this.
-        // TODO: add functionality to print any text at the console.
-        self.instructions_enter_immediate_value_to_register(90976593, MixalRegister::RA);
+        // The message is written to the standard output device block,
+        // whose words after the first three are never written. Thus,
+        // the rest of the printed line is always blank. The block can
+        // be overwritten, since the program halts after printing it.
+        let memory1 = self.memory_layout.output_buffer_address();
+        let memory2 = memory1 + 1;
+        let memory3 = memory1 + 2;
+
+        let text_addresses = [
+            self.constant_address(MixalMnemonic::ALF, String::from("\"EXCEP\"")),
+            self.constant_address(MixalMnemonic::ALF, String::from("\"TION \""))
+        ];
+        self.instruction_load_address_to_register(text_addresses[0], MixalRegister::RA);
         self.instruction_store_register_to_address(memory1, MixalRegister::RA);
-        self.instructions_enter_immediate_value_to_register(388301760, MixalRegister::RA);
+        self.instruction_load_address_to_register(text_addresses[1], MixalRegister::RA);
         self.instruction_store_register_to_address(memory2, MixalRegister::RA);
-        
+
         self.instruction_enter_two_byte_immediate_value_to_register(exception_code, MixalRegister::RA);
         self.instruction_char();
         self.instruction_store_register_to_address(memory3, MixalRegister::RX);
         
         self.instruction_out(memory1);
-        
-        self.next_memory_address_to_allocate -= 3;
 
         self.instruction_halt();        
     }
 }
+
+// Returns a copy of the tree where every use of a constant is replaced
+// by its value. The declarations of the constants are kept as they are.
+fn inline_constants(node: &Node<usize, Token>, constants: &HashMap<String, MixWord>) -> Node<usize, Token> {
+    if let Token::Id(identifier) = node.value() {
+        if let Some(value) = constants.get(identifier) {
+            return constant_value_to_node(*node.id(), *value);
+        }
+    }
+    let mut new_node = new_node_from_token(*node.id(), node.value().clone());
+    for child in node.children() {
+        if *node.value() == Token::Const {
+            new_node.add_child(child.clone());
+        } else {
+            new_node.add_child(inline_constants(child, constants));
+        }
+    }
+    return new_node;
+}
+
+// Negative values are modeled like the unary minus of the parser, so
+// that their magnitude is what gets loaded as an immediate value.
+pub fn constant_value_to_node(id: usize, value: MixWord) -> Node<usize, Token> {
+    if !value.negative {
+        return new_node_from_token(id, Token::Num(value.magnitude as i32));
+    }
+    let mut node = new_node_from_token(id, Token::Asterisk);
+    node.add_child(new_synthetic_node(id, 0, Token::Num(-1)));
+    node.add_child(new_synthetic_node(id, 1, Token::Num(value.magnitude as i32)));
+    return node;
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, CompileOptions};
+
+    #[test]
+    fn test_new_instruction_labels_are_unique() {
+        // The numbers of the labels skip the symbols that are already used
+        let mut assembler = MixalAssembler::new(Node::new(0, Token::LeftBrace), MemoryLayout::default());
+        assembler.used_labels.insert(String::from("L2"));
+        let labels: Vec<String> = (0..3).map(|_| assembler.new_instruction_label()).collect();
+        assert_eq!(labels, vec!["L1", "L3", "L4"]);
+    }
+
+    #[test]
+    fn test_negative_constants() {
+        // The uses of negative constants are entered without a 'MUL',
+        // whether their magnitude fits in 2 MIX bytes or not
+        let source = "{ const N = -7; const M = -5000; var a : int; a = N; print a + M; print M; }";
+        let artifacts = compile(source, &CompileOptions::default()).unwrap();
+        let mixal = artifacts.mixal().unwrap();
+        assert!(mixal.contains(" ENNA 7\n"));
+        assert!(!mixal.contains(" MUL "));
+    }
+
+    #[test]
+    fn test_constant_expressions() {
+        // The constant expressions are computed at compile time, also
+        // in loops and in the start values of the reduced products
+        let source = "{ var i, a : int; \
+            for (i = 1; i < 3; i++) { print 2 * 3; a = i * (0 - 4); print a; } print -1 * 0; }";
+        let artifacts = compile(source, &CompileOptions::default()).unwrap();
+        let mixal = artifacts.mixal().unwrap();
+        assert!(mixal.contains(" ENTA 6\n"));
+        assert!(mixal.contains(" ENNA 4\n"));
+        assert!(mixal.contains(" ENNA 0\n"));
+        assert!(!mixal.contains(" MUL "));
+    }
+}
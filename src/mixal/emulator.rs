@@ -0,0 +1,410 @@
+use std::cmp::Ordering;
+use super::loader::{MixProgram, MEMORY_SIZE};
+use super::word::MixWord;
+
+// The characters of the MIX character codes, as `mixvm` prints them
+pub const CHARACTER_SET: &str = " ABCDEFGHI~JKLMNOPQR[#STUVWXYZ0123456789.,()+-*/=$<>@;:'";
+
+// The units of the output devices that are supported, along
+// with the number of words of their blocks
+const LINE_PRINTER_DEVICE: u8 = 18;
+const LINE_PRINTER_BLOCK_SIZE: usize = 24;
+const TERMINAL_DEVICE: u8 = 19;
+const TERMINAL_BLOCK_SIZE: usize = 14;
+
+// How many times the instruction at an address has been executed,
+// and the time it has taken in total, in units of u
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InstructionProfile {
+    pub executions: u64,
+    pub time: u64
+}
+
+// An emulator of the MIX computer, which runs the programs that the
+// compiler generates without `mixvm`. The floating point, shifting,
+// moving and input instructions are not supported.
+pub struct MixMachine {
+    pub memory: Vec<MixWord>,
+    pub ra: MixWord,
+    pub rx: MixWord,
+    // The index registers RI1 to RI6, whose magnitudes have 2 bytes
+    pub ri: [MixWord; 6],
+    pub rj: u16,
+    pub comparison_indicator: Ordering,
+    pub overflow_toggle: bool,
+    // The address of the next instruction to execute
+    pub program_counter: u16,
+    pub halted: bool,
+    // The lines written to the output devices, without their trailing whitespace
+    pub output: Vec<String>,
+    pub executed_instructions: u64,
+    // The time the executed instructions have taken, in units of u
+    pub elapsed_time: u64,
+    // The profile of the instruction at every address of the memory
+    pub profile: Vec<InstructionProfile>
+}
+
+impl MixMachine {
+    pub fn new(program: &MixProgram) -> MixMachine {
+        MixMachine {
+            memory: program.memory.clone(),
+            ra: MixWord::default(),
+            rx: MixWord::default(),
+            ri: [MixWord::default(); 6],
+            rj: 0,
+            comparison_indicator: Ordering::Equal,
+            overflow_toggle: false,
+            program_counter: program.start_address,
+            halted: false,
+            output: vec![],
+            executed_instructions: 0,
+            elapsed_time: 0,
+            profile: vec![InstructionProfile::default(); MEMORY_SIZE]
+        }
+    }
+
+    // Runs the program until it halts. Fails if an instruction is invalid,
+    // or if the program executes more than `max_instructions` instructions.
+    pub fn run(&mut self, max_instructions: u64) -> Result<(), String> {
+        while !self.halted {
+            if self.executed_instructions >= max_instructions {
+                return Err(format!("the program did not halt after {} instructions", max_instructions));
+            }
+            self.step()?;
+        }
+        return Ok(());
+    }
+
+    // Executes the instruction at the program counter
+    pub fn step(&mut self) -> Result<(), String> {
+        let location = self.program_counter;
+        let instruction = self.read_memory(location as i64)?;
+        let bytes = instruction.bytes();
+        let address = MixWord::from_bytes(instruction.negative, &bytes[0..2]);
+        let index = bytes[2];
+        let field = bytes[3];
+        let code = bytes[4];
+        let (left, right) = (field / 8, field % 8);
+
+        let mut effective_address = address.to_i64();
+        if index > 6 {
+            return Err(format!("invalid index {} at address {}", index, location));
+        } else if index > 0 {
+            effective_address += self.ri[index as usize - 1].to_i64();
+        }
+        // The value of the 'ENTA' family when the address is zero
+        let immediate_value = if effective_address == 0 {
+            MixWord { negative: address.negative, magnitude: 0 }
+        } else {
+            MixWord::from_i64(effective_address)
+        };
+        let memory_field = |machine: &MixMachine| -> Result<MixWord, String> {
+            if right > 5 || left > right {
+                return Err(format!("invalid field specification {} at address {}", field, location));
+            }
+            return Ok(machine.read_memory(effective_address)?.field(left, right));
+        };
+
+        self.program_counter = location + 1;
+        self.executed_instructions += 1;
+        let time = instruction_time(code);
+        self.elapsed_time += time;
+        let profile = &mut self.profile[location as usize];
+        profile.executions += 1;
+        profile.time += time;
+        match code {
+            0 => {},
+            1 | 2 => {
+                let value = memory_field(self)?;
+                let (result, overflow) = if code == 1 { self.ra.add(value) } else { self.ra.subtract(value) };
+                self.ra = result;
+                self.overflow_toggle |= overflow;
+            },
+            3 => {
+                let value = memory_field(self)?;
+                (self.ra, self.rx) = self.ra.multiply(value);
+            },
+            4 => {
+                let value = memory_field(self)?;
+                match self.ra.divide(self.rx, value) {
+                    Some((quotient, remainder)) => (self.ra, self.rx) = (quotient, remainder),
+                    None => self.overflow_toggle = true
+                }
+            },
+            5 => match field {
+                0 => {
+                    let digits = self.ra.bytes().iter().chain(self.rx.bytes().iter())
+                        .fold(0, |value, byte| value * 10 + (*byte % 10) as u64);
+                    self.ra = MixWord::new(self.ra.negative, digits);
+                },
+                1 => {
+                    let digits = format!("{:010}", self.ra.magnitude);
+                    let codes: Vec<u8> = digits.bytes().map(|x| x - b'0' + 30).collect();
+                    self.ra = MixWord::from_bytes(self.ra.negative, &codes[0..5]);
+                    self.rx = MixWord::from_bytes(self.rx.negative, &codes[5..10]);
+                },
+                2 => self.halted = true,
+                _ => return Err(format!("unsupported instruction {}({}) at address {}", code, field, location))
+            },
+            8..=23 => {
+                let negated = code >= 16;
+                let mut value = memory_field(self)?;
+                if negated {
+                    value = value.negated();
+                }
+                self.set_register(if negated { code - 16 } else { code - 8 }, value);
+            },
+            24..=33 => {
+                let value = match code {
+                    32 => MixWord::from_i64(self.rj as i64),
+                    33 => MixWord::default(),
+                    _ => self.register(code - 24)
+                };
+                if right > 5 || left > right {
+                    return Err(format!("invalid field specification {} at address {}", field, location));
+                }
+                let target = self.read_memory(effective_address)?;
+                self.memory[effective_address as usize] = target.with_field(value, left, right);
+            },
+            37 => {
+                let block_size = match field {
+                    LINE_PRINTER_DEVICE => LINE_PRINTER_BLOCK_SIZE,
+                    TERMINAL_DEVICE => TERMINAL_BLOCK_SIZE,
+                    _ => return Err(format!("unsupported output device {} at address {}", field, location))
+                };
+                let mut line = String::new();
+                for offset in 0..block_size {
+                    let word = self.read_memory(effective_address + offset as i64)?;
+                    line.extend(word.bytes().iter().map(|x| CHARACTER_SET.chars().nth(*x as usize).unwrap_or('?')));
+                }
+                self.output.push(line.trim_end().to_string());
+            },
+            39 => {
+                let jump = match field {
+                    0 | 1 => true,
+                    2 | 3 => {
+                        let overflow = self.overflow_toggle;
+                        self.overflow_toggle = false;
+                        overflow == (field == 2)
+                    },
+                    4 => self.comparison_indicator == Ordering::Less,
+                    5 => self.comparison_indicator == Ordering::Equal,
+                    6 => self.comparison_indicator == Ordering::Greater,
+                    7 => self.comparison_indicator != Ordering::Less,
+                    8 => self.comparison_indicator != Ordering::Equal,
+                    9 => self.comparison_indicator != Ordering::Greater,
+                    _ => return Err(format!("unsupported instruction {}({}) at address {}", code, field, location))
+                };
+                if jump {
+                    // 'JSJ' is the only jump that does not set RJ
+                    if field != 1 {
+                        self.rj = location + 1;
+                    }
+                    self.jump(effective_address)?;
+                }
+            },
+            40..=47 => {
+                let value = self.register(code - 40).to_i64();
+                let jump = match field {
+                    0 => value < 0,
+                    1 => value == 0,
+                    2 => value > 0,
+                    3 => value >= 0,
+                    4 => value != 0,
+                    5 => value <= 0,
+                    _ => return Err(format!("unsupported instruction {}({}) at address {}", code, field, location))
+                };
+                if jump {
+                    self.rj = location + 1;
+                    self.jump(effective_address)?;
+                }
+            },
+            48..=55 => {
+                let register = code - 48;
+                let value = match field {
+                    0 | 1 => {
+                        let increment = if field == 0 { immediate_value } else { immediate_value.negated() };
+                        let (result, overflow) = self.register(register).add(increment);
+                        self.overflow_toggle |= overflow;
+                        result
+                    },
+                    2 => immediate_value,
+                    3 => immediate_value.negated(),
+                    _ => return Err(format!("unsupported instruction {}({}) at address {}", code, field, location))
+                };
+                self.set_register(register, value);
+            },
+            56..=63 => {
+                let value = memory_field(self)?;
+                self.comparison_indicator = self.register(code - 56).field(left, right).compare(value);
+            },
+            _ => return Err(format!("unsupported instruction {}({}) at address {}", code, field, location))
+        }
+        return Ok(());
+    }
+
+    fn read_memory(&self, address: i64) -> Result<MixWord, String> {
+        if address < 0 || address >= MEMORY_SIZE as i64 {
+            return Err(format!("the address {} is outside of the memory", address));
+        }
+        return Ok(self.memory[address as usize]);
+    }
+
+    fn jump(&mut self, address: i64) -> Result<(), String> {
+        self.read_memory(address)?;
+        self.program_counter = address as u16;
+        return Ok(());
+    }
+
+    // The registers are numbered as in the operation codes:
+    // 0 is RA, 1 to 6 are the index registers and 7 is RX
+    fn register(&self, register: u8) -> MixWord {
+        match register {
+            0 => self.ra,
+            7 => self.rx,
+            _ => self.ri[register as usize - 1]
+        }
+    }
+
+    fn set_register(&mut self, register: u8, value: MixWord) {
+        match register {
+            0 => self.ra = value,
+            7 => self.rx = value,
+            // Only the 2 LSBytes fit in the index registers
+            _ => self.ri[register as usize - 1] = value.field(0, 5).with_field(MixWord::default(), 1, 3)
+        }
+    }
+}
+
+// The execution time of an instruction in units of u, as defined in the
+// MIX specs. The output devices are always ready, so an 'OUT' takes 1u.
+fn instruction_time(code: u8) -> u64 {
+    match code {
+        // 'MUL'
+        3 => 10,
+        // 'DIV'
+        4 => 12,
+        // 'NUM', 'CHAR' and 'HLT'
+        5 => 10,
+        // 'NOP', the input-output instructions, the jumps and the 'ENTA' and 'INCA' families
+        0 | 34..=55 => 1,
+        // The loads, the stores, 'ADD', 'SUB', the shifts and the comparisons
+        _ => 2
+    }
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixal::instruction::MixalInstruction;
+    use crate::mixal::loader::load_program;
+    use crate::mixal::mnemonic::MixalMnemonic;
+
+    fn run_instructions(instructions: &[(Option<&str>, MixalMnemonic, Option<&str>)]) -> MixMachine {
+        let instructions: Vec<MixalInstruction> = instructions.iter()
+            .map(|(label, mnemonic, operand)| MixalInstruction::new(
+                label.map(String::from), *mnemonic, operand.map(String::from)
+            ))
+            .collect();
+        let program = load_program(&instructions).unwrap();
+        let mut machine = MixMachine::new(&program);
+        machine.run(1000).unwrap();
+        return machine;
+    }
+
+    #[test]
+    fn test_arithmetic_and_jumps() {
+        let machine = run_instructions(&[
+            (None, MixalMnemonic::ORIG, Some("100")),
+            (None, MixalMnemonic::ENTA, Some("7")),
+            (None, MixalMnemonic::STA, Some("0")),
+            (None, MixalMnemonic::ENTA, Some("20")),
+            (None, MixalMnemonic::STA, Some("1")),
+            (None, MixalMnemonic::ENNA, Some("2")),
+            (None, MixalMnemonic::STA, Some("2")),
+            (None, MixalMnemonic::ENTA, Some("0")),
+            (Some("LOOP"), MixalMnemonic::ADD, Some("0")),
+            (None, MixalMnemonic::CMPA, Some("1")),
+            (None, MixalMnemonic::JL, Some("LOOP")),
+            (None, MixalMnemonic::MUL, Some("2")),
+            (None, MixalMnemonic::STA, Some("3")),
+            (None, MixalMnemonic::LD1, Some("2")),
+            (None, MixalMnemonic::ENTA, Some("3,1")),
+            (None, MixalMnemonic::HLT, None),
+            (None, MixalMnemonic::END, Some("100"))
+        ]);
+        assert_eq!(machine.memory[3], MixWord { negative: true, magnitude: 0 });
+        assert_eq!(machine.rx.to_i64(), -42);
+        assert_eq!(machine.ri[0].to_i64(), -2);
+        assert_eq!(machine.ra.to_i64(), 1);
+        assert_eq!(machine.executed_instructions, 21);
+        // The loop of 'ADD', 'CMPA' and 'JL' runs 3 times
+        assert_eq!(machine.profile[107], InstructionProfile { executions: 3, time: 6 });
+        assert_eq!(machine.profile[109], InstructionProfile { executions: 3, time: 3 });
+        assert_eq!(machine.elapsed_time, 4 + 3 * 2 + 3 * (2 + 2 + 1) + 10 + 2 + 2 + 1 + 10);
+    }
+
+    #[test]
+    fn test_increments() {
+        let machine = run_instructions(&[
+            (None, MixalMnemonic::ORIG, Some("100")),
+            (None, MixalMnemonic::ENTA, Some("5")),
+            (None, MixalMnemonic::INCA, Some("3")),
+            (None, MixalMnemonic::DECA, Some("10")),
+            (None, MixalMnemonic::ENTX, Some("0")),
+            (None, MixalMnemonic::DECX, Some("1")),
+            (None, MixalMnemonic::INCX, Some("1")),
+            (None, MixalMnemonic::ENT1, Some("4")),
+            (None, MixalMnemonic::INC1, Some("2")),
+            (None, MixalMnemonic::DEC1, Some("7")),
+            (None, MixalMnemonic::HLT, None),
+            (None, MixalMnemonic::END, Some("100"))
+        ]);
+        assert_eq!(machine.ra.to_i64(), -2);
+        // A zero result keeps the sign of the register
+        assert_eq!(machine.rx, MixWord { negative: true, magnitude: 0 });
+        assert_eq!(machine.ri[0].to_i64(), -1);
+        assert!(!machine.overflow_toggle);
+    }
+
+    #[test]
+    fn test_print() {
+        let machine = run_instructions(&[
+            (None, MixalMnemonic::ORIG, Some("100")),
+            (None, MixalMnemonic::ENNA, Some("42")),
+            (None, MixalMnemonic::CHAR, None),
+            (None, MixalMnemonic::STA, Some("1")),
+            (None, MixalMnemonic::STX, Some("2")),
+            (None, MixalMnemonic::ENTX, Some("45")),
+            (None, MixalMnemonic::JAN, Some("SIGN")),
+            (None, MixalMnemonic::ENTX, Some("44")),
+            (Some("SIGN"), MixalMnemonic::STX, Some("0")),
+            (None, MixalMnemonic::OUT, Some("0(2:3)")),
+            (None, MixalMnemonic::HLT, None),
+            (None, MixalMnemonic::END, Some("100"))
+        ]);
+        assert_eq!(machine.output, vec!["    -0000000042"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let program = load_program(&[
+            MixalInstruction::new(None, MixalMnemonic::ORIG, Some(String::from("100"))),
+            MixalInstruction::new(Some(String::from("LOOP")), MixalMnemonic::JSJ, Some(String::from("LOOP"))),
+            MixalInstruction::new(None, MixalMnemonic::END, Some(String::from("100")))
+        ]).unwrap();
+        let mut machine = MixMachine::new(&program);
+        assert_eq!(machine.run(50), Err(String::from("the program did not halt after 50 instructions")));
+        let program = load_program(&[
+            MixalInstruction::new(None, MixalMnemonic::ORIG, Some(String::from("100"))),
+            MixalInstruction::new(None, MixalMnemonic::LDA, Some(String::from("-1"))),
+            MixalInstruction::new(None, MixalMnemonic::END, Some(String::from("100")))
+        ]).unwrap();
+        let mut machine = MixMachine::new(&program);
+        assert_eq!(machine.run(50), Err(String::from("the address -1 is outside of the memory")));
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use super::{instruction::MixalInstruction, mnemonic::MixalMnemonic};
+
+// A sequence of instructions that always run one after the other. Only
+// the first instruction of a block can have a label, and only the last
+// one can jump or halt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub instructions: Vec<MixalInstruction>,
+    // The indexes of the blocks that may run after this one. The
+    // target of a conditional jump comes before the next block.
+    pub successors: Vec<usize>,
+    // Whether the block is an entry of a jump table, which must keep
+    // its single jump and its place after the previous entry
+    pub is_jump_table_entry: bool
+}
+
+// The control flow graph of the code of a program, whose blocks are
+// kept in the order of the code. The first block is the entry of the
+// program. The optimizations change the instructions of the blocks,
+// and then rebuild the graph from them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowGraph {
+    pub blocks: Vec<BasicBlock>
+}
+
+impl FlowGraph {
+    // Builds the graph of the `instructions`, which are the machine
+    // instructions of the code, without the directives around them
+    pub fn new(instructions: Vec<MixalInstruction>) -> FlowGraph {
+        let mut blocks: Vec<BasicBlock> = vec![];
+        let mut ends_block = true;
+        for instruction in instructions {
+            if ends_block || instruction.label.is_some() {
+                blocks.push(BasicBlock { instructions: vec![], successors: vec![], is_jump_table_entry: false });
+            }
+            ends_block = jump_target(&instruction).is_some() || instruction.mnemonic == MixalMnemonic::HLT;
+            blocks.last_mut().expect("to exist").instructions.push(instruction);
+        }
+
+        let label_blocks = label_blocks(&blocks);
+        for index in 0..blocks.len() {
+            let last_instruction = blocks[index].instructions.last().expect("to exist");
+            let mut successors = vec![];
+            match jump_target(last_instruction) {
+                // The entries of a jump table are unlabeled jumps that follow
+                // its first entry, and are only reached through the table
+                Some(target) if is_indexed_jump(last_instruction) => {
+                    let first_entry = label_blocks[target];
+                    successors.push(first_entry);
+                    for (entry, block) in blocks.iter_mut().enumerate().skip(first_entry) {
+                        let is_entry = block.instructions.len() == 1
+                            && is_unconditional_jump(&block.instructions[0])
+                            && (entry == first_entry || block.instructions[0].label.is_none());
+                        if !is_entry {
+                            break;
+                        }
+                        block.is_jump_table_entry = true;
+                        if entry != first_entry {
+                            successors.push(entry);
+                        }
+                    }
+                },
+                Some(target) => {
+                    successors.push(label_blocks[target]);
+                    if !is_unconditional_jump(last_instruction) && index + 1 < blocks.len() {
+                        successors.push(index + 1);
+                    }
+                },
+                None if last_instruction.mnemonic == MixalMnemonic::HLT => {},
+                None if index + 1 < blocks.len() => successors.push(index + 1),
+                None => {}
+            }
+            blocks[index].successors = successors;
+        }
+        return FlowGraph { blocks };
+    }
+
+    // Returns the instructions of the blocks, in order
+    pub fn instructions(&self) -> Vec<MixalInstruction> {
+        return self.blocks.iter().flat_map(|x| x.instructions.iter().cloned()).collect();
+    }
+
+    // Runs the optimizations until none of them changes the code
+    pub fn optimize(&mut self) {
+        loop {
+            let changed = self.remove_unreachable_blocks()
+                | self.thread_jumps()
+                | self.remove_jumps_to_next_blocks()
+                | self.merge_empty_blocks();
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    // Removes the blocks that no path from the entry reaches, eg the
+    // statements after a 'break' or the block of an 'if (false)'
+    pub fn remove_unreachable_blocks(&mut self) -> bool {
+        let mut reachable = HashSet::from([0]);
+        let mut queue = VecDeque::from([0]);
+        while let Some(index) = queue.pop_front() {
+            for successor in &self.blocks[index].successors {
+                if reachable.insert(*successor) {
+                    queue.push_back(*successor);
+                }
+            }
+        }
+        if reachable.len() == self.blocks.len() || self.blocks.is_empty() {
+            return false;
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        let instructions: Vec<MixalInstruction> = blocks.into_iter()
+            .enumerate()
+            .filter(|(index, _)| reachable.contains(index))
+            .flat_map(|(_, x)| x.instructions)
+            .collect();
+        *self = FlowGraph::new(instructions);
+        return true;
+    }
+
+    // Makes the jumps to a block that only jumps elsewhere go directly
+    // to the final target, eg the jump out of an 'if' at the end of a loop
+    pub fn thread_jumps(&mut self) -> bool {
+        let label_blocks = label_blocks(&self.blocks);
+        // The label that a jump to the block ends up at, if the block only jumps
+        let forwarded_target = |index: usize| {
+            let mut instructions = self.blocks[index].instructions.iter().filter(|x| x.mnemonic != MixalMnemonic::NOP);
+            return match (instructions.next(), instructions.next()) {
+                (Some(jump), None) if is_unconditional_jump(jump) && !is_indexed_jump(jump) => jump_target(jump),
+                _ => None
+            };
+        };
+
+        let mut new_targets = vec![];
+        for (block_index, block) in self.blocks.iter().enumerate() {
+            for (instruction_index, instruction) in block.instructions.iter().enumerate() {
+                let Some(target) = jump_target(instruction).filter(|_| !is_indexed_jump(instruction)) else {
+                    continue;
+                };
+                // A jump into a loop of jumps that never ends is left as it is
+                let mut final_target = Some(target);
+                let mut visited = HashSet::from([target]);
+                while let Some(next_target) = final_target.and_then(|x| forwarded_target(label_blocks[x])) {
+                    final_target = Some(next_target).filter(|x| visited.insert(x));
+                }
+                if let Some(final_target) = final_target.filter(|x| *x != target) {
+                    new_targets.push((block_index, instruction_index, final_target.to_string()));
+                }
+            }
+        }
+        if new_targets.is_empty() {
+            return false;
+        }
+        for (block_index, instruction_index, target) in new_targets {
+            self.blocks[block_index].instructions[instruction_index].operand = Some(target);
+        }
+        *self = FlowGraph::new(self.instructions());
+        return true;
+    }
+
+    // Removes the jumps to the block that follows, which runs anyway
+    pub fn remove_jumps_to_next_blocks(&mut self) -> bool {
+        let mut changed = false;
+        for index in 0..self.blocks.len().saturating_sub(1) {
+            let next_label = self.blocks[index + 1].instructions[0].label.clone();
+            let block = &mut self.blocks[index];
+            let last_instruction = block.instructions.last().expect("to exist");
+            if block.is_jump_table_entry || is_indexed_jump(last_instruction) {
+                continue;
+            }
+            if jump_target(last_instruction).is_some() && jump_target(last_instruction) == next_label.as_deref() {
+                block.instructions.pop();
+                changed = true;
+            }
+        }
+        if changed {
+            *self = FlowGraph::new(self.instructions());
+        }
+        return changed;
+    }
+
+    // Removes the 'NOP' instructions that only hold a label, and moves
+    // their label to the instruction that follows. When that instruction
+    // already has a label, the jumps to the removed label are renamed.
+    pub fn merge_empty_blocks(&mut self) -> bool {
+        let mut renamed_labels: HashMap<String, String> = HashMap::new();
+        let mut changed = false;
+        let mut instructions = self.instructions();
+        let mut index = 0;
+        while index + 1 < instructions.len() {
+            if instructions[index].mnemonic != MixalMnemonic::NOP {
+                index += 1;
+                continue;
+            }
+            let nop = instructions.remove(index);
+            changed = true;
+            let Some(label) = nop.label else {
+                continue;
+            };
+            match &instructions[index].label {
+                Some(next_label) => {
+                    renamed_labels.insert(label, next_label.clone());
+                },
+                None => instructions[index].label = Some(label)
+            }
+        }
+        if !changed {
+            return false;
+        }
+        for instruction in &mut instructions {
+            let Some(target) = jump_target(instruction) else {
+                continue;
+            };
+            // A label may have been renamed to a label that was then renamed too
+            let mut new_target = target.to_string();
+            while let Some(renamed_label) = renamed_labels.get(&new_target) {
+                new_target = renamed_label.clone();
+            }
+            if new_target != target {
+                let index = instruction.operand.as_ref().expect("to exist").strip_prefix(target).expect("to exist");
+                instruction.operand = Some(format!("{}{}", new_target, index));
+            }
+        }
+        *self = FlowGraph::new(instructions);
+        return true;
+    }
+
+    // Returns the graph in the DOT language of Graphviz, with the
+    // instructions of every block in its node
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let label: String = block.instructions.iter()
+                .map(|x| format!("{}\\l", x.to_string().trim_end().replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect();
+            dot.push_str(&format!("    b{} [label=\"{}\"];\n", index, label));
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in &block.successors {
+                dot.push_str(&format!("    b{} -> b{};\n", index, successor));
+            }
+        }
+        dot.push_str("}\n");
+        return dot;
+    }
+}
+
+// The label that the instruction jumps to, if it is a jump
+fn jump_target(instruction: &MixalInstruction) -> Option<&str> {
+    let is_jump = matches!(
+        instruction.mnemonic,
+        MixalMnemonic::JSJ | MixalMnemonic::JL | MixalMnemonic::JE | MixalMnemonic::JG
+        | MixalMnemonic::JGE | MixalMnemonic::JNE | MixalMnemonic::JLE | MixalMnemonic::JAN
+    );
+    if !is_jump {
+        return None;
+    }
+    let operand = instruction.operand.as_deref().expect("to exist");
+    return Some(operand.split(',').next().expect("to exist"));
+}
+
+fn is_unconditional_jump(instruction: &MixalInstruction) -> bool {
+    return instruction.mnemonic == MixalMnemonic::JSJ;
+}
+
+// Whether the jump goes to its label plus the value of an index register
+fn is_indexed_jump(instruction: &MixalInstruction) -> bool {
+    return jump_target(instruction).is_some() && instruction.operand.as_ref().is_some_and(|x| x.contains(','));
+}
+
+// key: a label of the code
+// value: the index of the block that it starts
+fn label_blocks(blocks: &[BasicBlock]) -> HashMap<String, usize> {
+    return blocks.iter()
+        .enumerate()
+        .filter_map(|(index, block)| block.instructions[0].label.clone().map(|x| (x, index)))
+        .collect();
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parses the instructions of a test, one per line, as 'LABEL MNEMONIC OPERAND'
+    fn instructions(code: &str) -> Vec<MixalInstruction> {
+        let mnemonics = [
+            ("NOP", MixalMnemonic::NOP), ("ENTA", MixalMnemonic::ENTA), ("STA", MixalMnemonic::STA),
+            ("CMPA", MixalMnemonic::CMPA), ("JSJ", MixalMnemonic::JSJ), ("JE", MixalMnemonic::JE),
+            ("JG", MixalMnemonic::JG), ("ENT1", MixalMnemonic::ENT1), ("HLT", MixalMnemonic::HLT)
+        ];
+        return code.lines().filter(|x| !x.is_empty()).map(|line| {
+            let (label, rest) = line.split_once(' ').expect("to be valid");
+            let mut rest = rest.split(' ');
+            let mnemonic = rest.next().expect("to be valid");
+            let mnemonic = mnemonics.iter().find(|(x, _)| *x == mnemonic).expect("to be valid").1;
+            let label = Some(label.to_string()).filter(|x| !x.is_empty());
+            return MixalInstruction::new(label, mnemonic, rest.next().map(String::from));
+        }).collect();
+    }
+
+    fn code(graph: &FlowGraph) -> String {
+        return graph.instructions().iter().map(|x| x.to_string()).collect();
+    }
+
+    #[test]
+    fn test_blocks() {
+        let graph = FlowGraph::new(instructions("
+ CMPA A
+ JE L1
+ ENTA 1
+ JSJ L2
+L1 NOP
+ ENTA 2
+L2 STA A
+ HLT"));
+        let successors: Vec<Vec<usize>> = graph.blocks.iter().map(|x| x.successors.clone()).collect();
+        assert_eq!(successors, vec![vec![2, 1], vec![3], vec![3], vec![]]);
+        assert_eq!(graph.blocks[2].instructions.len(), 2);
+        assert!(graph.to_dot().contains("    b1 [label=\" ENTA 1\\l JSJ L2\\l\"];\n    b2"));
+        assert!(graph.to_dot().contains("    b0 -> b2;\n    b0 -> b1;\n"));
+    }
+
+    #[test]
+    fn test_remove_unreachable_blocks() {
+        let mut graph = FlowGraph::new(instructions("
+L1 NOP
+ JSJ L2
+ ENTA 1
+ STA A
+L3 ENTA 2
+L2 STA A
+ HLT
+ ENTA 3"));
+        assert!(graph.remove_unreachable_blocks());
+        assert_eq!(code(&graph), "L1 NOP \n JSJ L2\nL2 STA A\n HLT \n");
+        assert!(!graph.remove_unreachable_blocks());
+    }
+
+    #[test]
+    fn test_thread_jumps() {
+        let mut graph = FlowGraph::new(instructions("
+ CMPA A
+ JE L1
+ JSJ L4
+L1 NOP
+ JSJ L2
+L2 JSJ L5
+L4 JSJ L4
+L5 HLT"));
+        assert!(graph.thread_jumps());
+        // The jump to L4 loops forever, so it is kept
+        assert_eq!(code(&graph), " CMPA A\n JE L5\n JSJ L4\nL1 NOP \n JSJ L5\nL2 JSJ L5\nL4 JSJ L4\nL5 HLT \n");
+        assert!(!graph.thread_jumps());
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut graph = FlowGraph::new(instructions("
+ CMPA A
+ JE L1
+ ENTA 1
+ JSJ L2
+ ENTA 5
+L1 NOP
+L3 NOP
+ ENTA 2
+ JSJ L4
+L4 NOP
+L2 NOP
+ STA A
+ HLT"));
+        graph.optimize();
+        assert_eq!(code(&graph), " CMPA A\n JE L3\n ENTA 1\n JSJ L2\nL3 ENTA 2\nL2 STA A\n HLT \n");
+    }
+
+    #[test]
+    fn test_jump_tables() {
+        let mut graph = FlowGraph::new(instructions("
+ ENT1 0
+ JSJ L1,1
+L1 JSJ L2
+ JSJ L3
+ JSJ L3
+L2 NOP
+ ENTA 1
+ JSJ L4
+L3 ENTA 2
+L4 HLT"));
+        assert_eq!(graph.blocks[0].successors, vec![1, 2, 3]);
+        assert!(graph.blocks[1..4].iter().all(|x| x.is_jump_table_entry));
+        graph.optimize();
+        // The entries are kept, even if they jump to the block that follows the table
+        assert_eq!(code(&graph), " ENT1 0\n JSJ L1,1\nL1 JSJ L2\n JSJ L3\n JSJ L3\nL2 ENTA 1\n JSJ L4\nL3 ENTA 2\nL4 HLT \n");
+    }
+}
@@ -1,9 +1,14 @@
 use super::mnemonic::MixalMnemonic;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct MixalInstruction {
     pub label: Option<String>,
     pub mnemonic: MixalMnemonic,
     pub operand: Option<String>,
+    // The id of the AST node of the statement that the instruction was
+    // generated for, which is the index of the token of the statement.
+    // The directives of the data and the final instructions have none.
+    pub source: Option<usize>
 }
 
 impl MixalInstruction {
@@ -13,11 +18,12 @@ impl MixalInstruction {
         operand: Option<String>
     ) -> MixalInstruction{
         MixalInstruction {
-            label, mnemonic, operand
+            label, mnemonic, operand, source: None
         }
     }
 
-    pub fn to_string(&mut self) -> String {
+    #[allow(clippy::inherent_to_string, clippy::wrong_self_convention)]
+    pub fn to_string(&self) -> String {
         let mut label_value = String::from("");
         if let Option::Some(value) = &self.label {
             label_value = value.clone();
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use super::{emulator::CHARACTER_SET, instruction::MixalInstruction, mnemonic::MixalMnemonic, word::MixWord};
+
+// The number of words of the MIX memory
+pub const MEMORY_SIZE: usize = 4000;
+
+// The address part of an instruction has 2 bytes
+const INSTRUCTION_ADDRESS_MODULO: u64 = 1 << 12;
+
+// A MIXAL program, assembled and loaded to the MIX memory
+pub struct MixProgram {
+    pub memory: Vec<MixWord>,
+    // The address of the first instruction to execute
+    pub start_address: u16,
+    // key: a symbol (eg a label) of the program
+    // value: the value of the symbol (eg the address of a label)
+    pub symbols: HashMap<String, i64>
+}
+
+// Returns the operation code and the default field specification of a
+// MIX instruction, or `None` for the assembly pseudoinstructions.
+pub fn mnemonic_to_opcode(mnemonic: &MixalMnemonic) -> Option<(u8, u8)> {
+    let opcode = match mnemonic {
+        MixalMnemonic::ORIG | MixalMnemonic::END | MixalMnemonic::EQU
+        | MixalMnemonic::CON | MixalMnemonic::ALF => return None,
+        MixalMnemonic::NOP => (0, 0),
+        MixalMnemonic::ADD => (1, 5),
+        MixalMnemonic::SUB => (2, 5),
+        MixalMnemonic::MUL => (3, 5),
+        MixalMnemonic::DIV => (4, 5),
+        MixalMnemonic::CHAR => (5, 1),
+        MixalMnemonic::HLT => (5, 2),
+        MixalMnemonic::LDA => (8, 5),
+        MixalMnemonic::LD1 => (9, 5),
+        MixalMnemonic::LDX => (15, 5),
+        MixalMnemonic::STA => (24, 5),
+        MixalMnemonic::ST1 => (25, 5),
+        MixalMnemonic::STX => (31, 5),
+        MixalMnemonic::STZ => (33, 5),
+        MixalMnemonic::OUT => (37, 0),
+        MixalMnemonic::JSJ => (39, 1),
+        MixalMnemonic::JL => (39, 4),
+        MixalMnemonic::JE => (39, 5),
+        MixalMnemonic::JG => (39, 6),
+        MixalMnemonic::JGE => (39, 7),
+        MixalMnemonic::JNE => (39, 8),
+        MixalMnemonic::JLE => (39, 9),
+        MixalMnemonic::JAN => (40, 0),
+        MixalMnemonic::INCA => (48, 0),
+        MixalMnemonic::DECA => (48, 1),
+        MixalMnemonic::ENTA => (48, 2),
+        MixalMnemonic::ENNA => (48, 3),
+        MixalMnemonic::INC1 => (49, 0),
+        MixalMnemonic::DEC1 => (49, 1),
+        MixalMnemonic::ENT1 => (49, 2),
+        MixalMnemonic::ENN1 => (49, 3),
+        MixalMnemonic::INCX => (55, 0),
+        MixalMnemonic::DECX => (55, 1),
+        MixalMnemonic::ENTX => (55, 2),
+        MixalMnemonic::ENNX => (55, 3),
+        MixalMnemonic::CMPA => (56, 5),
+        MixalMnemonic::CMP1 => (57, 5),
+        MixalMnemonic::CMPX => (63, 5)
+    };
+    return Some(opcode);
+}
+
+// Assembles the instructions and loads them to the MIX memory, as
+// `mixasm` and `mixvm` do. Only the subset of MIXAL that the compiler
+// generates is supported. The operands have the form `ADDRESS,INDEX(FIELD)`,
+// where only the address is required, and the address is a number, a
+// symbol or `*`, optionally added to or subtracted from other ones.
+// The operand of `CON` and `EQU` is such an address, without an index
+// or a field, and the operand of `ALF` is 5 characters within quotes.
+pub fn load_program(instructions: &[MixalInstruction]) -> Result<MixProgram, String> {
+    // First pass: find the values of the symbols
+    let mut symbols = HashMap::new();
+    let mut location_counter: i64 = 0;
+    for instruction in instructions {
+        if let Some(label) = &instruction.label {
+            // The symbol of 'EQU' is the value of its operand, not an address
+            let value = match instruction.mnemonic {
+                MixalMnemonic::EQU => evaluate_address(operand(instruction)?, &symbols, location_counter)?,
+                _ => location_counter
+            };
+            if symbols.insert(label.clone(), value).is_some() {
+                return Err(format!("the symbol '{}' is defined more than once", label));
+            }
+        }
+        match instruction.mnemonic {
+            MixalMnemonic::ORIG => {
+                location_counter = evaluate_address(operand(instruction)?, &symbols, location_counter)?;
+            },
+            MixalMnemonic::EQU => {},
+            MixalMnemonic::END => break,
+            _ => location_counter += 1
+        }
+    }
+
+    // Second pass: encode the instructions to memory words
+    let mut memory = vec![MixWord::default(); MEMORY_SIZE];
+    let mut start_address = 0;
+    location_counter = 0;
+    for instruction in instructions {
+        let (code, default_field) = match mnemonic_to_opcode(&instruction.mnemonic) {
+            Some(opcode) => opcode,
+            None if instruction.mnemonic == MixalMnemonic::ORIG => {
+                location_counter = evaluate_address(operand(instruction)?, &symbols, location_counter)?;
+                continue;
+            },
+            None if instruction.mnemonic == MixalMnemonic::EQU => continue,
+            None if instruction.mnemonic == MixalMnemonic::CON || instruction.mnemonic == MixalMnemonic::ALF => {
+                let word = if instruction.mnemonic == MixalMnemonic::CON {
+                    MixWord::from_i64(evaluate_address(operand(instruction)?, &symbols, location_counter)?)
+                } else {
+                    alphanumeric_word(operand(instruction)?)?
+                };
+                memory[to_memory_address(location_counter)? as usize] = word;
+                location_counter += 1;
+                continue;
+            },
+            None => {
+                let address = evaluate_address(operand(instruction)?, &symbols, location_counter)?;
+                start_address = to_memory_address(address)?;
+                break;
+            }
+        };
+        let (address, index, field) = match &instruction.operand {
+            Some(operand) => parse_operand(operand, &symbols, location_counter)?,
+            None => (0, 0, None)
+        };
+        // As in `mixasm`, only the 2 LSBytes of an address that does not fit
+        // in the instruction are kept
+        let magnitude = address.unsigned_abs() % INSTRUCTION_ADDRESS_MODULO;
+        let word = MixWord::from_bytes(address < 0, &[
+            (magnitude >> 6) as u8,
+            (magnitude & 63) as u8,
+            index,
+            field.unwrap_or(default_field),
+            code
+        ]);
+        memory[to_memory_address(location_counter)? as usize] = word;
+        location_counter += 1;
+    }
+
+    return Ok(MixProgram { memory, start_address, symbols });
+}
+
+fn operand(instruction: &MixalInstruction) -> Result<&str, String> {
+    return instruction.operand.as_deref()
+        .ok_or(format!("'{:?}' requires an operand", instruction.mnemonic));
+}
+
+fn to_memory_address(address: i64) -> Result<u16, String> {
+    if address < 0 || address >= MEMORY_SIZE as i64 {
+        return Err(format!("the address {} is outside of the memory", address));
+    }
+    return Ok(address as u16);
+}
+
+// Returns the word of the 5 characters of an 'ALF' operand, eg `"EXCEP"`
+fn alphanumeric_word(operand: &str) -> Result<MixWord, String> {
+    let invalid_operand = || format!("invalid 'ALF' operand '{}'", operand);
+    let characters = operand.strip_prefix('"').and_then(|x| x.strip_suffix('"')).ok_or_else(invalid_operand)?;
+    let bytes = characters.chars()
+        .map(|x| CHARACTER_SET.chars().position(|y| y == x).map(|y| y as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid_operand)?;
+    if bytes.len() != 5 {
+        return Err(invalid_operand());
+    }
+    return Ok(MixWord::from_bytes(false, &bytes));
+}
+
+// Returns the address, the index and the field specification of an operand
+fn parse_operand(
+    operand: &str,
+    symbols: &HashMap<String, i64>,
+    location_counter: i64
+) -> Result<(i64, u8, Option<u8>), String> {
+    let (operand, field) = match operand.split_once('(') {
+        Some((operand, field)) => {
+            let field = field.strip_suffix(')').ok_or(format!("invalid field specification in '{}'", operand))?;
+            (operand, Some(parse_field(field)?))
+        },
+        None => (operand, None)
+    };
+    let (address, index) = match operand.split_once(',') {
+        Some((address, index)) => {
+            let index: u8 = index.trim().parse().map_err(|_| format!("invalid index '{}'", index))?;
+            if index > 6 {
+                return Err(format!("invalid index '{}'", index));
+            }
+            (address, index)
+        },
+        None => (operand, 0)
+    };
+    let address = if address.trim().is_empty() { 0 } else { evaluate_address(address, symbols, location_counter)? };
+    return Ok((address, index, field));
+}
+
+// Parses a field specification, which is either `L:R` or a number
+fn parse_field(field: &str) -> Result<u8, String> {
+    let invalid_field = || format!("invalid field specification '{}'", field);
+    let value = match field.split_once(':') {
+        Some((left, right)) => {
+            let left: u8 = left.trim().parse().map_err(|_| invalid_field())?;
+            let right: u8 = right.trim().parse().map_err(|_| invalid_field())?;
+            if left > right || right > 5 {
+                return Err(invalid_field());
+            }
+            8 * left + right
+        },
+        None => field.trim().parse().map_err(|_| invalid_field())?
+    };
+    if value > 63 {
+        return Err(invalid_field());
+    }
+    return Ok(value);
+}
+
+// Evaluates an address expression, eg `LABEL+1` or `-5`
+fn evaluate_address(expression: &str, symbols: &HashMap<String, i64>, location_counter: i64) -> Result<i64, String> {
+    let expression = expression.trim();
+    let mut value = 0;
+    let mut sign = 1;
+    let mut term_start = 0;
+    let characters: Vec<char> = expression.chars().collect();
+    for index in 0..=characters.len() {
+        let is_operator = index < characters.len()
+            && (characters[index] == '+' || characters[index] == '-')
+            // A sign at the start of the expression is not an operator
+            && index > 0;
+        if index < characters.len() && !is_operator {
+            continue;
+        }
+        let mut term: String = characters[term_start..index].iter().collect();
+        let mut term_sign = sign;
+        if term.starts_with('-') || term.starts_with('+') {
+            if term.starts_with('-') {
+                term_sign = -term_sign;
+            }
+            term.remove(0);
+        }
+        let term_value = if term == "*" {
+            location_counter
+        } else if let Ok(number) = term.parse::<i64>() {
+            number
+        } else {
+            *symbols.get(&term).ok_or(format!("undefined symbol '{}'", term))?
+        };
+        value += term_sign * term_value;
+        if index < characters.len() {
+            sign = if characters[index] == '-' { -1 } else { 1 };
+        }
+        term_start = index + 1;
+    }
+    return Ok(value);
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(label: Option<&str>, mnemonic: MixalMnemonic, operand: Option<&str>) -> MixalInstruction {
+        return MixalInstruction::new(label.map(String::from), mnemonic, operand.map(String::from));
+    }
+
+    #[test]
+    fn test_load_program() {
+        let program = load_program(&[
+            instruction(None, MixalMnemonic::ORIG, Some("2000")),
+            instruction(Some("LOOP"), MixalMnemonic::LDA, Some("1986(2:3)")),
+            instruction(None, MixalMnemonic::ENNA, Some("1")),
+            instruction(None, MixalMnemonic::JSJ, Some("LOOP+1")),
+            instruction(None, MixalMnemonic::LDX, Some("*-2,1")),
+            instruction(None, MixalMnemonic::ENTA, Some("4097")),
+            instruction(None, MixalMnemonic::END, Some("LOOP"))
+        ]).unwrap();
+        assert_eq!(program.start_address, 2000);
+        assert_eq!(program.symbols.get("LOOP"), Some(&2000));
+        assert_eq!(program.memory[2000], MixWord::from_bytes(false, &[31, 2, 0, 19, 8]));
+        assert_eq!(program.memory[2001], MixWord::from_bytes(false, &[0, 1, 0, 3, 48]));
+        assert_eq!(program.memory[2002], MixWord::from_bytes(false, &[31, 17, 0, 1, 39]));
+        assert_eq!(program.memory[2003], MixWord::from_bytes(false, &[31, 17, 1, 5, 15]));
+        assert_eq!(program.memory[2004], MixWord::from_bytes(false, &[0, 1, 0, 2, 48]));
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let load = |operand: &str| {
+            return load_program(&[
+                instruction(None, MixalMnemonic::ORIG, Some("100")),
+                instruction(Some("A"), MixalMnemonic::LDA, Some(operand)),
+                instruction(None, MixalMnemonic::END, Some("100"))
+            ]).err().unwrap();
+        };
+        assert_eq!(load("B"), "undefined symbol 'B'");
+        assert_eq!(load("1(3:2)"), "invalid field specification '3:2'");
+        assert_eq!(load("1,7"), "invalid index '7'");
+        let error = load_program(&[
+            instruction(Some("A"), MixalMnemonic::NOP, None),
+            instruction(Some("A"), MixalMnemonic::NOP, None)
+        ]).err().unwrap();
+        assert_eq!(error, "the symbol 'A' is defined more than once");
+        let error = load_program(&[instruction(None, MixalMnemonic::ALF, Some("\"AB\""))]).err().unwrap();
+        assert_eq!(error, "invalid 'ALF' operand '\"AB\"'");
+    }
+
+    #[test]
+    fn test_data_directives() {
+        let program = load_program(&[
+            instruction(Some("BUFFER"), MixalMnemonic::EQU, Some("1986")),
+            instruction(None, MixalMnemonic::ORIG, Some("BUFFER-2")),
+            instruction(Some("TOTAL"), MixalMnemonic::CON, Some("-100000")),
+            instruction(Some("TEXT"), MixalMnemonic::ALF, Some("\"EXCEP\"")),
+            instruction(None, MixalMnemonic::ORIG, Some("2000")),
+            instruction(None, MixalMnemonic::LDA, Some("TOTAL(0:5)")),
+            instruction(None, MixalMnemonic::STA, Some("BUFFER+1")),
+            instruction(None, MixalMnemonic::END, Some("2000"))
+        ]).unwrap();
+        assert_eq!(program.symbols.get("BUFFER"), Some(&1986));
+        assert_eq!(program.symbols.get("TOTAL"), Some(&1984));
+        assert_eq!(program.memory[1984], MixWord::from_i64(-100000));
+        assert_eq!(program.memory[1985], MixWord::from_bytes(false, &[5, 27, 3, 5, 17]));
+        assert_eq!(program.memory[2000], MixWord::from_bytes(false, &[31, 0, 0, 5, 8]));
+        assert_eq!(program.memory[2001], MixWord::from_bytes(false, &[31, 3, 0, 5, 24]));
+    }
+}
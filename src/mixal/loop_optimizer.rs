@@ -0,0 +1,465 @@
+use orange_trees::Node;
+use std::collections::{HashMap, HashSet};
+use crate::lexer::Token;
+use crate::interpreter::evaluate_constant_expression;
+use crate::utilities::{is_assignment_operator, new_node_from_token};
+use super::assembler::constant_value_to_node;
+
+// Optimizes the loops of a program on its tree, where a loop is a single
+// node along with its condition, its body and, for a 'for' loop, its step,
+// before the code is generated. Two optimizations move work out of the
+// iterations of a loop:
+//  - Loop-invariant code motion: an expression that only reads variables
+//    that the loop never assigns has the same value in every iteration,
+//    so it is computed once, into a variable, right before the loop.
+//  - Strength reduction: when a 'for' loop only changes its variable `i`
+//    by adding a constant in its step, `i * c` grows by the same amount
+//    in every iteration. The product is then kept in a variable that the
+//    step increments along with `i`, instead of being multiplied again.
+// The values of the optimizations are kept in variables with names that
+// are not identifiers, eg 'invariant.1', which are not declared in the
+// tree. The assembler stores them apart from the variables of the program.
+#[derive(Default)]
+pub struct LoopOptimizer {
+    // The variables that the optimizations have added, in order
+    pub variables: Vec<String>
+}
+
+impl LoopOptimizer {
+    pub fn new() -> LoopOptimizer {
+        return LoopOptimizer::default();
+    }
+
+    // Returns a copy of the tree of a valid program, with its loops
+    // optimized. The constants must already be replaced by their values.
+    pub fn run(&mut self, ast: &Node<usize, Token>) -> Node<usize, Token> {
+        return self.optimize_statement(ast);
+    }
+
+    // Returns the statement with the loops in it optimized. The statements
+    // that compute the values of a loop before it are placed right before
+    // the loop, in its block, and before its label, if any.
+    fn optimize_statement(&mut self, node: &Node<usize, Token>) -> Node<usize, Token> {
+        let mut new_node = new_node_from_token(*node.id(), node.value().clone());
+        for child in node.children() {
+            if matches!(node.value(), Token::Ast(_)) && loop_of_statement(child).is_some() {
+                let (preheader, statement) = self.optimize_loop(child);
+                for preheader_statement in preheader {
+                    new_node.add_child(preheader_statement);
+                }
+                new_node.add_child(statement);
+            } else {
+                new_node.add_child(self.optimize_statement(child));
+            }
+        }
+        return new_node;
+    }
+
+    // Optimizes the loop of the statement, which may be labeled, and then
+    // the loops in its body. Returns the statements to run before the loop,
+    // along with the new statement.
+    fn optimize_loop(&mut self, statement: &Node<usize, Token>) -> (Vec<Node<usize, Token>>, Node<usize, Token>) {
+        let loop_node = loop_of_statement(statement).expect("to exist");
+        let loop_id = *loop_node.id();
+        let assigned_variables = get_assigned_variables(loop_node);
+
+        // The initialization of a 'for' loop only runs once
+        let mut invariants = vec![];
+        let mut new_loop_node = new_node_from_token(loop_id, loop_node.value().clone());
+        for (index, child) in loop_node.children().iter().enumerate() {
+            if *loop_node.value() == Token::For && index == 0 {
+                new_loop_node.add_child(child.clone());
+            } else {
+                new_loop_node.add_child(self.hoist_invariant_expressions(child, &assigned_variables, &mut invariants));
+            }
+        }
+        let mut preheader: Vec<Node<usize, Token>> = invariants.into_iter()
+            .map(|(variable, expression)| new_assignment_node(loop_id, Token::Assignment, &variable, expression))
+            .collect();
+        if *loop_node.value() == Token::For {
+            new_loop_node = self.reduce_strength(new_loop_node, &mut preheader);
+        }
+
+        // The inner loops are optimized once the expressions that do not
+        // change in the outer loop are out of it
+        let mut new_statement = self.optimize_statement(&new_loop_node);
+        if let Token::Label(_) = statement.value() {
+            let mut label_node = new_node_from_token(*statement.id(), statement.value().clone());
+            label_node.add_child(new_statement);
+            new_statement = label_node;
+        }
+        return (preheader, new_statement);
+    }
+
+    // Replaces every expression of the node that has the same value in every
+    // iteration of the loop, where the `assigned_variables` are assigned, with
+    // a variable. The variables and the expressions they hold are added to
+    // `invariants`, and an expression that is already there reuses its variable.
+    fn hoist_invariant_expressions(
+        &mut self,
+        node: &Node<usize, Token>,
+        assigned_variables: &HashSet<String>,
+        invariants: &mut Vec<(String, Node<usize, Token>)>
+    ) -> Node<usize, Token> {
+        // Variables and constants are not worth a variable of their own, and
+        // the assembler enters the values of the constant expressions directly
+        let is_worth_hoisting = |x: &Node<usize, Token>| {
+            return !x.is_leaf() && evaluate_constant_expression(x, &HashMap::new()).is_none();
+        };
+        if is_expression(node.value()) && is_worth_hoisting(node) && is_invariant(node, assigned_variables) {
+            let variable = match invariants.iter().find(|(_, x)| is_same_expression(x, node)) {
+                Some((variable, _)) => variable.clone(),
+                None => {
+                    let variable = self.new_variable("invariant");
+                    invariants.push((variable.clone(), node.clone()));
+                    variable
+                }
+            };
+            return new_node_from_token(*node.id(), Token::Id(variable));
+        }
+        let mut new_node = new_node_from_token(*node.id(), node.value().clone());
+        for (index, child) in node.children().iter().enumerate() {
+            if is_case_label(node, index) {
+                new_node.add_child(child.clone());
+            } else {
+                new_node.add_child(self.hoist_invariant_expressions(child, assigned_variables, invariants));
+            }
+        }
+        return new_node;
+    }
+
+    // Replaces the products of the variable of the 'for' loop with a value
+    // that does not change in the loop, eg `i * c`, by variables that hold
+    // them. The products start with the first value of `i` before the loop,
+    // and grow along with `i` in its step.
+    //
+    // Only the loops whose variable starts at a constant that is not negative
+    // and grows by a positive constant are reduced, and only if their condition
+    // and body do not assign it. The variable is then never negative, so every
+    // product has the sign of `c`, and the additions give the same words as
+    // the multiplications of MIX, including when they overflow.
+    fn reduce_strength(&mut self, node: Node<usize, Token>, preheader: &mut Vec<Node<usize, Token>>) -> Node<usize, Token> {
+        let children = node.children();
+        let Some((variable, start, step)) = get_induction_variable(&children[0], &children[2]) else {
+            return node;
+        };
+        let mut body_assigned_variables = get_assigned_variables(&children[1]);
+        body_assigned_variables.extend(get_assigned_variables(&children[3]));
+        if body_assigned_variables.contains(&variable) {
+            return node;
+        }
+
+        let assigned_variables = get_assigned_variables(&node);
+        let mut products = vec![];
+        let condition_node = self.replace_products(&children[1], &variable, &assigned_variables, &mut products);
+        let code_block_node = self.replace_products(&children[3], &variable, &assigned_variables, &mut products);
+        if products.is_empty() {
+            return node;
+        }
+
+        let loop_id = *node.id();
+        let step_id = *children[2].id();
+        let mut step_node = new_node_from_token(step_id, Token::Ast(String::from("BLOCK")));
+        step_node.add_child(children[2].clone());
+        for (product, factor) in products {
+            let start_node = new_binary_node(loop_id, Token::Asterisk, Token::Num(start), factor.clone());
+            let increment_node = new_binary_node(loop_id, Token::Asterisk, Token::Num(step), factor.clone());
+            match evaluate_constant_expression(&increment_node, &HashMap::new()) {
+                Some(increment) => {
+                    let start_value = evaluate_constant_expression(&start_node, &HashMap::new()).expect("to be constant");
+                    preheader.push(new_assignment_node(loop_id, Token::Assignment, &product, constant_value_to_node(loop_id, start_value)));
+                    // A negative increment is subtracted, so that it is an immediate value
+                    let operator = if increment.negative { Token::SubtractionAssignment } else { Token::AdditionAssignment };
+                    let increment_node = new_node_from_token(step_id, Token::Num(increment.magnitude as i32));
+                    step_node.add_child(new_assignment_node(step_id, operator, &product, increment_node));
+                },
+                None => {
+                    preheader.push(new_assignment_node(loop_id, Token::Assignment, &product, start_node));
+                    let increment_node = if step == 1 {
+                        factor
+                    } else {
+                        let increment_variable = self.new_variable("step");
+                        preheader.push(new_assignment_node(loop_id, Token::Assignment, &increment_variable, increment_node));
+                        new_node_from_token(step_id, Token::Id(increment_variable))
+                    };
+                    step_node.add_child(new_assignment_node(step_id, Token::AdditionAssignment, &product, increment_node));
+                }
+            }
+        }
+
+        let mut new_node = new_node_from_token(loop_id, Token::For);
+        new_node.add_child(children[0].clone());
+        new_node.add_child(condition_node);
+        new_node.add_child(step_node);
+        new_node.add_child(code_block_node);
+        return new_node;
+    }
+
+    // Replaces every product of the `variable` with a factor that is constant
+    // or that the loop does not assign by a variable. The variables and the
+    // factors of their products are added to `products`.
+    fn replace_products(
+        &mut self,
+        node: &Node<usize, Token>,
+        variable: &str,
+        assigned_variables: &HashSet<String>,
+        products: &mut Vec<(String, Node<usize, Token>)>
+    ) -> Node<usize, Token> {
+        if let Some(factor) = get_product_factor(node, variable, assigned_variables) {
+            let product = match products.iter().find(|(_, x)| is_same_expression(x, factor)) {
+                Some((product, _)) => product.clone(),
+                None => {
+                    let product = self.new_variable("induction");
+                    products.push((product.clone(), factor.clone()));
+                    product
+                }
+            };
+            return new_node_from_token(*node.id(), Token::Id(product));
+        }
+        let mut new_node = new_node_from_token(*node.id(), node.value().clone());
+        for (index, child) in node.children().iter().enumerate() {
+            if is_case_label(node, index) {
+                new_node.add_child(child.clone());
+            } else {
+                new_node.add_child(self.replace_products(child, variable, assigned_variables, products));
+            }
+        }
+        return new_node;
+    }
+
+    // Returns a new variable, whose name is its kind and its number among them, eg 'step.2'
+    fn new_variable(&mut self, kind: &str) -> String {
+        let count = self.variables.iter().filter(|x| x.starts_with(&format!("{}.", kind))).count();
+        let variable = format!("{}.{}", kind, count + 1);
+        self.variables.push(variable.clone());
+        return variable;
+    }
+}
+
+// Returns the loop of the statement, which is either a loop or a labeled loop
+fn loop_of_statement(node: &Node<usize, Token>) -> Option<&Node<usize, Token>> {
+    return match node.value() {
+        Token::While | Token::For | Token::Do => Some(node),
+        Token::Label(_) => node.children().first().and_then(loop_of_statement),
+        _ => None
+    };
+}
+
+// Returns the variables that are assigned, incremented or decremented under the node
+fn get_assigned_variables(node: &Node<usize, Token>) -> HashSet<String> {
+    let assigns_variable = |x: &Node<usize, Token>| {
+        return is_assignment_operator(x.value()) || matches!(x.value(), Token::Increment | Token::Decrement);
+    };
+    return node.find(&assigns_variable).iter()
+        .filter_map(|x| match x.children()[0].value() {
+            Token::Id(identifier) => Some(identifier.clone()),
+            _ => None
+        })
+        .collect();
+}
+
+fn is_expression(token: &Token) -> bool {
+    return !matches!(
+        token,
+        Token::Ast(_) | Token::If | Token::Else | Token::While | Token::For | Token::Do
+        | Token::Switch | Token::Case | Token::Default | Token::Print | Token::Break
+        | Token::Continue | Token::Label(_) | Token::Int | Token::Bool | Token::Const
+    );
+}
+
+// Whether the child at `index` of the node is a label of a case, which
+// must stay a constant expression. The last child of a case is its block.
+fn is_case_label(node: &Node<usize, Token>, index: usize) -> bool {
+    return *node.value() == Token::Case && index + 1 < node.children().len();
+}
+
+// Whether the expression has the same value wherever it is evaluated in
+// the loop, and can be evaluated before it: it does not read any of the
+// `assigned_variables` or assign a variable, and it cannot throw, so it
+// only divides by constants that are not zero.
+fn is_invariant(node: &Node<usize, Token>, assigned_variables: &HashSet<String>) -> bool {
+    let is_variant = |x: &Node<usize, Token>| match x.value() {
+        Token::Id(identifier) => assigned_variables.contains(identifier),
+        Token::Increment | Token::Decrement => true,
+        Token::Slash | Token::Percent => {
+            evaluate_constant_expression(&x.children()[1], &HashMap::new()).is_none_or(|x| x.is_zero())
+        },
+        token => is_assignment_operator(token)
+    };
+    return node.find(&is_variant).is_empty();
+}
+
+// Whether both expressions have the same tokens, regardless of the ids of their nodes
+fn is_same_expression(first: &Node<usize, Token>, second: &Node<usize, Token>) -> bool {
+    return first.value() == second.value()
+        && first.children().len() == second.children().len()
+        && first.children().iter().zip(second.children()).all(|(x, y)| is_same_expression(x, y));
+}
+
+// Returns the variable of a 'for' loop whose initialization assigns it a
+// constant that is not negative and whose step increments it by a positive
+// constant, eg 'i = 0' and 'i += 2', along with these two constants.
+fn get_induction_variable(assignment_node: &Node<usize, Token>, statement_node: &Node<usize, Token>) -> Option<(String, i32, i32)> {
+    if *assignment_node.value() != Token::Assignment {
+        return None;
+    }
+    let (Token::Id(variable), Token::Num(start)) = (
+        assignment_node.children()[0].value(), assignment_node.children()[1].value()
+    ) else {
+        return None;
+    };
+    let step = match (statement_node.value(), statement_node.children().get(1).map(|x| x.value())) {
+        (Token::Increment, _) => 1,
+        (Token::AdditionAssignment, Some(Token::Num(step))) => *step,
+        _ => return None
+    };
+    let is_stepped_variable = matches!(statement_node.children()[0].value(), Token::Id(x) if x == variable);
+    if !is_stepped_variable || *start < 0 || step <= 0 {
+        return None;
+    }
+    return Some((variable.clone(), *start, step));
+}
+
+// Returns the other factor of a product of the `variable`, if it is
+// a constant or a variable that is not one of the `assigned_variables`
+fn get_product_factor<'a>(
+    node: &'a Node<usize, Token>,
+    variable: &str,
+    assigned_variables: &HashSet<String>
+) -> Option<&'a Node<usize, Token>> {
+    if *node.value() != Token::Asterisk {
+        return None;
+    }
+    let children = node.children();
+    let is_variable = |x: &Node<usize, Token>| matches!(x.value(), Token::Id(x) if x == variable);
+    let factor = if is_variable(&children[0]) {
+        &children[1]
+    } else if is_variable(&children[1]) {
+        &children[0]
+    } else {
+        return None;
+    };
+    let is_invariant_factor = match factor.value() {
+        Token::Id(identifier) => !assigned_variables.contains(identifier),
+        _ => evaluate_constant_expression(factor, &HashMap::new()).is_some()
+    };
+    return Some(factor).filter(|_| is_invariant_factor);
+}
+
+fn new_assignment_node(id: usize, operator: Token, variable: &str, expression_node: Node<usize, Token>) -> Node<usize, Token> {
+    let mut node = new_node_from_token(id, operator);
+    node.add_child(new_node_from_token(id, Token::Id(variable.to_string())));
+    node.add_child(expression_node);
+    return node;
+}
+
+fn new_binary_node(id: usize, operator: Token, left_operand: Token, right_operand_node: Node<usize, Token>) -> Node<usize, Token> {
+    let mut node = new_node_from_token(id, operator);
+    node.add_child(new_node_from_token(id, left_operand));
+    node.add_child(right_operand_node);
+    return node;
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex, parse};
+    use crate::utilities::token_to_source_text;
+
+    fn optimize(source: &str) -> String {
+        let (tokens, spans) = lex(source).unwrap();
+        let ast = LoopOptimizer::new().run(&parse(tokens, &spans).unwrap());
+        return to_source(&ast.children()[0]);
+    }
+
+    // Writes the statements of the tree on a single line, with
+    // parentheses around every operation, eg '{ a = (b + 1); }'
+    fn to_source(node: &Node<usize, Token>) -> String {
+        let children: Vec<String> = node.children().iter().map(to_source).collect();
+        match node.value() {
+            Token::Ast(_) => {
+                let statements: Vec<String> = node.children().iter().zip(&children)
+                    .map(|(x, source)| match x.value() {
+                        Token::Ast(_) | Token::For | Token::While | Token::Do | Token::Label(_) => source.clone(),
+                        _ => format!("{};", source)
+                    })
+                    .collect();
+                return format!("{{ {} }}", statements.join(" "));
+            },
+            Token::Id(identifier) => return identifier.clone(),
+            Token::Num(number) => return number.to_string(),
+            Token::Int => return format!("var {} : int", children.join(", ")),
+            Token::Print => return format!("print {}", children[0]),
+            Token::Increment => return format!("{}++", children[0]),
+            Token::Label(label) => return format!("{}: {}", label, children[0]),
+            Token::For => return format!("for ({}; {}; {}) {}", children[0], children[1], children[2], children[3]),
+            Token::While => return format!("while ({}) {}", children[0], children[1]),
+            Token::Do => return format!("do {} while ({})", children[0], children[1]),
+            token if is_assignment_operator(token) => {
+                return format!("{} {} {}", children[0], token_to_source_text(token), children[1]);
+            },
+            token => return format!("({} {} {})", children[0], token_to_source_text(token), children[1])
+        }
+    }
+
+    #[test]
+    fn test_invariant_code_motion() {
+        assert_eq!(
+            optimize("{ var i, a, n, k : int; for (i = 0; i < n * k; i += 1) a = a + n * k; }"),
+            "{ var i, a, n, k : int; invariant.1 = (n * k); \
+            for (i = 0; (i < invariant.1); i += 1) { a = (a + invariant.1); } }"
+        );
+        // The expressions that read assigned variables, that are constant,
+        // or that could throw stay in the loop. The constant ones cost no
+        // more than an invariant variable, since the assembler folds them.
+        assert_eq!(
+            optimize("{ var a, n : int; while (a < 10) { a += n - a; print 2 * 3; print n / a; } }"),
+            "{ var a, n : int; while ((a < 10)) { a += (n - a); print (2 * 3); print (n / a); } }"
+        );
+        assert_eq!(
+            optimize("{ var a, n : int; do { a += n % 4; } while (a < n / 0); }"),
+            "{ var a, n : int; invariant.1 = (n % 4); \
+            do { a += invariant.1; } while ((a < (n / 0))) }"
+        );
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        assert_eq!(
+            optimize("{ var i, j, a, n : int; outer: while (a < n) { j = 0; while (j < a + n) { j += n * n; } a++; } }"),
+            "{ var i, j, a, n : int; invariant.1 = (n * n); \
+            outer: while ((a < n)) { j = 0; invariant.2 = (a + n); \
+            while ((j < invariant.2)) { j += invariant.1; } a++; } }"
+        );
+    }
+
+    #[test]
+    fn test_strength_reduction() {
+        assert_eq!(
+            optimize("{ var i, a, n : int; for (i = 1; i < 10; i += 2) { a += i * n + 3 * i + i * n; } }"),
+            "{ var i, a, n : int; \
+            induction.1 = (1 * n); step.1 = (2 * n); induction.2 = 3; \
+            for (i = 1; (i < 10); { i += 2; induction.1 += step.1; induction.2 += 6; }) \
+            { a += ((induction.1 + induction.2) + induction.1); } }"
+        );
+        // A step of 1 adds the factor itself
+        assert_eq!(
+            optimize("{ var i, a, n : int; for (i = 0; i < n; i++) print i * n; }"),
+            "{ var i, a, n : int; induction.1 = (0 * n); \
+            for (i = 0; (i < n); { i++; induction.1 += n; }) { print induction.1; } }"
+        );
+        // The variable may become negative, or is assigned in the body
+        for source in [
+            "{ var i, a : int; for (i = 0; i < 9; i += 0) a = i * 2; }",
+            "{ var i, a : int; for (i = a; i < 9; i++) a = i * 2; }",
+            "{ var i, a : int; for (i = 0; i < 9; i++) { a = i * 2; i = a; } }",
+            "{ var i, a : int; for (i = 0; i < 9; i++) a = i * a; }"
+        ] {
+            assert!(!optimize(source).contains("induction"), "{}", source);
+        }
+    }
+}
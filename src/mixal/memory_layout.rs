@@ -0,0 +1,313 @@
+use crate::diagnostics::{Diagnostic, Diagnostics, MEMORY_OVERFLOW};
+use super::loader::MEMORY_SIZE;
+
+// The number of words of the block of the terminal, which is
+// the output device of the programs. Defined in MIX specs.
+pub const STANDARD_OUTPUT_DEVICE_BLOCK_SIZE: u16 = 14;
+
+// The regions of the MIX memory that the generated programs use,
+// in the order of their addresses. Every region starts at its
+// base address and ends right before the base of the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    // The scratch words that the instructions of an operator use
+    // for their operands, eg address 0 when moving a register to another
+    Temporaries,
+    // The variables of the program
+    Globals,
+    // The intermediate values of the expressions that are being evaluated,
+    // which are allocated and freed in LIFO order
+    Stack,
+    // The values that the program uses, but does not modify
+    Constants,
+    // The blocks of the input and output devices
+    IoBuffers,
+    // The instructions of the program
+    Code
+}
+
+pub const REGIONS: [Region; 6] = [
+    Region::Temporaries, Region::Globals, Region::Stack, Region::Constants, Region::IoBuffers, Region::Code
+];
+
+impl Region {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Temporaries => "temporaries",
+            Region::Globals => "globals",
+            Region::Stack => "stack",
+            Region::Constants => "constants",
+            Region::IoBuffers => "io-buffers",
+            Region::Code => "code"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Region> {
+        return REGIONS.iter().find(|x| x.name() == name).copied();
+    }
+
+    // The number of words the region needs, whatever the program is
+    fn min_size(&self) -> u16 {
+        match self {
+            Region::Temporaries | Region::Code => 1,
+            Region::IoBuffers => STANDARD_OUTPUT_DEVICE_BLOCK_SIZE,
+            _ => 0
+        }
+    }
+}
+
+// This class decides where the assembler places everything it stores
+// in the MIX memory, and keeps track of how much of every region is
+// used. The assembler never fails when a region overflows, instead the
+// overflows are reported once the whole program has been assembled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryLayout {
+    // The base address of every region, in the order of `REGIONS`
+    bases: [u16; REGIONS.len()],
+    // The number of words of every region that the program uses.
+    // For the stack, it is the largest depth it has reached.
+    used: [u16; REGIONS.len()],
+    stack_depth: u16,
+    // The variables stored in the globals region, along with their
+    // addresses, in the order they are allocated
+    pub globals: Vec<(String, u16)>
+}
+
+impl Default for MemoryLayout {
+    fn default() -> MemoryLayout {
+        MemoryLayout {
+            bases: [0, 1, 1000, 1500, 2000 - STANDARD_OUTPUT_DEVICE_BLOCK_SIZE, 2000],
+            used: [0; REGIONS.len()],
+            stack_depth: 0,
+            globals: vec![]
+        }
+    }
+}
+
+impl MemoryLayout {
+    pub fn base(&self, region: Region) -> u16 {
+        return self.bases[region as usize];
+    }
+
+    // The address right after the last word of the region
+    pub fn end(&self, region: Region) -> u16 {
+        return self.bases.get(region as usize + 1).copied().unwrap_or(MEMORY_SIZE as u16);
+    }
+
+    pub fn size(&self, region: Region) -> u16 {
+        return self.end(region) - self.base(region);
+    }
+
+    pub fn used(&self, region: Region) -> u16 {
+        return self.used[region as usize];
+    }
+
+    // Moves the start of the region. The layout has to be
+    // validated once all the bases have been set.
+    pub fn set_base(&mut self, region: Region, base: u16) {
+        self.bases[region as usize] = base;
+    }
+
+    // Checks that the regions are in order and that every
+    // region is large enough for what it always holds
+    pub fn validate(&self) -> Result<(), String> {
+        for region in REGIONS {
+            if self.base(region) as usize >= MEMORY_SIZE {
+                return Err(format!(
+                    "the base of the {} region is {}, outside of the memory", region.name(), self.base(region)
+                ));
+            }
+            if self.end(region) < self.base(region) {
+                return Err(format!(
+                    "the {} region must start after the {} region",
+                    REGIONS[region as usize + 1].name(), region.name()
+                ));
+            }
+            if self.size(region) < region.min_size() {
+                return Err(format!(
+                    "the {} region must have at least {} words, but it has {}",
+                    region.name(), region.min_size(), self.size(region)
+                ));
+            }
+        }
+        return Ok(());
+    }
+
+    // The address of the scratch word, which is used by the
+    // instructions of an operator and never holds a value between them
+    pub fn scratch_address(&self) -> u16 {
+        return self.base(Region::Temporaries);
+    }
+
+    pub fn allocate_global(&mut self, name: &str) -> u16 {
+        let address = self.base(Region::Globals) + self.used(Region::Globals);
+        self.used[Region::Globals as usize] += 1;
+        self.globals.push((name.to_string(), address));
+        return address;
+    }
+
+    // Allocates a word at the top of the stack
+    pub fn push_stack(&mut self) -> u16 {
+        let address = self.base(Region::Stack) + self.stack_depth;
+        self.stack_depth += 1;
+        let used = &mut self.used[Region::Stack as usize];
+        *used = (*used).max(self.stack_depth);
+        return address;
+    }
+
+    // Frees the word at the top of the stack
+    pub fn pop_stack(&mut self) {
+        self.stack_depth -= 1;
+    }
+
+    // Allocates a word of the pool of the values that do not fit in an operand
+    pub fn allocate_constant(&mut self) -> u16 {
+        let address = self.base(Region::Constants) + self.used(Region::Constants);
+        self.used[Region::Constants as usize] += 1;
+        return address;
+    }
+
+    // Returns the address of the block of the terminal
+    pub fn output_buffer_address(&mut self) -> u16 {
+        self.used[Region::IoBuffers as usize] = STANDARD_OUTPUT_DEVICE_BLOCK_SIZE;
+        return self.base(Region::IoBuffers);
+    }
+
+    pub fn set_code_size(&mut self, words: u16) {
+        self.used[Region::Code as usize] = words;
+    }
+
+    // Returns an error for every region that the program uses more of than it has.
+    // The scratch word is always counted as used, since most operators need it.
+    pub fn overflows(&self) -> Diagnostics {
+        let mut diagnostics = vec![];
+        for region in REGIONS {
+            let used = if region == Region::Temporaries { 1 } else { self.used(region) };
+            if used <= self.size(region) {
+                continue;
+            }
+            diagnostics.push(Diagnostic::error(MEMORY_OVERFLOW, None, format!(
+                "the {} region overflows: the program needs {} words, but it has {} (addresses {} to {})",
+                region.name(), used, self.size(region), self.base(region), self.end(region) as i32 - 1
+            )).with_note(String::from(
+                "the regions can be moved with '--memory-base <region>=<address>'"
+            )));
+        }
+        return diagnostics;
+    }
+
+    // Returns a report of the address range and the usage of every
+    // region, followed by the addresses of the variables
+    pub fn memory_map(&self) -> String {
+        let mut map = format!("{:<12}{:>6}{:>6}{:>6}{:>6}\n", "region", "first", "last", "size", "used");
+        for region in REGIONS {
+            let used = if region == Region::Temporaries { 1 } else { self.used(region) };
+            map.push_str(&format!(
+                "{:<12}{:>6}{:>6}{:>6}{:>6}\n",
+                region.name(), self.base(region), self.end(region) as i32 - 1, self.size(region), used
+            ));
+        }
+        if !self.globals.is_empty() {
+            map.push_str(&format!("\n{:<24}{:>7}\n", "variable", "address"));
+            for (name, address) in &self.globals {
+                map.push_str(&format!("{:<24}{:>7}\n", name, address));
+            }
+        }
+        return map;
+    }
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_layout() {
+        let layout = MemoryLayout::default();
+        assert_eq!(layout.validate(), Ok(()));
+        assert_eq!(layout.scratch_address(), 0);
+        assert_eq!((layout.base(Region::Globals), layout.end(Region::Globals)), (1, 1000));
+        assert_eq!((layout.base(Region::IoBuffers), layout.size(Region::IoBuffers)), (1986, 14));
+        assert_eq!((layout.base(Region::Code), layout.end(Region::Code)), (2000, 4000));
+        assert_eq!(Region::from_name("io-buffers"), Some(Region::IoBuffers));
+        assert_eq!(Region::from_name("heap"), None);
+    }
+
+    #[test]
+    fn test_allocations() {
+        let mut layout = MemoryLayout::default();
+        assert_eq!(layout.allocate_global("a"), 1);
+        assert_eq!(layout.allocate_global("b"), 2);
+        assert_eq!(layout.push_stack(), 1000);
+        assert_eq!(layout.push_stack(), 1001);
+        layout.pop_stack();
+        layout.pop_stack();
+        assert_eq!(layout.push_stack(), 1000);
+        assert_eq!(layout.used(Region::Stack), 2);
+        assert_eq!(layout.allocate_constant(), 1500);
+        assert_eq!(layout.used(Region::Constants), 1);
+        assert_eq!(layout.used(Region::Globals), 2);
+        assert_eq!(layout.globals, vec![(String::from("a"), 1), (String::from("b"), 2)]);
+        assert!(layout.overflows().is_empty());
+    }
+
+    #[test]
+    fn test_overflows() {
+        let mut layout = MemoryLayout::default();
+        layout.set_base(Region::Stack, 3);
+        layout.set_base(Region::Constants, 4);
+        assert_eq!(layout.validate(), Ok(()));
+        for name in ["a", "b", "c"] {
+            layout.allocate_global(name);
+        }
+        layout.push_stack();
+        layout.push_stack();
+        layout.set_code_size(2001);
+        let messages: Vec<String> = layout.overflows().iter().map(|x| x.message.clone()).collect();
+        assert_eq!(messages, vec![
+            "the globals region overflows: the program needs 3 words, but it has 2 (addresses 1 to 2)",
+            "the stack region overflows: the program needs 2 words, but it has 1 (addresses 3 to 3)",
+            "the code region overflows: the program needs 2001 words, but it has 2000 (addresses 2000 to 3999)"
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        let mut layout = MemoryLayout::default();
+        layout.set_base(Region::Stack, 1990);
+        assert_eq!(layout.validate(), Err(String::from("the constants region must start after the stack region")));
+        let mut layout = MemoryLayout::default();
+        layout.set_base(Region::Code, 1990);
+        assert_eq!(
+            layout.validate(),
+            Err(String::from("the io-buffers region must have at least 14 words, but it has 4"))
+        );
+        let mut layout = MemoryLayout::default();
+        layout.set_base(Region::Code, 4000);
+        assert_eq!(layout.validate(), Err(String::from("the base of the code region is 4000, outside of the memory")));
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut layout = MemoryLayout::default();
+        layout.allocate_global("total");
+        layout.output_buffer_address();
+        layout.set_code_size(12);
+        assert_eq!(layout.memory_map(), "\
+region       first  last  size  used
+temporaries      0     0     1     1
+globals          1   999   999     1
+stack         1000  1499   500     0
+constants     1500  1985   486     0
+io-buffers    1986  1999    14    14
+code          2000  3999  2000    12
+
+variable                address
+total                         1
+");
+    }
+}
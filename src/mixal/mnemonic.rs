@@ -1,8 +1,13 @@
-#[derive(Debug)]
+// The variants are named exactly as the MIXAL mnemonics they model
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MixalMnemonic {
     // Assembly pseudoinstructions
     ORIG,
     END,
+    EQU,
+    CON,
+    ALF,
     NOP,
 
     // MIX machine instructions
@@ -17,7 +22,13 @@ pub enum MixalMnemonic {
     ENT1,
     ENNA,
     ENNX,
-    ENN1,    
+    ENN1,
+    INCA,
+    INCX,
+    INC1,
+    DECA,
+    DECX,
+    DEC1,
     STZ,
     ADD,
     SUB,
@@ -3,3 +3,9 @@ pub mod mnemonic;
 pub mod assembler;
 pub mod register;
 pub mod utilities;
+pub mod word;
+pub mod loader;
+pub mod emulator;
+pub mod memory_layout;
+pub mod flow_graph;
+pub mod loop_optimizer;
@@ -1,7 +1,6 @@
-use std::process::Command;
-
-use crate::lexer::Token;
-use super::{register::MixalRegister, mnemonic::MixalMnemonic};
+use std::collections::HashSet;
+use crate::lexer::{Token, Span};
+use super::{register::MixalRegister, mnemonic::MixalMnemonic, instruction::MixalInstruction};
 
 pub fn mixal_register_to_load_mnemonic(register: MixalRegister) -> MixalMnemonic {
     match register {
@@ -27,6 +26,16 @@ pub fn mixal_register_to_enter_mnemonic(register: MixalRegister, value: i32) ->
     }    
 }
 
+// The instruction that adds `value` to the register, whose
+// operand is the magnitude of the value, eg `DECA 2` adds -2
+pub fn mixal_register_to_increment_mnemonic(register: MixalRegister, value: i32) -> MixalMnemonic {
+    match register {
+        MixalRegister::RA => if value >= 0 { MixalMnemonic::INCA } else { MixalMnemonic::DECA },
+        MixalRegister::RX => if value >= 0 { MixalMnemonic::INCX } else { MixalMnemonic::DECX },
+        MixalRegister::RI1 => if value >= 0 { MixalMnemonic::INC1 } else { MixalMnemonic::DEC1 },
+    }
+}
+
 pub fn comparison_token_to_jump_instruction(token: Token) -> MixalMnemonic {
     match token {
         Token::Equals => MixalMnemonic::JE,
@@ -39,18 +48,177 @@ pub fn comparison_token_to_jump_instruction(token: Token) -> MixalMnemonic {
     }
 }
 
-pub fn get_random_instruction_label() -> String {
-    let charset: String = ('A'..'Z').map(|c| c as char).collect();
-    return random_string::generate(3, charset.clone());
+// The longest symbol that MIXAL accepts
+pub const MAX_SYMBOL_LENGTH: usize = 10;
+
+// Turns a name of the program into a MIXAL symbol that is not in `used_symbols`.
+// A symbol has at most 10 letters and digits, and at least one letter, so the
+// name is uppercased and stripped of the other characters, eg 'is_prime' becomes
+// 'ISPRIME'. A name that does not start with a letter is prefixed with 'V', and
+// a symbol that is already used gets a number at its end, eg 'ISPRIME2'.
+pub fn mangle_symbol(name: &str, used_symbols: &HashSet<String>) -> String {
+    let mut symbol: String = name.chars()
+        .filter(|x| x.is_ascii_alphanumeric())
+        .map(|x| x.to_ascii_uppercase())
+        .collect();
+    if !symbol.starts_with(|x: char| x.is_ascii_alphabetic()) {
+        symbol.insert(0, 'V');
+    }
+    symbol.truncate(MAX_SYMBOL_LENGTH);
+    let mut candidate = symbol.clone();
+    let mut counter = 2;
+    while used_symbols.contains(&candidate) {
+        let suffix = counter.to_string();
+        let prefix: String = symbol.chars().take(MAX_SYMBOL_LENGTH - suffix.len()).collect();
+        candidate = format!("{}{}", prefix, suffix);
+        counter += 1;
+    }
+    return candidate;
 }
 
-pub fn run_mix_binary_file_and_print_output(file_path: &String) {
-    Command::new("mixvm")
-        .arg("--run")
-        .arg(file_path)
-        .output()
-        .expect("to execute")
-        .stdout
-        .iter()
-        .for_each(|x| print!("{}", *x as char));    
+// Returns the instructions as the text of a MIXAL source file
+pub fn instructions_to_mixal(instructions: &[MixalInstruction]) -> String {
+    return instructions.iter().map(|x| x.to_string()).collect();
+}
+
+// Returns the instructions as the text of a MIXAL source file, where
+// the instructions of every line of the program are preceded by that
+// line as a comment, eg '* 5: total += i;'. The `spans` are the spans
+// of the tokens of the program, which is stored in `source`.
+pub fn instructions_to_annotated_mixal(instructions: &[MixalInstruction], source: &str, spans: &[Span]) -> String {
+    let source_lines: Vec<&str> = source.lines().collect();
+    let mut mixal = String::new();
+    let mut previous_line = None;
+    for instruction in instructions {
+        let line = instruction_span(instruction, spans).map(|x| x.line);
+        if let Some(line) = line.filter(|x| Some(*x) != previous_line) {
+            let text = source_lines.get(line - 1).map(|x| x.trim()).unwrap_or_default();
+            mixal.push_str(&format!("* {}: {}\n", line, text));
+            previous_line = Some(line);
+        }
+        mixal.push_str(&instruction.to_string());
+    }
+    return mixal;
+}
+
+// The memory address at which every instruction will be loaded.
+// Directives that do not occupy a memory word have no address.
+pub fn instruction_addresses(instructions: &[MixalInstruction]) -> Vec<Option<u16>> {
+    let mut addresses = vec![];
+    let mut address: u16 = 0;
+    for instruction in instructions {
+        match instruction.mnemonic {
+            MixalMnemonic::ORIG => {
+                address = instruction.operand.as_deref().and_then(|x| x.parse().ok()).unwrap_or(address);
+                addresses.push(None);
+            },
+            MixalMnemonic::END | MixalMnemonic::EQU => addresses.push(None),
+            _ => {
+                addresses.push(Some(address));
+                address += 1;
+            }
+        }
+    }
+    return addresses;
+}
+
+// Lists the instructions one per line, along with the memory
+// address at which each of them will be loaded. Directives that
+// do not occupy a memory word are listed without an address.
+pub fn instructions_to_listing(instructions: &[MixalInstruction]) -> String {
+    let mut listing = String::new();
+    for (instruction, address) in instructions.iter().zip(instruction_addresses(instructions)) {
+        let label = instruction.label.clone().unwrap_or_default();
+        let mnemonic = format!("{:?}", instruction.mnemonic);
+        let operand = instruction.operand.clone().unwrap_or_default();
+        let address_column = match address {
+            Some(address) => format!("{:04}", address),
+            None => String::from("    ")
+        };
+        listing.push_str(&format!("{}  {:<4} {:<4} {}\n", address_column, label, mnemonic, operand));
+    }
+    return listing;
+}
+
+// Lists the address of every word of the program, along with the line and
+// column of the statement that it was generated for. The words of the data
+// and the final instructions, which belong to no statement, show a '-'.
+pub fn instructions_to_source_listing(instructions: &[MixalInstruction], spans: &[Span]) -> String {
+    let mut listing = format!("{:<9}{:<13}instruction\n", "address", "line:column");
+    for (instruction, address) in instructions.iter().zip(instruction_addresses(instructions)) {
+        let Some(address) = address else {
+            continue;
+        };
+        let position = match instruction_span(instruction, spans) {
+            Some(span) => format!("{}:{}", span.line, span.column),
+            None => String::from("-")
+        };
+        let label = instruction.label.clone().unwrap_or_default();
+        let mnemonic = format!("{:?}", instruction.mnemonic);
+        let operand = instruction.operand.clone().unwrap_or_default();
+        listing.push_str(&format!("{:<9}{:<13}{:<4} {:<4} {}\n", address, position, label, mnemonic, operand));
+    }
+    return listing;
+}
+
+// The span of the token of the statement that the instruction was generated for
+fn instruction_span<'a>(instruction: &MixalInstruction, spans: &'a [Span]) -> Option<&'a Span> {
+    return instruction.source.and_then(|x| spans.get(x));
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mangle_symbol() {
+        let used_symbols: HashSet<String> = ["TEMP", "ISPRIME", "ISPRIME2", "ABCDEFGHIJ"].iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(mangle_symbol("total", &used_symbols), "TOTAL");
+        assert_eq!(mangle_symbol("temp", &used_symbols), "TEMP2");
+        assert_eq!(mangle_symbol("is_prime", &used_symbols), "ISPRIME3");
+        assert_eq!(mangle_symbol("_2h", &used_symbols), "V2H");
+        assert_eq!(mangle_symbol("a_very_long_name", &used_symbols), "AVERYLONGN");
+        assert_eq!(mangle_symbol("abcdefghijk", &used_symbols), "ABCDEFGHI2");
+    }
+
+    #[test]
+    fn test_source_listing() {
+        let spans = [
+            Span { start: 0, end: 1, line: 1, column: 1 },
+            Span { start: 6, end: 7, line: 2, column: 5 }
+        ];
+        let mut instructions = vec![
+            MixalInstruction::new(Some(String::from("X")), MixalMnemonic::EQU, Some(String::from("5"))),
+            MixalInstruction::new(None, MixalMnemonic::ORIG, Some(String::from("100"))),
+            MixalInstruction::new(None, MixalMnemonic::ENTA, Some(String::from("1"))),
+            MixalInstruction::new(None, MixalMnemonic::STA, Some(String::from("X"))),
+            MixalInstruction::new(None, MixalMnemonic::HLT, None),
+            MixalInstruction::new(None, MixalMnemonic::END, Some(String::from("100")))
+        ];
+        instructions[2].source = Some(0);
+        instructions[3].source = Some(1);
+        assert_eq!(instruction_addresses(&instructions), vec![None, None, Some(100), Some(101), Some(102), None]);
+        assert_eq!(instructions_to_source_listing(&instructions, &spans), "\
+address  line:column  instruction
+100      1:1               ENTA 1
+101      2:5               STA  X
+102      -                 HLT  
+");
+        assert_eq!(instructions_to_annotated_mixal(&instructions, "a = 1;\nb = a;\n", &spans), "\
+X EQU 5
+ ORIG 100
+* 1: a = 1;
+ ENTA 1
+* 2: b = a;
+ STA X
+ HLT 
+ END 100
+");
+    }
 }
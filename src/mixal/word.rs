@@ -0,0 +1,201 @@
+use std::cmp::Ordering;
+
+// The number of bits of a MIX byte (a byte holds 64 values)
+pub const BYTE_SIZE: u32 = 6;
+// The number of bytes of a MIX word, not counting its sign
+pub const WORD_SIZE: u32 = 5;
+// The magnitudes of MIX words are in the range [0, 2^30)
+pub const WORD_MODULO: u64 = 1 << (BYTE_SIZE * WORD_SIZE);
+
+// A MIX word, modeled in sign-magnitude form. A word can be
+// "negative zero", which is equal to zero in the comparisons
+// but keeps its sign in the arithmetic operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MixWord {
+    pub negative: bool,
+    // Always less than `WORD_MODULO`
+    pub magnitude: u32
+}
+
+impl MixWord {
+    pub fn new(negative: bool, magnitude: u64) -> MixWord {
+        MixWord { negative, magnitude: (magnitude % WORD_MODULO) as u32 }
+    }
+
+    // Values that do not fit in a word are truncated to their 30 LSBits
+    pub fn from_i64(value: i64) -> MixWord {
+        return MixWord::new(value < 0, value.unsigned_abs());
+    }
+
+    pub fn to_i64(self) -> i64 {
+        let value = self.magnitude as i64;
+        return if self.negative { -value } else { value };
+    }
+
+    // The 5 bytes of the magnitude, starting from the MSByte
+    pub fn bytes(self) -> [u8; WORD_SIZE as usize] {
+        let mut bytes = [0; WORD_SIZE as usize];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            let shift = BYTE_SIZE * (WORD_SIZE - 1 - index as u32);
+            *byte = ((self.magnitude >> shift) & ((1 << BYTE_SIZE) - 1)) as u8;
+        }
+        return bytes;
+    }
+
+    pub fn from_bytes(negative: bool, bytes: &[u8]) -> MixWord {
+        let magnitude = bytes.iter().fold(0, |magnitude, byte| (magnitude << BYTE_SIZE) | *byte as u64);
+        return MixWord::new(negative, magnitude);
+    }
+
+    // The value of the field (L:R) of the word, as the loading
+    // instructions read it: the bytes are shifted to the right
+    // and the sign is only kept if it is part of the field.
+    pub fn field(self, left: u8, right: u8) -> MixWord {
+        let bytes = self.bytes();
+        let first_byte = left.max(1) as usize;
+        let field_bytes = if first_byte <= right as usize { &bytes[first_byte - 1..right as usize] } else { &[] };
+        return MixWord::from_bytes(left == 0 && self.negative, field_bytes);
+    }
+
+    // Returns the word with its field (L:R) replaced by the rightmost
+    // bytes of `source`, as the storing instructions write it.
+    pub fn with_field(self, source: MixWord, left: u8, right: u8) -> MixWord {
+        let mut bytes = self.bytes();
+        let source_bytes = source.bytes();
+        let first_byte = left.max(1) as usize;
+        let mut source_index = WORD_SIZE as usize;
+        for index in (first_byte..=right as usize).rev() {
+            source_index -= 1;
+            bytes[index - 1] = source_bytes[source_index];
+        }
+        let negative = if left == 0 { source.negative } else { self.negative };
+        return MixWord::from_bytes(negative, &bytes);
+    }
+
+    pub fn is_zero(self) -> bool {
+        return self.magnitude == 0;
+    }
+
+    pub fn negated(self) -> MixWord {
+        return MixWord { negative: !self.negative, magnitude: self.magnitude };
+    }
+
+    // The 'ADD' instruction, where `self` is the contents of register RA.
+    // If the result is zero, it keeps the sign of RA. If it does not fit in
+    // a word, it is truncated and the returned overflow flag is set.
+    // It is named after the instruction, unlike `std::ops::Add::add`,
+    // since it also returns the overflow flag.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(self, other: MixWord) -> (MixWord, bool) {
+        let sum = self.to_i64() + other.to_i64();
+        if sum == 0 {
+            return (MixWord { negative: self.negative, magnitude: 0 }, false);
+        }
+        let overflow = sum.unsigned_abs() >= WORD_MODULO;
+        return (MixWord::from_i64(sum), overflow);
+    }
+
+    // The 'SUB' instruction, which is 'ADD' with the sign of `other` reversed
+    pub fn subtract(self, other: MixWord) -> (MixWord, bool) {
+        return self.add(other.negated());
+    }
+
+    // The 'MUL' instruction. The 60 bits of the product are split to the
+    // returned words, which model registers RA (MSBits) and RX (LSBits).
+    // Both of them get the sign of the product, even when it is zero.
+    pub fn multiply(self, other: MixWord) -> (MixWord, MixWord) {
+        let negative = self.negative != other.negative;
+        let product = self.magnitude as u64 * other.magnitude as u64;
+        return (
+            MixWord::new(negative, product / WORD_MODULO),
+            MixWord::new(negative, product % WORD_MODULO)
+        );
+    }
+
+    // The 'DIV' instruction, where `self` and `lower` are the contents of
+    // registers RA and RX. The 60-bit dividend has the sign of RA. Returns
+    // the quotient and the remainder, which gets the sign of the dividend,
+    // or `None` when MIX overflows (ie the divisor is zero or the quotient
+    // does not fit in a word) and the contents of the registers are undefined.
+    pub fn divide(self, lower: MixWord, divisor: MixWord) -> Option<(MixWord, MixWord)> {
+        if self.magnitude >= divisor.magnitude {
+            return None;
+        }
+        let dividend = self.magnitude as u64 * WORD_MODULO + lower.magnitude as u64;
+        let divisor_magnitude = divisor.magnitude as u64;
+        return Some((
+            MixWord::new(self.negative != divisor.negative, dividend / divisor_magnitude),
+            MixWord::new(self.negative, dividend % divisor_magnitude)
+        ));
+    }
+
+    // The 'CMPA' instruction. Negative zero is equal to positive zero.
+    pub fn compare(self, other: MixWord) -> Ordering {
+        return self.to_i64().cmp(&other.to_i64());
+    }
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(value: i64) -> MixWord {
+        return MixWord::from_i64(value);
+    }
+
+    const NEGATIVE_ZERO: MixWord = MixWord { negative: true, magnitude: 0 };
+
+    #[test]
+    fn test_add_and_subtract() {
+        assert_eq!(word(2).add(word(-5)), (word(-3), false));
+        assert_eq!(word(5).subtract(word(5)), (word(0), false));
+        assert_eq!(NEGATIVE_ZERO.add(word(0)), (NEGATIVE_ZERO, false));
+        assert_eq!(word(-5).add(word(5)), (NEGATIVE_ZERO, false));
+        assert_eq!(word(WORD_MODULO as i64 - 1).add(word(2)), (word(1), true));
+        assert_eq!(word(1 - WORD_MODULO as i64).subtract(word(1)), (NEGATIVE_ZERO, true));
+    }
+
+    #[test]
+    fn test_multiply() {
+        assert_eq!(word(-6).multiply(word(7)), (NEGATIVE_ZERO, word(-42)));
+        assert_eq!(word(-1).multiply(word(0)), (NEGATIVE_ZERO, NEGATIVE_ZERO));
+        assert_eq!(word(1 << 20).multiply(word(1 << 20)), (word(1 << 10), word(0)));
+    }
+
+    #[test]
+    fn test_divide() {
+        let divide = |dividend: MixWord, divisor: i64| {
+            return MixWord::new(dividend.negative, 0).divide(dividend, word(divisor));
+        };
+        assert_eq!(divide(word(7), 2), Some((word(3), word(1))));
+        assert_eq!(divide(word(-7), 2), Some((word(-3), word(-1))));
+        assert_eq!(divide(word(7), -2), Some((word(-3), word(1))));
+        assert_eq!(divide(word(-6), 3), Some((word(-2), NEGATIVE_ZERO)));
+        assert_eq!(divide(word(7), 0), None);
+        assert_eq!(word(2).divide(word(0), word(2)), None);
+    }
+
+    #[test]
+    fn test_fields() {
+        let value = MixWord::from_bytes(true, &[1, 2, 3, 4, 5]);
+        assert_eq!(value.bytes(), [1, 2, 3, 4, 5]);
+        assert_eq!(value.field(0, 5), value);
+        assert_eq!(value.field(0, 0), NEGATIVE_ZERO);
+        assert_eq!(value.field(4, 5), word(4 * 64 + 5));
+        assert_eq!(value.field(0, 2), word(-(64 + 2)));
+        assert_eq!(value.with_field(word(7), 1, 1), MixWord::from_bytes(true, &[7, 2, 3, 4, 5]));
+        assert_eq!(value.with_field(word(64 + 9), 0, 2), MixWord::from_bytes(false, &[1, 9, 3, 4, 5]));
+        assert_eq!(value.with_field(word(0), 0, 0), MixWord::from_bytes(false, &[1, 2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(NEGATIVE_ZERO.compare(word(0)), Ordering::Equal);
+        assert_eq!(word(-1).compare(NEGATIVE_ZERO), Ordering::Less);
+        assert_eq!(word(3).compare(word(2)), Ordering::Greater);
+    }
+}
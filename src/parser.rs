@@ -1,7 +1,9 @@
 use std::mem;
 use std::collections::HashMap;
 use orange_trees::Node;
-use crate::{lexer::Token, utilities::new_node_from_token};
+use crate::lexer::{Token, Span};
+use crate::utilities::{new_node_from_token, new_synthetic_node, token_to_source_text, is_assignment_operator};
+use crate::diagnostics::{Diagnostic, SYNTAX_ERROR};
 
 // Implementation of the language's parser.
 // You can check the grammar of the language
@@ -12,6 +14,10 @@ pub struct Parser {
     pub tokens: Vec<Token>,
     pub ast: Node<usize, Token>,
 
+    // The furthest position in which a token did not match
+    // the grammar. This is where syntax errors are reported.
+    furthest_failed_pos: usize,
+
     // key: token_start_index
     // value: (token_end_index, Node)
     token_index_to_node: HashMap<usize, (usize, Node<usize, Token>)>
@@ -23,12 +29,51 @@ impl Parser {
             pos: 0, 
             tokens,
             ast: new_node_from_token(0, Token::Ast(String::from("ROOT_AST_NODE"))),
+            furthest_failed_pos: 0,
             token_index_to_node: HashMap::new()
         }
     }
 
     pub fn analyze_grammar(&mut self) -> bool {
-        return self.program_rule().tokens_consumed == self.tokens.len();
+        let rule_result = self.program_rule();
+        if rule_result.matched && rule_result.tokens_consumed < self.tokens.len() {
+            // The program is followed by more tokens
+            self.furthest_failed_pos = self.furthest_failed_pos.max(rule_result.tokens_consumed);
+        }
+        return rule_result.matched && rule_result.tokens_consumed == self.tokens.len();
+    }
+
+    // Parses a single declaration or statement, such as an input of the REPL,
+    // instead of a whole program. Its node is added as the only child of `ast`.
+    pub fn analyze_declaration_or_statement(&mut self) -> bool {
+        for rule in [Parser::decl_rule, Parser::stmt_rule] {
+            self.pos = 0;
+            let rule_result = rule(self);
+            if rule_result.matched && rule_result.tokens_consumed == self.tokens.len() {
+                let node = self.token_index_to_node.get(&0).expect("has value").1.clone();
+                self.ast.add_child(node);
+                return true;
+            }
+            self.furthest_failed_pos = self.furthest_failed_pos.max(rule_result.tokens_consumed);
+        }
+        return false;
+    }
+
+    // Describes why `analyze_grammar` or `analyze_declaration_or_statement` failed. The `spans`
+    // are the spans of the tokens given to the parser.
+    pub fn syntax_error(&self, spans: &[Span]) -> Diagnostic {
+        match self.tokens.get(self.furthest_failed_pos) {
+            Some(token) => Diagnostic::error(
+                SYNTAX_ERROR,
+                spans.get(self.furthest_failed_pos).copied(),
+                format!("unexpected token '{}'", token_to_source_text(token))
+            ),
+            None => Diagnostic::error(
+                SYNTAX_ERROR,
+                spans.last().copied(),
+                String::from("unexpected end of program")
+            )
+        }
     }
 
     fn program_rule(&mut self) -> RuleResult {
@@ -73,18 +118,34 @@ impl Parser {
                 Rhs::Terminal(Token::Id(String::from("_"))),
                 Rhs::Nonterminal(Parser::vars_rule),
                 Rhs::Terminal(Token::Colon),
-                Rhs::Terminal(Token::Int),
+                Rhs::Nonterminal(Parser::type_rule),
+                Rhs::Terminal(Token::Semicolon)
+            ],
+            vec![
+                Rhs::Terminal(Token::Const),
+                Rhs::Terminal(Token::Id(String::from("_"))),
+                Rhs::Terminal(Token::Assignment),
+                Rhs::Nonterminal(Parser::exp_rule),
                 Rhs::Terminal(Token::Semicolon)
             ]
         ], false);
 
-        if rule_result.matched {
+        if rule_result.matched && self.tokens[self.pos - rule_result.tokens_consumed] == Token::Const {
+            // The constant and the expression of its value
+            let index = self.pos - rule_result.tokens_consumed;
+            let mut node = new_node_from_token(index, Token::Const);
+            node.add_child(new_node_from_token(index+1, (*self.tokens.get(index+1).expect("has value")).clone()));
+            node.add_child(self.token_index_to_node.get(&(index+3)).expect("has value").1.clone());
+            self.token_index_to_node.insert(index, (self.pos, node));
+        } else if rule_result.matched {
             let mut node: Node<usize, Token>;
             let token_range_start = self.pos - rule_result.tokens_consumed;
             let token_range_end = self.pos;
-            node = new_node_from_token(token_range_start, Token::Int);
+            // The declaration node holds the type of the declared variables
+            let type_token = (*self.tokens.get(token_range_end - 2).expect("has value")).clone();
+            node = new_node_from_token(token_range_start, type_token);
             for i in token_range_start + 1 .. token_range_end {
-                let token = (*self.tokens.get(i).clone().expect("has value")).clone();
+                let token = (*self.tokens.get(i).expect("has value")).clone();
                 if let Token::Id(_) = token {
                     node.add_child(new_node_from_token(i, token.clone()));
                 }
@@ -108,6 +169,13 @@ impl Parser {
         ], true);
     }
 
+    fn type_rule(&mut self) -> RuleResult {
+        return self.run_rules_from_rhs(vec![
+            vec![Rhs::Terminal(Token::Int)],
+            vec![Rhs::Terminal(Token::Bool)]
+        ], false);
+    }
+
     fn stmts_rule(&mut self) -> RuleResult {
         return self.run_rules_from_rhs(vec![
             vec![
@@ -134,6 +202,13 @@ impl Parser {
                 index,
                 (self.pos, node.1.clone())
             );
+        } else if rule_result.matched && rule_result.tokens_consumed == 1 {
+            // The empty statement `;` is modeled as an empty block
+            let index = self.pos - 1;
+            self.token_index_to_node.insert(
+                index,
+                (self.pos, new_node_from_token(index, Token::Ast(String::from("BLOCK"))))
+            );
         }
 
         return rule_result;
@@ -149,28 +224,28 @@ impl Parser {
             vec![
                 Rhs::Terminal(Token::Print),
                 Rhs::Nonterminal(Parser::exp_rule)
-            ]
+            ],
+            vec![Rhs::Nonterminal(Parser::increment_rule)]
         ], false);
 
         if rule_result.matched {
             let index = self.pos - rule_result.tokens_consumed;
-            let first_token = (*self.tokens.get(index).clone().expect("has value")).clone();
-            let mut node = new_node_from_token(0, Token::Break);
-            match first_token {
-                Token::Id(_) => {
-                    let assignment_operator = (*self.tokens.get(index+1).clone().expect("has value")).clone();                    
-                    node = new_node_from_token(index+1, assignment_operator.clone());
-                    node.add_child(new_node_from_token(index, first_token));
-                    let expression_node = self.token_index_to_node.get(&(index+2)).expect("has value").clone();
-                    node.add_child(expression_node.1);
+            let first_token = (*self.tokens.get(index).expect("has value")).clone();
+            let node = match first_token {
+                Token::Id(_) if is_assignment_operator(self.tokens.get(index+1).expect("has value")) => {
+                    self.new_assignment_node(index)
                 },
                 Token::Print => {
-                    node = new_node_from_token(index, Token::Print);
+                    let mut print_node = new_node_from_token(index, Token::Print);
                     let expression_node = self.token_index_to_node.get(&(index+1)).expect("has value").clone();
-                    node.add_child(expression_node.1);
+                    print_node.add_child(expression_node.1);
+                    print_node
                 },
-                _ => {}
-            }
+                _ => {
+                    // The node of `++` or `--` is already in the map
+                    return rule_result;
+                }
+            };
             self.token_index_to_node.insert(
                 index,
                 (self.pos, node.clone())
@@ -180,6 +255,18 @@ impl Parser {
         return rule_result;
     }
 
+    // Creates the node of the assignment `id ASOP EXP`
+    // that starts from the token at `index`
+    fn new_assignment_node(&self, index: usize) -> Node<usize, Token> {
+        let identifier = (*self.tokens.get(index).expect("has value")).clone();
+        let assignment_operator = (*self.tokens.get(index+1).expect("has value")).clone();
+        let mut node = new_node_from_token(index+1, assignment_operator);
+        node.add_child(new_node_from_token(index, identifier));
+        let expression_node = self.token_index_to_node.get(&(index+2)).expect("has value").clone();
+        node.add_child(expression_node.1);
+        return node;
+    }
+
     fn control_rule(&mut self) -> RuleResult {
         let rule_result = self.run_rules_from_rhs(vec![
             vec![
@@ -208,14 +295,47 @@ impl Parser {
                 Rhs::Terminal(Token::RightParen),
                 Rhs::Nonterminal(Parser::block_rule),
             ],
+            vec![
+                Rhs::Terminal(Token::Do),
+                Rhs::Nonterminal(Parser::block_rule),
+                Rhs::Terminal(Token::While),
+                Rhs::Terminal(Token::LeftParen),
+                Rhs::Nonterminal(Parser::exp_rule),
+                Rhs::Terminal(Token::RightParen),
+                Rhs::Terminal(Token::Semicolon),
+            ],
+            vec![
+                Rhs::Terminal(Token::Switch),
+                Rhs::Terminal(Token::LeftParen),
+                Rhs::Nonterminal(Parser::exp_rule),
+                Rhs::Terminal(Token::RightParen),
+                Rhs::Terminal(Token::LeftBrace),
+                Rhs::Nonterminal(Parser::cases_rule),
+                Rhs::Terminal(Token::RightBrace),
+            ],
             vec![Rhs::Terminal(Token::Continue), Rhs::Terminal(Token::Semicolon)],
+            vec![
+                Rhs::Terminal(Token::Continue),
+                Rhs::Terminal(Token::Id(String::from("_"))),
+                Rhs::Terminal(Token::Semicolon)
+            ],
             vec![Rhs::Terminal(Token::Break), Rhs::Terminal(Token::Semicolon)],
+            vec![
+                Rhs::Terminal(Token::Break),
+                Rhs::Terminal(Token::Id(String::from("_"))),
+                Rhs::Terminal(Token::Semicolon)
+            ],
+            vec![
+                Rhs::Terminal(Token::Id(String::from("_"))),
+                Rhs::Terminal(Token::Colon),
+                Rhs::Nonterminal(Parser::stmt_rule)
+            ],
         ], false);
 
 
         if rule_result.matched {
             let index = self.pos - rule_result.tokens_consumed;
-            let token = (*self.tokens.get(index).clone().expect("has value")).clone();
+            let token = (*self.tokens.get(index).expect("has value")).clone();
             let mut node = new_node_from_token(0, Token::Break);
             match token {
                 Token::If => {
@@ -247,11 +367,38 @@ impl Parser {
                     let block_node = self.token_index_to_node.get(&(simp_node.0 + 1)).expect("has value").clone();
                     node.add_child(block_node.1.clone());
                 }
-                Token::Continue => {
-                    node = new_node_from_token(index, Token::Continue);
+                Token::Switch => {
+                    node = new_node_from_token(index, Token::Switch);
+                    let expression_node = self.token_index_to_node.get(&(index+2)).expect("has value").clone();
+                    node.add_child(expression_node.1);
+                    // Skip the ')' and the '{' that follow the expression
+                    let mut case_index = expression_node.0 + 2;
+                    while case_index < self.pos - 1 {
+                        let case_node = self.token_index_to_node.get(&case_index).expect("has value").clone();
+                        node.add_child(case_node.1.clone());
+                        case_index = case_node.0;
+                    }
+                },
+                Token::Do => {
+                    node = new_node_from_token(index, Token::Do);
+                    let block_node = self.token_index_to_node.get(&(index+1)).expect("has value").clone();
+                    node.add_child(block_node.1.clone());
+                    // Skip the 'while' and the '(' that follow the block
+                    let expression_node = self.token_index_to_node.get(&(block_node.0 + 2)).expect("has value").clone();
+                    node.add_child(expression_node.1);
+                },
+                Token::Continue | Token::Break => {
+                    node = new_node_from_token(index, token.clone());
+                    // The label of the loop that the statement refers to, if any
+                    if let Token::Id(label) = self.tokens.get(index+1).expect("has value") {
+                        node.add_child(new_node_from_token(index+1, Token::Label(label.clone())));
+                    }
                 },
-                Token::Break => {
-                    node = new_node_from_token(index, Token::Break);
+                Token::Id(label) => {
+                    // A labeled statement, eg `outer: while (...) ...`
+                    node = new_node_from_token(index, Token::Label(label));
+                    let statement_node = self.token_index_to_node.get(&(index+2)).expect("has value").clone();
+                    node.add_child(statement_node.1);
                 },
                 _ => {}
             }
@@ -276,7 +423,7 @@ impl Parser {
 
         if rule_result.matched {
             let index_start = self.pos - rule_result.tokens_consumed;
-            let first_token = (*self.tokens.get(index_start).clone().expect("has value")).clone();
+            let first_token = (*self.tokens.get(index_start).expect("has value")).clone();
             match first_token {
                 Token::LeftBrace => {
                     let token_range_start = self.pos - rule_result.tokens_consumed;
@@ -308,6 +455,77 @@ impl Parser {
         return rule_result;
     }
 
+    fn cases_rule(&mut self) -> RuleResult {
+        return self.run_rules_from_rhs(vec![
+            vec![
+                Rhs::Nonterminal(Parser::case_rule),
+                Rhs::Nonterminal(Parser::cases_rule)
+            ]
+        ], true);
+    }
+
+    fn case_rule(&mut self) -> RuleResult {
+        let rule_result = self.run_rules_from_rhs(vec![
+            vec![
+                Rhs::Terminal(Token::Case),
+                Rhs::Nonterminal(Parser::exp_rule),
+                Rhs::Nonterminal(Parser::case_labels_rule),
+                Rhs::Terminal(Token::Colon),
+                Rhs::Nonterminal(Parser::stmts_rule)
+            ],
+            vec![
+                Rhs::Terminal(Token::Default),
+                Rhs::Terminal(Token::Colon),
+                Rhs::Nonterminal(Parser::stmts_rule)
+            ]
+        ], false);
+
+        if rule_result.matched {
+            // The case node holds the labels, followed by a block
+            // with the statements, whose id is the index of the ':'
+            let index_start = self.pos - rule_result.tokens_consumed;
+            let first_token = (*self.tokens.get(index_start).expect("has value")).clone();
+            let mut node = new_node_from_token(index_start, first_token.clone());
+            let mut index = index_start + 1;
+            if first_token == Token::Case {
+                loop {
+                    let label_node = self.token_index_to_node.get(&index).expect("has value").clone();
+                    node.add_child(label_node.1.clone());
+                    index = label_node.0;
+                    if *self.tokens.get(index).expect("has value") == Token::Colon {
+                        break;
+                    }
+                    // Skip the ',' between the labels
+                    index += 1;
+                }
+            }
+            let mut block_node = new_node_from_token(index, Token::Ast(String::from("BLOCK")));
+            index += 1;
+            while index < self.pos {
+                let child = self.token_index_to_node.get(&index).expect("has value").clone();
+                block_node.add_child(child.1.clone());
+                index = child.0;
+            }
+            node.add_child(block_node);
+            self.token_index_to_node.insert(
+                index_start,
+                (self.pos, node.clone())
+            );
+        }
+
+        return rule_result;
+    }
+
+    fn case_labels_rule(&mut self) -> RuleResult {
+        return self.run_rules_from_rhs(vec![
+            vec![
+                Rhs::Terminal(Token::Comma),
+                Rhs::Nonterminal(Parser::exp_rule),
+                Rhs::Nonterminal(Parser::case_labels_rule)
+            ]
+        ], true);
+    }
+
     fn else_block_rule(&mut self) -> RuleResult {
         let rule_result = self.run_rules_from_rhs(vec![
             vec![
@@ -331,6 +549,76 @@ impl Parser {
     }
 
     fn exp_rule(&mut self) -> RuleResult {
+        let rule_result = self.run_rules_from_rhs(vec![
+            vec![
+                Rhs::Terminal(Token::Id(String::from("_"))),
+                Rhs::Nonterminal(Parser::asop_rule),
+                Rhs::Nonterminal(Parser::exp_rule),
+            ],
+            vec![Rhs::Nonterminal(Parser::precedence_0_rule)],
+        ], false);
+
+        if rule_result.matched && rule_result.tokens_consumed > 0 {
+            let index = self.pos - rule_result.tokens_consumed;
+            // An assignment is the only expression whose
+            // second token is an assignment operator
+            let is_assignment = self.tokens.get(index+1).is_some_and(is_assignment_operator);
+            if is_assignment {
+                let node = self.new_assignment_node(index);
+                self.token_index_to_node.insert(index, (self.pos, node));
+            } else {
+                self.construct_expression_node_from_token_range(index, self.pos);
+            }
+        }
+
+        return rule_result
+    }
+
+    // The conditional operator `c ? a : b` has the lowest precedence, and
+    // it is right associative, eg `a ? 1 : b ? 2 : 3` is `a ? 1 : (b ? 2 : 3)`
+    fn precedence_0_rule(&mut self) -> RuleResult {
+        let rule_result = self.run_rules_from_rhs(vec![
+            vec![
+                Rhs::Nonterminal(Parser::precedence_1_rule),
+                Rhs::Nonterminal(Parser::conditional_rule),
+            ],
+        ], false);
+
+        if rule_result.matched {
+            let start = self.pos - rule_result.tokens_consumed;
+            if let Some(question_mark) = self.find_question_mark(start, self.pos) {
+                // The condition may be a single token, which has no node yet
+                self.construct_expression_node_from_token_range(start, question_mark);
+                let condition_node = self.token_index_to_node.get(&start).expect("has value").1.clone();
+                let (colon, then_node) = self.token_index_to_node.get(&(question_mark+1)).expect("has value").clone();
+                let else_node = self.token_index_to_node.get(&(colon+1)).expect("has value").1.clone();
+                let mut node = new_node_from_token(question_mark, Token::QuestionMark);
+                node.add_child(condition_node);
+                node.add_child(then_node);
+                node.add_child(else_node);
+                self.token_index_to_node.insert(start, (self.pos, node));
+            }
+        }
+
+        return rule_result;
+    }
+
+    // Returns the index of the '?' of the conditional expression in the
+    // token range, which is the first one outside of parentheses
+    fn find_question_mark(&self, token_range_start: usize, token_range_end: usize) -> Option<usize> {
+        let mut depth = 0;
+        for index in token_range_start..token_range_end {
+            match self.tokens[index] {
+                Token::LeftParen => depth += 1,
+                Token::RightParen => depth -= 1,
+                Token::QuestionMark if depth == 0 => return Some(index),
+                _ => {}
+            }
+        }
+        return None;
+    }
+
+    fn precedence_1_rule(&mut self) -> RuleResult {
         let rule_result = self.run_rules_from_rhs(vec![
             vec![
                 Rhs::Nonterminal(Parser::precedence_2_rule),
@@ -338,14 +626,14 @@ impl Parser {
             ],
         ], false);
 
-        if rule_result.matched && rule_result.tokens_consumed > 0 {
+        if rule_result.matched && rule_result.tokens_consumed > 1 {
             self.construct_expression_node_from_token_range(
                 self.pos-rule_result.tokens_consumed,
                 self.pos
             );
         }
 
-        return rule_result
+        return rule_result;
     }
 
     fn precedence_2_rule(&mut self) -> RuleResult {
@@ -447,21 +735,23 @@ impl Parser {
             vec![Rhs::Nonterminal(Parser::base_rule)]
         ], false);
 
-        if rule_result.matched && rule_result.tokens_consumed == 2 {
+        let first_token = self.tokens.get(self.pos - rule_result.tokens_consumed);
+        let starts_with_unary_operator = matches!(first_token, Some(Token::Minus | Token::ExclamationMark));
+        if rule_result.matched && rule_result.tokens_consumed == 2 && starts_with_unary_operator {
             // For cases like -3, -alpha, !a, !3
             let index = self.pos-rule_result.tokens_consumed;
-            let unary_token = (*self.tokens.get(index).clone().expect("has value")).clone();
-            let value_token = (*self.tokens.get(index+1).clone().expect("has value")).clone();
+            let unary_token = (*self.tokens.get(index).expect("has value")).clone();
+            let value_token = (*self.tokens.get(index+1).expect("has value")).clone();
             let mut node = new_node_from_token(0, Token::Break);
             match unary_token {
                 Token::Minus => {
                     node = new_node_from_token(index, Token::Asterisk);      
-                    node.add_child(new_node_from_token(index+10001, Token::Num(-1)));
-                    node.add_child(new_node_from_token(index+10002, value_token.clone()));
+                    node.add_child(new_synthetic_node(index, 0, Token::Num(-1)));
+                    node.add_child(new_node_from_token(index+1, value_token.clone()));
                 }, 
                 Token::ExclamationMark => {
                     node = new_node_from_token(index, Token::ExclamationMark);
-                    node.add_child(new_node_from_token(index+10001, value_token.clone()));
+                    node.add_child(new_node_from_token(index+1, value_token.clone()));
                 }, 
                 _ => {}
             }
@@ -475,7 +765,7 @@ impl Parser {
             // will have already been parsed and stored in the map.
             let token_range_start = self.pos-rule_result.tokens_consumed;
             let token_range_end = self.pos;
-            let token = (*self.tokens.get(token_range_start).clone().expect("has value")).clone();
+            let token = (*self.tokens.get(token_range_start).expect("has value")).clone();
             // ensure that the expression starts with a unary operator.
             match token {
                 Token::Minus => {},
@@ -494,8 +784,8 @@ impl Parser {
                 let mut node = new_node_from_token(0, Token::Break);
                 match token {
                     Token::Minus => {
-                        node = new_node_from_token(token_index, Token::Asterisk);      
-                        node.add_child(new_node_from_token(token_index+10001, Token::Num(-1)));
+                        node = new_node_from_token(token_range_start, Token::Asterisk);
+                        node.add_child(new_synthetic_node(token_range_start, 0, Token::Num(-1)));
                         node.add_child(right_hand_side.1.clone());
                     },
                     Token::ExclamationMark => {
@@ -517,8 +807,11 @@ impl Parser {
 
     fn base_rule(&mut self) -> RuleResult {
         return self.run_rules_from_rhs(vec![
+            vec![Rhs::Nonterminal(Parser::increment_rule)],
             vec![Rhs::Terminal(Token::Id(String::from("_")))],
             vec![Rhs::Terminal(Token::Num(0))],
+            vec![Rhs::Terminal(Token::True)],
+            vec![Rhs::Terminal(Token::False)],
             vec![
                 Rhs::Terminal(Token::LeftParen),
                 Rhs::Nonterminal(Parser::exp_rule),
@@ -527,6 +820,40 @@ impl Parser {
         ], false);
     }    
 
+    // The prefix and postfix `++` and `--`, eg `++a` and `a--`
+    fn increment_rule(&mut self) -> RuleResult {
+        let rule_result = self.run_rules_from_rhs(vec![
+            vec![Rhs::Nonterminal(Parser::incop_rule), Rhs::Terminal(Token::Id(String::from("_")))],
+            vec![Rhs::Terminal(Token::Id(String::from("_"))), Rhs::Nonterminal(Parser::incop_rule)]
+        ], false);
+
+        if rule_result.matched {
+            let index = self.pos - rule_result.tokens_consumed;
+            let (operator_index, identifier_index) = match self.tokens.get(index).expect("has value") {
+                Token::Id(_) => (index+1, index),
+                _ => (index, index+1)
+            };
+            let operator = (*self.tokens.get(operator_index).expect("has value")).clone();
+            let identifier = (*self.tokens.get(identifier_index).expect("has value")).clone();
+            let mut node = new_node_from_token(operator_index, operator);
+            node.add_child(new_node_from_token(identifier_index, identifier));
+            self.token_index_to_node.insert(index, (self.pos, node));
+        }
+
+        return rule_result;
+    }
+
+    fn conditional_rule(&mut self) -> RuleResult {
+        return self.run_rules_from_rhs(vec![
+            vec![
+                Rhs::Terminal(Token::QuestionMark),
+                Rhs::Nonterminal(Parser::exp_rule),
+                Rhs::Terminal(Token::Colon),
+                Rhs::Nonterminal(Parser::exp_rule),
+            ],
+        ], true)
+    }
+
     fn precedence_1_recursive_rule(&mut self) -> RuleResult {
         return self.run_rules_from_rhs(vec![
             vec![
@@ -641,6 +968,13 @@ impl Parser {
         ], false)
     }
 
+    fn incop_rule(&mut self) -> RuleResult {
+        return self.run_rules_from_rhs(vec![
+            vec![Rhs::Terminal(Token::Increment)],
+            vec![Rhs::Terminal(Token::Decrement)],
+        ], false)
+    }
+
     fn unop_rule(&mut self) -> RuleResult {
         return self.run_rules_from_rhs(vec![
             vec![Rhs::Terminal(Token::ExclamationMark)],
@@ -652,9 +986,10 @@ impl Parser {
         // Using `mem::discriminant` instead of `==` because rust will compare 
         // both the enum variant AND the data contained in the variant (if applicable)
         // We don't want this behaviour here, since we only care about the enum variant equality
-        if mem::discriminant(&self.tokens[self.pos]) == mem::discriminant(token) {
+        if self.pos < self.tokens.len() && mem::discriminant(&self.tokens[self.pos]) == mem::discriminant(token) {
             return true;
         }
+        self.furthest_failed_pos = self.furthest_failed_pos.max(self.pos);
         return false;
     }
 
@@ -675,6 +1010,9 @@ impl Parser {
                     tokens_consumed: rule_result.tokens_consumed
                 };
             }
+            // Return the tokens consumed by the failed alternative,
+            // so that the next one starts from the same position
+            self.back_n_tokens(rule_result.tokens_consumed);
         }
         return RuleResult{matched: contains_epsilon, tokens_consumed: 0};
     }
@@ -690,12 +1028,12 @@ impl Parser {
     // rule was not eventually matched. In this method, we handle that by keeping
     // track of the consumed tokens and 'returning them back' if the rule
     // is not matched.
-    fn run_single_rule_from_rhs(&mut self, rhs:&Vec<Rhs>) -> RuleResult {
+    fn run_single_rule_from_rhs(&mut self, rhs: &[Rhs]) -> RuleResult {
         let mut count_tokens_matched = 0;
         for rhs_element in rhs.iter() {
             match rhs_element {
                 Rhs::Terminal(token) => {
-                    if !self.current_token_matches(&token) {
+                    if !self.current_token_matches(token) {
                         return RuleResult{
                             matched: false, 
                             tokens_consumed: count_tokens_matched
@@ -769,7 +1107,7 @@ impl Parser {
         let mut operand_stack = vec![];
         let mut token_index = token_range_start;
         while token_index < token_range_end {
-            let token = (*self.tokens.get(token_index).clone().expect("has value")).clone();
+            let token = (*self.tokens.get(token_index).expect("has value")).clone();
             if self.token_index_to_node.contains_key(&token_index) {
                 let end_index = self.token_index_to_node.get(&token_index).expect("defined").0;
                 operand_stack.push(
@@ -786,7 +1124,7 @@ impl Parser {
                     token_index += 1;
                     continue;
                 },
-                Token::Num(_) | Token::Id(_) => {
+                Token::Num(_) | Token::Id(_) | Token::True | Token::False => {
                     operand_stack.push((token_index, StackItem::Token(token)));
                 }
                 _ => {
@@ -811,35 +1149,25 @@ impl Parser {
         if operator_stack.is_empty() {
             node = left_node;
         } else {
-            while !operator_stack.is_empty() {
-                let operator_node = operator_stack.pop().expect("has value");
+            while let Some(operator_node) = operator_stack.pop() {
                 node = new_node_from_token(operator_node.0, operator_node.1);
                 let operand_node = operand_stack.pop().expect("has value");
-                let right_node;
-                match operand_node.1 {
-                    StackItem::Token(val) => {
-                        right_node = new_node_from_token(operand_node.0, val.clone());
-                    },
-                    StackItem::Node(val) => {
-                        right_node = val;
-                    }
-                }
+                let right_node = match operand_node.1 {
+                    StackItem::Token(val) => new_node_from_token(operand_node.0, val.clone()),
+                    StackItem::Node(val) => val
+                };
                 node.add_child(left_node.clone());
                 node.add_child(right_node.clone());
                 left_node = node.clone();
             }
         }
     
-        if !self.token_index_to_node.contains_key(&token_range_start) {
-            self.token_index_to_node.insert(
-                token_range_start, 
-                (token_range_end, node.clone())
-            
-            );
-        } else {
-            self.token_index_to_node.get_mut(&token_range_start).expect("not null").0 = token_range_end;
-            self.token_index_to_node.get_mut(&token_range_start).expect("not null").1 = node.clone();
-        }
+        // If a subexpression starting from the same token was parsed
+        // before, this expression contains it, so we overwrite it.
+        self.token_index_to_node.insert(
+            token_range_start,
+            (token_range_end, node.clone())
+        );
     }
 
 }
@@ -866,6 +1194,7 @@ pub enum StackItem {
 // ------------------------------------------------------
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use crate::utilities::get_tokens_from_program;
@@ -1027,4 +1356,230 @@ mod tests {
         let mut parser = Parser::new(tokens);
         assert_eq!(parser.analyze_grammar(), true);
     }
+
+    #[test]
+    fn test_unary_minus_node_ids() {
+        // The node of the negation has the id of the minus token, so
+        // that it differs from the id of the expression it negates
+        let program = String::from("{ a = -(b); }");
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert_eq!(parser.analyze_grammar(), true);
+        let negation_nodes = parser.ast.find(&|x| *x.value() == Token::Asterisk);
+        assert_eq!(negation_nodes.len(), 1);
+        assert_eq!(*negation_nodes[0].id(), 3);
+        let operand_node = negation_nodes[0].children().last().expect("to exist");
+        assert_eq!(*operand_node.value(), Token::Id(String::from("b")));
+        assert_eq!(*operand_node.id(), 5);
+    }
+
+    #[test]
+    fn test_switch() {
+        let program = String::from(
+            "{ \
+                var a : int; \
+                switch (a + 1) { \
+                    case 1: print a; a = 2; \
+                    case -2, 3 * 4: \
+                    default: \
+                        if (a == 1) break; \
+                        switch (a) { } \
+                    case 5: ; \
+                } \
+            }",
+        );
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let switch_node = &parser.ast.children()[0].children()[1];
+        assert_eq!(*switch_node.value(), Token::Switch);
+        let cases: Vec<(&Token, usize)> = switch_node.children()[1..].iter()
+            .map(|x| (x.value(), x.children().len()))
+            .collect();
+        assert_eq!(cases, vec![(&Token::Case, 2), (&Token::Case, 3), (&Token::Default, 1), (&Token::Case, 2)]);
+        // The statements of a case are in the block after its labels
+        assert_eq!(switch_node.children()[1].children()[1].children().len(), 2);
+
+        let program = String::from("{ switch (a) { case 1 print a; } }");
+        let mut parser = Parser::new(get_tokens_from_program(&program));
+        assert!(!parser.analyze_grammar());
+        let program = String::from("{ switch (a) { print a; } }");
+        let mut parser = Parser::new(get_tokens_from_program(&program));
+        assert!(!parser.analyze_grammar());
+    }
+
+    #[test]
+    fn test_increments_and_assignment_expressions() {
+        let program = String::from(
+            "{ \
+                var a, b : int; \
+                a++; \
+                --b; \
+                a = b = -a++ * ++b; \
+                for (a = 0; (b += 1) < 3; a--) print a--; \
+            }",
+        );
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let statements = parser.ast.children()[0].children();
+        assert_eq!(*statements[1].value(), Token::Increment);
+        assert_eq!(*statements[1].id(), 9);
+        assert_eq!(*statements[1].children()[0].id(), 8);
+        assert_eq!(*statements[2].value(), Token::Decrement);
+        assert_eq!(*statements[2].id(), 11);
+        assert_eq!(*statements[2].children()[0].id(), 12);
+        // The assignments are right associative
+        let assignment = &statements[3].children()[1];
+        assert_eq!(*assignment.value(), Token::Assignment);
+        assert_eq!(*assignment.children()[0].value(), Token::Id(String::from("b")));
+        let product = &assignment.children()[1];
+        assert_eq!(*product.value(), Token::Asterisk);
+        assert_eq!(*product.children()[0].children()[1].value(), Token::Increment);
+        assert_eq!(*product.children()[1].value(), Token::Increment);
+        let for_children = statements[4].children();
+        assert_eq!(*for_children[1].children()[0].value(), Token::AdditionAssignment);
+        assert_eq!(*for_children[2].value(), Token::Decrement);
+        assert_eq!(*for_children[3].children()[0].children()[0].value(), Token::Decrement);
+
+        for program in ["{ var a : int; a++ = 1; }", "{ var a : int; ++a++; }", "{ var a : int; (a) = 1; }"] {
+            let mut parser = Parser::new(get_tokens_from_program(program));
+            assert!(!parser.analyze_grammar());
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let program = String::from(
+            "{ \
+                const N = 10; \
+                var a : int; \
+                const M = -N * 2; \
+                a = N + M; \
+            }",
+        );
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let statements = parser.ast.children()[0].children();
+        assert_eq!(*statements[0].value(), Token::Const);
+        assert_eq!(*statements[0].id(), 1);
+        assert_eq!(*statements[0].children()[0].value(), Token::Id(String::from("N")));
+        assert_eq!(*statements[0].children()[1].value(), Token::Num(10));
+        assert_eq!(*statements[1].value(), Token::Int);
+        assert_eq!(*statements[2].value(), Token::Const);
+        assert_eq!(*statements[2].children()[1].value(), Token::Asterisk);
+        assert_eq!(*statements[3].value(), Token::Assignment);
+
+        for program in ["{ const N; }", "{ const N : int = 1; }", "{ const = 1; }", "{ print 1; const N = 1; }"] {
+            let mut parser = Parser::new(get_tokens_from_program(program));
+            assert!(!parser.analyze_grammar());
+        }
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        let program = String::from(
+            "{ \
+                var a, b : int; \
+                a = b > 0 ? 1 : b < 0 ? -1 : 0; \
+                print (a == 1 ? a : b) + 1; \
+                a = !true ? 1 : 2 * b; \
+                a = 1 + a ? 1 : 2 + 3; \
+            }",
+        );
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let statements = parser.ast.children()[0].children();
+        // The conditional operator is right associative
+        let conditional = &statements[1].children()[1];
+        assert_eq!(*conditional.value(), Token::QuestionMark);
+        assert_eq!(*conditional.id(), 13);
+        assert_eq!(*conditional.children()[0].value(), Token::GreaterThan);
+        assert_eq!(*conditional.children()[1].value(), Token::Num(1));
+        let nested_conditional = &conditional.children()[2];
+        assert_eq!(*nested_conditional.id(), 19);
+        assert_eq!(*nested_conditional.children()[0].value(), Token::LessThan);
+        assert_eq!(*nested_conditional.children()[2].value(), Token::Num(0));
+        let sum = &statements[2].children()[0];
+        assert_eq!(*sum.value(), Token::Plus);
+        assert_eq!(*sum.children()[0].value(), Token::QuestionMark);
+        let conditional = &statements[3].children()[1];
+        assert_eq!(*conditional.children()[0].value(), Token::ExclamationMark);
+        assert_eq!(*conditional.children()[2].value(), Token::Asterisk);
+        // The conditional operator has a lower precedence than the arithmetic ones
+        let conditional = &statements[4].children()[1];
+        assert_eq!(*conditional.value(), Token::QuestionMark);
+        assert_eq!(*conditional.children()[0].value(), Token::Plus);
+        assert_eq!(*conditional.children()[1].value(), Token::Num(1));
+        assert_eq!(*conditional.children()[2].value(), Token::Plus);
+
+        for program in ["{ var a : int; a = a ? 1; }", "{ var a : int; a = a ? 1 : ; }", "{ var a : int; print a ?; }"] {
+            let mut parser = Parser::new(get_tokens_from_program(program));
+            assert!(!parser.analyze_grammar());
+        }
+    }
+
+    #[test]
+    fn test_do_while_and_labels() {
+        let program = String::from(
+            "{ \
+                var a : int; \
+                do a += 1; while (a < 3); \
+                outer: while (a > 0) { \
+                    inner: do { \
+                        if (a == 2) continue outer; \
+                        break inner; \
+                    } while (true); \
+                    break; \
+                } \
+            }",
+        );
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let statements = parser.ast.children()[0].children();
+        assert_eq!(*statements[1].value(), Token::Do);
+        assert_eq!(*statements[1].children()[1].value(), Token::LessThan);
+        assert_eq!(*statements[2].value(), Token::Label(String::from("outer")));
+        assert_eq!(*statements[2].children()[0].value(), Token::While);
+        let jumps = parser.ast.find(&|x| matches!(x.value(), Token::Continue | Token::Break));
+        let labels: Vec<usize> = jumps.iter().map(|x| x.children().len()).collect();
+        assert_eq!(labels, vec![1, 1, 0]);
+        assert_eq!(*jumps[0].children()[0].value(), Token::Label(String::from("outer")));
+
+        let program = String::from("{ do print 1 while (true); }");
+        let mut parser = Parser::new(get_tokens_from_program(&program));
+        assert!(!parser.analyze_grammar());
+    }
+
+    #[test]
+    fn test_empty_statements() {
+        let program = String::from(
+            "{ \
+                ; \
+                if (true) ; else { ; } \
+            }",
+        );
+        let tokens = get_tokens_from_program(&program);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+    }
+
+    #[test]
+    fn test_declarations_and_statements() {
+        let parse = |input: &str| {
+            let mut parser = Parser::new(get_tokens_from_program(input));
+            let is_valid = parser.analyze_declaration_or_statement();
+            return (is_valid, parser.ast.children().first().map(|x| x.value().clone()));
+        };
+        assert_eq!(parse("var a, b : int;"), (true, Some(Token::Int)));
+        assert_eq!(parse("const N = 4;"), (true, Some(Token::Const)));
+        assert_eq!(parse("a += 1;"), (true, Some(Token::AdditionAssignment)));
+        assert_eq!(parse("while (a < 3) { a++; }"), (true, Some(Token::While)));
+        assert_eq!(parse("a = 1; a = 2;"), (false, None));
+        assert_eq!(parse("a = 1"), (false, None));
+        assert_eq!(parse("{ a = 1; }"), (false, None));
+    }
 }
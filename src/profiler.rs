@@ -0,0 +1,239 @@
+use orange_trees::Node;
+use std::collections::{BTreeMap, HashSet};
+use crate::Artifacts;
+use crate::lexer::{Token, Span};
+use crate::mixal::{emulator::MixMachine, instruction::MixalInstruction, loader::load_program};
+use crate::mixal::utilities::instruction_addresses;
+
+// The number of instructions after which the profiled program is
+// stopped, since it probably never halts
+const MAX_INSTRUCTIONS: u64 = 10_000_000;
+
+// The number of lines that the report lists as hot spots
+const HOT_SPOTS_COUNT: usize = 3;
+
+// The cost of the instructions that were generated for a line of the program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineProfile {
+    pub line: usize,
+    // The number of times the most executed instruction of the line has run,
+    // eg the condition of a loop runs once more than the loop iterates
+    pub executions: u64,
+    pub instructions: u64,
+    // In units of u
+    pub time: u64
+}
+
+// The cost of a loop, including the statements that it encloses
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoopProfile {
+    pub line: usize,
+    // The number of times the body of the loop has run
+    pub iterations: u64,
+    pub instructions: u64,
+    pub time: u64
+}
+
+// Where a program spends its time when it runs in the MIX emulator,
+// in terms of the lines and the loops of its YAL source code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    // The lines that have generated instructions, in order
+    pub lines: Vec<LineProfile>,
+    // The loops, in the order of their lines
+    pub loops: Vec<LoopProfile>,
+    // The totals of the program, which include the
+    // instructions that do not belong to any line
+    pub instructions: u64,
+    pub time: u64
+}
+
+impl Profile {
+    // The `machine` has run the program of the `instructions`, which were
+    // generated from the `ast`. The `spans` are the spans of its tokens.
+    pub fn new(
+        machine: &MixMachine,
+        instructions: &[MixalInstruction],
+        ast: &Node<usize, Token>,
+        spans: &[Span]
+    ) -> Profile {
+        // The statement and the profile of every executed word of the program
+        let words: Vec<(Option<usize>, u16)> = instructions.iter()
+            .zip(instruction_addresses(instructions))
+            .filter_map(|(instruction, address)| address.map(|x| (instruction.source, x)))
+            .collect();
+
+        let mut lines: BTreeMap<usize, LineProfile> = BTreeMap::new();
+        for (source, address) in &words {
+            let Some(span) = source.and_then(|x| spans.get(x)) else {
+                continue;
+            };
+            let profile = machine.profile[*address as usize];
+            let line = lines.entry(span.line).or_insert(LineProfile { line: span.line, ..LineProfile::default() });
+            line.executions = line.executions.max(profile.executions);
+            line.instructions += profile.executions;
+            line.time += profile.time;
+        }
+
+        let is_loop = |x: &Node<usize, Token>| matches!(x.value(), Token::While | Token::For | Token::Do);
+        let mut loops = vec![];
+        for loop_node in ast.find(&is_loop) {
+            let Some(span) = spans.get(*loop_node.id()) else {
+                continue;
+            };
+            let children = loop_node.children();
+            let body_node = match loop_node.value() {
+                Token::Do => &children[0],
+                _ => children.last().expect("to exist")
+            };
+            let loop_ids = subtree_ids(loop_node);
+            let body_ids = subtree_ids(body_node);
+            let mut loop_profile = LoopProfile { line: span.line, ..LoopProfile::default() };
+            for (source, address) in &words {
+                let Some(source) = source else {
+                    continue;
+                };
+                let profile = machine.profile[*address as usize];
+                if loop_ids.contains(source) {
+                    loop_profile.instructions += profile.executions;
+                    loop_profile.time += profile.time;
+                }
+            }
+            // The first instruction of the body runs once per iteration
+            if let Some((_, address)) = words.iter().find(|(x, _)| x.is_some_and(|x| body_ids.contains(&x))) {
+                loop_profile.iterations = machine.profile[*address as usize].executions;
+            }
+            loops.push(loop_profile);
+        }
+        loops.sort_by_key(|x| x.line);
+
+        return Profile {
+            lines: lines.into_values().collect(),
+            loops,
+            instructions: machine.executed_instructions,
+            time: machine.elapsed_time
+        };
+    }
+
+    // Returns the cost of every line and loop, followed by the lines that
+    // take the most time. The `source` is the text of the program.
+    pub fn report(&self, source: &str) -> String {
+        let source_lines: Vec<&str> = source.lines().collect();
+        let line_text = |line: usize| source_lines.get(line - 1).map(|x| x.trim()).unwrap_or_default();
+        let mut report = format!("The program ran {} instructions in {}u\n\n", self.instructions, self.time);
+
+        report.push_str(&format!(
+            "{:<6}{:>12}{:>14}{:>10}{:>8}  source\n", "line", "executions", "instructions", "time", "%"
+        ));
+        for line in &self.lines {
+            report.push_str(&format!(
+                "{:<6}{:>12}{:>14}{:>10}{:>8}  {}\n",
+                line.line, line.executions, line.instructions, line.time, self.percentage(line.time), line_text(line.line)
+            ));
+        }
+
+        if !self.loops.is_empty() {
+            report.push_str(&format!(
+                "\n{:<6}{:>12}{:>14}{:>10}{:>8}  source\n", "loop", "iterations", "instructions", "time", "%"
+            ));
+            for loop_profile in &self.loops {
+                report.push_str(&format!(
+                    "{:<6}{:>12}{:>14}{:>10}{:>8}  {}\n",
+                    loop_profile.line, loop_profile.iterations, loop_profile.instructions,
+                    loop_profile.time, self.percentage(loop_profile.time), line_text(loop_profile.line)
+                ));
+            }
+        }
+
+        report.push_str("\nhot spots\n");
+        let mut hot_lines: Vec<&LineProfile> = self.lines.iter().filter(|x| x.time > 0).collect();
+        hot_lines.sort_by_key(|x| std::cmp::Reverse(x.time));
+        for (rank, line) in hot_lines.iter().take(HOT_SPOTS_COUNT).enumerate() {
+            report.push_str(&format!(
+                "{}. line {}: {}u ({})  {}\n",
+                rank + 1, line.line, line.time, self.percentage(line.time), line_text(line.line)
+            ));
+        }
+        return report;
+    }
+
+    // Returns the `source` with the executions and the time of every line before it
+    pub fn annotated_source(&self, source: &str) -> String {
+        let lines: BTreeMap<usize, &LineProfile> = self.lines.iter().map(|x| (x.line, x)).collect();
+        let mut annotated_source = format!("{:>10}{:>10}  source\n", "executions", "time");
+        for (index, text) in source.lines().enumerate() {
+            match lines.get(&(index + 1)) {
+                Some(line) => annotated_source.push_str(&format!(
+                    "{:>10}{:>10}  {}\n", line.executions, format!("{}u", line.time), text
+                )),
+                None => annotated_source.push_str(&format!("{:>22}{}\n", "", text))
+            }
+        }
+        return annotated_source;
+    }
+
+    // The share of the time of the program, eg '42.5%'
+    fn percentage(&self, time: u64) -> String {
+        let percentage = if self.time == 0 { 0.0 } else { time as f64 * 100.0 / self.time as f64 };
+        return format!("{:.1}%", percentage);
+    }
+}
+
+fn subtree_ids(node: &Node<usize, Token>) -> HashSet<usize> {
+    return node.find(&|_| true).iter().map(|x| *x.id()).collect();
+}
+
+// Runs the program that has been compiled to the `artifacts` in the
+// MIX emulator. Returns its output along with its profile, or an
+// error if it cannot be loaded or does not halt.
+pub fn profile_program(artifacts: &Artifacts) -> Result<(Vec<String>, Profile), String> {
+    let instructions = artifacts.instructions.as_ref().expect("to exist");
+    let program = load_program(instructions)?;
+    let mut machine = MixMachine::new(&program);
+    machine.run(MAX_INSTRUCTIONS)?;
+    let profile = Profile::new(&machine, instructions, artifacts.ast.as_ref().expect("to exist"), &artifacts.spans);
+    return Ok((machine.output, profile));
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, CompileOptions};
+
+    const PROGRAM: &str = "\
+{
+    var i, total : int;
+    total = 0;
+    for (i = 0; i < 4; i++) {
+        total += i;
+    }
+    print total;
+}";
+
+    #[test]
+    fn test_profile() {
+        let artifacts = compile(PROGRAM, &CompileOptions::default()).unwrap();
+        let (output, profile) = profile_program(&artifacts).unwrap();
+        assert_eq!(output, vec!["    +0000000006"]);
+        let lines: Vec<(usize, u64)> = profile.lines.iter().map(|x| (x.line, x.executions)).collect();
+        assert_eq!(lines, vec![(3, 1), (4, 5), (5, 4), (7, 1)]);
+        assert_eq!(profile.loops.len(), 1);
+        assert_eq!((profile.loops[0].line, profile.loops[0].iterations), (4, 4));
+        // Every instruction but the final 'HLT' belongs to a line
+        let lines_time: u64 = profile.lines.iter().map(|x| x.time).sum();
+        assert_eq!(lines_time + 10, profile.time);
+        assert_eq!(profile.loops[0].time, profile.lines[1].time + profile.lines[2].time);
+
+        let report = profile.report(PROGRAM);
+        assert!(report.starts_with(&format!("The program ran {} instructions in {}u\n", profile.instructions, profile.time)));
+        assert!(report.contains(&format!("\nhot spots\n1. line 4: {}u", profile.lines[1].time)));
+        let annotated_source = profile.annotated_source(PROGRAM);
+        assert!(annotated_source.contains(&format!("         4{:>10}          total += i;\n", format!("{}u", profile.lines[2].time))));
+        assert!(annotated_source.contains("\n                          }\n"));
+    }
+
+}
@@ -0,0 +1,246 @@
+use orange_trees::Node;
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::{compile, CompileOptions, Stage};
+use crate::diagnostics::{Diagnostics, Severity};
+use crate::lexer::Token;
+use crate::parser::Parser;
+use crate::interpreter::Interpreter;
+use crate::type_checker::{Type, TypeChecker};
+use crate::mixal::{utilities::instructions_to_mixal, word::MixWord};
+use crate::utilities::{ast_to_string, get_tokens_and_spans_from_program};
+
+pub const REPL_HELP: &str = "\
+Enter a declaration or a statement to run it, eg 'var a : int;' or 'a = 2; print a * 3;'.
+:ast [statement]     Print the tree of the statement, or of the last input
+:mixal [statement]   Print the MIXAL code of the statement, or of the last input
+:vars                Print the values of the variables and the constants
+:help                Print this message
+:quit                Exit the REPL";
+
+// Runs the declarations and the statements of a YAL program one at a
+// time, as they are entered. The variables keep their values between
+// the inputs, and every input is checked along with the previous ones,
+// as if they were a single program. The statements are run by the
+// interpreter, which prints what the generated code would print.
+#[derive(Default)]
+pub struct Repl {
+    // The inputs that have been run, which are declarations or statements
+    declarations: Vec<String>,
+    statements: Vec<String>,
+    // The variables and the constants, in the order of their declarations
+    types: Vec<(String, Type)>,
+    values: HashMap<String, MixWord>,
+    last_input: Option<String>
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        return Repl::default();
+    }
+
+    // Runs an input, which is either a meta-command that starts with ':'
+    // or a declaration or statement, and returns what it prints. Fails
+    // with the diagnostics of an invalid input.
+    pub fn evaluate(&mut self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        let (command, argument) = match input.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (input, "")
+        };
+        match command {
+            "" => return Ok(String::new()),
+            ":ast" => {
+                let node = self.parse(self.meta_command_input(argument)?)?;
+                return Ok(ast_to_string(&node));
+            },
+            ":mixal" => return self.mixal(self.meta_command_input(argument)?),
+            ":vars" => return Ok(self.variables()),
+            ":help" => return Ok(format!("{}\n", REPL_HELP)),
+            _ if command.starts_with(':') => {
+                return Err(format!("unknown command '{}', type ':help' for the commands", command));
+            },
+            _ => {}
+        }
+
+        let node = self.parse(input)?;
+        let is_declaration = matches!(node.value(), Token::Int | Token::Bool | Token::Const);
+        let (program, input_lines) = self.program_with_input(input, is_declaration, true);
+        let options = CompileOptions { stop_after: Stage::SemanticAnalysis, ..CompileOptions::default() };
+        compile(&program, &options).map_err(|x| input_errors(x, &input_lines))?;
+
+        let mut interpreter = Interpreter::new(&node);
+        interpreter.variables = std::mem::take(&mut self.values);
+        // An exception is part of the output, and the effects of
+        // the statement until the exception are kept
+        let _ = interpreter.run();
+        self.values = interpreter.variables;
+        if is_declaration {
+            self.add_declaration_types(&node);
+            self.declarations.push(input.to_string());
+        } else {
+            self.statements.push(input.to_string());
+        }
+        self.last_input = Some(input.to_string());
+        return Ok(interpreter.output.iter().map(|x| format!("{}\n", x)).collect());
+    }
+
+    // The input of ':ast' and ':mixal', which defaults to the last input
+    fn meta_command_input<'a>(&'a self, argument: &'a str) -> Result<&'a str, String> {
+        if !argument.is_empty() {
+            return Ok(argument);
+        }
+        return self.last_input.as_deref().ok_or(String::from("there is no previous input"));
+    }
+
+    // Parses a declaration or a statement, and returns its node
+    fn parse(&self, input: &str) -> Result<Node<usize, Token>, String> {
+        let (tokens, spans) = get_tokens_and_spans_from_program(input).map_err(|x| x.to_string())?;
+        let mut parser = Parser::new(tokens);
+        if !parser.analyze_declaration_or_statement() {
+            return Err(parser.syntax_error(&spans).to_string());
+        }
+        return Ok(parser.ast.children()[0].clone());
+    }
+
+    // Returns a program with the declarations and, if `with_statements`,
+    // the statements that have been entered, along with the input, one per
+    // line. The lines of the input in the program are returned too.
+    fn program_with_input(&self, input: &str, is_declaration: bool, with_statements: bool) -> (String, Range<usize>) {
+        let mut lines = vec![String::from("{")];
+        lines.extend(self.declarations.iter().cloned());
+        if is_declaration {
+            lines.push(input.to_string());
+        }
+        let mut input_line = lines.len();
+        if with_statements {
+            lines.extend(self.statements.iter().cloned());
+        }
+        if !is_declaration {
+            lines.push(input.to_string());
+            input_line = lines.len();
+        }
+        lines.push(String::from("}"));
+        return (lines.join("\n"), input_line..input_line + input.lines().count());
+    }
+
+    // Returns the code that is generated for the statement, without the
+    // code of the declarations or the statements that have been entered
+    fn mixal(&self, input: &str) -> Result<String, String> {
+        let node = self.parse(input)?;
+        let is_declaration = matches!(node.value(), Token::Int | Token::Bool | Token::Const);
+        let (program, input_lines) = self.program_with_input(input, is_declaration, false);
+        let artifacts = compile(&program, &CompileOptions::default()).map_err(|x| input_errors(x, &input_lines))?;
+        let instructions: Vec<_> = artifacts.instructions.expect("to exist").into_iter()
+            .filter(|x| x.source.and_then(|x| artifacts.spans.get(x)).is_some_and(|x| input_lines.contains(&x.line)))
+            .collect();
+        return Ok(instructions_to_mixal(&instructions));
+    }
+
+    // Lists the variables and the constants, with their types and values
+    fn variables(&self) -> String {
+        let mut variables = String::new();
+        for (name, variable_type) in &self.types {
+            let value = self.values.get(name).copied().unwrap_or_default();
+            let value = match variable_type {
+                Type::Int => value.to_i64().to_string(),
+                Type::Bool => (!value.is_zero()).to_string()
+            };
+            variables.push_str(&format!("{} : {} = {}\n", name, variable_type, value));
+        }
+        return variables;
+    }
+
+    fn add_declaration_types(&mut self, node: &Node<usize, Token>) {
+        if let Some(variable_type) = Type::from_token(node.value()) {
+            for child in node.children() {
+                if let Token::Id(identifier) = child.value() {
+                    self.types.push((identifier.clone(), variable_type));
+                }
+            }
+            return;
+        }
+        // The type of a constant is the type of its value
+        let children = node.children();
+        let symbol_table: HashMap<&String, Type> = self.types.iter().map(|(name, x)| (name, *x)).collect();
+        let constant_type = TypeChecker::new(&symbol_table, &[])
+            .infer_expression_type(&children[1])
+            .unwrap_or(Type::Int);
+        if let Token::Id(identifier) = children[0].value() {
+            self.types.push((identifier.clone(), constant_type));
+        }
+    }
+}
+
+// Returns the errors about a program that the REPL has built around an input,
+// whose lines are `input_lines`, with their lines relative to the input. The
+// warnings are left out, since they are about a program that is not complete,
+// and so are the notes, which may refer to the lines of the previous inputs.
+fn input_errors(diagnostics: Diagnostics, input_lines: &Range<usize>) -> String {
+    return diagnostics.into_iter()
+        .filter(|x| x.severity == Severity::Error)
+        .map(|mut diagnostic| {
+            diagnostic.notes.clear();
+            if let Some(span) = diagnostic.span.as_mut().filter(|x| input_lines.contains(&x.line)) {
+                span.line -= input_lines.start - 1;
+            }
+            return diagnostic.to_string();
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.evaluate("var a, b : int;"), Ok(String::new()));
+        assert_eq!(repl.evaluate("const N = 3;"), Ok(String::new()));
+        assert_eq!(repl.evaluate("a = N * 2;"), Ok(String::new()));
+        assert_eq!(repl.evaluate("for (b = 0; b < N; b++) {\n    a += b;\n}"), Ok(String::new()));
+        assert_eq!(repl.evaluate("print a;"), Ok(String::from("    +0000000009\n")));
+        assert_eq!(repl.evaluate("var done : bool;"), Ok(String::new()));
+        assert_eq!(repl.evaluate("done = a > N;"), Ok(String::new()));
+        assert_eq!(repl.evaluate(":vars"), Ok(String::from(
+            "a : int = 9\nb : int = 3\nN : int = 3\ndone : bool = true\n"
+        )));
+        assert_eq!(repl.evaluate("print a / (b - N);"), Ok(String::from("EXCEPTION 00001\n")));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.evaluate("var a : int;"), Ok(String::new()));
+        assert_eq!(repl.evaluate("a = ;"), Err(String::from("error[E0002]: 1:5: unexpected token ';'")));
+        assert_eq!(
+            repl.evaluate("var a : bool;"),
+            Err(String::from("error[E0003]: 1:5: re-declaration of identifier 'a'"))
+        );
+        assert!(repl.evaluate("a = true;").unwrap_err().starts_with("error[E0008]: 1:"));
+        assert!(repl.evaluate("break;").unwrap_err().starts_with("error[E0005]: 1:1:"));
+        assert_eq!(repl.evaluate(":unknown"), Err(String::from("unknown command ':unknown', type ':help' for the commands")));
+        // The invalid inputs are not kept
+        assert_eq!(repl.evaluate(":vars"), Ok(String::from("a : int = 0\n")));
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.evaluate(":ast"), Err(String::from("there is no previous input")));
+        assert_eq!(repl.evaluate("var a : int;"), Ok(String::new()));
+        assert_eq!(repl.evaluate("a = 2;"), Ok(String::new()));
+        assert_eq!(repl.evaluate(":ast"), Ok(ast_to_string(&repl.parse("a = 2;").unwrap())));
+        assert!(repl.evaluate(":ast print a;").unwrap().contains("Print"));
+        let mixal = repl.evaluate(":mixal").unwrap();
+        assert!(mixal.contains("ENTA 2"));
+        assert!(!mixal.contains("HLT"));
+        assert!(repl.evaluate(":help").unwrap().starts_with("Enter a declaration"));
+    }
+}
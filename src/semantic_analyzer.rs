@@ -1,87 +1,389 @@
 use orange_trees::Node;
-use crate::lexer::Token;
-use std::collections::HashSet;
+use crate::lexer::{Token, Span};
+use crate::type_checker::{Type, TypeChecker};
+use crate::dataflow_analyzer::DataflowAnalyzer;
+use crate::interpreter::evaluate_constant_expression;
+use crate::mixal::word::{MixWord, WORD_MODULO};
+use crate::utilities::is_assignment_operator;
+use crate::diagnostics::*;
+use std::collections::{HashMap, hash_map::Entry};
 
 pub struct SemanticAnalyzer<'a> {
     pub ast: &'a Node<usize, Token>,
-    pub symbol_table: HashSet<&'a String>
+    pub spans: &'a [Span],
+    pub symbol_table: HashMap<&'a String, Type>,
+
+    // key: the id of an expression node
+    // value: the type of the expression modeled by the node
+    pub expression_types: HashMap<usize, Type>,
+
+    // key: the name of a constant
+    // value: the value of the constant, which is known at compile time
+    pub constants: HashMap<String, MixWord>,
+
+    // key: the name of a constant
+    // value: the span of its declaration
+    constant_spans: HashMap<&'a String, Option<Span>>,
+
+    diagnostics: Vec<Diagnostic>
 }
 
 impl<'a> SemanticAnalyzer<'a> {
-    pub fn new(ast: &Node<usize, Token>) -> SemanticAnalyzer {
+    pub fn new(ast: &'a Node<usize, Token>, spans: &'a [Span]) -> SemanticAnalyzer<'a> {
         SemanticAnalyzer {
             ast,
-            symbol_table: HashSet::new()
+            spans,
+            symbol_table: HashMap::new(),
+            expression_types: HashMap::new(),
+            constants: HashMap::new(),
+            constant_spans: HashMap::new(),
+            diagnostics: vec![]
         }
     }
 
-    pub fn run(&mut self) -> bool {
-        let mut violations = 0;
-        violations += self.populate_symbol_table_and_check_for_variable_re_declarations();
-        violations += self.check_for_undeclared_identifiers();
-        violations += self.check_for_break_or_continue_outside_of_loop_block();
-        return violations == 0;
+    // Runs all the semantic checks and returns the problems found.
+    // The program is semantically valid if none of them is an error,
+    // warnings point to code that is valid but suspicious.
+    pub fn run(&mut self) -> Vec<Diagnostic> {
+        self.check_literal_ranges();
+        self.populate_symbol_table_and_check_for_variable_re_declarations();
+        self.check_for_undeclared_identifiers();
+        self.check_for_assignments_to_constants();
+        self.check_for_break_or_continue_outside_of_loop_block();
+        self.check_case_labels();
+        self.check_types();
+        if !has_errors(&self.diagnostics) {
+            // The dataflow analyses assume that the program is valid
+            self.collect_dataflow_warnings();
+        }
+        return self.diagnostics.clone();
     }
 
-    fn populate_symbol_table_and_check_for_variable_re_declarations(&mut self) -> u8 {
-        let mut violations = 0;
-        let variable_declaration_nodes = self.ast.find(&|x| *x.value() == Token::Int);
-        for variable_declaration_node in variable_declaration_nodes {
-            for identifier_node in variable_declaration_node.children() {
+    // Constants and variables share the symbol table. The value of a
+    // constant may only use the constants that are declared before it,
+    // so the declarations are visited in the order of the program.
+    fn populate_symbol_table_and_check_for_variable_re_declarations(&mut self) {
+        let mut declaration_spans = HashMap::new();
+        let declaration_nodes = self.ast.find(&|x| {
+            return Type::from_token(x.value()).is_some() || *x.value() == Token::Const;
+        });
+        for declaration_node in declaration_nodes {
+            let (variable_type, identifier_nodes) = match Type::from_token(declaration_node.value()) {
+                Some(variable_type) => (variable_type, declaration_node.children()),
+                None => (self.add_constant(declaration_node), &declaration_node.children()[..1])
+            };
+            for identifier_node in identifier_nodes {
                 if let Token::Id(identifier_name) = identifier_node.value() {
-                    if self.symbol_table.contains(&identifier_name) {
-                        println!("ERROR: re-declaration of identifier '{}'", &identifier_name);
-                        violations += 1;
-                    } else {
-                        self.symbol_table.insert(identifier_name);
+                    let span = self.spans.get(*identifier_node.id()).copied();
+                    match self.symbol_table.entry(identifier_name) {
+                        Entry::Occupied(_) => {
+                            let mut diagnostic = Diagnostic::error(
+                                VARIABLE_RE_DECLARATION,
+                                span,
+                                format!("re-declaration of identifier '{}'", &identifier_name)
+                            );
+                            if let Some(Some(first_span)) = declaration_spans.get(identifier_name) {
+                                let first_span: &Span = first_span;
+                                diagnostic = diagnostic.with_note(format!(
+                                    "'{}' is first declared at {}:{}",
+                                    identifier_name, first_span.line, first_span.column
+                                ));
+                            }
+                            self.diagnostics.push(diagnostic);
+                        },
+                        Entry::Vacant(entry) => {
+                            entry.insert(variable_type);
+                            declaration_spans.insert(identifier_name, span);
+                            if *declaration_node.value() == Token::Const {
+                                self.constant_spans.insert(identifier_name, span);
+                            }
+                        }
                     }
-                    
+
                 }
             }
         }
-        return violations;
     }
 
-    fn check_for_undeclared_identifiers(&self) -> u8 {
-        let violating_nodes = self.ast.find(&|x| {
-            if let Token::Id(identifier_name) = x.value() {
-                if !self.symbol_table.contains(&identifier_name) {
-                    println!("ERROR: undeclared identifier '{}'", identifier_name);
-                    return true;
+    // Infers the type of a constant and evaluates its value, which
+    // must be a constant expression. Returns the type of the constant.
+    fn add_constant(&mut self, constant_node: &'a Node<usize, Token>) -> Type {
+        let children = constant_node.children();
+        let (identifier_node, value_node) = (&children[0], &children[1]);
+        // The errors of the value are reported when the types are checked
+        let constant_type = TypeChecker::new(&self.symbol_table, self.spans)
+            .infer_expression_type(value_node)
+            .unwrap_or(Type::Int);
+        if let Token::Id(identifier_name) = identifier_node.value() {
+            match evaluate_constant_expression(value_node, &self.constants) {
+                Some(value) => {
+                    self.constants.entry(identifier_name.clone()).or_insert(value);
+                },
+                None => {
+                    self.diagnostics.push(Diagnostic::error(
+                        NON_CONSTANT_INITIALIZER,
+                        self.get_expression_start_span(value_node),
+                        format!("the value of constant '{}' is not a constant expression", identifier_name)
+                    ).with_note(String::from(
+                        "it can only contain literals, previous constants and operators, and cannot divide by zero"
+                    )));
                 }
             }
+        }
+        return constant_type;
+    }
+
+    // The literals must fit in a MIX word, otherwise
+    // the generated code would silently wrap them
+    fn check_literal_ranges(&mut self) {
+        let literal_nodes = self.ast.find(&|x| {
+            if let Token::Num(value) = x.value() {
+                return value.unsigned_abs() as u64 >= WORD_MODULO;
+            }
             return false;
         });
-        return violating_nodes.len().try_into().unwrap();
+        for node in literal_nodes {
+            if let Token::Num(value) = node.value() {
+                self.diagnostics.push(Diagnostic::error(
+                    LITERAL_OUT_OF_RANGE,
+                    self.spans.get(*node.id()).copied(),
+                    format!("the literal {} does not fit in a MIX word", value)
+                ).with_note(format!("the magnitude of a number can be at most {}", WORD_MODULO - 1)));
+            }
+        }
+    }
+
+    fn check_for_undeclared_identifiers(&mut self) {
+        self.check_for_undeclared_identifiers_under_node(self.ast);
+    }
+
+    // Reports the undeclared identifiers under `node` in the order of the
+    // program. The note suggests a declaration with the type that the
+    // identifier must have where it is used, if it can be inferred.
+    fn check_for_undeclared_identifiers_under_node(&mut self, node: &Node<usize, Token>) {
+        for (index, child) in node.children().iter().enumerate() {
+            if let Token::Id(identifier_name) = child.value() {
+                if !self.symbol_table.contains_key(&identifier_name) {
+                    let declaration_type = TypeChecker::new(&self.symbol_table, self.spans)
+                        .infer_operand_type(node, index)
+                        .map_or(String::from("<type>"), |x| x.to_string());
+                    self.diagnostics.push(Diagnostic::error(
+                        UNDECLARED_IDENTIFIER,
+                        self.spans.get(*child.id()).copied(),
+                        format!("undeclared identifier '{}'", identifier_name)
+                    ).with_note(format!("declare it with 'var {} : {};'", identifier_name, declaration_type)));
+                }
+            }
+            self.check_for_undeclared_identifiers_under_node(child);
+        }
     }
 
-    fn check_for_break_or_continue_outside_of_loop_block(&self) -> u8 {
-        let mut violations = HashSet::new();
+    fn check_for_assignments_to_constants(&mut self) {
+        let assignment_nodes = self.ast.find(&|x| {
+            return is_assignment_operator(x.value()) || matches!(x.value(), Token::Increment | Token::Decrement);
+        });
+        for assignment_node in assignment_nodes {
+            let identifier_node = &assignment_node.children()[0];
+            if let Token::Id(identifier_name) = identifier_node.value() {
+                if let Some(declaration_span) = self.constant_spans.get(identifier_name) {
+                    let mut diagnostic = Diagnostic::error(
+                        ASSIGNMENT_TO_CONSTANT,
+                        self.spans.get(*identifier_node.id()).copied(),
+                        format!("cannot assign to constant '{}'", identifier_name)
+                    );
+                    if let Some(declaration_span) = declaration_span {
+                        diagnostic = diagnostic.with_note(format!(
+                            "'{}' is declared as a constant at {}:{}",
+                            identifier_name, declaration_span.line, declaration_span.column
+                        ));
+                    }
+                    self.diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
 
-        // Assume all 'continue' and 'break' statements are violations
-        let continue_and_break_nodes = 
-            self.ast.find(&|x| *x.value() == Token::Continue || *x.value() == Token::Break);
-        for node in continue_and_break_nodes {
-            violations.insert(node.id());
+    fn check_for_break_or_continue_outside_of_loop_block(&mut self) {
+        // key: the name of a label
+        // value: the span of the first statement with the label
+        let mut label_spans = HashMap::new();
+        // The labels that 'continue' and 'break' statements refer
+        // to have no children, unlike the labels of statements
+        for node in self.ast.find(&|x| matches!(x.value(), Token::Label(_)) && !x.children().is_empty()) {
+            if let Token::Label(name) = node.value() {
+                label_spans.entry(name).or_insert(self.spans.get(*node.id()).copied());
+            }
         }
+        let mut enclosing_loop_labels = vec![];
+        self.check_jumps_under_node(self.ast, &mut enclosing_loop_labels, &label_spans);
+    }
 
-        // Find 'continue' and 'break' statements that are not
-        // violations (ie that have a 'while' or 'for' ancestor)
-        // and remove them from the violations set
-        let while_and_for_nodes = 
-            self.ast.find(&|x| *x.value() == Token::While || *x.value() == Token::For);
-        for node in while_and_for_nodes {
-            let continue_and_break_nodes_under_node 
-                = node.find(&|x| *x.value() == Token::Continue || *x.value() == Token::Break);
-            for node in continue_and_break_nodes_under_node {
-                violations.remove(node.id());
+    // Walks the statements under the node and checks that every 'continue'
+    // and 'break' statement is inside a loop, and that the label it refers
+    // to, if any, is the label of one of the loops that enclose it. The
+    // stack holds the labels of the enclosing loops, innermost last.
+    fn check_jumps_under_node(
+        &mut self,
+        node: &'a Node<usize, Token>,
+        enclosing_loop_labels: &mut Vec<Option<&'a String>>,
+        label_spans: &HashMap<&String, Option<Span>>
+    ) {
+        match node.value() {
+            Token::While | Token::For | Token::Do => {
+                enclosing_loop_labels.push(None);
+                for child in node.children() {
+                    self.check_jumps_under_node(child, enclosing_loop_labels, label_spans);
+                }
+                enclosing_loop_labels.pop();
+            },
+            Token::Label(name) => {
+                let statement_node = &node.children()[0];
+                let span = self.spans.get(*node.id()).copied();
+                if enclosing_loop_labels.contains(&Some(name)) {
+                    self.diagnostics.push(Diagnostic::error(
+                        MISPLACED_LABEL,
+                        span,
+                        format!("label '{}' shadows the label of an enclosing loop", name)
+                    ));
+                }
+                if !matches!(statement_node.value(), Token::While | Token::For | Token::Do) {
+                    self.diagnostics.push(Diagnostic::error(
+                        MISPLACED_LABEL,
+                        span,
+                        format!("label '{}' is not placed on a loop", name)
+                    ).with_note(String::from("only 'while', 'do' and 'for' statements can be labeled")));
+                    self.check_jumps_under_node(statement_node, enclosing_loop_labels, label_spans);
+                    return;
+                }
+                enclosing_loop_labels.push(Some(name));
+                for child in statement_node.children() {
+                    self.check_jumps_under_node(child, enclosing_loop_labels, label_spans);
+                }
+                enclosing_loop_labels.pop();
+            },
+            Token::Continue | Token::Break => {
+                let span = self.spans.get(*node.id()).copied();
+                match node.children().first().map(|x| (x.value(), x.id())) {
+                    None => {
+                        if enclosing_loop_labels.is_empty() {
+                            self.diagnostics.push(Diagnostic::error(
+                                JUMP_OUTSIDE_OF_LOOP,
+                                span,
+                                String::from("continue/break statement outside of loop")
+                            ));
+                        }
+                    },
+                    Some((Token::Label(name), label_id)) => {
+                        if !enclosing_loop_labels.contains(&Some(name)) {
+                            let mut diagnostic = Diagnostic::error(
+                                UNKNOWN_LABEL,
+                                self.spans.get(*label_id).copied(),
+                                format!("label '{}' is not the label of an enclosing loop", name)
+                            );
+                            if let Some(Some(label_span)) = label_spans.get(name) {
+                                diagnostic = diagnostic.with_note(format!(
+                                    "'{}' labels the statement at {}:{}", name, label_span.line, label_span.column
+                                ));
+                            }
+                            self.diagnostics.push(diagnostic);
+                        }
+                    },
+                    Some(_) => {}
+                }
+            },
+            _ => {
+                for child in node.children() {
+                    self.check_jumps_under_node(child, enclosing_loop_labels, label_spans);
+                }
             }
         }
-        
-        for _ in &violations {
-            println!("ERROR: continue/break statement outside of loop");
+    }
+
+    // The labels of the cases of a switch statement must be constant
+    // expressions, so that they can be compared with the value of the
+    // switch at compile time, and no two of them can have the same value.
+    fn check_case_labels(&mut self) {
+        for switch_node in self.ast.find(&|x| *x.value() == Token::Switch) {
+            // key: the value of a label
+            // value: the span of the first label with that value
+            let mut label_spans: HashMap<i64, Option<Span>> = HashMap::new();
+            let mut default_span: Option<Option<Span>> = None;
+            for case_node in &switch_node.children()[1..] {
+                let span = self.spans.get(*case_node.id()).copied();
+                if *case_node.value() == Token::Default {
+                    match default_span {
+                        Some(first_span) => {
+                            let mut diagnostic =
+                                Diagnostic::error(DUPLICATE_CASE_LABEL, span, String::from("duplicate default label"));
+                            if let Some(first_span) = first_span {
+                                diagnostic = diagnostic.with_note(format!(
+                                    "the first default label is at {}:{}", first_span.line, first_span.column
+                                ));
+                            }
+                            self.diagnostics.push(diagnostic);
+                        },
+                        None => default_span = Some(span)
+                    }
+                    continue;
+                }
+                // The last child of a case is its block
+                let label_nodes = &case_node.children()[..case_node.children().len() - 1];
+                for label_node in label_nodes {
+                    let span = self.get_expression_start_span(label_node);
+                    let Some(value) = evaluate_constant_expression(label_node, &self.constants) else {
+                        self.diagnostics.push(Diagnostic::error(
+                            NON_CONSTANT_CASE_LABEL,
+                            span,
+                            String::from("case label is not a constant expression")
+                        ).with_note(String::from(
+                            "case labels can only contain literals, constants and operators, and cannot divide by zero"
+                        )));
+                        continue;
+                    };
+                    match label_spans.entry(value.to_i64()) {
+                        Entry::Occupied(entry) => {
+                            let mut diagnostic = Diagnostic::error(
+                                DUPLICATE_CASE_LABEL,
+                                span,
+                                format!("duplicate case label with the value {}", value.to_i64())
+                            );
+                            if let Some(first_span) = entry.get() {
+                                diagnostic = diagnostic.with_note(format!(
+                                    "the first label with this value is at {}:{}", first_span.line, first_span.column
+                                ));
+                            }
+                            self.diagnostics.push(diagnostic);
+                        },
+                        Entry::Vacant(entry) => {
+                            entry.insert(span);
+                        }
+                    }
+                }
+            }
         }
+    }
 
-        return violations.len().try_into().unwrap();
-    }    
+    // Returns the span of the first token of an expression, which is
+    // the token of the node with the smallest id (the unary minus adds
+    // nodes whose ids do not match a token, and have no span).
+    fn get_expression_start_span(&self, node: &Node<usize, Token>) -> Option<Span> {
+        return node.find(&|x| self.spans.get(*x.id()).is_some()).iter()
+            .map(|x| *x.id())
+            .min()
+            .and_then(|x| self.spans.get(x).copied());
+    }
+
+    fn check_types(&mut self) {
+        let mut type_checker = TypeChecker::new(&self.symbol_table, self.spans);
+        type_checker.run(self.ast);
+        self.expression_types = type_checker.expression_types;
+        self.diagnostics.extend(type_checker.diagnostics);
+    }
+
+    fn collect_dataflow_warnings(&mut self) {
+        let mut dataflow_analyzer = DataflowAnalyzer::new(self.ast, self.spans, &self.symbol_table);
+        dataflow_analyzer.run();
+        self.diagnostics.extend(dataflow_analyzer.warnings);
+    }
 }
+
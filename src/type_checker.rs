@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::fmt;
+use orange_trees::Node;
+use crate::lexer::{Token, Span};
+use crate::utilities::token_to_source_text;
+use crate::diagnostics::*;
+
+// The types of the language. Every variable has one of these
+// types, which is given in its declaration, and so does every
+// expression, which is inferred by the `TypeChecker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool
+}
+
+impl Type {
+    pub fn from_token(token: &Token) -> Option<Type> {
+        match token {
+            Token::Int => Some(Type::Int),
+            Token::Bool => Some(Type::Bool),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool")
+        }
+    }
+}
+
+// This pass infers the type of every expression in the AST and
+// rejects the expressions whose operands have the wrong type.
+// Integers and booleans never mix: arithmetic and ordering
+// operators expect ints, logical operators and the conditions
+// of control statements expect bools, and the two sides of an
+// equality or an assignment must have the same type, as well as
+// the value of a switch statement and the labels of its cases.
+pub struct TypeChecker<'a> {
+    symbol_table: &'a HashMap<&'a String, Type>,
+    spans: &'a [Span],
+
+    // key: the id of an expression node
+    // value: the type of the expression modeled by the node
+    pub expression_types: HashMap<usize, Type>,
+
+    pub diagnostics: Vec<Diagnostic>
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(symbol_table: &'a HashMap<&'a String, Type>, spans: &'a [Span]) -> TypeChecker<'a> {
+        TypeChecker {
+            symbol_table,
+            spans,
+            expression_types: HashMap::new(),
+            diagnostics: vec![]
+        }
+    }
+
+    // Type checks the tree starting from `node`. The
+    // violations found are stored in `diagnostics`.
+    pub fn run(&mut self, node: &Node<usize, Token>) {
+        self.check_statement(node);
+    }
+
+    fn check_statement(&mut self, node: &Node<usize, Token>) {
+        let children = node.children();
+        match node.value() {
+            Token::Ast(_) | Token::Else | Token::Label(_) => {
+                for child in children {
+                    self.check_statement(child);
+                }
+            },
+            // The assignments and the increments are also expressions
+            Token::Assignment | Token::AdditionAssignment | Token::SubtractionAssignment
+            | Token::MultiplicationAssignment | Token::DivisionAssignment
+            | Token::ModuloAssignment | Token::Increment | Token::Decrement => {
+                self.infer_expression_type(node);
+            },
+            Token::Print => {
+                self.infer_expression_type(&children[0]);
+            },
+            // The type of a constant is the type of its value
+            Token::Const => {
+                self.infer_expression_type(&children[1]);
+            },
+            Token::If | Token::While => {
+                self.expect_condition(node, &children[0]);
+                for child in &children[1..] {
+                    self.check_statement(child);
+                }
+            },
+            Token::Do => {
+                self.check_statement(&children[0]);
+                self.expect_condition(node, &children[1]);
+            },
+            Token::For => {
+                self.check_statement(&children[0]);
+                self.expect_condition(node, &children[1]);
+                self.check_statement(&children[2]);
+                self.check_statement(&children[3]);
+            },
+            Token::Switch => {
+                let switch_type = self.infer_expression_type(&children[0]);
+                for case_node in &children[1..] {
+                    let (block_node, label_nodes) = case_node.children().split_last().expect("to exist");
+                    for label_node in label_nodes {
+                        let label_type = self.infer_expression_type(label_node);
+                        if let (Some(switch_type), Some(label_type)) = (switch_type, label_type) {
+                            if switch_type != label_type {
+                                self.report(label_node, MISMATCHED_OPERAND_TYPES, format!(
+                                    "a case label of type {} cannot match a switch value of type {}",
+                                    label_type, switch_type
+                                ));
+                            }
+                        }
+                    }
+                    self.check_statement(block_node);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // Infers the type of the expression starting from `node` and stores it
+    // in `expression_types`. Returns `None` if the type can not be determined,
+    // which happens for undeclared identifiers (they are reported elsewhere).
+    pub fn infer_expression_type(&mut self, node: &Node<usize, Token>) -> Option<Type> {
+        let children = node.children();
+        let expression_type = match node.value() {
+            Token::Num(_) => Some(Type::Int),
+            Token::True | Token::False => Some(Type::Bool),
+            Token::Id(identifier) => self.symbol_table.get(identifier).copied(),
+            Token::ExclamationMark => {
+                if let Some(operand_type) = self.infer_expression_type(&children[0]) {
+                    if operand_type != Type::Bool {
+                        self.report(node, MISMATCHED_OPERAND_TYPES, format!(
+                            "operator '!' expects a bool operand, found {}", operand_type
+                        ));
+                    }
+                }
+                Some(Type::Bool)
+            },
+            Token::Assignment => {
+                let identifier_type = self.infer_expression_type(&children[0]);
+                let expression_type = self.infer_expression_type(&children[1]);
+                if let (Some(identifier_type), Some(expression_type)) = (identifier_type, expression_type) {
+                    if identifier_type != expression_type {
+                        self.report(node, MISMATCHED_ASSIGNMENT_TYPES, format!(
+                            "cannot assign a value of type {} to a variable of type {}",
+                            expression_type, identifier_type
+                        ));
+                    }
+                }
+                // The value of an assignment is the assigned value
+                identifier_type
+            },
+            Token::AdditionAssignment | Token::SubtractionAssignment
+            | Token::MultiplicationAssignment | Token::DivisionAssignment
+            | Token::ModuloAssignment => {
+                self.expect_operand_types(node, &children[0], &children[1], Type::Int);
+                Some(Type::Int)
+            },
+            Token::Increment | Token::Decrement => {
+                if let Some(operand_type) = self.infer_expression_type(&children[0]) {
+                    if operand_type != Type::Int {
+                        self.report(node, MISMATCHED_OPERAND_TYPES, format!(
+                            "operator '{}' expects an int operand, found {}",
+                            token_to_source_text(node.value()), operand_type
+                        ));
+                    }
+                }
+                Some(Type::Int)
+            },
+            Token::Asterisk if is_unary_minus(node) => {
+                if let Some(operand_type) = self.infer_expression_type(&children[1]) {
+                    if operand_type != Type::Int {
+                        self.report(node, MISMATCHED_OPERAND_TYPES, format!(
+                            "operator '-' expects an int operand, found {}", operand_type
+                        ));
+                    }
+                }
+                self.infer_expression_type(&children[0]);
+                Some(Type::Int)
+            },
+            Token::Plus | Token::Minus | Token::Asterisk
+            | Token::Slash | Token::Percent => {
+                self.expect_operand_types(node, &children[0], &children[1], Type::Int);
+                Some(Type::Int)
+            },
+            Token::LessThan | Token::LessThanOrEquals
+            | Token::GreaterThan | Token::GreaterThanOrEquals => {
+                self.expect_operand_types(node, &children[0], &children[1], Type::Int);
+                Some(Type::Bool)
+            },
+            Token::And | Token::Or => {
+                self.expect_operand_types(node, &children[0], &children[1], Type::Bool);
+                Some(Type::Bool)
+            },
+            Token::QuestionMark => {
+                if let Some(condition_type) = self.infer_expression_type(&children[0]) {
+                    if condition_type != Type::Bool {
+                        self.report(&children[0], NON_BOOL_CONDITION, format!(
+                            "the condition of the '?' operator must be bool, found {}", condition_type
+                        ));
+                    }
+                }
+                let then_type = self.infer_expression_type(&children[1]);
+                let else_type = self.infer_expression_type(&children[2]);
+                if let (Some(then_type), Some(else_type)) = (then_type, else_type) {
+                    if then_type != else_type {
+                        self.report(node, MISMATCHED_OPERAND_TYPES, format!(
+                            "the branches of the '?' operator have different types, {} and {}",
+                            then_type, else_type
+                        ));
+                    }
+                }
+                then_type.or(else_type)
+            },
+            Token::Equals | Token::NotEquals => {
+                let left_type = self.infer_expression_type(&children[0]);
+                let right_type = self.infer_expression_type(&children[1]);
+                if let (Some(left_type), Some(right_type)) = (left_type, right_type) {
+                    if left_type != right_type {
+                        self.report(node, MISMATCHED_OPERAND_TYPES, format!(
+                            "operator '{}' cannot compare {} with {}",
+                            token_to_source_text(node.value()), left_type, right_type
+                        ));
+                    }
+                }
+                Some(Type::Bool)
+            },
+            _ => None
+        };
+
+        if let Some(expression_type) = expression_type {
+            self.expression_types.insert(*node.id(), expression_type);
+        }
+        return expression_type;
+    }
+
+    // Infers the type that the operand at `index` of `parent` must have,
+    // from the operator or the statement that uses it, eg the type of the
+    // other side of an assignment. Returns `None` if any type would do,
+    // or if the type of the other side can not be determined.
+    pub fn infer_operand_type(&mut self, parent: &Node<usize, Token>, index: usize) -> Option<Type> {
+        let children = parent.children();
+        match parent.value() {
+            Token::Assignment | Token::Equals | Token::NotEquals => {
+                return self.infer_expression_type(&children[1 - index]);
+            },
+            Token::ExclamationMark | Token::And | Token::Or => return Some(Type::Bool),
+            Token::Plus | Token::Minus | Token::Asterisk | Token::Slash | Token::Percent
+            | Token::LessThan | Token::LessThanOrEquals
+            | Token::GreaterThan | Token::GreaterThanOrEquals
+            | Token::AdditionAssignment | Token::SubtractionAssignment
+            | Token::MultiplicationAssignment | Token::DivisionAssignment
+            | Token::ModuloAssignment => return Some(Type::Int),
+            Token::If | Token::While if index == 0 => return Some(Type::Bool),
+            Token::For if index == 1 => return Some(Type::Bool),
+            _ => return None
+        }
+    }
+
+    // Reports a violation if any of the two operands of the
+    // binary operator `node` does not have the `expected_type`.
+    fn expect_operand_types(
+        &mut self,
+        node: &Node<usize, Token>,
+        left_operand: &Node<usize, Token>,
+        right_operand: &Node<usize, Token>,
+        expected_type: Type
+    ) {
+        let left_type = self.infer_expression_type(left_operand);
+        let right_type = self.infer_expression_type(right_operand);
+        for operand_type in [left_type, right_type].into_iter().flatten() {
+            if operand_type != expected_type {
+                self.report(node, MISMATCHED_OPERAND_TYPES, format!(
+                    "operator '{}' expects {} operands, found {}",
+                    token_to_source_text(node.value()), expected_type, operand_type
+                ));
+                return;
+            }
+        }
+    }
+
+    fn expect_condition(&mut self, node: &Node<usize, Token>, condition: &Node<usize, Token>) {
+        if let Some(condition_type) = self.infer_expression_type(condition) {
+            if condition_type != Type::Bool {
+                self.report(condition, NON_BOOL_CONDITION, format!(
+                    "the condition of the '{}' statement must be bool, found {}",
+                    token_to_source_text(node.value()), condition_type
+                ));
+            }
+        }
+    }
+
+    fn report(&mut self, node: &Node<usize, Token>, code: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic::error(code, self.spans.get(*node.id()).copied(), message));
+    }
+}
+
+// The parser models the unary minus `-x` as `(-1) * x`. Since
+// the lexer never produces negative numbers, a multiplication
+// whose left operand is -1 always originates from a unary minus.
+fn is_unary_minus(node: &Node<usize, Token>) -> bool {
+    return *node.value() == Token::Asterisk
+        && node.children().first().map(|child| child.value()) == Some(&Token::Num(-1));
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+    use crate::utilities::get_tokens_and_spans_from_program;
+    use crate::diagnostics::has_errors;
+
+    fn type_check(program: &str) -> bool {
+        let (tokens, spans) = get_tokens_and_spans_from_program(program).expect("to be valid");
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let mut semantic_analyzer = SemanticAnalyzer::new(&parser.ast, &spans);
+        return !has_errors(&semantic_analyzer.run());
+    }
+
+    #[test]
+    fn test_well_typed_program() {
+        assert!(type_check(
+            "{ \
+                var a, b : int; \
+                var done : bool; \
+                done = false; \
+                while (!done && a < 10) { \
+                    a += 1; \
+                    b = -(a * 2) % 3; \
+                    done = a == b || b != 0 == true; \
+                } \
+                if (done) print a; \
+            }"
+        ));
+    }
+
+    #[test]
+    fn test_int_condition() {
+        assert!(!type_check("{ var a : int; if (a + 1) print a; }"));
+        assert!(!type_check("{ var a : int; while (a) print a; }"));
+        assert!(!type_check("{ var a : int; for (a = 0; a; a += 1) print a; }"));
+    }
+
+    #[test]
+    fn test_bool_in_arithmetic() {
+        assert!(!type_check("{ var a, b, x : int; x = (a < b) * 3; }"));
+        assert!(!type_check("{ var a : bool; a += 1; }"));
+        assert!(!type_check("{ var a : int; a = -true; }"));
+    }
+
+    #[test]
+    fn test_int_in_logical_operators() {
+        assert!(!type_check("{ var a : int; var b : bool; b = a && true; }"));
+        assert!(!type_check("{ var b : bool; b = false || 1; }"));
+        assert!(!type_check("{ var a : int; var b : bool; b = !a; }"));
+    }
+
+    #[test]
+    fn test_switch() {
+        assert!(type_check("{ var a : int; switch (a) { case 1, -2: a = 1; default: print a; } }"));
+        assert!(type_check("{ var b : bool; switch (!b) { case true: print 1; case 1 > 2: print 2; } }"));
+        assert!(!type_check("{ var a : int; switch (a) { case true: print a; } }"));
+        assert!(!type_check("{ var a : int; switch (a) { default: a = false; } }"));
+    }
+
+    #[test]
+    fn test_increments_and_assignment_expressions() {
+        assert!(type_check("{ var a : int; var b : bool; a++; --a; b = (a += 2) > a--; print b = !b; }"));
+        assert!(type_check("{ var a, c : int; var b : bool; a = c = 1; while (b = a < 3) a++; }"));
+        assert!(!type_check("{ var b : bool; b++; }"));
+        assert!(!type_check("{ var a : int; var b : bool; print -(b = true); }"));
+        assert!(!type_check("{ var a : int; var b : bool; a = b = true; }"));
+        assert!(!type_check("{ var a : int; if (a += 1) print a; }"));
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        assert!(type_check("{ var a : int; var b : bool; a = b ? 1 : a > 0 ? -a : 0; b = a < 0 ? b : !b; }"));
+        assert!(!type_check("{ var a : int; a = a ? 1 : 2; }"));
+        assert!(!type_check("{ var a : int; var b : bool; a = b ? 1 : false; }"));
+        assert!(!type_check("{ var a : int; var b : bool; b = b ? a : a; }"));
+        assert!(!type_check("{ var a : int; var b : bool; print (b ? a : a) && b; }"));
+    }
+
+    #[test]
+    fn test_do_while() {
+        assert!(type_check("{ var a : int; outer: do { a += 1; continue outer; } while (a < 3); }"));
+        assert!(!type_check("{ var a : int; do a += 1; while (a); }"));
+        assert!(!type_check("{ var a : int; outer: do a = true; while (false); }"));
+    }
+
+    #[test]
+    fn test_mismatched_assignment_and_equality() {
+        assert!(!type_check("{ var a : int; a = true; }"));
+        assert!(!type_check("{ var a : int; var b : bool; b = a == b; }"));
+    }
+}
@@ -1,18 +1,57 @@
-use crate::lexer::Token;
+use crate::lexer::{Token, Span};
+use crate::diagnostics::{Diagnostic, INVALID_TOKEN};
+use crate::json::JsonValue;
 use logos::Logos; // defines Token::lexer so it must be imported, read more here: https://stackoverflow.com/questions/25273816/why-do-i-need-to-import-a-trait-to-use-the-methods-it-defines-for-a-type
 use orange_trees::Node;
 
-pub fn get_tokens_from_program(program: &String) -> Vec<Token> {
-    let mut lex = Token::lexer(&program);
+#[cfg(test)]
+pub fn get_tokens_from_program(program: &str) -> Vec<Token> {
+    return get_tokens_and_spans_from_program(program).expect("to be valid").0;
+}
+
+// Same as `get_tokens_from_program`, but it also returns the span of
+// every token. The span of the token `tokens[i]` is stored in `spans[i]`.
+// If the program contains text that is not a token, an error is returned.
+pub fn get_tokens_and_spans_from_program(program: &str) -> Result<(Vec<Token>, Vec<Span>), Diagnostic> {
+    let mut lex = Token::lexer(program);
     let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut scanned_until = 0;
     loop {
         let iter = lex.next();
         match iter {
             None => break,
-            Some(val) => tokens.push(val.unwrap())
+            Some(val) => {
+                let range = lex.span();
+                // Count the newlines between the previous token and this one
+                for (offset, character) in program[scanned_until..range.start].char_indices() {
+                    if character == '\n' {
+                        line += 1;
+                        line_start = scanned_until + offset + 1;
+                    }
+                }
+                scanned_until = range.start;
+                let span = Span {
+                    start: range.start,
+                    end: range.end,
+                    line,
+                    column: range.start - line_start + 1
+                };
+                match val {
+                    Ok(token) => tokens.push(token),
+                    Err(_) => return Err(Diagnostic::error(
+                        INVALID_TOKEN,
+                        Some(span),
+                        format!("invalid token '{}'", &program[range])
+                    ))
+                }
+                spans.push(span);
+            }
         }
     }
-    return tokens;
+    return Ok((tokens, spans));
 }
 
 pub fn new_node_from_token(token_index: usize, token: Token) ->  Node<usize, Token> {
@@ -22,6 +61,29 @@ pub fn new_node_from_token(token_index: usize, token: Token) ->  Node<usize, Tok
     );
 }
 
+// The nodes that the compiler adds to the tree, such as the factor -1 of
+// a unary minus, are not created from a token. Their ids start from this
+// value, so they never collide with the index of a token.
+pub const SYNTHETIC_NODE_ID_BASE: usize = 1 << 31;
+
+// Creates a node that is added for the token at `token_index`. The
+// `offset`, 0 or 1, tells apart the nodes added for the same token.
+pub fn new_synthetic_node(token_index: usize, offset: usize, token: Token) -> Node<usize, Token> {
+    return new_node_from_token(SYNTHETIC_NODE_ID_BASE + 2 * token_index + offset, token);
+}
+
+pub fn is_synthetic_node(node: &Node<usize, Token>) -> bool {
+    return *node.id() >= SYNTHETIC_NODE_ID_BASE;
+}
+
+// The span of the token that the node was created from, if any
+pub fn node_span(node: &Node<usize, Token>, spans: &[Span]) -> Option<Span> {
+    if is_synthetic_node(node) {
+        return None;
+    }
+    return spans.get(*node.id()).copied();
+}
+
 pub fn arithmetic_assignment_operator_to_arithmetic_operator(
     arithmetic_assignment_operator: Token
 ) -> Token {
@@ -33,4 +95,238 @@ pub fn arithmetic_assignment_operator_to_arithmetic_operator(
         Token::ModuloAssignment => Token::Percent,
         _ => panic!("First argument must be an arithmetic assignment operator")
     }
-}
\ No newline at end of file
+}
+
+pub fn is_assignment_operator(token: &Token) -> bool {
+    return matches!(
+        token,
+        Token::Assignment | Token::AdditionAssignment | Token::SubtractionAssignment
+        | Token::MultiplicationAssignment | Token::DivisionAssignment | Token::ModuloAssignment
+    );
+}
+
+// The node of `++` or `--` models the operator token, and its only
+// child the variable. Thus, the operator is postfix, eg `a++`, if
+// the token of the variable comes before the token of the operator.
+pub fn is_postfix_increment_or_decrement(node: &Node<usize, Token>) -> bool {
+    return node.children()[0].id() < node.id();
+}
+
+// Boolean values are represented as the numbers 1 (true)
+// and 0 (false) in MIX. This function maps the boolean
+// literals to these numbers and leaves other tokens as they are.
+pub fn boolean_literal_to_number(token: &Token) -> Token {
+    match token {
+        Token::True => Token::Num(1),
+        Token::False => Token::Num(0),
+        _ => token.clone()
+    }
+}
+
+// Returns the text that produces `token` in the source code.
+pub fn token_to_source_text(token: &Token) -> String {
+    let text = match token {
+        Token::Print => "print",
+        Token::If => "if",
+        Token::Else => "else",
+        Token::While => "while",
+        Token::For => "for",
+        Token::Do => "do",
+        Token::Continue => "continue",
+        Token::Break => "break",
+        Token::Switch => "switch",
+        Token::Case => "case",
+        Token::Default => "default",
+        Token::LeftParen => "(",
+        Token::RightParen => ")",
+        Token::LeftBrace => "{",
+        Token::RightBrace => "}",
+        Token::Var => "var",
+        Token::Const => "const",
+        Token::Int => "int",
+        Token::Bool => "bool",
+        Token::Comma => ",",
+        Token::Colon => ":",
+        Token::Semicolon => ";",
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Asterisk => "*",
+        Token::Slash => "/",
+        Token::Percent => "%",
+        Token::ExclamationMark => "!",
+        Token::Assignment => "=",
+        Token::AdditionAssignment => "+=",
+        Token::SubtractionAssignment => "-=",
+        Token::MultiplicationAssignment => "*=",
+        Token::DivisionAssignment => "/=",
+        Token::ModuloAssignment => "%=",
+        Token::Increment => "++",
+        Token::Decrement => "--",
+        Token::Equals => "==",
+        Token::NotEquals => "!=",
+        Token::LessThan => "<",
+        Token::GreaterThan => ">",
+        Token::LessThanOrEquals => "<=",
+        Token::GreaterThanOrEquals => ">=",
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::QuestionMark => "?",
+        Token::True => "true",
+        Token::False => "false",
+        Token::Id(identifier) => return identifier.clone(),
+        Token::Num(number) => return number.to_string(),
+        Token::Ast(name) => return name.clone(),
+        Token::Label(name) => return name.clone()
+    };
+    return String::from(text);
+}
+
+// Returns a textual representation of the tree under `node`,
+// with one node per line, indented by its depth in the tree.
+pub fn ast_to_string(node: &Node<usize, Token>) -> String {
+    let mut text = String::new();
+    write_ast_node(node, 0, &mut text);
+    return text;
+}
+
+fn write_ast_node(node: &Node<usize, Token>, depth: usize, text: &mut String) {
+    text.push_str(&format!("{}{:?} (id: {})\n", "  ".repeat(depth), node.value(), node.id()));
+    for child in node.children() {
+        write_ast_node(child, depth + 1, text);
+    }
+}
+
+// The name of the variant of the token, eg 'Id' for `Token::Id("a")`
+pub fn token_kind(token: &Token) -> String {
+    let text = format!("{:?}", token);
+    return text.split('(').next().expect("to exist").to_string();
+}
+
+// The value that the token carries, eg the name of an identifier
+fn token_value_to_json(token: &Token) -> JsonValue {
+    match token {
+        Token::Id(name) | Token::Ast(name) | Token::Label(name) => return JsonValue::String(name.clone()),
+        Token::Num(number) => return JsonValue::Number(*number as i64),
+        _ => return JsonValue::Null
+    }
+}
+
+// Returns every token along with its index, which is the id of the
+// nodes of the tree that are created from it, and its span
+pub fn tokens_to_json(tokens: &[Token], spans: &[Span]) -> JsonValue {
+    return JsonValue::Array(tokens.iter().zip(spans).enumerate().map(|(index, (token, span))| {
+        return JsonValue::object(vec![
+            ("index", JsonValue::Number(index as i64)),
+            ("kind", JsonValue::String(token_kind(token))),
+            ("value", token_value_to_json(token)),
+            ("text", JsonValue::String(token_to_source_text(token))),
+            ("span", span.to_json())
+        ]);
+    }).collect());
+}
+
+// Returns the tree under `node` as nested JSON objects. The span of a node
+// is the span of the token it was created from, and it is omitted for the
+// nodes that the parser adds, such as the factor -1 of a unary minus.
+pub fn ast_to_json(node: &Node<usize, Token>, spans: &[Span]) -> JsonValue {
+    let mut fields = vec![
+        ("id", JsonValue::Number(*node.id() as i64)),
+        ("kind", JsonValue::String(token_kind(node.value()))),
+        ("value", token_value_to_json(node.value()))
+    ];
+    if let Some(span) = node_span(node, spans) {
+        fields.push(("span", span.to_json()));
+    }
+    fields.push(("children", JsonValue::Array(node.children().iter().map(|x| ast_to_json(x, spans)).collect())));
+    return JsonValue::object(fields);
+}
+
+// Returns the tree under `node` as a Graphviz graph, with the id and the
+// line and column of every node in its label. The graph nodes are numbered
+// in preorder, since the root has the same id as the node of the program.
+pub fn ast_to_dot(node: &Node<usize, Token>, spans: &[Span]) -> String {
+    let mut dot = String::from("digraph ast {\n    node [shape=box, fontname=monospace];\n");
+    write_dot_node(node, spans, &mut 0, &mut dot);
+    dot.push_str("}\n");
+    return dot;
+}
+
+// Writes the node and its subtree, and returns the number of the node
+fn write_dot_node(node: &Node<usize, Token>, spans: &[Span], nodes_count: &mut usize, dot: &mut String) -> usize {
+    let number = *nodes_count;
+    *nodes_count += 1;
+    let position = match node_span(node, spans) {
+        Some(span) => format!("{}:{}", span.line, span.column),
+        None => String::from("-")
+    };
+    let label = format!("{:?}\nid {}, {}", node.value(), node.id(), position);
+    let label = label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    dot.push_str(&format!("    n{} [label=\"{}\"];\n", number, label));
+    for child in node.children() {
+        let child_number = write_dot_node(child, spans, nodes_count, dot);
+        dot.push_str(&format!("    n{} -> n{};\n", number, child_number));
+    }
+    return number;
+}
+
+// ------------------------------------------------------
+//                        TESTS
+// ------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::parse_json;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_dumps() {
+        let (tokens, spans) = get_tokens_and_spans_from_program("{\n    var a : int;\n    a = 7;\n}").unwrap();
+        let tokens_json = tokens_to_json(&tokens, &spans);
+        assert_eq!(tokens_json.as_array().unwrap()[2].to_string(), parse_json(
+            r#"{"index": 2, "kind": "Id", "value": "a", "text": "a",
+                "span": {"start": 10, "end": 11, "line": 2, "column": 9}}"#
+        ).unwrap().to_string());
+
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let ast_json = ast_to_json(&parser.ast, &spans);
+        let assignment = &ast_json.get("children").unwrap().as_array().unwrap()[0]
+            .get("children").unwrap().as_array().unwrap()[1];
+        assert_eq!(assignment.get("kind").and_then(|x| x.as_str()), Some("Assignment"));
+        assert_eq!(assignment.get("id").and_then(|x| x.as_i64()), Some(7));
+        assert_eq!(assignment.get("span").and_then(|x| x.get("line")).and_then(|x| x.as_i64()), Some(3));
+        let number = &assignment.get("children").unwrap().as_array().unwrap()[1];
+        assert_eq!(number.get("value").and_then(|x| x.as_i64()), Some(7));
+
+        let dot = ast_to_dot(&parser.ast, &spans);
+        assert!(dot.starts_with("digraph ast {\n"));
+        assert!(dot.contains("    n0 [label=\"Ast(\\\"ROOT_AST_NODE\\\")\\nid 0, 1:1\"];\n"));
+        assert!(dot.contains("    n4 [label=\"Assignment\\nid 7, 3:7\"];\n"));
+        assert!(dot.contains("    n1 -> n4;\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_dumps_of_synthetic_nodes() {
+        // The program has more tokens than the ids that
+        // the synthetic nodes had before they were marked
+        let program = format!("{{\n    var a : int;\n    a = -a;\n{}}}", "    a = 1;\n".repeat(2600));
+        let (tokens, spans) = get_tokens_and_spans_from_program(&program).unwrap();
+        assert!(tokens.len() > 10002);
+        let mut parser = Parser::new(tokens);
+        assert!(parser.analyze_grammar());
+        let ast_json = ast_to_json(&parser.ast, &spans);
+        let negation = &ast_json.get("children").unwrap().as_array().unwrap()[0]
+            .get("children").unwrap().as_array().unwrap()[1]
+            .get("children").unwrap().as_array().unwrap()[1];
+        assert_eq!(negation.get("kind").and_then(|x| x.as_str()), Some("Asterisk"));
+        let factors = negation.get("children").unwrap().as_array().unwrap();
+        assert_eq!(factors[0].get("value").and_then(|x| x.as_i64()), Some(-1));
+        assert!(factors[0].get("span").is_none());
+        assert_eq!(factors[1].get("span").and_then(|x| x.get("column")).and_then(|x| x.as_i64()), Some(10));
+
+        let dot = ast_to_dot(&parser.ast, &spans);
+        assert!(dot.contains(&format!("    n7 [label=\"Num(-1)\\nid {}, -\"];\n", SYNTHETIC_NODE_ID_BASE + 2 * 8)));
+    }
+}
@@ -0,0 +1,167 @@
+// Runs the language server as an editor would: it writes a script of
+// messages to the stdin of 'yal-lsp', then reads all of the responses
+// and notifications from its stdout and checks them.
+
+// The code base favours explicit `return` statements
+#![allow(clippy::needless_return)]
+
+use mixal_compiler::json::{parse_json, JsonValue};
+use mixal_compiler::language_server::{read_message, write_message};
+use std::process::{Command, Stdio};
+
+const URI: &str = "file:///program.yal";
+
+const INVALID_PROGRAM: &str = "{\n    var a : int;\n    a = true;\n}\n";
+
+const VALID_PROGRAM: &str = "\
+{
+    var a, b : int;
+    a = 1;
+    b = a + 2;
+    print b;
+}
+";
+
+fn request(id: i64, method: &str, params: JsonValue) -> String {
+    return JsonValue::object(vec![
+        ("jsonrpc", JsonValue::string("2.0")),
+        ("id", JsonValue::Number(id)),
+        ("method", JsonValue::string(method)),
+        ("params", params)
+    ]).to_string();
+}
+
+fn notification(method: &str, params: JsonValue) -> String {
+    return JsonValue::object(vec![
+        ("jsonrpc", JsonValue::string("2.0")),
+        ("method", JsonValue::string(method)),
+        ("params", params)
+    ]).to_string();
+}
+
+fn text_document_position(line: i64, character: i64) -> Vec<(&'static str, JsonValue)> {
+    return vec![
+        ("textDocument", JsonValue::object(vec![("uri", JsonValue::string(URI))])),
+        ("position", JsonValue::object(vec![
+            ("line", JsonValue::Number(line)),
+            ("character", JsonValue::Number(character))
+        ]))
+    ];
+}
+
+// Runs the server over the messages and returns its status code and its messages
+fn run_server(messages: &[String]) -> (Option<i32>, Vec<JsonValue>) {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_yal-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("the server to start");
+    let mut stdin = server.stdin.take().expect("to exist");
+    for message in messages {
+        write_message(&mut stdin, message).expect("the server to read its input");
+    }
+    drop(stdin);
+
+    let output = server.wait_with_output().expect("the server to exit");
+    let mut stdout = output.stdout.as_slice();
+    let mut responses = vec![];
+    while let Some(message) = read_message(&mut stdout).expect("a valid message") {
+        responses.push(parse_json(&message).expect("a valid JSON message"));
+    }
+    return (output.status.code(), responses);
+}
+
+fn find_response(responses: &[JsonValue], id: i64) -> &JsonValue {
+    return responses.iter()
+        .find(|x| x.get("id").and_then(|x| x.as_i64()) == Some(id))
+        .expect("the response to exist");
+}
+
+fn range_start(location: &JsonValue) -> (i64, i64) {
+    let start = location.get("range").and_then(|x| x.get("start")).expect("to exist");
+    return (
+        start.get("line").and_then(|x| x.as_i64()).expect("to exist"),
+        start.get("character").and_then(|x| x.as_i64()).expect("to exist")
+    );
+}
+
+#[test]
+fn test_session() {
+    let text_document = |text: &str| JsonValue::object(vec![
+        ("uri", JsonValue::string(URI)),
+        ("languageId", JsonValue::string("yal")),
+        ("version", JsonValue::Number(1)),
+        ("text", JsonValue::string(text))
+    ]);
+    let messages = vec![
+        request(1, "initialize", JsonValue::object(vec![("capabilities", JsonValue::object(vec![]))])),
+        notification("initialized", JsonValue::object(vec![])),
+        notification("textDocument/didOpen", JsonValue::object(vec![("textDocument", text_document(INVALID_PROGRAM))])),
+        notification("textDocument/didChange", JsonValue::object(vec![
+            ("textDocument", JsonValue::object(vec![("uri", JsonValue::string(URI)), ("version", JsonValue::Number(2))])),
+            ("contentChanges", JsonValue::Array(vec![JsonValue::object(vec![("text", JsonValue::string(VALID_PROGRAM))])]))
+        ])),
+        // 'b' in 'b = a + 2'
+        request(2, "textDocument/hover", JsonValue::object(text_document_position(3, 4))),
+        // 'a' in 'b = a + 2'
+        request(3, "textDocument/definition", JsonValue::object(text_document_position(3, 8))),
+        request(4, "textDocument/references", JsonValue::object(
+            text_document_position(3, 4).into_iter()
+                .chain([("context", JsonValue::object(vec![("includeDeclaration", JsonValue::Bool(true))]))])
+                .collect()
+        )),
+        request(5, "textDocument/documentSymbol", JsonValue::object(vec![
+            ("textDocument", JsonValue::object(vec![("uri", JsonValue::string(URI))]))
+        ])),
+        request(6, "textDocument/formatting", JsonValue::object(vec![])),
+        String::from("{\"id\": 7,"),
+        request(8, "shutdown", JsonValue::Null),
+        notification("exit", JsonValue::Null)
+    ];
+    let (status, responses) = run_server(&messages);
+    assert_eq!(status, Some(0));
+
+    let capabilities = find_response(&responses, 1).get("result").and_then(|x| x.get("capabilities")).expect("to exist");
+    assert_eq!(capabilities.get("hoverProvider"), Some(&JsonValue::Bool(true)));
+
+    // The invalid program is reported, and then the diagnostics are cleared
+    let diagnostics: Vec<&[JsonValue]> = responses.iter()
+        .filter(|x| x.get("method").and_then(|x| x.as_str()) == Some("textDocument/publishDiagnostics"))
+        .map(|x| x.get("params").and_then(|x| x.get("diagnostics")).and_then(|x| x.as_array()).expect("to exist"))
+        .collect();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].len(), 1);
+    assert_eq!(diagnostics[0][0].get("severity"), Some(&JsonValue::Number(1)));
+    assert_eq!(range_start(&diagnostics[0][0]).0, 2);
+    assert!(diagnostics[1].is_empty());
+
+    let hover = find_response(&responses, 2).get("result").and_then(|x| x.get("contents")).expect("to exist");
+    let hover = hover.get("value").and_then(|x| x.as_str()).expect("to exist");
+    assert!(hover.contains("var b : int;"), "{}", hover);
+    assert!(hover.contains("address 2"), "{}", hover);
+
+    let definition = find_response(&responses, 3).get("result").expect("to exist");
+    assert_eq!(range_start(definition), (1, 8));
+
+    let references = find_response(&responses, 4).get("result").and_then(|x| x.as_array()).expect("to exist");
+    let references: Vec<(i64, i64)> = references.iter().map(range_start).collect();
+    assert_eq!(references, vec![(1, 11), (3, 4), (4, 10)]);
+
+    let symbols = find_response(&responses, 5).get("result").and_then(|x| x.as_array()).expect("to exist");
+    let symbols: Vec<&str> = symbols.iter().map(|x| x.get("name").and_then(|x| x.as_str()).expect("to exist")).collect();
+    assert_eq!(symbols, vec!["a", "b"]);
+
+    let error_code = |response: &JsonValue| response.get("error").and_then(|x| x.get("code")).and_then(|x| x.as_i64());
+    assert_eq!(error_code(find_response(&responses, 6)), Some(-32601));
+    let parse_error = responses.iter().find(|x| x.get("id") == Some(&JsonValue::Null)).expect("to exist");
+    assert_eq!(error_code(parse_error), Some(-32700));
+
+    assert_eq!(find_response(&responses, 8).get("result"), Some(&JsonValue::Null));
+}
+
+#[test]
+fn test_exit_without_shutdown() {
+    let (status, responses) = run_server(&[notification("exit", JsonValue::Null)]);
+    assert_eq!(status, Some(1));
+    assert!(responses.is_empty());
+}
@@ -0,0 +1,213 @@
+// Compiles every program of the `tests/programs` corpus, runs it in
+// the MIX emulator and in the interpreter, and compares both outputs
+// with the expected output, which is stored next to the program in
+// a file with the same name and the '.out' extension.
+//
+// The tree of every program and its generated MIXAL are also compared with
+// the snapshots of `tests/snapshots`. Run the tests with UPDATE_SNAPSHOTS=1
+// to write the current outputs as the snapshots, and review their diff.
+//
+// The benchmark programs are run with and without the loop optimizations,
+// which must make them take less time in MIX.
+
+// The code base favours explicit `return` statements
+#![allow(clippy::needless_return)]
+
+use mixal_compiler::{compile, CompileOptions};
+use mixal_compiler::fuzzer::find_first_diverging_line;
+use mixal_compiler::interpreter::Interpreter;
+use mixal_compiler::mixal::{emulator::MixMachine, loader::load_program};
+use mixal_compiler::mixal::memory_layout::{MemoryLayout, Region};
+use mixal_compiler::profiler::profile_program;
+use mixal_compiler::utilities::ast_to_string;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PROGRAMS_DIRECTORY: &str = "tests/programs";
+const SNAPSHOTS_DIRECTORY: &str = "tests/snapshots";
+
+// The environment variable that makes the tests update the snapshots
+const UPDATE_SNAPSHOTS_VARIABLE: &str = "UPDATE_SNAPSHOTS";
+
+// The programs of the corpus whose loops the optimizations speed up
+const BENCHMARK_PROGRAMS: [&str; 2] = ["loop_optimizations.yal", "table_sums.yal"];
+
+// Programs that execute more instructions are assumed to never halt
+const MAX_INSTRUCTIONS: u64 = 10_000_000;
+
+fn get_program_paths() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(PROGRAMS_DIRECTORY);
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+        .expect("to exist")
+        .map(|entry| entry.expect("to be readable").path())
+        .filter(|path| path.extension().is_some_and(|x| x == "yal"))
+        .collect();
+    paths.sort();
+    return paths;
+}
+
+// Returns the outputs of the program in MIX and in the interpreter
+fn run_program(source: &str, options: &CompileOptions) -> Result<(Vec<String>, Vec<String>), String> {
+    let artifacts = compile(source, options).map_err(|diagnostics| {
+        let diagnostics: Vec<String> = diagnostics.iter().map(|x| x.to_string()).collect();
+        return format!("the program does not compile:\n{}", diagnostics.join("\n"));
+    })?;
+
+    let program = load_program(artifacts.instructions.as_ref().expect("to exist"))
+        .map_err(|error| format!("the generated MIXAL does not assemble: {}", error))?;
+    let mut machine = MixMachine::new(&program);
+    machine.run(MAX_INSTRUCTIONS).map_err(|error| format!("the MIX program failed: {}", error))?;
+
+    let mut interpreter = Interpreter::new(artifacts.ast.as_ref().expect("to exist"));
+    // An exception is part of the output of the program
+    let _ = interpreter.run();
+
+    return Ok((machine.output, interpreter.output));
+}
+
+// Checks that the programs of the corpus print their expected output,
+// both in MIX and in the interpreter, when compiled with the options
+fn check_programs(options: &CompileOptions) {
+    let mut failures = vec![];
+    let program_paths = get_program_paths();
+    assert!(!program_paths.is_empty(), "no programs found in '{}'", PROGRAMS_DIRECTORY);
+
+    for program_path in program_paths {
+        let name = program_path.file_name().expect("to exist").to_string_lossy().to_string();
+        let source = fs::read_to_string(&program_path).expect("to be readable");
+        let Ok(expected_output) = fs::read_to_string(program_path.with_extension("out")) else {
+            failures.push(format!("{}: the expected output file is missing", name));
+            continue;
+        };
+        let expected_lines: Vec<String> = expected_output.lines().map(String::from).collect();
+
+        match run_program(&source, options) {
+            Err(error) => failures.push(format!("{}: {}", name, error)),
+            Ok((mix_output, interpreter_output)) => {
+                if let Some(difference) = find_first_diverging_line(&expected_lines, &mix_output) {
+                    failures.push(format!("{} (MIX): {}", name, difference));
+                }
+                if let Some(difference) = find_first_diverging_line(&expected_lines, &interpreter_output) {
+                    failures.push(format!("{} (interpreter): {}", name, difference));
+                }
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{} mismatch(es):\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn test_programs() {
+    check_programs(&CompileOptions::default());
+}
+
+// Every address that the generated code uses must come from the memory layout
+#[test]
+fn test_programs_with_moved_regions() {
+    let mut memory_layout = MemoryLayout::default();
+    for (region, base) in [
+        (Region::Temporaries, 100), (Region::Globals, 150), (Region::Stack, 400),
+        (Region::Constants, 600), (Region::IoBuffers, 700), (Region::Code, 1000)
+    ] {
+        memory_layout.set_base(region, base);
+    }
+    assert_eq!(memory_layout.validate(), Ok(()));
+    check_programs(&CompileOptions { memory_layout, ..CompileOptions::default() });
+}
+
+#[test]
+fn test_programs_without_loop_optimizations() {
+    check_programs(&CompileOptions { optimize_loops: false, ..CompileOptions::default() });
+}
+
+#[test]
+fn test_loop_optimizations() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(PROGRAMS_DIRECTORY);
+    for name in BENCHMARK_PROGRAMS {
+        let source = fs::read_to_string(directory.join(name)).expect("to be readable");
+        let profile = |optimize_loops: bool| {
+            let options = CompileOptions { optimize_loops, ..CompileOptions::default() };
+            let artifacts = compile(&source, &options).expect("to compile");
+            return profile_program(&artifacts).expect("to run");
+        };
+        let (output, optimized_profile) = profile(true);
+        let (unoptimized_output, unoptimized_profile) = profile(false);
+        assert_eq!(output, unoptimized_output, "{}", name);
+        assert!(
+            optimized_profile.time < unoptimized_profile.time,
+            "{} takes {}u with the loop optimizations and {}u without them",
+            name, optimized_profile.time, unoptimized_profile.time
+        );
+    }
+}
+
+// A switch whose labels are dense, but span more values than a jump
+// table may have, is compiled to a chain of comparisons instead
+#[test]
+fn test_jump_table_size() {
+    for (labels_count, step, uses_table) in [(128, 2, true), (129, 2, false), (300, 1, false)] {
+        let labels: Vec<String> = (0..labels_count).map(|x| (x * step).to_string()).collect();
+        let source = format!(
+            "{{ var a : int; a = {}; switch (a) {{ case {}: print 1; default: print 0; }} }}",
+            (labels_count - 1) * step, labels.join(", ")
+        );
+        let artifacts = compile(&source, &CompileOptions::default()).expect("to compile");
+        // The jump to the entry of the table is indexed by register RI1
+        let mixal = artifacts.mixal().expect("to exist");
+        let has_table = mixal.lines().any(|x| x.contains("JSJ") && x.ends_with(",1"));
+        assert_eq!(has_table, uses_table, "{} labels, {} apart", labels_count, step);
+        let (mix_output, interpreter_output) = run_program(&source, &CompileOptions::default()).expect("to run");
+        assert_eq!(mix_output, interpreter_output);
+    }
+}
+
+// Compares `actual` with the snapshot at `snapshot_path`, or replaces
+// the snapshot in update mode. Returns the difference, if any.
+fn check_snapshot(snapshot_path: &Path, actual: &str, update: bool) -> Option<String> {
+    if update {
+        fs::write(snapshot_path, actual).expect("to be writable");
+        return None;
+    }
+    let Ok(expected) = fs::read_to_string(snapshot_path) else {
+        return Some(String::from("the snapshot is missing"));
+    };
+    let expected_lines: Vec<String> = expected.lines().map(String::from).collect();
+    let actual_lines: Vec<String> = actual.lines().map(String::from).collect();
+    return find_first_diverging_line(&expected_lines, &actual_lines);
+}
+
+#[test]
+fn test_snapshots() {
+    let update = env::var(UPDATE_SNAPSHOTS_VARIABLE).is_ok_and(|x| x == "1");
+    let snapshots_directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOTS_DIRECTORY);
+    if update {
+        fs::create_dir_all(&snapshots_directory).expect("to be writable");
+    }
+    let mut failures = vec![];
+    for program_path in get_program_paths() {
+        let name = program_path.file_stem().expect("to exist").to_string_lossy().to_string();
+        let source = fs::read_to_string(&program_path).expect("to be readable");
+        let artifacts = match compile(&source, &CompileOptions::default()) {
+            Ok(artifacts) => artifacts,
+            Err(_) => {
+                failures.push(format!("{}: the program does not compile", name));
+                continue;
+            }
+        };
+        let ast = ast_to_string(artifacts.ast.as_ref().expect("to exist"));
+        let mixal = artifacts.mixal().expect("to exist");
+        for (extension, actual) in [("ast", ast), ("mixal", mixal)] {
+            let snapshot_path = snapshots_directory.join(format!("{}.{}", name, extension));
+            if let Some(difference) = check_snapshot(&snapshot_path, &actual, update) {
+                failures.push(format!("{}.{}: {}", name, extension, difference));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} snapshot mismatch(es), run the tests with {}=1 to accept the changes:\n{}",
+        failures.len(), UPDATE_SNAPSHOTS_VARIABLE, failures.join("\n")
+    );
+}